@@ -0,0 +1,24 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use quefi::bench::Library;
+use ratatui::{buffer::Buffer, layout::Rect};
+
+fn render(c: &mut Criterion) {
+    let area = Rect::new(0, 0, 120, 40);
+    let mut buf = Buffer::empty(area);
+
+    for songs in [100, 10_000] {
+        let mut library = Library::new(songs);
+        c.bench_function(&format!("render {songs} songs"), |b| {
+            b.iter(|| library.render(area, &mut buf))
+        });
+        c.bench_function(&format!("scroll through {songs} songs"), |b| {
+            b.iter(|| {
+                library.select_next_song();
+                library.render(area, &mut buf);
+            })
+        });
+    }
+}
+
+criterion_group!(benches, render);
+criterion_main!(benches);
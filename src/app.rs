@@ -1,11 +1,35 @@
-use crate::{SaveData, TaskResult};
-use ratatui::widgets::ListState;
-use regex::Regex;
+use crate::{
+    cache::Cache,
+    get_cache_dir,
+    locale::Locale,
+    party::Submission,
+    songs_dir,
+    spotify::{self, SearchItem, TrackInfo},
+    tags::SongTags,
+    update::Update,
+    util,
+    youtube::{self, AudioFormat, Dlp, PendingDownload, SearchResult},
+    DownloadId, SaveData, SearchFor, TaskResult,
+};
+use ratatui::{crossterm::event::Event, widgets::ListState};
 use reqwest::Client;
 use rodio::{OutputStream, OutputStreamBuilder, Sink};
 use serde::{Deserialize, Serialize};
-use std::{collections::HashMap, time::Duration};
-use tokio::task::JoinHandle;
+use std::{
+    cmp::Reverse,
+    collections::{BTreeMap, HashMap, HashSet, VecDeque},
+    fmt::{self, Display, Formatter},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{
+    sync::{
+        mpsc::{self, UnboundedReceiver, UnboundedSender},
+        watch, Semaphore,
+    },
+    task::{JoinError, JoinHandle},
+};
+use tokio_util::sync::CancellationToken;
 use tui_textarea::TextArea;
 
 #[macro_use]
@@ -14,10 +38,35 @@
 mod imp;
 mod widget;
 
+#[doc(hidden)]
+pub mod bench;
+
+use widget::SongItems;
+
+#[cfg(test)]
+mod tests;
+
 fn is_valid_youtube_link(url: &str) -> bool {
-    let re = Regex::new(r"^https?://(www\.)?(youtube\.com/watch\?v=|youtu\.be/)[\w-]{11}(&.*)?$")
-        .unwrap();
-    re.is_match(url)
+    util::YOUTUBE_LINK.is_match(url)
+}
+
+fn is_valid_youtube_playlist_link(url: &str) -> bool {
+    util::YOUTUBE_PLAYLIST_LINK.is_match(url)
+}
+
+fn is_valid_youtube_album_link(url: &str) -> bool {
+    util::YOUTUBE_ALBUM_LINK.is_match(url)
+}
+
+fn is_valid_metadata_link(url: &str) -> bool {
+    util::METADATA_LINK.is_match(url)
+}
+
+// What wakes up the run loop
+enum AppEvent {
+    Input(Event),
+    TaskDone(Result<TaskResult, JoinError>),
+    Tick,
 }
 
 #[derive(Debug, PartialEq)]
@@ -25,12 +74,21 @@ enum Mode {
     Input(InputMode),
     Normal,
     Help,
+    Stats,
+    Notes(String),
+    Changelog,
+    PickResult,
+    PickSpotifyResult,
+    // Waiting for the background tasks to finish before quitting
+    Quitting,
 }
 
 #[derive(Debug, PartialEq)]
 enum InputMode {
     DownloadLink,
     AddPlaylist,
+    ClonePlaylist,
+    PlaylistDescription,
     AddSongToPlaylist,
     ChooseFile(String),
     AddGlobalSong,
@@ -38,6 +96,38 @@ enum InputMode {
     DlpPath,
     SpotifyClientId,
     SpotifyClientSecret,
+    CleanupDays,
+    StreamLink,
+    SpotifySearch,
+    RelocateSong(String),
+    ReplaceSong(String),
+    NowPlayingPath,
+    ImportDirectory,
+    ImportPlaylists(String),
+    MoveToIndex,
+    SongPosition,
+    Filter,
+    DlpNiceness,
+    MaxDlpProcesses,
+    Language,
+    AutoSkipAfter,
+    PartyPort,
+    CacheSize,
+    StartupPlaylist,
+    MaxParallelSongs,
+    Cookies,
+    MusicDir,
+    // Previous music directory
+    MoveSongs(String),
+    GetFfmpeg,
+    FfmpegPath,
+    DownloadHook,
+    SpotifyMarket,
+    Rename,
+    ExportPlaylist,
+    ExportFailures,
+    ResumeDownloads,
+    Notes(String),
 }
 
 #[derive(Debug, PartialEq)]
@@ -52,6 +142,17 @@ enum Window {
     GlobalSongs,
     ConfigurationMenu,
     DownloadManager,
+    Cleanup,
+    Queue,
+    Integrity,
+    History,
+    Journal,
+    Skipped,
+    PartyQueue,
+    Removed,
+    DownloadHistory,
+    PlaylistSync,
+    Log,
 }
 
 #[derive(Debug, PartialEq, Clone, Copy)]
@@ -78,20 +179,107 @@ enum Repeat {
     One,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub(crate) struct SerializablePlaylist {
     songs: Vec<String>,
     name: String,
+    #[serde(default)]
+    description: String,
+    // Used for songs downloaded into this playlist
+    #[serde(default)]
+    audio_format: AudioFormat,
+    // Every change made to the songs, oldest first
+    #[serde(default)]
+    journal: Vec<JournalEntry>,
+    // Spotify playlist it was imported from, empty if it wasn't
+    #[serde(default)]
+    spotify_id: String,
 }
 
-#[derive(Debug, Serialize, Deserialize, Clone)]
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) enum PlaylistChange {
+    Created,
+    // Song name and its position in the playlist
+    Added(String, usize),
+    Removed(String, usize),
+    Moved(String, usize, usize),
+    Sorted(String),
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct JournalEntry {
+    change: PlaylistChange,
+    time: u64,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize, Clone)]
 pub(crate) struct SerializableSong {
     name: String,
+    // Can start with a ${NAME} placeholder on disk, see SaveData::expand_paths
+    pub(crate) path: String,
+    #[serde(default)]
+    favorite: bool,
+    #[serde(default)]
+    last_played: u64,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    added: u64,
+    #[serde(default)]
+    play_count: u32,
+    // Times the song was skipped right after it started
+    #[serde(default)]
+    skips: u32,
+    // In seconds, 0 if unknown
+    #[serde(default)]
+    duration: u64,
+    // Lyrics, chords, credits or anything else the user wants to remember about the song
+    #[serde(default)]
+    notes: String,
+}
+
+// Search results of a single song, waiting for one to be picked
+struct ResultChoice {
+    id: DownloadId,
+    search_for: SearchFor,
+    results: Vec<SearchResult>,
+}
+
+// A song removed from the library, kept for a while so it can be restored
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct RemovedSong {
+    pub(crate) song: SerializableSong,
+    // Playlists and positions the song was taken out of, in the order it happened
+    positions: Vec<(String, usize)>,
+    removed: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct HistoryEntry {
+    name: String,
+    played: u64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub(crate) struct DownloadRecord {
+    link: String,
     path: String,
+    downloaded: u64,
+    // In bytes
+    size: u64,
+}
+
+// A message that was shown in the log line
+#[derive(Debug)]
+struct LogEntry {
+    time: u64,
+    text: String,
+    error: bool,
 }
 
 #[derive(Debug, Clone)]
 struct Playlist {
+    song_list_state: ListState,
     songs: Vec<Song>,
     selected: Selected,
     playing: bool,
@@ -104,18 +292,173 @@ struct Song {
     name: String,
     path: String,
     playing: bool,
+    favorite: bool,
+    // In seconds, 0 if unknown
+    duration: u64,
+}
+
+#[derive(Debug)]
+struct CleanupCandidate {
+    name: String,
+    path: String,
+    days: u64,
+}
+
+// What a Spotify search found, items are taken out as they're imported
+#[derive(Debug)]
+struct SpotifySearch {
+    query: String,
+    items: Vec<SearchItem>,
+}
+
+// What changed in a Spotify playlist since it was imported, shown before syncing it
+#[derive(Debug)]
+struct PlaylistSync {
+    playlist_idx: usize,
+    added: Vec<TrackInfo>,
+    // Songs of the playlist that aren't on Spotify anymore
+    removed: Vec<SongName>,
+}
+
+#[derive(Debug)]
+enum IntegrityProblem {
+    Missing,
+    Undecodable(String),
+}
+
+#[derive(Debug)]
+struct IntegrityIssue {
+    name: String,
+    path: String,
+    problem: IntegrityProblem,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum UiSound {
+    TrackChange,
+    Error,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum QueuedFrom {
+    GlobalSong(usize),
+    Playlist(usize, PlaylistSongIdx),
+    Manual,
+    Stream,
 }
 
 #[derive(Debug)]
 struct QueuedSong {
+    from: QueuedFrom,
     name: String,
-    song_idx: usize,
+    path: String,
     duration: Duration,
 }
 
+struct Preview {
+    sink: Sink,
+    // Whether the main queue was playing before the preview paused it
+    resume: bool,
+}
+
+// A bug in quefi itself, shown in a popup that can be closed instead of crashing the session
+#[derive(Debug, PartialEq)]
+enum AppError {
+    NothingSelected,
+    Seek(String),
+    // The result of a playlist import came for a download that isn't one
+    NotPlaylistImport(DownloadId),
+    NoInputHandler(String),
+    TaskPanicked(String),
+}
+
+impl Display for AppError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            AppError::NothingSelected => write!(f, "Nothing is selected in the list"),
+            AppError::Seek(err) => write!(f, "Couldn't seek in the song: {err}"),
+            AppError::NotPlaylistImport(id) => {
+                write!(
+                    f,
+                    "Download {id} got a song of a playlist, but isn't importing one"
+                )
+            }
+            AppError::NoInputHandler(mode) => write!(f, "Nothing handles the input of {mode}"),
+            AppError::TaskPanicked(err) => write!(f, "A background task crashed: {err}"),
+        }
+    }
+}
+
+// A fix for the error in the log, b runs it if there's a shortcut for it
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum Hint {
+    GetDlp,
+    InstallFfmpeg,
+    SpotifyCredentials,
+    UpdateDlp,
+    Cookies,
+}
+
+impl Hint {
+    fn text(self) -> &'static str {
+        match self {
+            Hint::GetDlp => "b - download yt-dlp",
+            Hint::InstallFfmpeg => "b - download ffmpeg",
+            Hint::SpotifyCredentials => "b - set the Spotify client ID/secret",
+            Hint::UpdateDlp => "b - update yt-dlp",
+            Hint::Cookies => "b - set browser cookies",
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+enum SortBy {
+    Name,
+    Duration,
+    Added,
+    PlayCount,
+}
+
+impl SortBy {
+    fn next(self) -> SortBy {
+        match self {
+            SortBy::Name => SortBy::Duration,
+            SortBy::Duration => SortBy::Added,
+            SortBy::Added => SortBy::PlayCount,
+            SortBy::PlayCount => SortBy::Name,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
 enum ConfigFieldType {
     SpotifyClientSecret,
     SpotifyClientId,
+    HighContrast,
+    CleanupDays,
+    NowPlayingPath,
+    ScrollTitles,
+    DlpNiceness,
+    MaxDlpProcesses,
+    NormalizeLoudness,
+    Language,
+    ScreenReader,
+    TrackNotification,
+    AutoSkipAfter,
+    UiSounds,
+    PartyPort,
+    CacheSize,
+    CheckForUpdates,
+    StartupWindow,
+    StartupPlaylist,
+    LowMemory,
+    MaxParallelSongs,
+    AudioFormat,
+    Cookies,
+    MusicDir,
+    FfmpegPath,
+    DownloadHook,
+    SpotifyMarket,
     DlpPath,
 }
 
@@ -128,9 +471,126 @@ struct ConfigField {
 struct Config {
     spotify_client_secret: ConfigField,
     spotify_client_id: ConfigField,
+    high_contrast: ConfigField,
+    cleanup_days: ConfigField,
+    now_playing_path: ConfigField,
+    scroll_titles: ConfigField,
+    dlp_niceness: ConfigField,
+    max_dlp_processes: ConfigField,
+    normalize_loudness: ConfigField,
+    language: ConfigField,
+    screen_reader: ConfigField,
+    track_notification: ConfigField,
+    auto_skip_after: ConfigField,
+    ui_sounds: ConfigField,
+    party_port: ConfigField,
+    cache_size: ConfigField,
+    check_for_updates: ConfigField,
+    startup_window: ConfigField,
+    startup_playlist: ConfigField,
+    low_memory: ConfigField,
+    max_parallel_songs: ConfigField,
+    audio_format: ConfigField,
+    cookies: ConfigField,
+    music_dir: ConfigField,
+    ffmpeg_path: ConfigField,
+    download_hook: ConfigField,
+    spotify_market: ConfigField,
     dlp_path: ConfigField,
 }
 
+impl Config {
+    fn fields(&self) -> Vec<&ConfigField> {
+        vec![
+            &self.dlp_path,
+            &self.spotify_client_id,
+            &self.spotify_client_secret,
+            &self.high_contrast,
+            &self.cleanup_days,
+            &self.now_playing_path,
+            &self.scroll_titles,
+            &self.dlp_niceness,
+            &self.max_dlp_processes,
+            &self.normalize_loudness,
+            &self.language,
+            &self.screen_reader,
+            &self.track_notification,
+            &self.auto_skip_after,
+            &self.ui_sounds,
+            &self.party_port,
+            &self.cache_size,
+            &self.check_for_updates,
+            &self.startup_window,
+            &self.startup_playlist,
+            &self.low_memory,
+            &self.max_parallel_songs,
+            &self.audio_format,
+            &self.cookies,
+            &self.music_dir,
+            &self.ffmpeg_path,
+            &self.download_hook,
+            &self.spotify_market,
+        ]
+    }
+
+    fn field_mut(&mut self, idx: usize) -> Option<&mut ConfigField> {
+        [
+            &mut self.dlp_path,
+            &mut self.spotify_client_id,
+            &mut self.spotify_client_secret,
+            &mut self.high_contrast,
+            &mut self.cleanup_days,
+            &mut self.now_playing_path,
+            &mut self.scroll_titles,
+            &mut self.dlp_niceness,
+            &mut self.max_dlp_processes,
+            &mut self.normalize_loudness,
+            &mut self.language,
+            &mut self.screen_reader,
+            &mut self.track_notification,
+            &mut self.auto_skip_after,
+            &mut self.ui_sounds,
+            &mut self.party_port,
+            &mut self.cache_size,
+            &mut self.check_for_updates,
+            &mut self.startup_window,
+            &mut self.startup_playlist,
+            &mut self.low_memory,
+            &mut self.max_parallel_songs,
+            &mut self.audio_format,
+            &mut self.cookies,
+            &mut self.music_dir,
+            &mut self.ffmpeg_path,
+            &mut self.download_hook,
+            &mut self.spotify_market,
+        ]
+        .into_iter()
+        .nth(idx)
+    }
+}
+
+fn on_off(value: bool) -> String {
+    String::from(if value { "On" } else { "Off" })
+}
+
+fn notification_name(value: u8) -> String {
+    String::from(match value {
+        1 => "Bell",
+        2 => "Desktop (OSC 9)",
+        _ => "Off",
+    })
+}
+
+fn startup_window_name(value: u8) -> String {
+    String::from(match value {
+        1 => "Songs",
+        2 => "All songs",
+        3 => "Download manager",
+        4 => "Queue",
+        _ => "Playlists",
+    })
+}
+
 type SongQuery = String;
 type SongName = String;
 
@@ -143,61 +603,225 @@ struct ProcessingPlaylistSongs {
     playlist_name: String,
     downloaded: u16,
     searched: u16,
+    // Tracks that were already in the library, so they weren't downloaded again
+    reused: usize,
+    failed: usize,
+    // The rest of a long Spotify playlist is still being fetched
+    more_tracks: bool,
+    // Downloaded songs and the Spotify metadata to write into them once the import is done
+    to_tag: Vec<(String, TrackInfo)>,
+}
+
+// Songs of a playlist's imports that couldn't be downloaded, and why
+#[derive(Debug)]
+struct FailedImport {
+    playlist_name: String,
+    songs: Vec<(SongName, String)>,
+}
+
+#[derive(Debug)]
+struct WritingTags {
+    playlist_name: String,
+    remaining: Vec<(String, TrackInfo)>,
+    total: usize,
+    written: usize,
 }
 
 #[derive(Debug)]
 enum Download {
     ProcessingPlaylistSongs(ProcessingPlaylistSongs),
+    WritingTags(WritingTags),
     SearchingForSong(SongQuery),
+    PickingResult(SongName),
     DownloadingSong(SongName),
     DownloadingYoutubeSong,
+    FetchingStream(String),
+    FetchingLinkMetadata,
     FetchingSpotifyToken,
     FetchingPlaylistInfo,
     FetchingTrackInfo,
+    SearchingSpotify(String),
+    FetchingNewReleases,
     Empty,
 }
 
+struct SessionStats {
+    listening_time: Duration,
+    downloaded_bytes: u64,
+    failed_downloads: u32,
+    tracks_played: u32,
+    last_tick: Instant,
+}
+
+impl SessionStats {
+    fn new() -> Self {
+        SessionStats {
+            listening_time: Duration::ZERO,
+            downloaded_bytes: 0,
+            failed_downloads: 0,
+            tracks_played: 0,
+            last_tick: Instant::now(),
+        }
+    }
+}
+
 pub(crate) struct App<'a> {
-    _keep_alive: OutputStream,
-    join_handles: Vec<JoinHandle<TaskResult>>,
+    stream: Option<OutputStream>,
+    preview: Option<Preview>,
+    // Created on the first UI sound, so the music's sink isn't affected by them
+    ui_sink: Option<Sink>,
+    // Tasks whose result hasn't been handled yet
+    running_tasks: usize,
+    // Every task sends its result to it, which wakes up the run loop to handle it
+    // A task that panicked sends its JoinError instead
+    task_result_sender: UnboundedSender<Result<TaskResult, JoinError>>,
+    task_results: UnboundedReceiver<Result<TaskResult, JoinError>>,
+    // Stops every running task, along with their yt-dlp and ffmpeg processes
+    cancel: CancellationToken,
+    dlp: Dlp,
+    // Taken by every search/download of a playlist import while it runs
+    playlist_slots: Arc<Semaphore>,
+    // Searches and downloads that haven't started yet wait while it's true
+    downloads_paused: watch::Sender<bool>,
     global_song_list_state: ListState,
-    downloads: HashMap<u8, Download>,
+    downloads: BTreeMap<DownloadId, Download>,
+    next_download_id: DownloadId,
+    streams: HashMap<String, Arc<[u8]>>,
+    cache: Cache,
+    // Found by the update check on launch
+    update: Option<Update>,
+    song_tags: HashMap<String, SongTags>,
+    song_items: SongItems,
+    // Spotify tracks that are being searched for or downloaded, by song name
+    spotify_tracks: HashMap<String, TrackInfo>,
     playlist_list_state: ListState,
     pub(crate) save_data: SaveData,
+    pub(crate) read_only: bool,
+    // Started with --offline, nothing is fetched from the network
+    pub(crate) offline: bool,
+    // Started with --playlist, played once the library is loaded
+    pub(crate) start_playlist: Option<String>,
+    // Set when the kiosk playlist from config.toml was found on launch
+    kiosk: bool,
+    last_kiosk_restart: Option<Instant>,
+    party_server: Option<JoinHandle<()>>,
+    party_receiver: Option<mpsc::Receiver<Submission>>,
+    party_requests: Vec<Submission>,
+    party_state: ListState,
+    // Downloads approved from the party queue, their songs get queued once downloaded
+    party_downloads: HashSet<DownloadId>,
+    // Downloads interrupted by a crash, with the playlist and format they were started for
+    resumed_downloads: HashMap<DownloadId, PendingDownload>,
+    // Songs being replaced with another video, their old file goes once the new one is picked
+    replacing_songs: HashSet<String>,
+    failed_imports: Vec<FailedImport>,
     config_menu_state: ListState,
+    playlist_cursor: Option<(usize, PlaylistSongIdx)>,
+    global_cursor: Option<usize>,
+    global_order: Vec<usize>,
     song_queue: Vec<QueuedSong>,
+    pending_queue: Vec<QueuedSong>,
+    queue_state: ListState,
+    cleanup_report: Vec<CleanupCandidate>,
+    cleanup_state: ListState,
+    history_state: ListState,
+    journal_state: ListState,
+    skipped_state: ListState,
+    removed_state: ListState,
+    download_history_state: ListState,
+    playlist_sync: Option<PlaylistSync>,
+    playlist_sync_state: ListState,
+    // The first one is shown, the rest wait for it to be picked
+    result_choices: Vec<ResultChoice>,
+    result_choice_state: ListState,
+    spotify_search: Option<SpotifySearch>,
+    spotify_search_state: ListState,
+    // Where the playlist song being moved was before moving started
+    moving_from: Option<usize>,
+    integrity_report: Vec<IntegrityIssue>,
+    integrity_state: ListState,
     song_list_state: ListState,
+    session_stats: SessionStats,
     download_state: ListState,
+    last_inbox_scan: Instant,
+    // Hash of the data when it was last saved, and when it was last checked for changes
+    saved_hash: u64,
+    checked_hash: u64,
+    last_save_check: Instant,
+    // Saving waits until nothing has changed for a bit
+    changed_at: Instant,
+    last_release_attempt: Option<Instant>,
+    // `quefi spotify-login` was used, Spotify tokens are made for the user then
+    spotify_logged_in: bool,
+    title_shown_at: Instant,
+    // The last frame scrolled the title, it only moves when it doesn't fit
+    title_scrolls: bool,
     playlists: Vec<Playlist>,
-    last_queue_length: usize,
     global_songs: Vec<Song>,
     text_area: TextArea<'a>,
     valid_input: bool,
+    queue_moving: bool,
     playing: Playing,
+    shuffle: bool,
+    sort_by: Option<SortBy>,
+    locale: Locale,
     focused: Focused,
     config: Config,
     client: Client,
     window: Window,
     repeat: Repeat,
     log: String,
+    // Oldest first, also written to the log file
+    log_history: VecDeque<LogEntry>,
+    log_state: ListState,
+    // Along with the log message it belongs to
+    hint: Option<(Hint, String)>,
+    // Shown over everything until it's closed
+    app_error: Option<AppError>,
+    // None until it's checked, when the configuration menu is opened
+    dlp_version: Option<String>,
     sink: Sink,
     mode: Mode,
 }
 
 impl App<'_> {
     pub(crate) fn new(data: SaveData) -> Self {
+        let mut stream = OutputStreamBuilder::open_default_stream().unwrap();
+        let sink = Sink::connect_new(stream.mixer());
+
+        stream.log_on_drop(false);
+
+        App::with_sink(data, sink, Some(stream))
+    }
+
+    // Tests use a sink that isn't connected to any audio device
+    fn with_sink(data: SaveData, sink: Sink, stream: Option<OutputStream>) -> Self {
         let client = Client::builder()
             .timeout(Duration::from_secs(10))
             .build()
             .unwrap();
-
-        let mut stream = OutputStreamBuilder::open_default_stream().unwrap();
-        let sink = Sink::connect_new(stream.mixer());
-        
-        stream.log_on_drop(false);
+        let (task_result_sender, task_results) = mpsc::unbounded_channel();
 
         App {
-            _keep_alive: stream,
+            stream,
+            preview: None,
+            ui_sink: None,
+            dlp: Dlp::new(
+                data.dlp_path.clone(),
+                data.dlp_niceness,
+                data.max_dlp_processes,
+                data.normalize_loudness,
+                data.dlp_cookies.clone(),
+                songs_dir(&data.music_dir),
+                data.ffmpeg_path.clone(),
+            ),
+            playlist_slots: Arc::new(Semaphore::new(youtube::max_permits(
+                data.max_parallel_songs,
+            ))),
+            downloads_paused: watch::Sender::new(false),
+            locale: Locale::new(&data.language),
+            cache: Cache::new(get_cache_dir(), data.cache_size),
+            update: None,
             client,
             sink,
             config: Config {
@@ -216,6 +840,131 @@ pub(crate) fn new(data: SaveData) -> Self {
                     value: data.spotify_client_secret.clone(),
                     selected: Selected::None,
                 },
+                high_contrast: ConfigField {
+                    field_type: ConfigFieldType::HighContrast,
+                    value: on_off(data.high_contrast),
+                    selected: Selected::None,
+                },
+                cleanup_days: ConfigField {
+                    field_type: ConfigFieldType::CleanupDays,
+                    value: data.cleanup_days.to_string(),
+                    selected: Selected::None,
+                },
+                now_playing_path: ConfigField {
+                    field_type: ConfigFieldType::NowPlayingPath,
+                    value: data.now_playing_path.clone(),
+                    selected: Selected::None,
+                },
+                scroll_titles: ConfigField {
+                    field_type: ConfigFieldType::ScrollTitles,
+                    value: on_off(data.scroll_titles),
+                    selected: Selected::None,
+                },
+                dlp_niceness: ConfigField {
+                    field_type: ConfigFieldType::DlpNiceness,
+                    value: data.dlp_niceness.to_string(),
+                    selected: Selected::None,
+                },
+                max_dlp_processes: ConfigField {
+                    field_type: ConfigFieldType::MaxDlpProcesses,
+                    value: data.max_dlp_processes.to_string(),
+                    selected: Selected::None,
+                },
+                normalize_loudness: ConfigField {
+                    field_type: ConfigFieldType::NormalizeLoudness,
+                    value: on_off(data.normalize_loudness),
+                    selected: Selected::None,
+                },
+                language: ConfigField {
+                    field_type: ConfigFieldType::Language,
+                    value: data.language.clone(),
+                    selected: Selected::None,
+                },
+                screen_reader: ConfigField {
+                    field_type: ConfigFieldType::ScreenReader,
+                    value: on_off(data.screen_reader),
+                    selected: Selected::None,
+                },
+                track_notification: ConfigField {
+                    field_type: ConfigFieldType::TrackNotification,
+                    value: notification_name(data.track_notification),
+                    selected: Selected::None,
+                },
+                auto_skip_after: ConfigField {
+                    field_type: ConfigFieldType::AutoSkipAfter,
+                    value: data.auto_skip_after.to_string(),
+                    selected: Selected::None,
+                },
+                ui_sounds: ConfigField {
+                    field_type: ConfigFieldType::UiSounds,
+                    value: on_off(data.ui_sounds),
+                    selected: Selected::None,
+                },
+                party_port: ConfigField {
+                    field_type: ConfigFieldType::PartyPort,
+                    value: data.party_port.to_string(),
+                    selected: Selected::None,
+                },
+                cache_size: ConfigField {
+                    field_type: ConfigFieldType::CacheSize,
+                    value: data.cache_size.to_string(),
+                    selected: Selected::None,
+                },
+                check_for_updates: ConfigField {
+                    field_type: ConfigFieldType::CheckForUpdates,
+                    value: on_off(data.check_for_updates),
+                    selected: Selected::None,
+                },
+                startup_window: ConfigField {
+                    field_type: ConfigFieldType::StartupWindow,
+                    value: startup_window_name(data.startup_window),
+                    selected: Selected::None,
+                },
+                startup_playlist: ConfigField {
+                    field_type: ConfigFieldType::StartupPlaylist,
+                    value: data.startup_playlist.clone(),
+                    selected: Selected::None,
+                },
+                low_memory: ConfigField {
+                    field_type: ConfigFieldType::LowMemory,
+                    value: on_off(data.low_memory),
+                    selected: Selected::None,
+                },
+                max_parallel_songs: ConfigField {
+                    field_type: ConfigFieldType::MaxParallelSongs,
+                    value: data.max_parallel_songs.to_string(),
+                    selected: Selected::None,
+                },
+                audio_format: ConfigField {
+                    field_type: ConfigFieldType::AudioFormat,
+                    value: data.audio_format.name().to_string(),
+                    selected: Selected::None,
+                },
+                cookies: ConfigField {
+                    field_type: ConfigFieldType::Cookies,
+                    value: data.dlp_cookies.clone(),
+                    selected: Selected::None,
+                },
+                music_dir: ConfigField {
+                    field_type: ConfigFieldType::MusicDir,
+                    value: data.music_dir.clone(),
+                    selected: Selected::None,
+                },
+                ffmpeg_path: ConfigField {
+                    field_type: ConfigFieldType::FfmpegPath,
+                    value: data.ffmpeg_path.clone(),
+                    selected: Selected::None,
+                },
+                download_hook: ConfigField {
+                    field_type: ConfigFieldType::DownloadHook,
+                    value: data.download_hook.clone(),
+                    selected: Selected::None,
+                },
+                spotify_market: ConfigField {
+                    field_type: ConfigFieldType::SpotifyMarket,
+                    value: data.spotify_market.clone(),
+                    selected: Selected::None,
+                },
             },
             repeat: Repeat::None,
             window: Window::Songs,
@@ -225,18 +974,144 @@ pub(crate) fn new(data: SaveData) -> Self {
             song_list_state: ListState::default().with_selected(Some(0)),
             config_menu_state: ListState::default().with_selected(Some(0)),
             focused: Focused::Left,
-            last_queue_length: 0,
+            session_stats: SessionStats::new(),
+            streams: HashMap::new(),
+            song_tags: HashMap::new(),
+            song_items: SongItems::default(),
+            spotify_tracks: HashMap::new(),
+            last_inbox_scan: Instant::now(),
+            saved_hash: data.content_hash(),
+            checked_hash: data.content_hash(),
+            last_save_check: Instant::now(),
+            changed_at: Instant::now(),
+            last_release_attempt: None,
+            spotify_logged_in: spotify::read_refresh_token().is_some(),
+            title_shown_at: Instant::now(),
+            title_scrolls: false,
             save_data: data,
-            join_handles: Vec::new(),
+            read_only: false,
+            offline: false,
+            start_playlist: None,
+            kiosk: false,
+            last_kiosk_restart: None,
+            party_server: None,
+            party_receiver: None,
+            party_requests: Vec::new(),
+            party_state: ListState::default().with_selected(Some(0)),
+            party_downloads: HashSet::new(),
+            resumed_downloads: HashMap::new(),
+            replacing_songs: HashSet::new(),
+            failed_imports: Vec::new(),
+            running_tasks: 0,
+            task_result_sender,
+            task_results,
+            cancel: CancellationToken::new(),
+            playlist_cursor: None,
+            global_cursor: None,
+            global_order: Vec::new(),
             song_queue: Vec::new(),
+            pending_queue: Vec::new(),
+            queue_state: ListState::default().with_selected(Some(0)),
+            cleanup_report: Vec::new(),
+            cleanup_state: ListState::default().with_selected(Some(0)),
+            history_state: ListState::default().with_selected(Some(0)),
+            journal_state: ListState::default().with_selected(Some(0)),
+            skipped_state: ListState::default().with_selected(Some(0)),
+            removed_state: ListState::default().with_selected(Some(0)),
+            download_history_state: ListState::default().with_selected(Some(0)),
+            playlist_sync: None,
+            playlist_sync_state: ListState::default().with_selected(Some(0)),
+            result_choices: Vec::new(),
+            result_choice_state: ListState::default().with_selected(Some(0)),
+            spotify_search: None,
+            spotify_search_state: ListState::default().with_selected(Some(0)),
+            moving_from: None,
+            integrity_report: Vec::new(),
+            integrity_state: ListState::default().with_selected(Some(0)),
             global_songs: Vec::new(),
-            downloads: HashMap::new(),
+            downloads: BTreeMap::new(),
+            next_download_id: 0,
             playlists: Vec::new(),
             playing: Playing::None,
             log: String::from("Initialized!"),
+            log_history: VecDeque::new(),
+            log_state: ListState::default().with_selected(Some(0)),
+            hint: None,
+            app_error: None,
+            dlp_version: None,
             mode: Mode::Normal,
             text_area: TextArea::default(),
             valid_input: false,
+            queue_moving: false,
+            shuffle: false,
+            sort_by: None,
+        }
+    }
+
+    // Only while the message it was given for is still in the log
+    fn current_hint(&self) -> Option<Hint> {
+        self.hint
+            .as_ref()
+            .filter(|(_, message)| *message == self.log)
+            .map(|&(hint, _)| hint)
+    }
+
+    // Songs that were skipped at least once, most skipped first
+    fn frequently_skipped(&self) -> Vec<&SerializableSong> {
+        let mut songs: Vec<&SerializableSong> = self
+            .save_data
+            .songs
+            .iter()
+            .filter(|song| song.skips > 0)
+            .collect();
+        songs.sort_by_key(|song| Reverse(song.skips));
+        songs
+    }
+
+    fn song_notes(&self, name: &str) -> &str {
+        self.save_data
+            .songs
+            .iter()
+            .find(|song| song.name == name)
+            .map_or("", |song| song.notes.as_str())
+    }
+
+    // Items of the focused list that match the filter, with the positions of the matched chars
+    fn filter_matches(&self) -> Vec<(usize, Vec<usize>)> {
+        let filter = &self.text_area.lines()[0];
+        let song_matches = |songs: &[Song]| {
+            songs
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, song)| {
+                    let label = match self.song_tags.get(&song.path) {
+                        Some(tags) => tags.label(&song.name),
+                        None => song.name.clone(),
+                    };
+                    util::fuzzy_match(filter, &label).map(|chars| (idx, chars))
+                })
+                .collect()
+        };
+
+        if self.focused == Focused::Left {
+            return self
+                .playlists
+                .iter()
+                .enumerate()
+                .filter_map(|(idx, playlist)| {
+                    util::fuzzy_match(filter, &playlist.name).map(|chars| (idx, chars))
+                })
+                .collect();
+        }
+
+        match self.window {
+            Window::Songs => self
+                .playlist_list_state
+                .selected()
+                .and_then(|idx| self.playlists.get(idx))
+                .map_or_else(Vec::new, |playlist| song_matches(&playlist.songs)),
+            Window::GlobalSongs => song_matches(&self.global_songs),
+            _ => Vec::new(),
         }
     }
 }
@@ -0,0 +1,76 @@
+//! Lets benches/ render the app, they only see the public API of the library
+
+use super::{App, Focused, SerializablePlaylist, SerializableSong, Window};
+use crate::{init_dirs, SaveData};
+use ratatui::{
+    buffer::Buffer,
+    crossterm::event::{KeyCode, KeyEvent},
+    layout::Rect,
+    widgets::Widget,
+};
+use rodio::{queue::SourcesQueueOutput, Sink};
+use std::env;
+use tokio::runtime::Runtime;
+
+/// A library with one playlist of every song, shown in the songs window. The songs don't
+/// exist on disk, so only the rendering is measured.
+pub struct Library {
+    app: App<'static>,
+    runtime: Runtime,
+    _output: SourcesQueueOutput,
+}
+
+impl Library {
+    pub fn new(songs: usize) -> Self {
+        init_dirs(Some(env::temp_dir().join("quefi-bench")), false);
+
+        let names: Vec<String> = (0..songs)
+            .map(|idx| format!("Artist {} - Song {idx}", idx % 100))
+            .collect();
+        let data = SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![SerializablePlaylist {
+                name: String::from("Everything"),
+                songs: names.clone(),
+                ..SerializablePlaylist::default()
+            }],
+            songs: names
+                .into_iter()
+                .enumerate()
+                .map(|(idx, name)| SerializableSong {
+                    name,
+                    path: format!("/nonexistent/{idx}.mp3"),
+                    favorite: idx % 7 == 0,
+                    duration: 120 + idx as u64 % 240,
+                    ..SerializableSong::default()
+                })
+                .collect(),
+            ..SaveData::default()
+        };
+
+        let (sink, output) = Sink::new();
+        let mut app = App::with_sink(data, sink, None);
+        // Nothing gets saved, spawned or cleaned up
+        app.read_only = true;
+        app.offline = true;
+        app.init().unwrap();
+        app.window = Window::Songs;
+        app.focused = Focused::Right;
+
+        Library {
+            app,
+            runtime: Runtime::new().unwrap(),
+            _output: output,
+        }
+    }
+
+    pub fn render(&mut self, area: Rect, buf: &mut Buffer) {
+        (&mut self.app).render(area, buf);
+    }
+
+    /// Selects the next song like the down arrow does, wrapping around at the end
+    pub fn select_next_song(&mut self) {
+        self.runtime
+            .block_on(self.app.handle_key(KeyEvent::from(KeyCode::Down)));
+    }
+}
@@ -1,937 +1,5852 @@
 use crate::{
-    get_quefi_dir, make_safe_filename,
+    get_quefi_dir, hook, link,
+    locale::{self, Locale},
+    make_safe_filename,
+    party::{self, Submission},
+    playlist_io::{self, Entry},
+    songs_dir,
     spotify::{
-        create_token, fetch_playlist_info, fetch_track_info, validate_spotify_link, SpotifyLink,
+        self, create_token, fetch_new_releases, fetch_playlist_info, fetch_track_info,
+        validate_spotify_link, SpotifyLink, TrackInfo,
     },
-    youtube::{self, download_song, search_ytmusic},
-    Error, SearchFor, TaskResult, TaskReturn,
+    tags::{read_duration, read_tags, SongTags},
+    unix_timestamp,
+    update::check_for_update,
+    util::{civil_date, normalize_title},
+    youtube::{
+        self, download_song, download_tagged_song, fetch_album, fetch_metadata,
+        fetch_playlist_entries, search_ytmusic, search_ytmusic_choices, stream_song, write_tags,
+        DlpProblem, PendingDownload, SearchFilter, YoutubePlaylistEntry,
+    },
+    DownloadId, Error, SearchFor, TaskResult, TaskReturn, NEW_RELEASES_PLAYLIST,
+    RELEASE_CHECK_INTERVAL,
 };
 use ratatui::{
     backend::Backend,
-    crossterm::event::{self, poll, Event, KeyCode, KeyEventKind},
+    crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers},
     style::{Style, Stylize},
     symbols::border,
-    widgets::Block,
+    widgets::{Block, ListState},
     Terminal,
 };
-use rodio::{Decoder, Source};
-use std::{fs::File, io, path::Path, time::Duration};
-use tui_textarea::{CursorMove, Input, Key};
+use rodio::{source::SineWave, Decoder, Sink, Source};
+use serde_json::json;
+use std::{
+    cmp::Ordering,
+    collections::{hash_map::RandomState, HashMap, HashSet},
+    env,
+    fs::{self, File},
+    future::Future,
+    hash::{BuildHasher, Hasher},
+    io::{self, Cursor, Read, Seek, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+use tokio::{
+    sync::{mpsc, Semaphore},
+    task::JoinError,
+};
+use tui_textarea::{CursorMove, Input, Key, TextArea};
 
 use super::{
-    App, Download, Focused, InputMode, Mode, Playing, Playlist, ProcessingPlaylistSongs,
-    QueuedSong, Repeat, Selected, SerializablePlaylist, SerializableSong, Song, Window,
+    notification_name, on_off, startup_window_name, App, AppError, AppEvent, CleanupCandidate,
+    ConfigFieldType, Download, DownloadRecord, FailedImport, Focused, Hint, HistoryEntry,
+    InputMode, IntegrityIssue, IntegrityProblem, JournalEntry, LogEntry, Mode, Playing, Playlist,
+    PlaylistChange, PlaylistSync, Preview, ProcessingPlaylistSongs, QueuedFrom, QueuedSong,
+    RemovedSong, Repeat, ResultChoice, Selected, SerializablePlaylist, SerializableSong,
+    SessionStats, Song, SortBy, SpotifySearch, UiSound, Window, WritingTags,
 };
 
 const PRELOAD_SONG_COUNT: usize = 2;
+const HISTORY_LENGTH: usize = 1000;
+const JOURNAL_LENGTH: usize = 1000;
+// How often playback, the inbox and autosave are checked, and the UI redrawn without any input
+const PLAYING_TICK_INTERVAL: Duration = Duration::from_millis(100);
+const IDLE_TICK_INTERVAL: Duration = Duration::from_secs(1);
+const LOG_HISTORY_LENGTH: usize = 500;
+// Songs tagged per task after a playlist import, progress is updated between them
+const TAG_BATCH_SIZE: usize = 5;
+// Removed songs can be restored for 30 days
+const REMOVED_KEEP_SECS: u64 = 30 * 86_400;
+const MAX_DESCRIPTION_LENGTH: usize = 500;
+// Skipping a song before this counts as not wanting to hear it
+const SKIP_THRESHOLD: Duration = Duration::from_secs(20);
+const PREVIEW_LENGTH: Duration = Duration::from_secs(10);
+// UI sounds play at this fraction of the music's volume
+const UI_SOUND_GAIN: f32 = 0.2;
+const INBOX_SCAN_INTERVAL: Duration = Duration::from_secs(2);
+// Looking for changes serializes all of the data, so it isn't done every frame
+const SAVE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+// How long the data has to stay the same before it's saved
+const AUTOSAVE_DELAY: Duration = Duration::from_secs(5);
+// Tokens this close to expiring are refreshed before the request instead of after a 401
+const TOKEN_EXPIRY_MARGIN: u64 = 60;
+// After a failed check for new releases, e.g. when offline
+const RELEASE_RETRY_INTERVAL: Duration = Duration::from_secs(3600);
+// Keeps a playlist whose songs all fail to load from being restarted every frame
+const KIOSK_RESTART_INTERVAL: Duration = Duration::from_secs(5);
+
+fn clamp_selection(state: &mut ListState, len: usize) {
+    match state.selected() {
+        Some(idx) if idx >= len => state.select(Some(len.saturating_sub(1))),
+        None => state.select_first(),
+        _ => {}
+    }
+}
+
+fn extract_links(contents: &str) -> Vec<String> {
+    contents
+        .split_whitespace()
+        // Internet shortcut (.url) files store the link as URL=...
+        .map(|token| link::normalize(token.strip_prefix("URL=").unwrap_or(token)))
+        .filter(|link| {
+            super::is_valid_youtube_link(link)
+                || super::is_valid_metadata_link(link)
+                || validate_spotify_link(link) != SpotifyLink::Invalid
+        })
+        .collect()
+}
+
+fn probe_duration(path: &str) -> u64 {
+    read_duration(path).map_or(0, |duration| duration.as_secs())
+}
+
+// Puts the item that was at order[i] at i
+fn reorder<T>(items: &mut Vec<T>, order: &[usize]) {
+    let mut old: Vec<Option<T>> = items.drain(..).map(Some).collect();
+    items.extend(order.iter().map(|&idx| old[idx].take().unwrap()));
+}
+
+// Keys that change the library, playlists or settings
+fn is_kiosk_locked(code: KeyCode) -> bool {
+    matches!(
+        code,
+        KeyCode::Char(
+            'a' | 'n'
+                | 'm'
+                | 'M'
+                | 'O'
+                | 'D'
+                | 'R'
+                | 'I'
+                | 'c'
+                | 'C'
+                | 'v'
+                | 'B'
+                | 'K'
+                | 'A'
+                | 'Z'
+                | 'b'
+                | 'W'
+                | 'G'
+        ) | KeyCode::F(2)
+    )
+}
+
+fn random_index(len: usize) -> usize {
+    // RandomState is seeded randomly, good enough to pick a song without pulling in a crate
+    let random = RandomState::new().build_hasher().finish();
+    (random % len as u64) as usize
+}
+
+const AUDIO_EXTENSIONS: [&str; 6] = ["mp3", "flac", "ogg", "m4a", "wav", "opus"];
+
+fn is_audio_file(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| AUDIO_EXTENSIONS.contains(&ext.to_ascii_lowercase().as_str()))
+}
+
+// Returns audio files grouped by the directory they're in, sorted by path
+fn find_audio_files(dir: &Path, found: &mut Vec<(PathBuf, Vec<PathBuf>)>, locale: &Locale) {
+    let Ok(entries) = fs::read_dir(dir) else {
+        return;
+    };
+
+    let mut paths: Vec<PathBuf> = entries.flatten().map(|entry| entry.path()).collect();
+    paths.sort_by(|a, b| locale.compare_paths(a, b));
+
+    let mut files = Vec::new();
+    let mut subdirs = Vec::new();
+    for path in paths {
+        if path.is_dir() {
+            subdirs.push(path);
+        } else if is_audio_file(&path) {
+            files.push(path);
+        }
+    }
+
+    if !files.is_empty() {
+        found.push((dir.to_path_buf(), files));
+    }
+    for subdir in subdirs {
+        find_audio_files(&subdir, found, locale);
+    }
+}
+
+// Renaming doesn't work across drives, those files get copied instead
+fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    if let Some(parent) = to.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+    fs::copy(from, to)?;
+    fs::remove_file(from)
+}
+
+// Reading the terminal blocks, so it's done on its own thread and sent to the run loop
+fn read_input() -> mpsc::UnboundedReceiver<io::Result<Event>> {
+    let (sender, receiver) = mpsc::unbounded_channel();
+    std::thread::spawn(move || loop {
+        let event = event::read();
+        let failed = event.is_err();
+        if sender.send(event).is_err() || failed {
+            break;
+        }
+    });
+    receiver
+}
+
+fn file_size(path: &str) -> u64 {
+    fs::metadata(path)
+        .map(|metadata| metadata.len())
+        .unwrap_or(0)
+}
 
 impl App<'_> {
     pub(crate) async fn run(&mut self, mut terminal: Terminal<impl Backend>) -> io::Result<()> {
+        let mut input = read_input();
+        let mut next_tick = Instant::now();
+
         loop {
+            // Only after something happened, an idle quefi doesn't redraw more than once a second
             terminal.draw(|frame| {
                 frame.render_widget(&mut *self, frame.area());
             })?;
 
-            // Force updates every 0.1 seconds
-            if poll(Duration::from_millis(100))? {
-                if let Event::Key(key) = event::read()? {
-                    match self.mode {
-                        Mode::Normal if key.kind == KeyEventKind::Press => match key.code {
-                            KeyCode::Char('q') => break,
-                            KeyCode::Char('y') => self.help(),
-                            KeyCode::Char(' ') => self.pause(),
-                            KeyCode::Char('o') => self.seek_back(),
-                            KeyCode::Char('p') => self.seek_forward(),
-                            KeyCode::Char('a') => self.add_item(),
-                            KeyCode::Char('n') => self.remove_current(),
-                            KeyCode::Char('r') => self.toggle_repeat(),
-                            KeyCode::Char('m') => self.move_item(),
-                            KeyCode::Char('f') => self.sink.skip_one(),
-                            KeyCode::Char('g') => self.window = Window::GlobalSongs,
-                            KeyCode::Char('d') => self.window = Window::DownloadManager,
-                            KeyCode::Char('c') => self.window = Window::ConfigurationMenu,
-                            KeyCode::Char('u') => self.decrease_volume(),
-                            KeyCode::Char('i') => self.increase_volume(),
-                            KeyCode::Char('h') | KeyCode::Left => self.select_left_window(),
-                            KeyCode::Char('l') | KeyCode::Right => self.select_right_window(),
-                            KeyCode::Char('j') | KeyCode::Down => self.select_next(),
-                            KeyCode::Char('k') | KeyCode::Up => self.select_previous(),
-                            KeyCode::Enter => self.play_current(),
-                            _ => {}
-                        },
-                        Mode::Input(_) if key.kind == KeyEventKind::Press => match key.code {
-                            KeyCode::Esc => self.exit_input_mode(),
-                            KeyCode::Enter => self.submit_input().await,
-                            _ => {
-                                let input: Input = key.into();
-                                if !(input.key == Key::Char('m') && input.ctrl)
-                                    && self.text_area.input(key)
-                                {
-                                    self.validate_input();
-                                }
-                            }
-                        },
-                        Mode::Help if key.kind == KeyEventKind::Press => match key.code {
-                            KeyCode::Char('y') => self.help(),
-                            KeyCode::Char('q') => break,
-                            _ => {}
-                        },
-                        _ => {}
+            let event = tokio::select! {
+                event = input.recv() => match event {
+                    Some(event) => AppEvent::Input(event?),
+                    None => break,
+                },
+                Some(result) = self.task_results.recv() => AppEvent::TaskDone(result),
+                _ = tokio::time::sleep_until(next_tick.into()) => {
+                    next_tick = Instant::now() + self.tick_interval();
+                    AppEvent::Tick
+                }
+            };
+            match event {
+                AppEvent::Input(Event::Key(key)) => {
+                    if self.handle_key(key).await {
+                        break;
                     }
                 }
+                AppEvent::Input(_) => {}
+                AppEvent::TaskDone(result) => self.finish_task(result),
+                AppEvent::Tick => self.tick(),
+            }
+            self.show_result_choices();
+            self.show_spotify_search();
+            self.record_log();
+            // Starting a song shouldn't wait for the slow tick to show its progress
+            next_tick = next_tick.min(Instant::now() + self.tick_interval());
+
+            if self.mode == Mode::Quitting && self.running_tasks == 0 {
+                break;
             }
-            self.update_song_queue();
+        }
+        Ok(())
+    }
 
-            let mut completed_futures = Vec::new();
+    // The progress bar and a scrolling title move smoothly, otherwise ticks are rare
+    pub(super) fn tick_interval(&self) -> Duration {
+        if (!self.sink.empty() && !self.sink.is_paused())
+            || self.preview.is_some()
+            || self.title_scrolls
+        {
+            PLAYING_TICK_INTERVAL
+        } else {
+            IDLE_TICK_INTERVAL
+        }
+    }
 
-            for handle in self.join_handles.iter_mut() {
-                if handle.is_finished() {
-                    completed_futures.push(handle.await.unwrap());
-                }
+    // Playback and everything else that's checked on a timer
+    fn tick(&mut self) {
+        self.update_song_queue();
+        self.update_preview();
+        self.update_session_stats();
+        self.scan_inbox();
+        self.autosave();
+        self.check_new_releases();
+        self.keep_kiosk_playing();
+        self.receive_party_requests();
+    }
+
+    // Returns true when the app should quit
+    pub(crate) async fn handle_key(&mut self, key: KeyEvent) -> bool {
+        if self.app_error.is_some() {
+            if key.kind == KeyEventKind::Press && matches!(key.code, KeyCode::Esc | KeyCode::Enter)
+            {
+                self.app_error = None;
+            }
+            return false;
+        }
+
+        match self.mode {
+            Mode::Normal | Mode::Notes(_)
+                if key.kind == KeyEventKind::Press && self.kiosk && is_kiosk_locked(key.code) =>
+            {
+                self.log = String::from("Disabled in kiosk mode");
             }
+            Mode::Normal if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('q') => return self.quit(),
+                KeyCode::Char('y') => self.help(),
+                KeyCode::Char('s') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    self.save_now()
+                }
+                KeyCode::Char('s') => self.stats(),
+                KeyCode::Char(' ') => self.pause(),
+                KeyCode::Char('o') => self.seek_back(),
+                KeyCode::Char('p') => self.seek_forward(),
+                KeyCode::Char('a') => self.add_item(),
+                KeyCode::Char('n') => self.remove_current(),
+                KeyCode::Char('r') => self.toggle_repeat(),
+                KeyCode::Char('S') => self.toggle_shuffle(),
+                KeyCode::Char('m') => self.move_item(),
+                KeyCode::Char('M') => self.enter_move_to_index(),
+                KeyCode::Char('/') => self.start_filter(),
+                KeyCode::Char('O') => self.sort_songs(),
+                KeyCode::Home => self.fast_move(0),
+                KeyCode::End => self.fast_move(usize::MAX),
+                KeyCode::Char('f') => self.skip_song(),
+                KeyCode::Char('z') => self.play_random(),
+                KeyCode::Char('x') => self.queue_selected(true),
+                KeyCode::Char('e') => self.queue_selected(false),
+                KeyCode::Char('g') => self.switch_window(Window::GlobalSongs),
+                KeyCode::Char('d') => self.switch_window(Window::DownloadManager),
+                KeyCode::Char('D') => self.download_missing_songs(),
+                KeyCode::Char('R') => self.repair_selected_song(),
+                KeyCode::Char('W') => self.start_replacing_song(),
+                KeyCode::Char('P') => self.preview_selected_song(),
+                KeyCode::Char('t') => self.enter_network_input_mode(InputMode::StreamLink),
+                KeyCode::Char('w') => self.switch_window(Window::Queue),
+                KeyCode::Char('H') => self.open_history(),
+                KeyCode::Char('J') => self.open_journal(),
+                KeyCode::Char('E') => self.start_export(),
+                KeyCode::Char('B') => self.start_clone_playlist(),
+                KeyCode::Char('Q') => self.switch_window(Window::PartyQueue),
+                KeyCode::Char('U') => self.show_changelog(),
+                KeyCode::Char('A') => self.cycle_audio_format(),
+                KeyCode::Char('Z') => self.open_recently_removed(),
+                KeyCode::Char('Y') => self.open_download_history(),
+                KeyCode::Char('V') => self.open_log(),
+                KeyCode::Char('K') => self.start_playlist_sync(),
+                KeyCode::Char('G') => self.enter_network_input_mode(InputMode::SpotifySearch),
+                KeyCode::Char('X') => self.start_failure_export(),
+                KeyCode::Char('b') => self.fix_hinted_error(),
+                KeyCode::Char('F') => self.open_frequently_skipped(),
+                KeyCode::F(2) => self.start_rename(),
+                KeyCode::Char('N') => self.show_notes(),
+                KeyCode::Char('c') => self.switch_window(Window::ConfigurationMenu),
+                KeyCode::Char('C') => self.open_cleanup_report(),
+                KeyCode::Char('L') => self.check_integrity(),
+                KeyCode::Char('I') => self.enter_input_mode(InputMode::ImportDirectory),
+                KeyCode::Char('v') => self.toggle_favorite(),
+                KeyCode::Char('u') => self.decrease_volume(),
+                KeyCode::Char('i') => self.increase_volume(),
+                KeyCode::Char('h') | KeyCode::Left => self.select_left_window(),
+                KeyCode::Char('l') | KeyCode::Right => self.select_right_window(),
+                KeyCode::Char('j') | KeyCode::Down => self.select_next(),
+                KeyCode::Char('k') | KeyCode::Up => self.select_previous(),
+                KeyCode::Enter => self.play_current(),
+                _ => {}
+            },
+            Mode::Input(_) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Esc => self.exit_input_mode(),
+                KeyCode::Down if self.mode == Mode::Input(InputMode::Filter) => {
+                    self.select_filter_match(true)
+                }
+                KeyCode::Up if self.mode == Mode::Input(InputMode::Filter) => {
+                    self.select_filter_match(false)
+                }
+                // Notes can have multiple lines, pasted lyrics come in as Enter presses too
+                KeyCode::Enter if matches!(self.mode, Mode::Input(InputMode::Notes(_))) => {
+                    self.text_area.insert_newline()
+                }
+                KeyCode::Char('s')
+                    if key.modifiers.contains(KeyModifiers::CONTROL)
+                        && matches!(self.mode, Mode::Input(InputMode::Notes(_))) =>
+                {
+                    self.submit_input().await
+                }
+                KeyCode::Enter => self.submit_input().await,
+                _ => {
+                    let input: Input = key.into();
+                    if !(input.key == Key::Char('m') && input.ctrl) && self.text_area.input(key) {
+                        self.validate_input();
+                    }
+                }
+            },
+            Mode::Help if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('y') => self.help(),
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::Stats if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('s') => self.stats(),
+                KeyCode::Char('r') => self.session_stats = SessionStats::new(),
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::PickResult if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('j') | KeyCode::Down => {
+                    let len = self.result_choices.first().map_or(0, |c| c.results.len());
+                    let idx = self.result_choice_state.selected().unwrap_or(0);
+                    self.result_choice_state
+                        .select(Some((idx + 1).min(len.saturating_sub(1))));
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    let idx = self.result_choice_state.selected().unwrap_or(0);
+                    self.result_choice_state.select(Some(idx.saturating_sub(1)));
+                }
+                KeyCode::Char('P') => {
+                    let url = self
+                        .result_choices
+                        .first()
+                        .and_then(|choice| self.spotify_tracks.get(choice.search_for.song_name()))
+                        .and_then(|track| track.preview_url.clone());
+                    self.preview_spotify_track(url);
+                }
+                KeyCode::Enter => self.pick_result(true),
+                KeyCode::Esc => self.pick_result(false),
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::PickSpotifyResult if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('j') | KeyCode::Down => {
+                    let len = self.spotify_search.as_ref().map_or(0, |s| s.items.len());
+                    let idx = self.spotify_search_state.selected().unwrap_or(0);
+                    self.spotify_search_state
+                        .select(Some((idx + 1).min(len.saturating_sub(1))));
+                }
+                KeyCode::Char('k') | KeyCode::Up => {
+                    let idx = self.spotify_search_state.selected().unwrap_or(0);
+                    self.spotify_search_state
+                        .select(Some(idx.saturating_sub(1)));
+                }
+                KeyCode::Char('P') => {
+                    let url = self.spotify_search.as_ref().and_then(|search| {
+                        let idx = self.spotify_search_state.selected().unwrap_or(0);
+                        search.items.get(idx)?.preview_url.clone()
+                    });
+                    self.preview_spotify_track(url);
+                }
+                KeyCode::Enter => self.import_spotify_result(),
+                KeyCode::Esc => {
+                    self.stop_preview();
+                    self.spotify_search = None;
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::Changelog if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('U') | KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::Notes(_) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('N') | KeyCode::Esc => self.mode = Mode::Normal,
+                KeyCode::Char('a') => self.edit_notes(),
+                KeyCode::Char('q') => return self.quit(),
+                _ => {}
+            },
+            Mode::Quitting if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('a') | KeyCode::Char('q') => self.stop_tasks(),
+                KeyCode::Esc if !self.cancel.is_cancelled() => self.mode = Mode::Normal,
+                _ => {}
+            },
+            _ => {}
+        }
+        false
+    }
+
+    // Returns true when nothing runs in the background, otherwise it's waited for first
+    fn quit(&mut self) -> bool {
+        if self.running_tasks == 0 {
+            return true;
+        }
+        self.mode = Mode::Quitting;
+        false
+    }
 
-            self.join_handles.retain(|handle| !handle.is_finished());
+    fn stop_tasks(&mut self) {
+        self.cancel.cancel();
+        self.log = String::from("Stopping the downloads...");
+    }
 
-            for completed_future in completed_futures {
-                self.handle_result(completed_future);
+    // Every task goes through here, so quitting can stop it at its next await
+    pub(super) fn spawn_task(&mut self, task: impl Future<Output = TaskResult> + Send + 'static) {
+        let cancel = self.cancel.clone();
+        let results = self.task_result_sender.clone();
+        self.running_tasks += 1;
+        let task = tokio::spawn(async move {
+            tokio::select! {
+                result = task => result,
+                // Dropping the task kills its processes, they're spawned with kill_on_drop
+                _ = cancel.cancelled() => Err(Error::Cancelled),
             }
+        });
+        // Awaited in a task of its own, so a panic still sends something
+        tokio::spawn(async move {
+            let _ = results.send(task.await);
+        });
+    }
+
+    pub(super) fn finish_task(&mut self, result: Result<TaskResult, JoinError>) {
+        self.running_tasks -= 1;
+        match result {
+            Ok(result) => self.handle_result(result),
+            Err(err) => self.show_error(AppError::TaskPanicked(err.to_string())),
         }
-        Ok(())
+        // After the result, so the error of the failed task doesn't replace it in the log
+        self.check_dlp_problem();
     }
 
-    fn handle_result(&mut self, result: TaskResult) {
+    pub(super) fn handle_result(&mut self, result: TaskResult) {
         match result {
             Ok(TaskReturn::PlaylistInfo(id, playlist_info)) => {
-                self.downloads.insert(
+                let playlist_idx = self.add_downloaded_playlist(playlist_info.name);
+                if let Some(spotify_id) = playlist_info.spotify_id {
+                    self.save_data.playlists[playlist_idx].spotify_id = spotify_id;
+                }
+                self.download_into_playlist(
                     id,
-                    Download::ProcessingPlaylistSongs(ProcessingPlaylistSongs {
-                        playlist_name: playlist_info.name.clone(),
-                        searching_songs: Vec::new(),
-                        downloading_songs: Vec::new(),
-                        total_to_search: playlist_info.tracks.len(),
-                        total_to_download: 0,
-                        downloaded: 0,
-                        searched: 0,
-                    }),
+                    playlist_idx,
+                    playlist_info.tracks,
+                    playlist_info.next,
                 );
-
-                let tracks_len = playlist_info.tracks.len();
-
-                self.save_data.playlists.push(SerializablePlaylist {
-                    songs: vec![String::new(); tracks_len],
-                    name: playlist_info.name.clone(),
-                });
-
-                self.playlists.push(Playlist {
-                    songs: vec![
-                        Song {
-                            selected: Selected::None,
-                            name: String::new(),
-                            path: String::new(),
-                            playing: false,
-                        };
-                        tracks_len
-                    ],
-                    selected: Selected::None,
-                    playing: false,
-                    name: playlist_info.name,
-                });
-
-                let playlist_idx = self.save_data.playlists.len() - 1;
-
-                for (idx, track) in playlist_info.tracks.into_iter().enumerate() {
-                    let client = self.client.clone();
-
-                    if let Download::ProcessingPlaylistSongs(processing) =
-                        self.downloads.get_mut(&id).unwrap()
-                    {
-                        processing.searching_songs.push(track.name.clone());
+            }
+            Ok(TaskReturn::PlaylistSync(id, playlist_idx, tracks)) => {
+                self.downloads.remove(&id);
+                self.show_playlist_sync(playlist_idx, tracks);
+            }
+            Ok(TaskReturn::PlaylistTracks(id, playlist_idx, tracks, next)) => {
+                // The import could've been cancelled while the page was fetched
+                if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                    self.downloads.get_mut(&id)
+                {
+                    processing.more_tracks = next.is_some();
+                    if let Some(next) = next {
+                        self.fetch_playlist_tracks(id, playlist_idx, next);
                     }
+                    self.add_playlist_tracks(id, playlist_idx, tracks);
+                }
+            }
+            Ok(TaskReturn::YoutubePlaylist(id, playlist)) => {
+                // Flat playlists still list videos that can't be watched anymore
+                let entries: Vec<YoutubePlaylistEntry> = playlist
+                    .entries
+                    .into_iter()
+                    .filter(|entry| {
+                        !matches!(entry.title.as_str(), "[Private video]" | "[Deleted video]")
+                    })
+                    .collect();
 
-                    self.join_handles.push(tokio::spawn(async move {
-                        search_ytmusic(
-                            id,
-                            &client,
-                            &track.query,
-                            SearchFor::Playlist(playlist_idx, track.name, idx),
-                        )
-                        .await
-                    }));
+                if entries.is_empty() {
+                    self.downloads.remove(&id);
+                    self.log = format!("{} has no videos that can be downloaded", playlist.title);
+                } else {
+                    let playlist_idx = self.add_downloaded_playlist(playlist.title);
+                    self.download_youtube_entries(id, playlist_idx, entries);
+                }
+            }
+            Ok(TaskReturn::SpotifyPreview(data)) => self.play_spotify_preview(data),
+            Ok(TaskReturn::SpotifySearch(id, query, items)) => {
+                self.downloads.remove(&id);
+                if items.is_empty() {
+                    self.log = format!("Nothing found on Spotify for {query}");
+                } else {
+                    self.spotify_search = Some(SpotifySearch { query, items });
+                    self.show_spotify_search();
+                }
+            }
+            Ok(TaskReturn::NewReleases(id, tracks)) => {
+                self.save_data.last_release_check = unix_timestamp();
+
+                // A single usually comes out again on the album
+                let mut seen = HashSet::new();
+                let tracks: Vec<TrackInfo> = tracks
+                    .into_iter()
+                    .filter(|track| {
+                        seen.insert(track.name.clone())
+                            && !self
+                                .save_data
+                                .songs
+                                .iter()
+                                .any(|song| song.name == track.name)
+                    })
+                    .collect();
+
+                if tracks.is_empty() {
+                    self.downloads.remove(&id);
+                    self.log = String::from("No new releases from followed artists");
+                } else {
+                    self.log = format!("Downloading {} new release(s)", tracks.len());
+                    let playlist_idx = self.new_releases_playlist();
+                    self.download_into_playlist(id, playlist_idx, tracks, None);
                 }
             }
             Ok(TaskReturn::TrackInfo(id, track_info)) => {
                 self.downloads
                     .insert(id, Download::SearchingForSong(track_info.query.clone()));
+                let query = track_info.query.clone();
+                let name = track_info.name.clone();
+                let duration_ms = track_info.duration_ms;
+                let filter = track_info.filter;
+                self.spotify_tracks.insert(name.clone(), track_info);
 
                 let client = self.client.clone();
 
-                self.join_handles.push(tokio::spawn(async move {
-                    search_ytmusic(
+                // Only one song, so there's time to pick the right version of it
+                self.spawn_download(async move {
+                    search_ytmusic_choices(
                         id,
                         &client,
-                        &track_info.query,
-                        SearchFor::GlobalSong(track_info.name),
+                        &query,
+                        duration_ms,
+                        filter,
+                        SearchFor::GlobalSong(name),
                     )
                     .await
-                }));
+                });
+            }
+            Ok(TaskReturn::SearchResults(id, results, search_for)) => {
+                self.downloads.insert(
+                    id,
+                    Download::PickingResult(search_for.song_name().to_string()),
+                );
+                self.result_choices.push(ResultChoice {
+                    id,
+                    search_for,
+                    results,
+                });
+                self.show_result_choices();
             }
             Ok(TaskReturn::SearchResult(
                 id,
                 search_result,
                 SearchFor::Playlist(idx, song_name, song_idx),
             )) => {
-                if let Download::ProcessingPlaylistSongs(processing) =
-                    self.downloads.get_mut(&id).unwrap()
-                {
-                    processing.searching_songs.retain(|song| song != &song_name);
-                    processing.downloading_songs.push(song_name.clone());
-                    processing.total_to_download += 1;
-                    processing.searched += 1;
-                } else {
-                    panic!("Expected Download::ProcessingPlaylistSongs");
+                let link = format!("https://youtube.com/watch?v={}", search_result.video_id);
+                // Found the same video that's already in the library
+                let existing = self
+                    .save_data
+                    .songs
+                    .iter()
+                    .find(|song| song.url == link)
+                    .map(|song| song.name.clone());
+
+                match self.downloads.get_mut(&id) {
+                    Some(Download::ProcessingPlaylistSongs(processing)) => {
+                        processing.searching_songs.retain(|song| song != &song_name);
+                        processing.searched += 1;
+                        if existing.is_some() {
+                            processing.reused += 1;
+                        } else {
+                            processing.downloading_songs.push(song_name.clone());
+                            processing.total_to_download += 1;
+                        }
+                    }
+                    // The import was cancelled while the song was searched for
+                    None => return,
+                    Some(_) => {
+                        self.show_error(AppError::NotPlaylistImport(id));
+                        return;
+                    }
                 }
 
-                let filename = make_safe_filename(&song_name);
-                let dlp_path = self.save_data.dlp_path.clone();
+                // Kept until the download is done, for the tags written after the import
+                let artist = self
+                    .spotify_tracks
+                    .get(&song_name)
+                    .map(|track| track.artist.clone());
+                if let Some(existing) = existing {
+                    self.spotify_tracks.remove(&song_name);
+                    self.fill_playlist_slot(idx, song_idx, &existing);
+                    self.finish_playlist_download(id);
+                } else {
+                    let download = PendingDownload {
+                        name: song_name.clone(),
+                        filename: make_safe_filename(&song_name),
+                        link,
+                        playlist: self.save_data.playlists[idx].name.clone(),
+                        format: self.save_data.playlists[idx].audio_format,
+                    };
+                    let dlp = self.dlp.clone();
 
-                self.join_handles.push(tokio::spawn(async move {
-                    download_song(
-                        id,
-                        &dlp_path,
-                        &format!("https://youtube.com/watch?v={}", search_result.video_id),
-                        &filename,
-                        SearchFor::Playlist(idx, song_name, song_idx),
-                    )
-                    .await
-                }));
+                    self.spawn_playlist_task(async move {
+                        download_song(
+                            id,
+                            &dlp,
+                            download,
+                            SearchFor::Playlist(idx, song_name, song_idx),
+                            artist.as_deref(),
+                        )
+                        .await
+                    });
+                }
             }
             Ok(TaskReturn::SearchResult(id, search_result, SearchFor::GlobalSong(song_name))) => {
                 self.downloads
                     .insert(id, Download::DownloadingSong(song_name.clone()));
 
-                let filename = make_safe_filename(&song_name);
-                let dlp_path = self.save_data.dlp_path.clone();
+                let download = PendingDownload {
+                    name: song_name.clone(),
+                    filename: make_safe_filename(&song_name),
+                    link: format!("https://youtube.com/watch?v={}", search_result.video_id),
+                    playlist: String::new(),
+                    format: self.save_data.audio_format,
+                };
+                let dlp = self.dlp.clone();
+                let artist = self
+                    .spotify_tracks
+                    .remove(&song_name)
+                    .map(|track| track.artist);
 
-                self.join_handles.push(tokio::spawn(async move {
+                self.spawn_download(async move {
                     download_song(
                         id,
-                        &dlp_path,
-                        &format!("https://youtube.com/watch?v={}", search_result.video_id),
-                        &filename,
+                        &dlp,
+                        download,
                         SearchFor::GlobalSong(song_name),
+                        artist.as_deref(),
                     )
                     .await
-                }));
+                });
             }
-            Ok(TaskReturn::SongDownloaded(id, SearchFor::Playlist(idx, song_name, song_idx))) => {
-                if let Download::ProcessingPlaylistSongs(processing) =
-                    self.downloads.get_mut(&id).unwrap()
-                {
-                    processing
-                        .downloading_songs
-                        .retain(|song| song != &song_name);
-                    processing.downloaded += 1;
-
-                    if processing.downloaded as usize == processing.total_to_search {
-                        self.downloads.remove(&id);
+            Ok(TaskReturn::SongDownloaded(
+                id,
+                SearchFor::Playlist(idx, song_name, song_idx),
+                url,
+            )) => {
+                match self.downloads.get_mut(&id) {
+                    Some(Download::ProcessingPlaylistSongs(processing)) => {
+                        processing
+                            .downloading_songs
+                            .retain(|song| song != &song_name);
+                        processing.downloaded += 1;
+                    }
+                    None => return,
+                    Some(_) => {
+                        self.show_error(AppError::NotPlaylistImport(id));
+                        return;
                     }
-                } else {
-                    panic!("Expected Download::ProcessingPlaylistSongs");
                 }
 
+                let path = self
+                    .dlp
+                    .songs_dir
+                    .join(format!(
+                        "{}.{}",
+                        make_safe_filename(&song_name),
+                        self.save_data.playlists[idx].audio_format.extension()
+                    ))
+                    .to_string_lossy()
+                    .to_string();
+                if let Some(track) = self.spotify_tracks.remove(&song_name) {
+                    if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                        self.downloads.get_mut(&id)
+                    {
+                        processing.to_tag.push((path.clone(), track));
+                    }
+                }
+                self.finish_playlist_download(id);
                 let serializable_song = SerializableSong {
-                    path: get_quefi_dir()
-                        .join("songs")
-                        .join(format!("{}.mp3", make_safe_filename(&song_name)))
-                        .to_string_lossy()
-                        .to_string(),
+                    duration: probe_duration(&path),
+                    path,
                     name: song_name.clone(),
+                    favorite: false,
+                    last_played: 0,
+                    url,
+                    added: unix_timestamp(),
+                    play_count: 0,
+                    skips: 0,
+                    notes: String::new(),
                 };
 
+                self.record_download(&serializable_song.url, &serializable_song.path);
+
                 let song = Song {
                     path: serializable_song.path.clone(),
                     name: song_name.clone(),
                     playing: false,
+                    favorite: false,
                     selected: Selected::None,
+                    duration: serializable_song.duration,
                 };
 
+                self.load_tags(&song.path);
                 self.global_songs.push(song.clone());
                 self.save_data.playlists[idx].songs[song_idx] = song_name.clone();
+                self.journal(idx, PlaylistChange::Added(song_name.clone(), song_idx));
                 self.save_data.songs.push(serializable_song.clone());
 
                 self.playlists[idx].songs[song_idx] = song;
             }
-            Ok(TaskReturn::SongDownloaded(id, SearchFor::GlobalSong(name))) => {
+            Ok(TaskReturn::SongDownloaded(id, SearchFor::GlobalSong(name), url)) => {
                 self.log = format!("{name} downloaded!");
                 self.downloads.remove(&id);
-
-                let path = get_quefi_dir()
-                    .join(make_safe_filename(&name))
+                let resumed = self.resumed_downloads.remove(&id);
+                let format = resumed
+                    .as_ref()
+                    .map_or(self.save_data.audio_format, |download| download.format);
+
+                let path = self
+                    .dlp
+                    .songs_dir
+                    .join(format!(
+                        "{}.{}",
+                        make_safe_filename(&name),
+                        format.extension()
+                    ))
                     .to_string_lossy()
                     .to_string();
 
+                self.record_download(&url, &path);
+
+                let duration = probe_duration(&path);
                 self.save_data.songs.push(SerializableSong {
                     path: path.clone(),
                     name: name.clone(),
+                    favorite: false,
+                    last_played: 0,
+                    url,
+                    added: unix_timestamp(),
+                    play_count: 0,
+                    skips: 0,
+                    notes: String::new(),
+                    duration,
                 });
 
+                self.load_tags(&path);
                 self.global_songs.push(Song {
-                    path,
-                    name,
+                    path: path.clone(),
+                    name: name.clone(),
                     playing: false,
+                    favorite: false,
                     selected: Selected::None,
+                    duration,
                 });
-            }
-            Ok(TaskReturn::DlpDownloaded) => {}
-            Ok(TaskReturn::Token(id, token, link)) => {
-                self.save_data.last_valid_token = token;
-                self.handle_link(id, link);
-            }
-            Err(err) => {
-                if let Error::SpotifyBadAuth(id, link) = err {
-                    self.recreate_spotify_token(id, link);
-                } else {
-                    self.log = err.to_string();
-                }
-            }
-        }
-    }
-
-    fn recreate_spotify_token(&mut self, id: u8, link: SpotifyLink) {
-        self.downloads.insert(id, Download::FetchingSpotifyToken);
-
-        let client_id = self.save_data.spotify_client_id.clone();
-        let client_secret = self.save_data.spotify_client_secret.clone();
-        let client = self.client.clone();
 
-        self.join_handles.push(tokio::spawn(async move {
-            create_token(id, &client, &client_id, &client_secret, link).await
-        }));
-    }
-
-    fn preload_songs(&mut self, start_idx: usize) {
-        let idx = self.playlist_list_state.selected().unwrap();
-
-        let song = self.playlists[idx].songs[start_idx].clone();
-        self.play_path(&song.name, &song.path);
-
-        let next_idx = start_idx + 1;
+                if let Some(playlist_idx) = resumed.and_then(|download| {
+                    self.playlists
+                        .iter()
+                        .position(|playlist| playlist.name == download.playlist)
+                }) {
+                    // The slot it was downloaded for, unless the playlist was saved without it
+                    let slot = match self.save_data.playlists[playlist_idx]
+                        .songs
+                        .iter()
+                        .position(String::is_empty)
+                    {
+                        Some(slot) => slot,
+                        None => self.reserve_playlist_slots(playlist_idx, 1),
+                    };
+                    self.fill_playlist_slot(playlist_idx, slot, &name);
+                }
 
-        let song_idx = if next_idx >= self.playlists[idx].songs.len() {
-            if self.repeat == Repeat::All {
-                0
-            } else {
-                return;
+                if self.party_downloads.remove(&id) {
+                    let queued = QueuedSong {
+                        from: QueuedFrom::Manual,
+                        name,
+                        path,
+                        duration: Duration::ZERO,
+                    };
+                    self.enqueue(queued, false);
+                }
             }
-        } else {
-            next_idx
-        };
-
-        let song = self.playlists[idx].songs[song_idx].clone();
-        self.play_path(&song.name, &song.path);
-    }
+            Ok(TaskReturn::SearchResult(id, search_result, SearchFor::MissingSong(song_name))) => {
+                if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                    self.downloads.get_mut(&id)
+                {
+                    processing.searching_songs.retain(|song| song != &song_name);
+                    processing.searched += 1;
+                }
 
-    fn preload_song(&mut self, song_idx: usize) {
-        let idx = self.playlist_list_state.selected().unwrap();
+                self.download_missing_song(
+                    id,
+                    song_name,
+                    format!("https://youtube.com/watch?v={}", search_result.video_id),
+                );
+            }
+            Ok(TaskReturn::SongDownloaded(id, SearchFor::MissingSong(song_name), url)) => {
+                if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                    self.downloads.get_mut(&id)
+                {
+                    processing
+                        .downloading_songs
+                        .retain(|song| song != &song_name);
+                    processing.downloaded += 1;
 
-        let song = self.playlists[idx].songs[song_idx].clone();
-        self.play_path(&song.name, &song.path);
-    }
+                    if processing.downloaded as usize + processing.failed
+                        == processing.total_to_search
+                    {
+                        self.log =
+                            format!("Downloaded missing songs for {}", processing.playlist_name);
+                        self.downloads.remove(&id);
+                    }
+                } else {
+                    self.log = format!("{song_name} downloaded!");
+                    self.downloads.remove(&id);
+                }
 
-    fn update_song_queue(&mut self) {
-        if self.sink.len() != self.last_queue_length {
-            // TODO: Implement Repeat::One
+                let path = self
+                    .dlp
+                    .songs_dir
+                    .join(format!(
+                        "{}.{}",
+                        make_safe_filename(&song_name),
+                        self.save_data.audio_format.extension()
+                    ))
+                    .to_string_lossy()
+                    .to_string();
 
-            if !self.song_queue.is_empty() {
-                self.song_queue.remove(0);
+                self.record_download(&url, &path);
 
-                if self.repeat == Repeat::One {
-                    if let Playing::Playlist(_, song_idx) = self.playing {
-                        self.preload_song(song_idx);
-                    }
-                } else if let Playing::Playlist(playlist_idx, idx) = self.playing {
-                    let mut song_idx = idx + PRELOAD_SONG_COUNT;
-
-                    let out_of_bounds = song_idx >= self.playlists[playlist_idx].songs.len();
-                    if !out_of_bounds {
-                        self.log = format!("Preloading a song from idx {song_idx}...");
-                        self.preload_song(song_idx);
-                    } else if self.repeat == Repeat::All {
-                        song_idx %= self.playlists[playlist_idx].songs.len();
-                        self.log = format!("Preloading a song from idx {song_idx}...");
-                        self.preload_song(song_idx);
-                    }
+                if let Some(song) = self
+                    .save_data
+                    .songs
+                    .iter_mut()
+                    .find(|song| song.name == song_name)
+                {
+                    song.path = path.clone();
+                    song.url = url;
+                    // Don't let the cleanup delete it right away again
+                    song.last_played = unix_timestamp();
+                }
 
-                    let out_of_bounds = idx + 1 >= self.playlists[playlist_idx].songs.len();
-                    let new_idx = if out_of_bounds {
-                        if self.repeat == Repeat::All {
-                            0
-                        } else {
-                            self.playlists[playlist_idx].songs[idx].playing = false;
-                            return;
-                        }
-                    } else {
-                        idx + 1
-                    };
+                let duration = probe_duration(&path);
+                self.set_song_duration(&song_name, duration);
 
-                    self.playlists[playlist_idx].songs[idx].playing = false;
-                    self.playlists[playlist_idx].songs[new_idx].playing = true;
-                    self.playing = Playing::Playlist(playlist_idx, new_idx);
+                let songs = self.global_songs.iter_mut().chain(
+                    self.playlists
+                        .iter_mut()
+                        .flat_map(|playlist| &mut playlist.songs),
+                );
+                for song in songs.filter(|song| song.name == song_name) {
+                    song.path = path.clone();
                 }
-            } else if self.repeat == Repeat::One {
-                if let Playing::Playlist(_, song_idx) = self.playing {
-                    self.preload_song(song_idx);
+                self.load_tags(&path);
+            }
+            Ok(TaskReturn::SongStreamed(id, url, data)) => {
+                self.downloads.remove(&id);
+                if !self.read_only {
+                    if let Err(err) = self.cache.put("streams", &url, &data) {
+                        self.log = format!("Failed to cache the stream: {err}");
+                    }
                 }
-            } else {
-                self.log = String::from("Queue is empty");
+                self.streams.insert(url.clone(), data.into());
+                self.enqueue(
+                    QueuedSong {
+                        from: QueuedFrom::Stream,
+                        name: format!("Stream: {url}"),
+                        path: url,
+                        duration: Duration::ZERO,
+                    },
+                    false,
+                );
             }
+            Ok(TaskReturn::LinkMetadata(id, url, metadata)) => {
+                let song_name = metadata.song_name();
+                self.downloads
+                    .insert(id, Download::DownloadingSong(song_name.clone()));
 
-            self.last_queue_length = self.sink.len();
-        }
-    }
-
-    fn move_item(&mut self) {
-        if self.focused == Focused::Left {
-            let idx = self.playlist_list_state.selected().unwrap();
+                let download = PendingDownload {
+                    name: song_name.clone(),
+                    filename: make_safe_filename(&song_name),
+                    link: url,
+                    playlist: String::new(),
+                    format: self.save_data.audio_format,
+                };
+                let dlp = self.dlp.clone();
 
-            if self.playlists[idx].selected == Selected::Moving {
-                self.playlists[idx].selected = Selected::Focused;
-            } else {
-                self.playlists[idx].selected = Selected::Moving;
+                self.spawn_download(async move {
+                    download_tagged_song(id, &dlp, download, SearchFor::GlobalSong(song_name)).await
+                });
             }
-        } else {
-            match self.window {
-                Window::Songs => {
-                    let playlist_idx = self.playlist_list_state.selected().unwrap();
-                    let idx = self.song_list_state.selected().unwrap();
-
-                    if self.playlists[playlist_idx].songs[idx].selected == Selected::Moving {
-                        self.playlists[playlist_idx].songs[idx].selected = Selected::Focused;
-                    } else {
-                        self.playlists[playlist_idx].songs[idx].selected = Selected::Moving;
-                    }
+            Ok(TaskReturn::UpdateChecked(update)) => {
+                if let Some(update) = &update {
+                    self.log = format!(
+                        "Quefi {} is available, press U to see what changed",
+                        update.version
+                    );
+                }
+                self.update = update;
+            }
+            Ok(TaskReturn::TagsWritten(id, paths)) => {
+                for path in &paths {
+                    self.load_tags(path);
                 }
-                Window::GlobalSongs => {
-                    let idx = self.global_song_list_state.selected().unwrap();
 
-                    if self.global_songs[idx].selected == Selected::Moving {
-                        self.global_songs[idx].selected = Selected::Focused;
-                    } else {
-                        self.global_songs[idx].selected = Selected::Moving;
-                    }
+                let Some(Download::WritingTags(writing)) = self.downloads.get_mut(&id) else {
+                    return;
+                };
+                writing.written += paths.len();
+                if writing.remaining.is_empty() {
+                    self.log = format!(
+                        "Wrote tags for {} song(s) in {}",
+                        writing.written, writing.playlist_name
+                    );
+                    self.downloads.remove(&id);
+                } else {
+                    self.write_next_tags(id);
+                }
+            }
+            Ok(TaskReturn::DlpDownloaded) => {
+                self.log = String::from("yt-dlp downloaded!");
+                if !Path::new(&self.save_data.dlp_path).exists() {
+                    let path = youtube::downloaded_dlp_path();
+                    let path = std::path::absolute(&path).unwrap_or(path);
+                    self.set_dlp_path(path.to_string_lossy().to_string());
+                } else {
+                    self.refresh_dlp_version();
+                }
+            }
+            Ok(TaskReturn::DlpVersion(version)) => self.dlp_version = Some(version),
+            Ok(TaskReturn::FfmpegDownloaded(path)) => {
+                self.log = String::from("ffmpeg downloaded!");
+                self.set_ffmpeg_path(path.to_string_lossy().to_string());
+            }
+            Ok(TaskReturn::DownloadHookRan) => {}
+            Ok(TaskReturn::Token(id, token, expires, link)) => {
+                self.save_data.last_valid_token = token;
+                self.save_data.token_expires = expires;
+                self.handle_link(id, link);
+            }
+            Err(Error::SongFailed(id, search_for, reason)) => {
+                self.session_stats.failed_downloads += 1;
+                self.play_ui_sound(UiSound::Error);
+                if let Some(Download::ProcessingPlaylistSongs(_)) = self.downloads.get(&id) {
+                    self.import_song_failed(id, search_for.song_name(), reason);
+                } else {
+                    self.downloads.remove(&id);
+                    self.resumed_downloads.remove(&id);
+                    self.log_error(Error::SongFailed(id, search_for, reason));
+                }
+            }
+            Err(Error::PlaylistTracks(id, reason)) => {
+                // Whatever was fetched so far still gets imported
+                if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                    self.downloads.get_mut(&id)
+                {
+                    processing.more_tracks = false;
+                }
+                self.finish_playlist_download(id);
+                self.log_error(Error::PlaylistTracks(id, reason));
+            }
+            Err(
+                err @ (Error::Stream(id, _)
+                | Error::Metadata(id, _)
+                | Error::YoutubePlaylist(id, _)),
+            ) => {
+                self.downloads.remove(&id);
+                self.session_stats.failed_downloads += 1;
+                self.log_error(err);
+                self.play_ui_sound(UiSound::Error);
+            }
+            // The downloads it stopped are resumed on the next start
+            Err(Error::Cancelled) => {}
+            Err(err @ (Error::DownloadHook(..) | Error::SpotifyPreview(_))) => {
+                self.log_error(err);
+                self.play_ui_sound(UiSound::Error);
+            }
+            Err(err) => {
+                if let Error::SpotifyBadAuth(id, link) = err {
+                    self.recreate_spotify_token(id, link);
+                } else {
+                    self.log_error(err);
+                    self.play_ui_sound(UiSound::Error);
                 }
-                _ => {}
             }
         }
     }
 
-    fn toggle_repeat(&mut self) {
-        self.repeat = match self.repeat {
-            Repeat::None => Repeat::All,
-            Repeat::All => Repeat::One,
-            Repeat::One => Repeat::None,
-        };
+    // Waits for popups and inputs to be closed first
+    fn show_result_choices(&mut self) {
+        if self.mode == Mode::Normal && !self.result_choices.is_empty() {
+            self.result_choice_state.select(Some(0));
+            self.mode = Mode::PickResult;
+        }
+    }
 
-        self.save_data.last_repeat_mode = match self.repeat {
-            Repeat::None => 0,
-            Repeat::All => 1,
-            Repeat::One => 2,
-        };
+    // Like the result choices, waits for popups and inputs to be closed
+    fn show_spotify_search(&mut self) {
+        if self.mode == Mode::Normal && self.spotify_search.is_some() {
+            self.spotify_search_state.select(Some(0));
+            self.mode = Mode::PickSpotifyResult;
+        }
     }
 
-    fn select_left_window(&mut self) {
-        if self.focused == Focused::Left {
-            self.see_songs_in_playlist();
+    // The search stays open, so more than one result can be imported
+    fn import_spotify_result(&mut self) {
+        let Some(search) = &mut self.spotify_search else {
             return;
+        };
+        let idx = self
+            .spotify_search_state
+            .selected()
+            .unwrap_or(0)
+            .min(search.items.len() - 1);
+        let item = search.items.remove(idx);
+        if search.items.is_empty() {
+            self.spotify_search = None;
+            self.mode = Mode::Normal;
+        } else {
+            clamp_selection(&mut self.spotify_search_state, search.items.len());
         }
 
-        let playlist_idx = self.playlist_list_state.selected().unwrap();
+        self.log = format!("Importing {} from Spotify", item.name);
+        let id = self.new_download_id();
+        self.handle_link(id, item.link);
+    }
 
-        self.playlists[playlist_idx].selected = Selected::Focused;
-        self.focused = Focused::Left;
+    fn pick_result(&mut self, download: bool) {
+        let ResultChoice {
+            id,
+            search_for,
+            mut results,
+        } = self.result_choices.remove(0);
+        self.mode = Mode::Normal;
+        self.stop_preview();
+
+        if download {
+            let idx = self
+                .result_choice_state
+                .selected()
+                .unwrap_or(0)
+                .min(results.len() - 1);
+            let result = results.swap_remove(idx);
+            self.handle_result(Ok(TaskReturn::SearchResult(id, result, search_for)));
+        } else {
+            self.downloads.remove(&id);
+            self.spotify_tracks.remove(search_for.song_name());
+            self.replacing_songs.remove(search_for.song_name());
+            self.party_downloads.remove(&id);
+            self.log = format!("Cancelled downloading {}", search_for.song_name());
+        }
+        self.show_result_choices();
+    }
 
-        match self.window {
-            Window::Songs => {
-                let idx = self.song_list_state.selected().unwrap();
-                moving_warning!(self.playlists[playlist_idx].songs[idx], self.log);
+    fn set_dlp_path(&mut self, path: String) {
+        self.config.dlp_path.value = path.clone();
+        self.dlp.path = path.clone();
+        self.save_data.dlp_path = path;
+        self.refresh_dlp_version();
+    }
 
-                self.playlists[playlist_idx].songs[idx].selected = Selected::Unfocused;
-            }
-            Window::GlobalSongs => {
-                let idx = self.global_song_list_state.selected().unwrap();
-                moving_warning!(self.global_songs[idx], self.log);
+    fn set_ffmpeg_path(&mut self, path: String) {
+        self.config.ffmpeg_path.value = path.clone();
+        self.dlp.ffmpeg_path = path.clone();
+        self.save_data.ffmpeg_path = path;
+    }
 
-                self.global_songs[idx].selected = Selected::Unfocused;
-            }
-            Window::DownloadManager => {}
-            Window::ConfigurationMenu => {
-                if let Some(idx) = self.config_menu_state.selected() {
-                    match idx {
-                        0 => self.config.dlp_path.selected = Selected::Unfocused,
-                        1 => self.config.spotify_client_id.selected = Selected::Unfocused,
-                        2 => self.config.spotify_client_secret.selected = Selected::Unfocused,
-                        _ => panic!("Index out of range for config menu"),
-                    }
-                }
-            }
+    // yt-dlp can't convert to mp3 without it
+    fn ffmpeg_found(&self) -> bool {
+        if !self.save_data.ffmpeg_path.is_empty() {
+            return Path::new(&self.save_data.ffmpeg_path).is_file();
         }
+        env::var_os("PATH")
+            .and_then(|paths| youtube::find_in_paths(&paths, youtube::FFMPEG_EXECUTABLE_NAME))
+            .is_some()
     }
 
-    fn select_right_window(&mut self) {
-        if self.focused == Focused::Right {
+    fn refresh_dlp_version(&mut self) {
+        self.dlp_version = None;
+        if self.window == Window::ConfigurationMenu {
+            self.check_dlp_version();
+        }
+    }
+
+    fn check_dlp_version(&mut self) {
+        if !Path::new(&self.save_data.dlp_path).is_file() {
             return;
         }
+        let dlp = self.dlp.clone();
+        self.spawn_task(async move { youtube::fetch_dlp_version(&dlp).await });
+    }
 
-        let playlist_idx = self.playlist_list_state.selected().unwrap();
-        moving_warning!(self.playlists[playlist_idx], self.log);
+    fn log_error(&mut self, err: Error) {
+        let hint = match err {
+            Error::DlpMissing => Some(Hint::GetDlp),
+            Error::SpotifyCredentials => Some(Hint::SpotifyCredentials),
+            _ => None,
+        };
+        self.log = err.to_string();
+        self.hint = hint.map(|hint| (hint, self.log.clone()));
+        tracing::error!(error = ?err, "{}", self.log);
+        self.push_log_entry(true);
+    }
 
-        self.playlists[playlist_idx].selected = Selected::Unfocused;
-        self.focused = Focused::Right;
+    fn show_error(&mut self, err: AppError) {
+        tracing::error!(error = ?err, "{err}");
+        self.app_error = Some(err);
+    }
 
-        match self.window {
-            Window::Songs => {
-                for (i, song) in self.playlists[playlist_idx].songs.iter_mut().enumerate() {
-                    if song.selected == Selected::Unfocused {
-                        self.song_list_state.select(Some(i));
-                        song.selected = Selected::Focused;
-                        return;
-                    }
-                }
-                // If couldn't find a song with Selected::Unfocused, select first
-                self.playlists[playlist_idx].songs[0].selected = Selected::Focused;
-            }
-            Window::GlobalSongs => {
-                let idx = self.global_song_list_state.selected().unwrap();
-                self.global_songs[idx].selected = Selected::Focused;
-            }
-            Window::DownloadManager => {}
-            Window::ConfigurationMenu => {
-                if let Some(idx) = self.config_menu_state.selected() {
-                    match idx {
-                        0 => self.config.dlp_path.selected = Selected::Focused,
-                        1 => self.config.spotify_client_id.selected = Selected::Focused,
-                        2 => self.config.spotify_client_secret.selected = Selected::Focused,
-                        _ => panic!("Index out of range for config menu"),
-                    }
-                }
-            }
+    // Keeps what was in the log line before it gets replaced
+    pub(super) fn record_log(&mut self) {
+        if self.log.is_empty()
+            || self
+                .log_history
+                .back()
+                .is_some_and(|entry| entry.text == self.log)
+        {
+            return;
         }
+        tracing::info!("{}", self.log);
+        self.push_log_entry(false);
     }
 
-    fn seek_back(&mut self) {
-        if !self.song_queue.is_empty() {
-            self.sink
-                .try_seek(self.sink.get_pos().saturating_sub(Duration::from_secs(5)))
-                .expect("Seeking failed");
+    fn push_log_entry(&mut self, error: bool) {
+        if self.log_history.len() == LOG_HISTORY_LENGTH {
+            self.log_history.pop_front();
         }
+        self.log_history.push_back(LogEntry {
+            time: unix_timestamp(),
+            text: self.log.clone(),
+            error,
+        });
     }
 
-    fn seek_forward(&mut self) {
-        if !self.song_queue.is_empty() {
-            self.sink
-                .try_seek(self.sink.get_pos() + Duration::from_secs(5))
-                .expect("Seeking failed");
-        }
+    fn open_log(&mut self) {
+        self.record_log();
+        self.log_state.select_first();
+        self.switch_window(Window::Log);
     }
 
-    fn pause(&mut self) {
-        if self.sink.is_paused() {
-            self.sink.play();
-        } else {
-            self.sink.pause();
+    // Failed yt-dlp processes don't fail their task, they report what went wrong here
+    fn check_dlp_problem(&mut self) {
+        let Some(problem) = self.dlp.take_problem() else {
+            return;
+        };
+
+        let (message, hint) = match problem {
+            DlpProblem::FfmpegMissing => ("yt-dlp couldn't find ffmpeg", Hint::InstallFfmpeg),
+            DlpProblem::Forbidden => (
+                "YouTube refused the download, yt-dlp might be outdated",
+                Hint::UpdateDlp,
+            ),
+            DlpProblem::SignInRequired => (
+                "YouTube wants you to sign in to download this video",
+                Hint::Cookies,
+            ),
+        };
+        self.log = String::from(message);
+        self.hint = Some((hint, self.log.clone()));
+        tracing::warn!("{}", self.log);
+        self.push_log_entry(true);
+    }
+
+    fn fix_hinted_error(&mut self) {
+        match self.current_hint() {
+            Some(Hint::GetDlp | Hint::UpdateDlp) => self.enter_input_mode(InputMode::GetDlp),
+            Some(Hint::SpotifyCredentials) => {
+                self.open_config_field(ConfigFieldType::SpotifyClientId)
+            }
+            Some(Hint::Cookies) => self.open_config_field(ConfigFieldType::Cookies),
+            Some(Hint::InstallFfmpeg) => self.enter_input_mode(InputMode::GetFfmpeg),
+            None => {}
         }
     }
 
-    fn help(&mut self) {
-        if self.mode == Mode::Help {
-            self.mode = Mode::Normal;
+    fn open_config_field(&mut self, field_type: ConfigFieldType) {
+        self.switch_window(Window::ConfigurationMenu);
+        // Switching windows isn't allowed while moving an item
+        if self.window != Window::ConfigurationMenu {
+            return;
+        }
+
+        let current = self.config_menu_state.selected().unwrap_or(0);
+        let Some(idx) = self
+            .config
+            .fields()
+            .iter()
+            .position(|field| field.field_type == field_type)
+        else {
+            return;
+        };
+        self.select_config_field(current, idx);
+
+        if self.playlists.is_empty() {
+            // Nothing to take the focus from, the field is only highlighted
+            if let Some(field) = self.config.field_mut(idx) {
+                field.selected = Selected::Unfocused;
+            }
         } else {
-            self.mode = Mode::Help;
+            self.select_right_window();
         }
     }
 
-    fn see_songs_in_playlist(&mut self) {
-        self.window = Window::Songs;
-        self.song_list_state.select_first();
+    fn add_downloaded_playlist(&mut self, name: String) -> usize {
+        self.save_data.playlists.push(SerializablePlaylist {
+            songs: Vec::new(),
+            name: name.clone(),
+            description: String::new(),
+            audio_format: self.save_data.audio_format,
+            journal: Vec::new(),
+            spotify_id: String::new(),
+        });
+
+        self.playlists.push(Playlist {
+            songs: Vec::new(),
+            song_list_state: ListState::default().with_selected(Some(0)),
+            selected: Selected::None,
+            playing: false,
+            name,
+        });
+
+        let playlist_idx = self.save_data.playlists.len() - 1;
+        self.journal(playlist_idx, PlaylistChange::Created);
+        playlist_idx
     }
 
-    fn increase_volume(&mut self) {
-        let new_volume = self.sink.volume() + 0.05;
-        if new_volume >= 5.05 {
-            self.log = String::from("Volume can't be above 500%");
-        } else {
-            self.sink.set_volume(new_volume);
-            self.save_data.last_volume = new_volume;
+    // Same as download_into_playlist, but the videos are already known so nothing is searched
+    fn download_youtube_entries(
+        &mut self,
+        id: DownloadId,
+        playlist_idx: usize,
+        entries: Vec<YoutubePlaylistEntry>,
+    ) {
+        let start = self.reserve_playlist_slots(playlist_idx, entries.len());
+        let mut processing = ProcessingPlaylistSongs {
+            playlist_name: self.playlists[playlist_idx].name.clone(),
+            searching_songs: Vec::new(),
+            downloading_songs: Vec::new(),
+            total_to_search: entries.len(),
+            total_to_download: 0,
+            downloaded: 0,
+            searched: entries.len() as u16,
+            reused: 0,
+            failed: 0,
+            more_tracks: false,
+            to_tag: Vec::new(),
+        };
+        let playlist = &self.save_data.playlists[playlist_idx];
+        let (playlist_name, format) = (playlist.name.clone(), playlist.audio_format);
+
+        for (idx, entry) in entries.into_iter().enumerate() {
+            let link = format!("https://youtube.com/watch?v={}", entry.id);
+            if let Some(existing) = self
+                .save_data
+                .songs
+                .iter()
+                .find(|song| song.url == link)
+                .map(|song| song.name.clone())
+            {
+                self.fill_playlist_slot(playlist_idx, start + idx, &existing);
+                processing.reused += 1;
+                continue;
+            }
+
+            processing.downloading_songs.push(entry.title.clone());
+            processing.total_to_download += 1;
+            let download = PendingDownload {
+                name: entry.title.clone(),
+                filename: make_safe_filename(&entry.title),
+                link,
+                playlist: playlist_name.clone(),
+                format,
+            };
+            let dlp = self.dlp.clone();
+
+            self.spawn_playlist_task(async move {
+                download_song(
+                    id,
+                    &dlp,
+                    download,
+                    SearchFor::Playlist(playlist_idx, entry.title, start + idx),
+                    None,
+                )
+                .await
+            });
         }
+
+        self.downloads
+            .insert(id, Download::ProcessingPlaylistSongs(processing));
+        self.finish_playlist_download(id);
     }
 
-    fn decrease_volume(&mut self) {
-        let new_volume = self.sink.volume() - 0.05;
-        if new_volume < 0. {
-            self.log = String::from("Volume can't be negative");
+    // Searches and downloads wait while downloads are paused, running ones aren't stopped
+    pub(super) fn spawn_download(
+        &mut self,
+        task: impl Future<Output = TaskResult> + Send + 'static,
+    ) {
+        let mut paused = self.downloads_paused.subscribe();
+        self.spawn_task(async move {
+            let _ = paused.wait_for(|paused| !paused).await;
+            task.await
+        });
+    }
+
+    // Big imports would otherwise search for and download every song at the same time
+    pub(super) fn spawn_playlist_task(
+        &mut self,
+        task: impl Future<Output = TaskResult> + Send + 'static,
+    ) {
+        let slots = self.playlist_slots.clone();
+        let mut paused = self.downloads_paused.subscribe();
+        self.spawn_task(async move {
+            let _permit = slots.acquire_owned().await.unwrap();
+            // Checked after getting a slot, downloads could've been paused while waiting for it
+            let _ = paused.wait_for(|paused| !paused).await;
+            task.await
+        });
+    }
+
+    fn toggle_downloads_paused(&mut self) {
+        self.downloads_paused
+            .send_modify(|paused| *paused = !*paused);
+        self.log = if *self.downloads_paused.borrow() {
+            String::from("Downloads paused, the ones already running will finish")
         } else {
-            self.sink.set_volume(new_volume);
-            self.save_data.last_volume = new_volume;
+            String::from("Downloads resumed")
+        };
+    }
+
+    // Empty slots at the end of the playlist, filled in as songs finish downloading
+    fn reserve_playlist_slots(&mut self, playlist_idx: usize, count: usize) -> usize {
+        let start = self.playlists[playlist_idx].songs.len();
+        let len = start + count;
+        self.save_data.playlists[playlist_idx]
+            .songs
+            .resize(len, String::new());
+        self.playlists[playlist_idx].songs.resize(
+            len,
+            Song {
+                selected: Selected::None,
+                name: String::new(),
+                path: String::new(),
+                playing: false,
+                favorite: false,
+                duration: 0,
+            },
+        );
+        start
+    }
+
+    fn new_download_id(&mut self) -> DownloadId {
+        let id = self.next_download_id;
+        self.next_download_id = self.next_download_id.wrapping_add(1);
+        id
+    }
+
+    // Next is the link to the rest of the tracks, they're added as their pages come in
+    fn download_into_playlist(
+        &mut self,
+        id: DownloadId,
+        playlist_idx: usize,
+        tracks: Vec<TrackInfo>,
+        next: Option<String>,
+    ) {
+        self.downloads.insert(
+            id,
+            Download::ProcessingPlaylistSongs(ProcessingPlaylistSongs {
+                playlist_name: self.playlists[playlist_idx].name.clone(),
+                searching_songs: Vec::new(),
+                downloading_songs: Vec::new(),
+                total_to_search: 0,
+                total_to_download: 0,
+                downloaded: 0,
+                searched: 0,
+                reused: 0,
+                failed: 0,
+                more_tracks: next.is_some(),
+                to_tag: Vec::new(),
+            }),
+        );
+
+        if let Some(next) = next {
+            self.fetch_playlist_tracks(id, playlist_idx, next);
         }
+        self.add_playlist_tracks(id, playlist_idx, tracks);
     }
 
-    fn validate_input(&mut self) {
-        match self.mode {
-            Mode::Input(InputMode::AddPlaylist) => {
-                let text = self.text_area.lines()[0].trim();
-                let mut name_exists = false;
-                for playlist in &self.save_data.playlists {
-                    if playlist.name == text {
-                        name_exists = true;
-                        break;
-                    }
-                }
+    fn fetch_playlist_tracks(&mut self, id: DownloadId, playlist_idx: usize, url: String) {
+        let client = self.client.clone();
+        let token = self.save_data.last_valid_token.clone();
+        self.spawn_task(async move {
+            spotify::fetch_playlist_tracks(id, &client, playlist_idx, &url, &token).await
+        });
+    }
 
-                let bad_input = if text.is_empty() {
-                    String::from("Playlist name cannot be empty")
-                } else if name_exists {
-                    String::from("Playlist name cannot be same as existing playlist's name")
-                } else if text.len() > 64 {
-                    String::from("Playlist name cannot be longer than 64 characters")
-                } else {
-                    String::new()
-                };
+    // Tracks get empty slots at the end of the playlist, filled in as they finish downloading
+    fn add_playlist_tracks(&mut self, id: DownloadId, playlist_idx: usize, tracks: Vec<TrackInfo>) {
+        if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id) {
+            processing.total_to_search += tracks.len();
+        }
+        let start = self.reserve_playlist_slots(playlist_idx, tracks.len());
 
-                self.textarea_condition(
-                    !text.is_empty() && !name_exists && text.len() <= 64,
-                    String::from("Input playlist name"),
-                    bad_input,
-                );
-            }
-            Mode::Input(InputMode::AddSongToPlaylist) => {
-                let text = self.text_area.lines()[0].trim();
-                let mut name_exists = false;
-                for song in &self.save_data.songs {
-                    if song.name == text {
-                        name_exists = true;
-                        break;
-                    }
+        for (idx, track) in tracks.into_iter().enumerate() {
+            if let Some(existing) = self.library_match(&track) {
+                self.fill_playlist_slot(playlist_idx, start + idx, &existing);
+                if let Some(Download::ProcessingPlaylistSongs(processing)) =
+                    self.downloads.get_mut(&id)
+                {
+                    processing.reused += 1;
                 }
+                continue;
+            }
 
-                self.textarea_condition(
-                    name_exists,
-                    String::from("Input song name"),
-                    String::from("Song doesn't exist"),
-                );
+            if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id)
+            {
+                processing.searching_songs.push(track.name.clone());
             }
-            Mode::Input(InputMode::AddGlobalSong) => {
-                let text = self.text_area.lines()[0].trim();
-                let mut name_exists = false;
-                for song in &self.save_data.songs {
-                    if song.name == text {
-                        name_exists = true;
-                        break;
-                    }
-                }
+            let client = self.client.clone();
+            let cache = self.cache.clone();
+            let query = track.query.clone();
+            let name = track.name.clone();
+            let duration_ms = track.duration_ms;
+            let filter = track.filter;
+            self.spotify_tracks.insert(track.name.clone(), track);
+
+            self.spawn_playlist_task(async move {
+                search_ytmusic(
+                    id,
+                    &client,
+                    Some(&cache),
+                    &query,
+                    duration_ms,
+                    filter,
+                    SearchFor::Playlist(playlist_idx, name, start + idx),
+                )
+                .await
+            });
+        }
 
-                let bad_input = if text.is_empty() {
-                    String::from("Song name cannot be empty")
-                } else if name_exists {
-                    String::from("Song name cannot be same as existing song's name")
+        // Everything could've been in the library already
+        self.finish_playlist_download(id);
+    }
+
+    fn library_match(&self, track: &TrackInfo) -> Option<String> {
+        self.save_data
+            .songs
+            .iter()
+            .find(|song| self.matches_track(song, track))
+            .map(|song| song.name.clone())
+    }
+
+    // Same title and artist as the Spotify track
+    fn matches_track(&self, song: &SerializableSong, track: &TrackInfo) -> bool {
+        let first_artist = |artist: &str| normalize_title(artist.split(',').next().unwrap_or(""));
+        match self.song_tags.get(&song.path) {
+            Some(SongTags {
+                title: Some(tag_title),
+                artist: Some(tag_artist),
+                ..
+            }) => {
+                normalize_title(tag_title) == normalize_title(&track.name)
+                    && first_artist(tag_artist) == first_artist(&track.artist)
+            }
+            // Songs downloaded from Spotify are named after the track
+            _ => {
+                let name = normalize_title(&song.name);
+                name == normalize_title(&track.name) || name == normalize_title(&track.query)
+            }
+        }
+    }
+
+    fn start_playlist_sync(&mut self) {
+        if self.offline_blocked() {
+            return;
+        }
+        let Some(playlist_idx) = self.playlist_list_state.selected() else {
+            return;
+        };
+        let Some(playlist) = self.save_data.playlists.get(playlist_idx) else {
+            return;
+        };
+        if playlist.spotify_id.is_empty() {
+            self.log = format!("{} wasn't imported from Spotify", playlist.name);
+            return;
+        }
+
+        let link = SpotifyLink::Sync(playlist_idx, playlist.spotify_id.clone());
+        self.log = format!("Checking {} for changes...", playlist.name);
+        let id = self.new_download_id();
+        self.handle_link(id, link);
+    }
+
+    pub(super) fn show_playlist_sync(&mut self, playlist_idx: usize, tracks: Vec<TrackInfo>) {
+        let Some(playlist) = self.save_data.playlists.get(playlist_idx) else {
+            return;
+        };
+        let songs: Vec<&SerializableSong> = playlist
+            .songs
+            .iter()
+            .filter_map(|name| self.save_data.songs.iter().find(|song| &song.name == name))
+            .collect();
+
+        let removed = songs
+            .iter()
+            .filter(|song| !tracks.iter().any(|track| self.matches_track(song, track)))
+            .map(|song| song.name.clone())
+            .collect::<Vec<_>>();
+        let added = tracks
+            .into_iter()
+            .filter(|track| !songs.iter().any(|song| self.matches_track(song, track)))
+            .collect::<Vec<_>>();
+
+        if added.is_empty() && removed.is_empty() {
+            self.log = format!("{} is up to date", playlist.name);
+            return;
+        }
+        self.log = format!(
+            "{}: {} new, {} removed on Spotify",
+            playlist.name,
+            added.len(),
+            removed.len()
+        );
+        self.playlist_sync = Some(PlaylistSync {
+            playlist_idx,
+            added,
+            removed,
+        });
+        self.playlist_sync_state.select_first();
+        self.switch_window(Window::PlaylistSync);
+    }
+
+    fn playlist_sync_changes(&self) -> usize {
+        self.playlist_sync
+            .as_ref()
+            .map_or(0, |sync| sync.added.len() + sync.removed.len())
+    }
+
+    // New tracks are listed first, then the removed songs
+    fn leave_out_of_sync(&mut self) {
+        let Some(idx) = self.playlist_sync_state.selected() else {
+            return;
+        };
+        let Some(sync) = &mut self.playlist_sync else {
+            return;
+        };
+
+        if idx < sync.added.len() {
+            sync.added.remove(idx);
+        } else if idx - sync.added.len() < sync.removed.len() {
+            sync.removed.remove(idx - sync.added.len());
+        }
+        let len = self.playlist_sync_changes();
+        clamp_selection(&mut self.playlist_sync_state, len);
+    }
+
+    fn apply_playlist_sync(&mut self) {
+        let Some(sync) = self.playlist_sync.take() else {
+            return;
+        };
+        let playlist_idx = sync.playlist_idx;
+        if playlist_idx >= self.playlists.len() {
+            return;
+        }
+
+        for name in &sync.removed {
+            while let Some(idx) = self.save_data.playlists[playlist_idx]
+                .songs
+                .iter()
+                .position(|song| song == name)
+            {
+                self.playlists[playlist_idx].songs.remove(idx);
+                self.save_data.playlists[playlist_idx].songs.remove(idx);
+                self.journal(playlist_idx, PlaylistChange::Removed(name.clone(), idx));
+                self.fix_queue_after_song_removal(playlist_idx, idx);
+            }
+        }
+        let len = self.playlists[playlist_idx].songs.len();
+        clamp_selection(&mut self.song_list_state, len);
+
+        self.log = format!(
+            "Synced {}, removed {} song(s)",
+            self.playlists[playlist_idx].name,
+            sync.removed.len()
+        );
+        if !sync.added.is_empty() {
+            self.log += &format!(", downloading {} new track(s)", sync.added.len());
+            let id = self.new_download_id();
+            self.download_into_playlist(id, playlist_idx, sync.added, None);
+        }
+        self.switch_window(Window::Songs);
+    }
+
+    fn fill_playlist_slot(&mut self, playlist_idx: usize, slot: usize, song_name: &str) {
+        let Some(song) = self.global_songs.iter().find(|song| song.name == song_name) else {
+            return;
+        };
+
+        self.playlists[playlist_idx].songs[slot] = Song {
+            selected: Selected::None,
+            playing: false,
+            ..song.clone()
+        };
+        self.save_data.playlists[playlist_idx].songs[slot] = song_name.to_string();
+        self.journal(
+            playlist_idx,
+            PlaylistChange::Added(song_name.to_string(), slot),
+        );
+    }
+
+    fn finish_playlist_download(&mut self, id: DownloadId) {
+        let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get(&id) else {
+            return;
+        };
+        if processing.more_tracks
+            || processing.downloaded as usize + processing.reused + processing.failed
+                < processing.total_to_search
+        {
+            return;
+        }
+
+        self.log = if processing.reused > 0 {
+            format!(
+                "Finished {}, reused {} track(s) already in the library",
+                processing.playlist_name, processing.reused
+            )
+        } else {
+            format!("Finished {}", processing.playlist_name)
+        };
+        if processing.failed > 0 {
+            self.log += &format!(
+                ", {} failed (listed in the download manager)",
+                processing.failed
+            );
+        }
+
+        // Tagging takes a while, so it's done after the import instead of slowing it down
+        if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.remove(&id) {
+            if !processing.to_tag.is_empty() {
+                self.downloads.insert(
+                    id,
+                    Download::WritingTags(WritingTags {
+                        playlist_name: processing.playlist_name,
+                        total: processing.to_tag.len(),
+                        written: 0,
+                        remaining: processing.to_tag,
+                    }),
+                );
+                self.write_next_tags(id);
+            }
+        }
+    }
+
+    // Listed with the playlist's other failures, so they don't overwrite each other in the log
+    fn import_song_failed(&mut self, id: DownloadId, song_name: &str, reason: String) {
+        let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id)
+        else {
+            return;
+        };
+        processing.searching_songs.retain(|song| song != song_name);
+        processing
+            .downloading_songs
+            .retain(|song| song != song_name);
+        processing.failed += 1;
+
+        let playlist_name = processing.playlist_name.clone();
+        let failed = (song_name.to_string(), reason);
+        match self
+            .failed_imports
+            .iter_mut()
+            .find(|import| import.playlist_name == playlist_name)
+        {
+            Some(import) => import.songs.push(failed),
+            None => self.failed_imports.push(FailedImport {
+                playlist_name,
+                songs: vec![failed],
+            }),
+        }
+        self.finish_playlist_download(id);
+    }
+
+    fn start_failure_export(&mut self) {
+        if self.failed_imports.is_empty() {
+            self.log = String::from("No playlist songs failed to download");
+            return;
+        }
+
+        let path = get_quefi_dir().join("exports").join("failed downloads.txt");
+        self.text_area.insert_str(path.to_string_lossy());
+        self.enter_input_mode(InputMode::ExportFailures);
+    }
+
+    fn export_failures(&mut self, path: &Path) {
+        let mut contents = String::new();
+        for import in &self.failed_imports {
+            contents += &format!("{}\n", import.playlist_name);
+            for (name, reason) in &import.songs {
+                contents += &format!("  {name}: {reason}\n");
+            }
+        }
+
+        let result = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| fs::write(path, contents));
+        self.log = match result {
+            Ok(()) => format!("Exported failed downloads to {}", path.display()),
+            Err(err) => format!("Could not export failed downloads: {err}"),
+        };
+    }
+
+    fn write_next_tags(&mut self, id: DownloadId) {
+        let Some(Download::WritingTags(writing)) = self.downloads.get_mut(&id) else {
+            return;
+        };
+        let batch_size = TAG_BATCH_SIZE.min(writing.remaining.len());
+        let batch: Vec<(String, TrackInfo)> = writing.remaining.drain(..batch_size).collect();
+        let dlp = self.dlp.clone();
+        let client = self.client.clone();
+
+        self.spawn_task(async move { write_tags(id, &dlp, &client, batch).await });
+    }
+
+    fn new_releases_playlist(&mut self) -> usize {
+        if let Some(idx) = self
+            .save_data
+            .playlists
+            .iter()
+            .position(|playlist| playlist.name == NEW_RELEASES_PLAYLIST)
+        {
+            return idx;
+        }
+
+        let was_empty = self.playlists.is_empty();
+        self.save_data.playlists.push(SerializablePlaylist {
+            name: String::from(NEW_RELEASES_PLAYLIST),
+            songs: Vec::new(),
+            description: String::new(),
+            audio_format: self.save_data.audio_format,
+            journal: Vec::new(),
+            spotify_id: String::new(),
+        });
+        self.playlists.push(Playlist {
+            songs: Vec::new(),
+            song_list_state: ListState::default().with_selected(Some(0)),
+            selected: Selected::None,
+            playing: false,
+            name: String::from(NEW_RELEASES_PLAYLIST),
+        });
+
+        let idx = self.playlists.len() - 1;
+        self.journal(idx, PlaylistChange::Created);
+        if was_empty {
+            select!(self.playlists, self.playlist_list_state, 0);
+            self.see_songs_in_playlist();
+        }
+        idx
+    }
+
+    fn kiosk_playlist_idx(&self) -> Option<usize> {
+        self.playlists
+            .iter()
+            .position(|playlist| playlist.name == self.save_data.kiosk_playlist)
+    }
+
+    fn start_kiosk(&mut self) {
+        if self.save_data.kiosk_playlist.is_empty() {
+            return;
+        }
+        let Some(playlist_idx) = self.kiosk_playlist_idx() else {
+            self.log = format!(
+                "Kiosk playlist {} doesn't exist, starting normally",
+                self.save_data.kiosk_playlist
+            );
+            return;
+        };
+
+        self.kiosk = true;
+        if let Some(playlist) = self.playlists.get_mut(0) {
+            playlist.selected = Selected::None;
+        }
+        select!(self.playlists, self.playlist_list_state, playlist_idx);
+        self.see_songs_in_playlist();
+        self.restart_kiosk_playlist(playlist_idx);
+    }
+
+    fn restart_kiosk_playlist(&mut self, playlist_idx: usize) {
+        self.last_kiosk_restart = Some(Instant::now());
+        self.stop_playing_current();
+        self.play_playlist(playlist_idx, 0);
+        if self.playing != Playing::None {
+            self.log = format!("Kiosk mode: playing {}", self.playlists[playlist_idx].name);
+        }
+    }
+
+    // Starts the playlist over once it ends, or when its songs failed to load
+    fn keep_kiosk_playing(&mut self) {
+        if !self.kiosk
+            || self.playing != Playing::None
+            || !self.sink.empty()
+            || self
+                .last_kiosk_restart
+                .is_some_and(|restart| restart.elapsed() < KIOSK_RESTART_INTERVAL)
+        {
+            return;
+        }
+        if let Some(playlist_idx) = self.kiosk_playlist_idx() {
+            self.restart_kiosk_playlist(playlist_idx);
+        }
+    }
+
+    fn trim_cache(&mut self) {
+        if self.read_only {
+            return;
+        }
+        match self.cache.trim() {
+            Ok(0) => {}
+            Ok(freed) => {
+                self.log = format!(
+                    "Freed {} MB of cache",
+                    self.locale.number(freed / 1024 / 1024)
+                )
+            }
+            Err(err) => self.log = format!("Failed to trim the cache: {err}"),
+        }
+    }
+
+    // Restarts the server, so a changed port takes effect right away
+    fn start_party_server(&mut self) {
+        if let Some(server) = self.party_server.take() {
+            server.abort();
+        }
+        self.party_receiver = None;
+
+        let port = self.save_data.party_port;
+        if port == 0 || self.read_only {
+            return;
+        }
+
+        let listener = std::net::TcpListener::bind(("0.0.0.0", port)).and_then(|listener| {
+            listener.set_nonblocking(true)?;
+            tokio::net::TcpListener::from_std(listener)
+        });
+        match listener {
+            Ok(listener) => {
+                let (sender, receiver) = mpsc::channel(party::MAX_PENDING);
+                self.party_receiver = Some(receiver);
+                self.party_server = Some(tokio::spawn(party::serve(listener, sender)));
+                self.log = format!("Party queue is open on port {port}");
+            }
+            Err(err) => self.log = format!("Could not open the party queue on port {port}: {err}"),
+        }
+    }
+
+    fn receive_party_requests(&mut self) {
+        // Failed downloads are removed, there's nothing left to queue for them
+        let downloads = &self.downloads;
+        self.party_downloads.retain(|id| downloads.contains_key(id));
+
+        let Some(receiver) = &mut self.party_receiver else {
+            return;
+        };
+        // The rest wait in the channel, so guests are told to try later once it's full too
+        while self.party_requests.len() < party::MAX_PENDING {
+            let Ok(submission) = receiver.try_recv() else {
+                break;
+            };
+            self.log = format!(
+                "{} asked for {}, press Q to review",
+                submission.from, submission.text
+            );
+            self.party_requests.push(submission);
+        }
+    }
+
+    fn approve_party_request(&mut self) {
+        let Some(idx) = self
+            .party_state
+            .selected()
+            .filter(|&idx| idx < self.party_requests.len())
+        else {
+            return;
+        };
+        let Submission { text, .. } = self.party_requests.remove(idx);
+        clamp_selection(&mut self.party_state, self.party_requests.len());
+
+        let text = link::normalize(&text);
+        let id = if super::is_valid_youtube_link(&text)
+            || super::is_valid_metadata_link(&text)
+            || validate_spotify_link(&text) != SpotifyLink::Invalid
+        {
+            self.download_link(&text)
+        } else {
+            let id = self.new_download_id();
+            self.downloads
+                .insert(id, Download::SearchingForSong(text.clone()));
+            let client = self.client.clone();
+            let cache = self.cache.clone();
+            let query = text.clone();
+            self.spawn_download(async move {
+                search_ytmusic(
+                    id,
+                    &client,
+                    Some(&cache),
+                    &query,
+                    0,
+                    SearchFilter::Songs,
+                    SearchFor::GlobalSong(query.clone()),
+                )
+                .await
+            });
+            id
+        };
+
+        self.party_downloads.insert(id);
+        self.log = format!("Approved {text}, it will be queued once downloaded");
+    }
+
+    // Weekly, once `quefi spotify-login` was used
+    fn check_new_releases(&mut self) {
+        if self.read_only
+            || self.offline
+            || !self.spotify_logged_in
+            || unix_timestamp().saturating_sub(self.save_data.last_release_check)
+                < RELEASE_CHECK_INTERVAL
+            || self
+                .last_release_attempt
+                .is_some_and(|attempt| attempt.elapsed() < RELEASE_RETRY_INTERVAL)
+        {
+            return;
+        }
+        self.last_release_attempt = Some(Instant::now());
+
+        let id = self.new_download_id();
+        let (year, month, day) = civil_date(self.save_data.last_release_check);
+        self.handle_link(
+            id,
+            SpotifyLink::NewReleases(format!("{year:04}-{month:02}-{day:02}")),
+        );
+    }
+
+    fn recreate_spotify_token(&mut self, id: DownloadId, link: SpotifyLink) {
+        // A playlist being imported keeps showing its progress while the token is refreshed
+        if !matches!(link, SpotifyLink::PlaylistPage(..)) {
+            self.downloads.insert(id, Download::FetchingSpotifyToken);
+        }
+
+        let client_id = self.save_data.spotify_client_id.clone();
+        let client_secret = self.save_data.spotify_client_secret.clone();
+        let client = self.client.clone();
+        let logged_in = self.spotify_logged_in;
+
+        self.spawn_task(async move {
+            if logged_in {
+                spotify::create_user_token(id, &client, &client_id, link).await
+            } else {
+                create_token(id, &client, &client_id, &client_secret, link).await
+            }
+        });
+    }
+
+    fn next_queued_song(&mut self) -> Option<QueuedSong> {
+        if !self.pending_queue.is_empty() {
+            return Some(self.pending_queue.remove(0));
+        }
+
+        if self.repeat == Repeat::One {
+            if let Some(last) = self.song_queue.last() {
+                return Some(QueuedSong {
+                    from: last.from,
+                    name: last.name.clone(),
+                    path: last.path.clone(),
+                    duration: Duration::ZERO,
+                });
+            }
+        }
+
+        if let Some(order_idx) = self.global_cursor {
+            let order_idx = if order_idx < self.global_order.len() {
+                order_idx
+            } else if self.repeat == Repeat::All && !self.global_order.is_empty() {
+                0
+            } else {
+                return None;
+            };
+
+            self.global_cursor = Some(order_idx + 1);
+            let idx = self.global_order[order_idx];
+            return Some(QueuedSong {
+                from: QueuedFrom::GlobalSong(idx),
+                name: self.global_songs[idx].name.clone(),
+                path: self.global_songs[idx].path.clone(),
+                duration: Duration::ZERO,
+            });
+        }
+
+        let (playlist_idx, song_idx) = self.playlist_cursor?;
+        let songs = &self.playlists[playlist_idx].songs;
+
+        let song_idx = if song_idx < songs.len() {
+            song_idx
+        } else if self.repeat == Repeat::All && !songs.is_empty() {
+            0
+        } else {
+            return None;
+        };
+
+        self.playlist_cursor = Some((playlist_idx, song_idx + 1));
+        Some(QueuedSong {
+            from: QueuedFrom::Playlist(playlist_idx, song_idx),
+            name: songs[song_idx].name.clone(),
+            path: songs[song_idx].path.clone(),
+            duration: Duration::ZERO,
+        })
+    }
+
+    fn fill_song_queue(&mut self) {
+        let mut failed = 0;
+        let mut auto_skipped = 0;
+        // The song the user picked plays even if it's usually skipped
+        let mut picked = self.song_queue.is_empty();
+
+        // Only the playing song is kept loaded in low memory mode
+        let preload = if self.save_data.low_memory {
+            1
+        } else {
+            PRELOAD_SONG_COUNT
+        };
+
+        while self.song_queue.len() < preload && failed < preload {
+            let Some(queued) = self.next_queued_song() else {
+                break;
+            };
+
+            if !picked && self.is_auto_skipped(&queued) {
+                // Stops repeat all from going around forever when every song is skipped
+                auto_skipped += 1;
+                if auto_skipped > self.save_data.songs.len() {
+                    break;
+                }
+                self.log = format!("Auto-skipped {}", queued.name);
+                continue;
+            }
+            picked = false;
+
+            if !self.play_path(&queued.name, &queued.path, queued.from) {
+                failed += 1;
+            }
+        }
+    }
+
+    fn is_auto_skipped(&self, queued: &QueuedSong) -> bool {
+        let after = self.save_data.auto_skip_after;
+        after > 0
+            && matches!(
+                queued.from,
+                QueuedFrom::Playlist(..) | QueuedFrom::GlobalSong(_)
+            )
+            && self
+                .save_data
+                .songs
+                .iter()
+                .any(|song| song.path == queued.path && song.skips >= after)
+    }
+
+    fn skip_song(&mut self) {
+        if let Some(queued) = self.song_queue.first() {
+            if self.sink.get_pos() < SKIP_THRESHOLD && queued.from != QueuedFrom::Stream {
+                for song in &mut self.save_data.songs {
+                    if song.path == queued.path {
+                        song.skips += 1;
+                    }
+                }
+            }
+        }
+        self.sink.skip_one();
+    }
+
+    // Take the preloaded songs out of the sink, so the songs after the current one can be changed
+    fn requeue_after_current(&mut self) {
+        if self.song_queue.len() < 2 {
+            return;
+        }
+
+        let paused = self.sink.is_paused();
+        let pos = self.sink.get_pos();
+        let dropped = self.song_queue.split_off(1);
+        let current = self.song_queue.remove(0);
+
+        self.sink.clear();
+        self.pending_queue.splice(0..0, dropped);
+
+        if self.play_path(&current.name, &current.path, current.from) {
+            if let Err(err) = self.sink.try_seek(pos) {
+                self.log = format!("Failed to seek: {err}");
+            }
+        }
+
+        if !paused {
+            self.sink.play();
+        }
+    }
+
+    fn update_playing(&mut self) {
+        match self.playing {
+            Playing::Playlist(playlist_idx, song_idx) => {
+                if let Some(song) = self.playlists[playlist_idx].songs.get_mut(song_idx) {
+                    song.playing = false;
+                }
+            }
+            Playing::GlobalSong(idx) => {
+                if let Some(song) = self.global_songs.get_mut(idx) {
+                    song.playing = false;
+                }
+            }
+            Playing::None => {}
+        }
+
+        let Some(queued) = self.song_queue.first() else {
+            if let Playing::Playlist(playlist_idx, _) = self.playing {
+                self.playlists[playlist_idx].playing = false;
+            }
+            // Nothing was playing when the song failed to load, keep the reason in the log
+            if self.playing != Playing::None {
+                self.log = String::from("Queue is empty");
+            }
+            self.playing = Playing::None;
+            self.playlist_cursor = None;
+            self.global_cursor = None;
+            self.write_now_playing();
+            return;
+        };
+
+        self.session_stats.tracks_played += 1;
+        self.title_shown_at = Instant::now();
+
+        let label = match self.song_tags.get(&queued.path) {
+            Some(tags) => tags.label(&queued.name),
+            None => queued.name.clone(),
+        };
+
+        let now = unix_timestamp();
+        for song in &mut self.save_data.songs {
+            if song.path == queued.path {
+                song.last_played = now;
+                song.play_count += 1;
+
+                self.save_data.history.push(HistoryEntry {
+                    name: song.name.clone(),
+                    played: now,
+                });
+            }
+        }
+        let overflow = self.save_data.history.len().saturating_sub(HISTORY_LENGTH);
+        self.save_data.history.drain(..overflow);
+
+        match queued.from {
+            QueuedFrom::Playlist(playlist_idx, song_idx) => {
+                self.playlists[playlist_idx].songs[song_idx].playing = true;
+                self.playlists[playlist_idx].playing = true;
+                self.playing = Playing::Playlist(playlist_idx, song_idx);
+            }
+            QueuedFrom::GlobalSong(idx) => {
+                self.global_songs[idx].playing = true;
+                self.playing = Playing::GlobalSong(idx);
+            }
+            // Keep the playlist that's playing, so it continues after the manually queued songs
+            QueuedFrom::Manual | QueuedFrom::Stream => {}
+        }
+        self.announce(format!("Now playing: {label}"));
+        self.notify_track_change(&label);
+        self.play_ui_sound(UiSound::TrackChange);
+        self.write_now_playing();
+    }
+
+    // Lets status bars and stream overlays show the current track
+    fn write_now_playing(&mut self) {
+        if self.save_data.now_playing_path.is_empty() {
+            return;
+        }
+
+        let info = match self.song_queue.first() {
+            Some(queued) => {
+                let tags = self
+                    .song_tags
+                    .get(&queued.path)
+                    .cloned()
+                    .unwrap_or_default();
+                json!({
+                    "status": if self.sink.is_paused() { "paused" } else { "playing" },
+                    "name": queued.name,
+                    "title": tags.title,
+                    "artist": tags.artist,
+                    "album": tags.album,
+                    "path": queued.path,
+                    "duration": queued.duration.as_secs(),
+                })
+            }
+            None => json!({ "status": "stopped" }),
+        };
+
+        if let Err(err) = fs::write(&self.save_data.now_playing_path, info.to_string()) {
+            self.log = format!("Failed to write the now playing file: {err}");
+        }
+    }
+
+    fn update_song_queue(&mut self) {
+        if self.song_queue.len() <= self.sink.len() {
+            return;
+        }
+
+        while self.song_queue.len() > self.sink.len() {
+            self.song_queue.remove(0);
+        }
+
+        // Streamed songs are only kept in memory while they're queued
+        let queued: Vec<&str> = self
+            .song_queue
+            .iter()
+            .chain(&self.pending_queue)
+            .map(|queued| queued.path.as_str())
+            .collect();
+        self.streams.retain(|url, _| queued.contains(&url.as_str()));
+
+        self.fill_song_queue();
+        self.update_playing();
+    }
+
+    fn queue_selected(&mut self, play_next: bool) {
+        if self.focused != Focused::Right {
+            return;
+        }
+
+        let song = match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                self.song_list_state
+                    .selected()
+                    .and_then(|idx| self.playlists[playlist_idx].songs.get(idx))
+                    .cloned()
+            }
+            Window::GlobalSongs => self
+                .global_song_list_state
+                .selected()
+                .and_then(|idx| self.global_songs.get(idx))
+                .cloned(),
+            _ => None,
+        };
+
+        let Some(song) = song else {
+            return;
+        };
+
+        let queued = QueuedSong {
+            from: QueuedFrom::Manual,
+            name: song.name,
+            path: song.path,
+            duration: Duration::ZERO,
+        };
+        self.enqueue(queued, play_next);
+    }
+
+    fn enqueue(&mut self, queued: QueuedSong, play_next: bool) {
+        if play_next {
+            self.log = format!("Playing {} next", queued.name);
+            self.requeue_after_current();
+            self.pending_queue.insert(0, queued);
+        } else {
+            self.log = format!("Added {} to the queue", queued.name);
+            self.pending_queue.push(queued);
+        }
+
+        let was_empty = self.song_queue.is_empty();
+        self.fill_song_queue();
+
+        if was_empty {
+            self.update_playing();
+            self.sink.play();
+        }
+    }
+
+    fn queue_len(&self) -> usize {
+        self.song_queue.len() + self.pending_queue.len()
+    }
+
+    // Returns the index of an upcoming queue entry in the pending queue
+    fn detach_queue_entry(&mut self, idx: usize) -> Option<usize> {
+        if idx == 0 || idx >= self.queue_len() {
+            return None;
+        }
+
+        if idx < self.song_queue.len() {
+            self.requeue_after_current();
+            return Some(idx - 1);
+        }
+        Some(idx - self.song_queue.len())
+    }
+
+    fn remove_queue_entry(&mut self, idx: usize) {
+        if idx == 0 {
+            self.sink.skip_one();
+            return;
+        }
+
+        if let Some(pending_idx) = self.detach_queue_entry(idx) {
+            let removed = self.pending_queue.remove(pending_idx);
+            self.log = format!("Removed {} from the queue", removed.name);
+            self.fill_song_queue();
+        }
+
+        if idx >= self.queue_len() {
+            self.queue_state
+                .select(Some(self.queue_len().saturating_sub(1)));
+        }
+    }
+
+    fn jump_to_queue_entry(&mut self, idx: usize) {
+        if idx == 0 || idx >= self.queue_len() {
+            return;
+        }
+
+        self.requeue_after_current();
+        let pending_idx = idx - self.song_queue.len();
+        let queued = self.pending_queue.remove(pending_idx);
+        self.pending_queue.insert(0, queued);
+
+        self.fill_song_queue();
+        self.sink.skip_one();
+        self.queue_state.select_first();
+    }
+
+    fn select_queue_entry(&mut self, next: bool) {
+        let len = self.queue_len();
+        let Some(idx) = self.queue_state.selected() else {
+            return;
+        };
+        if len == 0 {
+            return;
+        }
+
+        let new_idx = if next {
+            (idx + 1) % len
+        } else {
+            (idx + len - 1) % len
+        };
+
+        if self.queue_moving {
+            // The current song can't be moved, neither can other songs be moved before it
+            if new_idx == 0 || (next && new_idx < idx) || (!next && new_idx > idx) {
+                return;
+            }
+
+            let Some(pending_idx) = self.detach_queue_entry(idx.min(new_idx)) else {
+                return;
+            };
+            self.pending_queue.swap(pending_idx, pending_idx + 1);
+            self.fill_song_queue();
+        }
+
+        self.queue_state.select(Some(new_idx));
+    }
+
+    fn fix_queue_after_song_insertion(&mut self, playlist_idx: usize, idx: usize) {
+        for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+            if let QueuedFrom::Playlist(queued_playlist_idx, song_idx) = queued.from {
+                if queued_playlist_idx == playlist_idx && song_idx >= idx {
+                    queued.from = QueuedFrom::Playlist(playlist_idx, song_idx + 1);
+                }
+            }
+        }
+
+        if let Playing::Playlist(playing_playlist_idx, playing_idx) = self.playing {
+            if playing_playlist_idx == playlist_idx && playing_idx >= idx {
+                self.playing = Playing::Playlist(playlist_idx, playing_idx + 1);
+            }
+        }
+
+        if let Some((cursor_playlist_idx, cursor_idx)) = self.playlist_cursor {
+            if cursor_playlist_idx == playlist_idx && cursor_idx >= idx {
+                self.playlist_cursor = Some((playlist_idx, cursor_idx + 1));
+            }
+        }
+    }
+
+    fn fix_queue_after_song_removal(&mut self, playlist_idx: usize, idx: usize) {
+        for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+            if let QueuedFrom::Playlist(queued_playlist_idx, song_idx) = queued.from {
+                if queued_playlist_idx != playlist_idx {
+                    continue;
+                }
+
+                if song_idx == idx {
+                    queued.from = QueuedFrom::Manual;
+                } else if song_idx > idx {
+                    queued.from = QueuedFrom::Playlist(playlist_idx, song_idx - 1);
+                }
+            }
+        }
+
+        if let Playing::Playlist(playing_playlist_idx, playing_idx) = self.playing {
+            if playing_playlist_idx == playlist_idx && playing_idx > idx {
+                self.playing = Playing::Playlist(playlist_idx, playing_idx - 1);
+            }
+        }
+
+        if let Some((cursor_playlist_idx, cursor_idx)) = self.playlist_cursor {
+            if cursor_playlist_idx == playlist_idx && cursor_idx > idx {
+                self.playlist_cursor = Some((playlist_idx, cursor_idx - 1));
+            }
+        }
+    }
+
+    fn move_item(&mut self) {
+        if self.focused == Focused::Left {
+            let idx = selected!(self, self.playlist_list_state);
+
+            if self.playlists[idx].selected == Selected::Moving {
+                self.playlists[idx].selected = Selected::Focused;
+            } else {
+                self.playlists[idx].selected = Selected::Moving;
+            }
+        } else {
+            match self.window {
+                Window::Songs => {
+                    let playlist_idx = selected!(self, self.playlist_list_state);
+                    let idx = selected!(self, self.song_list_state);
+
+                    if self.playlists[playlist_idx].songs[idx].selected == Selected::Moving {
+                        self.playlists[playlist_idx].songs[idx].selected = Selected::Focused;
+
+                        if let Some(from) = self.moving_from.take().filter(|&from| from != idx) {
+                            let name = self.playlists[playlist_idx].songs[idx].name.clone();
+                            self.journal(playlist_idx, PlaylistChange::Moved(name, from, idx));
+                        }
+                    } else {
+                        self.playlists[playlist_idx].songs[idx].selected = Selected::Moving;
+                        self.moving_from = Some(idx);
+                    }
+                }
+                Window::GlobalSongs => {
+                    let idx = selected!(self, self.global_song_list_state);
+
+                    if self.global_songs[idx].selected == Selected::Moving {
+                        self.global_songs[idx].selected = Selected::Focused;
+                    } else {
+                        self.global_songs[idx].selected = Selected::Moving;
+                    }
+                }
+                Window::Queue if self.queue_state.selected().unwrap_or(0) != 0 => {
+                    self.queue_moving = !self.queue_moving;
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // Returns false when there's no item being moved
+    fn move_selected_to(&mut self, new_idx: usize) -> bool {
+        if self.focused == Focused::Left {
+            move_to!(
+                self.playlists,
+                self.playlist_list_state,
+                self.save_data.playlists,
+                new_idx
+            );
+            return false;
+        }
+
+        match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                move_to!(
+                    self.playlists[playlist_idx].songs,
+                    self.song_list_state,
+                    self.save_data.playlists[playlist_idx].songs,
+                    new_idx
+                );
+            }
+            Window::GlobalSongs => {
+                move_to!(
+                    self.global_songs,
+                    self.global_song_list_state,
+                    self.save_data.songs,
+                    new_idx
+                );
+            }
+            _ => {}
+        }
+        false
+    }
+
+    fn fast_move(&mut self, new_idx: usize) {
+        if !self.move_selected_to(new_idx) {
+            self.log = String::from("Press m on an item to start moving it first");
+        }
+    }
+
+    fn enter_move_to_index(&mut self) {
+        let moving = match self.focused {
+            Focused::Left => self
+                .playlist_list_state
+                .selected()
+                .and_then(|idx| self.playlists.get(idx))
+                .is_some_and(|playlist| playlist.selected == Selected::Moving),
+            Focused::Right => self.is_moving_in_window(),
+        };
+
+        if moving {
+            self.enter_input_mode(InputMode::MoveToIndex);
+        } else if self.focused == Focused::Right && self.window == Window::Songs {
+            // Songs can also be given their new position straight away
+            let Some(idx) = self.song_list_state.selected().filter(|&idx| {
+                self.playlist_list_state
+                    .selected()
+                    .is_some_and(|playlist_idx| idx < self.playlists[playlist_idx].songs.len())
+            }) else {
+                self.log = String::from("Select a song first");
+                return;
+            };
+            self.text_area.insert_str((idx + 1).to_string());
+            self.enter_input_mode(InputMode::SongPosition);
+        } else {
+            self.log = String::from("Press m on an item to start moving it first");
+        }
+    }
+
+    fn move_song_to_position(&mut self, new_idx: usize) {
+        let playlist_idx = selected!(self, self.playlist_list_state);
+        let Some(idx) = self.song_list_state.selected() else {
+            return;
+        };
+        let len = self.playlists[playlist_idx].songs.len();
+        if idx >= len {
+            return;
+        }
+        let new_idx = new_idx.min(len - 1);
+        if new_idx == idx {
+            return;
+        }
+
+        let song = self.playlists[playlist_idx].songs.remove(idx);
+        self.playlists[playlist_idx].songs.insert(new_idx, song);
+        let name = self.save_data.playlists[playlist_idx].songs.remove(idx);
+        self.save_data.playlists[playlist_idx]
+            .songs
+            .insert(new_idx, name.clone());
+
+        self.song_list_state.select(Some(new_idx));
+        self.journal(playlist_idx, PlaylistChange::Moved(name, idx, new_idx));
+        self.fix_queue_after_song_move(playlist_idx, idx, new_idx);
+    }
+
+    fn fix_queue_after_song_move(&mut self, playlist_idx: usize, from: usize, to: usize) {
+        let moved = |idx: usize| {
+            if idx == from {
+                to
+            } else if from < idx && idx <= to {
+                idx - 1
+            } else if to <= idx && idx < from {
+                idx + 1
+            } else {
+                idx
+            }
+        };
+
+        for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+            if let QueuedFrom::Playlist(queued_playlist_idx, song_idx) = queued.from {
+                if queued_playlist_idx == playlist_idx {
+                    queued.from = QueuedFrom::Playlist(playlist_idx, moved(song_idx));
+                }
+            }
+        }
+
+        if let Playing::Playlist(playing_playlist_idx, playing_idx) = self.playing {
+            if playing_playlist_idx == playlist_idx {
+                self.playing = Playing::Playlist(playlist_idx, moved(playing_idx));
+            }
+        }
+
+        if let Some((cursor_playlist_idx, cursor_idx)) = self.playlist_cursor {
+            if cursor_playlist_idx == playlist_idx {
+                self.playlist_cursor = Some((playlist_idx, moved(cursor_idx)));
+            }
+        }
+    }
+
+    fn start_filter(&mut self) {
+        let moving = match self.focused {
+            Focused::Left => self
+                .playlist_list_state
+                .selected()
+                .and_then(|idx| self.playlists.get(idx))
+                .is_some_and(|playlist| playlist.selected == Selected::Moving),
+            Focused::Right => {
+                if !matches!(self.window, Window::Songs | Window::GlobalSongs) {
+                    return;
+                }
+                self.is_moving_in_window()
+            }
+        };
+
+        if moving {
+            self.log = String::from("Can't filter while moving an item");
+        } else {
+            self.enter_input_mode(InputMode::Filter);
+        }
+    }
+
+    fn filtered_list_selection(&self) -> Option<usize> {
+        match (&self.focused, &self.window) {
+            (Focused::Left, _) => self.playlist_list_state.selected(),
+            (Focused::Right, Window::Songs) => self.song_list_state.selected(),
+            (Focused::Right, Window::GlobalSongs) => self.global_song_list_state.selected(),
+            _ => None,
+        }
+    }
+
+    fn select_filter_match(&mut self, forward: bool) {
+        let matches = self.filter_matches();
+        if matches.is_empty() {
+            return;
+        }
+
+        let selected = self.filtered_list_selection();
+        let len = matches.len();
+        let new_pos = match matches.iter().position(|(idx, _)| Some(*idx) == selected) {
+            Some(pos) if forward => (pos + 1) % len,
+            Some(pos) => (pos + len - 1) % len,
+            None => 0,
+        };
+        self.select_filtered_item(matches[new_pos].0);
+    }
+
+    fn select_filtered_item(&mut self, idx: usize) {
+        if self.focused == Focused::Left {
+            self.save_song_list_state();
+            if let Some(playlist) = self
+                .playlist_list_state
+                .selected()
+                .and_then(|selected| self.playlists.get_mut(selected))
+            {
+                playlist.selected = Selected::None;
+            }
+            select!(self.playlists, self.playlist_list_state, idx);
+            self.see_songs_in_playlist();
+            return;
+        }
+
+        match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                let songs = &mut self.playlists[playlist_idx].songs;
+                if let Some(song) = self
+                    .song_list_state
+                    .selected()
+                    .and_then(|selected| songs.get_mut(selected))
+                {
+                    song.selected = Selected::None;
+                }
+                select!(songs, self.song_list_state, idx);
+            }
+            Window::GlobalSongs => {
+                if let Some(song) = self
+                    .global_song_list_state
+                    .selected()
+                    .and_then(|selected| self.global_songs.get_mut(selected))
+                {
+                    song.selected = Selected::None;
+                }
+                select!(self.global_songs, self.global_song_list_state, idx);
+            }
+            _ => {}
+        }
+    }
+
+    // Each call sorts by the next key, the new order is kept like a manual reorder
+    fn sort_songs(&mut self) {
+        if self.focused != Focused::Right
+            || !matches!(self.window, Window::Songs | Window::GlobalSongs)
+        {
+            return;
+        }
+        if self.is_moving_in_window() {
+            self.log = String::from("Can't sort while moving an item");
+            return;
+        }
+
+        let sort_by = self.sort_by.map_or(SortBy::Name, SortBy::next);
+        self.sort_by = Some(sort_by);
+        let playlist_idx = self.playlist_list_state.selected().unwrap_or(0);
+
+        let order = {
+            let songs: HashMap<&str, &SerializableSong> = self
+                .save_data
+                .songs
+                .iter()
+                .map(|song| (song.name.as_str(), song))
+                .collect();
+            let names: Vec<&str> = match self.window {
+                Window::Songs => match self.playlists.get(playlist_idx) {
+                    Some(playlist) => playlist
+                        .songs
+                        .iter()
+                        .map(|song| song.name.as_str())
+                        .collect(),
+                    None => return,
+                },
+                _ => self
+                    .global_songs
+                    .iter()
+                    .map(|song| song.name.as_str())
+                    .collect(),
+            };
+            let key = |name: &str| {
+                songs.get(name).map_or((0, 0, 0), |song| {
+                    (song.duration, song.added, song.play_count)
+                })
+            };
+
+            let mut order: Vec<usize> = (0..names.len()).collect();
+            order.sort_by(|&a, &b| {
+                let ((a_duration, a_added, a_plays), (b_duration, b_added, b_plays)) =
+                    (key(names[a]), key(names[b]));
+                let ordering = match sort_by {
+                    SortBy::Name => Ordering::Equal,
+                    SortBy::Duration => a_duration.cmp(&b_duration),
+                    SortBy::Added => a_added.cmp(&b_added),
+                    // Most played first
+                    SortBy::PlayCount => b_plays.cmp(&a_plays),
+                };
+                ordering.then_with(|| self.locale.compare(names[a], names[b]))
+            });
+            order
+        };
+
+        let mut new_idx = vec![0; order.len()];
+        for (pos, &old) in order.iter().enumerate() {
+            new_idx[old] = pos;
+        }
+        let remap = |idx: usize| new_idx.get(idx).copied().unwrap_or(idx);
+
+        if self.window == Window::Songs {
+            reorder(&mut self.playlists[playlist_idx].songs, &order);
+            self.save_data.playlists[playlist_idx].songs = self.playlists[playlist_idx]
+                .songs
+                .iter()
+                .map(|song| song.name.clone())
+                .collect();
+
+            if let Playing::Playlist(idx, song_idx) = self.playing {
+                if idx == playlist_idx {
+                    self.playing = Playing::Playlist(idx, remap(song_idx));
+                }
+            }
+            if let Some((idx, song_idx)) = self.playlist_cursor {
+                if idx == playlist_idx {
+                    self.playlist_cursor = Some((idx, remap(song_idx)));
+                }
+            }
+            for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+                if let QueuedFrom::Playlist(idx, song_idx) = queued.from {
+                    if idx == playlist_idx {
+                        queued.from = QueuedFrom::Playlist(idx, remap(song_idx));
+                    }
+                }
+            }
+            self.song_list_state
+                .select(self.song_list_state.selected().map(remap));
+        } else {
+            reorder(&mut self.global_songs, &order);
+            reorder(&mut self.save_data.songs, &order);
+
+            if let Playing::GlobalSong(idx) = self.playing {
+                self.playing = Playing::GlobalSong(remap(idx));
+            }
+            for idx in &mut self.global_order {
+                *idx = remap(*idx);
+            }
+            for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+                if let QueuedFrom::GlobalSong(idx) = queued.from {
+                    queued.from = QueuedFrom::GlobalSong(remap(idx));
+                }
+            }
+            self.global_song_list_state
+                .select(self.global_song_list_state.selected().map(remap));
+        }
+
+        let criterion = match sort_by {
+            SortBy::Name => "name",
+            SortBy::Duration => "duration",
+            SortBy::Added => "date added",
+            SortBy::PlayCount => "play count",
+        };
+        if self.window == Window::Songs {
+            self.journal(playlist_idx, PlaylistChange::Sorted(criterion.to_string()));
+        }
+        self.log = format!("Sorted by {criterion}");
+    }
+
+    fn toggle_repeat(&mut self) {
+        self.repeat = match self.repeat {
+            Repeat::None => Repeat::All,
+            Repeat::All => Repeat::One,
+            Repeat::One => Repeat::None,
+        };
+
+        self.save_data.last_repeat_mode = match self.repeat {
+            Repeat::None => 0,
+            Repeat::All => 1,
+            Repeat::One => 2,
+        };
+        self.announce(String::from(match self.repeat {
+            Repeat::None => "Repeat off",
+            Repeat::All => "Repeat all",
+            Repeat::One => "Repeat one",
+        }));
+    }
+
+    fn select_left_window(&mut self) {
+        if self.focused == Focused::Left {
+            self.see_songs_in_playlist();
+            return;
+        }
+
+        if self.window == Window::Queue && self.queue_moving {
+            self.log = String::from("Can't change windows while moving an item");
+            return;
+        }
+
+        let playlist_idx = selected!(self, self.playlist_list_state);
+
+        self.playlists[playlist_idx].selected = Selected::Focused;
+        self.focused = Focused::Left;
+
+        match self.window {
+            Window::Songs => {
+                if let Some(song) = self
+                    .song_list_state
+                    .selected()
+                    .and_then(|idx| self.playlists[playlist_idx].songs.get_mut(idx))
+                {
+                    moving_warning!(song, self.log);
+                    song.selected = Selected::Unfocused;
+                }
+            }
+            Window::GlobalSongs => {
+                if let Some(song) = self
+                    .global_song_list_state
+                    .selected()
+                    .and_then(|idx| self.global_songs.get_mut(idx))
+                {
+                    moving_warning!(song, self.log);
+                    song.selected = Selected::Unfocused;
+                }
+            }
+            Window::DownloadManager => {}
+            Window::Queue
+            | Window::Cleanup
+            | Window::Integrity
+            | Window::History
+            | Window::Journal
+            | Window::Skipped
+            | Window::PartyQueue
+            | Window::Removed
+            | Window::DownloadHistory
+            | Window::PlaylistSync
+            | Window::Log => {}
+            Window::ConfigurationMenu => {
+                if let Some(field) = self
+                    .config_menu_state
+                    .selected()
+                    .and_then(|idx| self.config.field_mut(idx))
+                {
+                    field.selected = Selected::Unfocused;
+                }
+            }
+        }
+    }
+
+    fn select_right_window(&mut self) {
+        if self.focused == Focused::Right {
+            return;
+        }
+
+        let playlist_idx = selected!(self, self.playlist_list_state);
+        moving_warning!(self.playlists[playlist_idx], self.log);
+
+        self.playlists[playlist_idx].selected = Selected::Unfocused;
+        self.focused = Focused::Right;
+
+        match self.window {
+            Window::Songs => {
+                for (i, song) in self.playlists[playlist_idx].songs.iter_mut().enumerate() {
+                    if song.selected == Selected::Unfocused {
+                        self.song_list_state.select(Some(i));
+                        song.selected = Selected::Focused;
+                        return;
+                    }
+                }
+                // If couldn't find a song with Selected::Unfocused, select first
+                if let Some(song) = self.playlists[playlist_idx].songs.first_mut() {
+                    song.selected = Selected::Focused;
+                }
+            }
+            Window::GlobalSongs => {
+                if let Some(song) = self
+                    .global_song_list_state
+                    .selected()
+                    .and_then(|idx| self.global_songs.get_mut(idx))
+                {
+                    song.selected = Selected::Focused;
+                }
+            }
+            Window::DownloadManager => {}
+            Window::Queue
+            | Window::Cleanup
+            | Window::Integrity
+            | Window::History
+            | Window::Journal
+            | Window::Skipped
+            | Window::PartyQueue
+            | Window::Removed
+            | Window::DownloadHistory
+            | Window::PlaylistSync
+            | Window::Log => {}
+            Window::ConfigurationMenu => {
+                if let Some(field) = self
+                    .config_menu_state
+                    .selected()
+                    .and_then(|idx| self.config.field_mut(idx))
+                {
+                    field.selected = Selected::Focused;
+                }
+            }
+        }
+    }
+
+    fn seek_back(&mut self) {
+        if !self.song_queue.is_empty() {
+            self.seek(self.sink.get_pos().saturating_sub(Duration::from_secs(5)));
+        }
+    }
+
+    fn seek_forward(&mut self) {
+        if !self.song_queue.is_empty() {
+            self.seek(self.sink.get_pos() + Duration::from_secs(5));
+        }
+    }
+
+    fn seek(&mut self, pos: Duration) {
+        if let Err(err) = self.sink.try_seek(pos) {
+            self.show_error(AppError::Seek(err.to_string()));
+        }
+    }
+
+    fn pause(&mut self) {
+        if self.sink.is_paused() {
+            self.sink.play();
+            self.announce(String::from("Playing"));
+        } else {
+            self.sink.pause();
+            self.announce(String::from("Paused"));
+        }
+        self.write_now_playing();
+    }
+
+    // The log line is what screen readers pick up, so state changes that are otherwise only
+    // shown with symbols get spelled out there
+    fn announce(&mut self, text: String) {
+        if self.save_data.screen_reader {
+            self.log = text;
+        }
+    }
+
+    fn notify_track_change(&self, title: &str) {
+        let notification = match self.save_data.track_notification {
+            1 => String::from("\x07"),
+            2 => format!("\x1b]9;Now playing: {title}\x07"),
+            _ => return,
+        };
+
+        let mut stdout = io::stdout();
+        let _ = stdout.write_all(notification.as_bytes());
+        let _ = stdout.flush();
+    }
+
+    fn help(&mut self) {
+        if self.mode == Mode::Help {
+            self.mode = Mode::Normal;
+        } else {
+            self.mode = Mode::Help;
+        }
+    }
+
+    fn open_recently_removed(&mut self) {
+        if self.save_data.removed.is_empty() {
+            self.log = String::from("No songs were removed in the last 30 days");
+            return;
+        }
+        self.removed_state.select_first();
+        self.switch_window(Window::Removed);
+    }
+
+    // Index into save_data.removed, which is shown newest first
+    fn selected_removed_song(&self) -> Option<usize> {
+        let len = self.save_data.removed.len();
+        self.removed_state
+            .selected()
+            .filter(|&idx| idx < len)
+            .map(|idx| len - 1 - idx)
+    }
+
+    fn restore_removed_song(&mut self) {
+        let Some(idx) = self.selected_removed_song() else {
+            return;
+        };
+        let name = &self.save_data.removed[idx].song.name;
+        if self.save_data.songs.iter().any(|song| &song.name == name) {
+            self.log = format!("There's already a song named {name} in the library");
+            return;
+        }
+
+        let RemovedSong {
+            song, positions, ..
+        } = self.save_data.removed.remove(idx);
+        let restored = Song {
+            selected: Selected::None,
+            name: song.name.clone(),
+            path: song.path.clone(),
+            playing: false,
+            favorite: song.favorite,
+            duration: song.duration,
+        };
+        self.load_tags(&song.path);
+        self.global_songs.push(restored.clone());
+        self.save_data.songs.push(song);
+
+        // Put back in reverse, so every position is the same as when the song was taken out
+        for (playlist_name, position) in positions.into_iter().rev() {
+            let Some(playlist_idx) = self
+                .playlists
+                .iter()
+                .position(|playlist| playlist.name == playlist_name)
+            else {
+                continue;
+            };
+            let position = position.min(self.playlists[playlist_idx].songs.len());
+            self.playlists[playlist_idx]
+                .songs
+                .insert(position, restored.clone());
+            self.save_data.playlists[playlist_idx]
+                .songs
+                .insert(position, restored.name.clone());
+            self.journal(
+                playlist_idx,
+                PlaylistChange::Added(restored.name.clone(), position),
+            );
+            self.fix_queue_after_song_insertion(playlist_idx, position);
+        }
+
+        self.log = format!("Restored {}", restored.name);
+        clamp_selection(&mut self.removed_state, self.save_data.removed.len());
+    }
+
+    fn show_changelog(&mut self) {
+        if self.update.is_some() {
+            self.mode = Mode::Changelog;
+        } else if self.save_data.check_for_updates {
+            self.log = String::from("No update was found");
+        } else {
+            self.log = String::from("Turn on update checks in the configuration menu first");
+        }
+    }
+
+    fn show_notes(&mut self) {
+        if self.focused == Focused::Left {
+            self.edit_description();
+            return;
+        }
+        if let Some(song) = self.selected_song() {
+            self.mode = Mode::Notes(song.name.clone());
+        }
+    }
+
+    // Name of the focused playlist, playlist song or global song
+    fn rename_target(&self) -> Option<String> {
+        if self.focused == Focused::Left {
+            return self
+                .playlist_list_state
+                .selected()
+                .and_then(|idx| self.playlists.get(idx))
+                .map(|playlist| playlist.name.clone());
+        }
+
+        let song = match self.window {
+            Window::Songs => self
+                .playlist_list_state
+                .selected()
+                .and_then(|idx| self.playlists.get(idx))
+                .zip(self.song_list_state.selected())
+                .and_then(|(playlist, idx)| playlist.songs.get(idx)),
+            Window::GlobalSongs => self
+                .global_song_list_state
+                .selected()
+                .and_then(|idx| self.global_songs.get(idx)),
+            _ => None,
+        };
+        song.map(|song| song.name.clone())
+    }
+
+    fn start_rename(&mut self) {
+        if self.is_moving_in_window() {
+            self.log = String::from("Can't rename while moving an item");
+            return;
+        }
+        let Some(name) = self.rename_target() else {
+            self.log = String::from("Select a playlist or a song to rename");
+            return;
+        };
+
+        self.text_area.insert_str(name);
+        self.enter_input_mode(InputMode::Rename);
+    }
+
+    fn start_clone_playlist(&mut self) {
+        let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.playlists.get(idx))
+        else {
+            self.log = String::from("Select a playlist first");
+            return;
+        };
+
+        self.text_area
+            .insert_str(format!("{} (copy)", playlist.name));
+        self.enter_input_mode(InputMode::ClonePlaylist);
+    }
+
+    // The copy goes to the end, so indices held by the queue and history stay valid
+    fn clone_playlist(&mut self, name: String) {
+        let idx = selected!(self, self.playlist_list_state);
+        let songs = self.save_data.playlists[idx].songs.clone();
+        let original = self.playlists[idx].name.clone();
+
+        self.save_data.playlists.push(SerializablePlaylist {
+            name: name.clone(),
+            songs,
+            description: self.save_data.playlists[idx].description.clone(),
+            audio_format: self.save_data.playlists[idx].audio_format,
+            journal: Vec::new(),
+            spotify_id: String::new(),
+        });
+        self.playlists.push(Playlist {
+            songs: self.playlists[idx]
+                .songs
+                .iter()
+                .map(|song| Song {
+                    selected: Selected::None,
+                    playing: false,
+                    ..song.clone()
+                })
+                .collect(),
+            song_list_state: ListState::default().with_selected(Some(0)),
+            selected: Selected::None,
+            playing: false,
+            name: name.clone(),
+        });
+
+        let new_idx = self.playlists.len() - 1;
+        self.journal(new_idx, PlaylistChange::Created);
+        self.save_song_list_state();
+        self.playlists[idx].selected = Selected::None;
+        select!(self.playlists, self.playlist_list_state, new_idx);
+        self.see_songs_in_playlist();
+        self.log = format!("Copied {original} to {name}");
+    }
+
+    fn cycle_audio_format(&mut self) {
+        let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.save_data.playlists.get_mut(idx))
+        else {
+            self.log = String::from("Select a playlist first");
+            return;
+        };
+
+        playlist.audio_format = playlist.audio_format.next();
+        self.log = format!(
+            "Songs downloaded into {} will be {}",
+            playlist.name,
+            playlist.audio_format.name()
+        );
+    }
+
+    fn start_export(&mut self) {
+        let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.playlists.get(idx))
+        else {
+            self.log = String::from("Select a playlist first");
+            return;
+        };
+
+        let path = get_quefi_dir()
+            .join("exports")
+            .join(format!("{}.xspf", make_safe_filename(&playlist.name)));
+        self.text_area.insert_str(path.to_string_lossy());
+        self.enter_input_mode(InputMode::ExportPlaylist);
+    }
+
+    fn export_playlist(&mut self, path: &Path) {
+        let playlist = &self.playlists[selected!(self, self.playlist_list_state)];
+        let format = playlist_io::Format::from_path(path).unwrap();
+
+        // Other players resolve relative paths against the playlist file, not quefi's directory
+        let paths: Vec<String> = playlist
+            .songs
+            .iter()
+            .map(|song| {
+                std::path::absolute(&song.path)
+                    .map(|path| path.to_string_lossy().into_owned())
+                    .unwrap_or_else(|_| song.path.clone())
+            })
+            .collect();
+        let entries: Vec<Entry> = playlist
+            .songs
+            .iter()
+            .zip(&paths)
+            .map(|(song, path)| Entry {
+                title: &song.name,
+                path,
+                duration: song.duration,
+            })
+            .collect();
+        let contents = format.serialize(&playlist.name, &entries);
+
+        let result = match path.parent() {
+            Some(parent) => fs::create_dir_all(parent),
+            None => Ok(()),
+        }
+        .and_then(|_| fs::write(path, contents));
+        self.log = match result {
+            Ok(()) => format!("Exported {} to {}", playlist.name, path.display()),
+            Err(err) => format!("Could not export {}: {err}", playlist.name),
+        };
+    }
+
+    // Everything that refers to the song by name has to follow
+    fn rename_song(&mut self, old_name: &str, new_name: &str) {
+        for song in &mut self.save_data.songs {
+            if song.name == old_name {
+                song.name = new_name.to_string();
+            }
+        }
+        for playlist in &mut self.save_data.playlists {
+            for name in &mut playlist.songs {
+                if name == old_name {
+                    *name = new_name.to_string();
+                }
+            }
+        }
+        for entry in &mut self.save_data.history {
+            if entry.name == old_name {
+                entry.name = new_name.to_string();
+            }
+        }
+
+        let songs = self
+            .playlists
+            .iter_mut()
+            .flat_map(|playlist| &mut playlist.songs)
+            .chain(&mut self.global_songs);
+        for song in songs.filter(|song| song.name == old_name) {
+            song.name = new_name.to_string();
+        }
+        for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+            if queued.name == old_name {
+                queued.name = new_name.to_string();
+            }
+        }
+    }
+
+    fn edit_description(&mut self) {
+        let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.save_data.playlists.get(idx))
+        else {
+            return;
+        };
+
+        self.text_area.insert_str(&playlist.description);
+        self.enter_input_mode(InputMode::PlaylistDescription);
+    }
+
+    fn edit_notes(&mut self) {
+        let Mode::Notes(name) = &self.mode else {
+            return;
+        };
+        let name = name.clone();
+
+        self.text_area = TextArea::from(self.song_notes(&name).lines());
+        self.text_area.move_cursor(CursorMove::Bottom);
+        self.text_area.move_cursor(CursorMove::End);
+        self.enter_input_mode(InputMode::Notes(name));
+    }
+
+    fn stats(&mut self) {
+        if self.mode == Mode::Stats {
+            self.mode = Mode::Normal;
+        } else {
+            self.mode = Mode::Stats;
+        }
+    }
+
+    fn update_session_stats(&mut self) {
+        let now = Instant::now();
+        if !self.sink.is_paused() && !self.song_queue.is_empty() {
+            self.session_stats.listening_time += now - self.session_stats.last_tick;
+        }
+        self.session_stats.last_tick = now;
+    }
+
+    fn see_songs_in_playlist(&mut self) {
+        self.window = Window::Songs;
+
+        let playlist_idx = selected!(self, self.playlist_list_state);
+        if let Some(playlist) = self.playlists.get(playlist_idx) {
+            self.song_list_state = playlist.song_list_state.clone();
+            clamp_selection(&mut self.song_list_state, playlist.songs.len());
+        } else {
+            self.song_list_state.select_first();
+        }
+    }
+
+    fn save_song_list_state(&mut self) {
+        let playlist_idx = selected!(self, self.playlist_list_state);
+        if let Some(playlist) = self.playlists.get_mut(playlist_idx) {
+            playlist.song_list_state = self.song_list_state.clone();
+        }
+    }
+
+    fn set_window_selected(&mut self, selected: Selected) {
+        match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                if let Some(song) = self.playlists.get_mut(playlist_idx).and_then(|playlist| {
+                    playlist
+                        .songs
+                        .get_mut(self.song_list_state.selected().unwrap_or(0))
+                }) {
+                    song.selected = selected;
+                }
+            }
+            Window::GlobalSongs => {
+                let idx = self.global_song_list_state.selected().unwrap_or(0);
+                if let Some(song) = self.global_songs.get_mut(idx) {
+                    song.selected = selected;
+                }
+            }
+            Window::ConfigurationMenu => {
+                if let Some(field) = self
+                    .config_menu_state
+                    .selected()
+                    .and_then(|idx| self.config.field_mut(idx))
+                {
+                    field.selected = selected;
+                }
+            }
+            Window::DownloadManager
+            | Window::Queue
+            | Window::Cleanup
+            | Window::Integrity
+            | Window::History
+            | Window::Journal
+            | Window::Skipped
+            | Window::PartyQueue
+            | Window::Removed
+            | Window::DownloadHistory
+            | Window::PlaylistSync
+            | Window::Log => {}
+        }
+    }
+
+    fn is_moving_in_window(&self) -> bool {
+        match self.window {
+            Window::Songs => self
+                .playlist_list_state
+                .selected()
+                .and_then(|playlist_idx| self.playlists.get(playlist_idx))
+                .and_then(|playlist| {
+                    playlist
+                        .songs
+                        .get(self.song_list_state.selected().unwrap_or(0))
+                })
+                .is_some_and(|song| song.selected == Selected::Moving),
+            Window::GlobalSongs => self
+                .global_songs
+                .get(self.global_song_list_state.selected().unwrap_or(0))
+                .is_some_and(|song| song.selected == Selected::Moving),
+            Window::Queue => self.queue_moving,
+            Window::DownloadManager
+            | Window::ConfigurationMenu
+            | Window::Cleanup
+            | Window::Integrity
+            | Window::History
+            | Window::Journal
+            | Window::Skipped
+            | Window::PartyQueue
+            | Window::Removed
+            | Window::DownloadHistory
+            | Window::PlaylistSync
+            | Window::Log => false,
+        }
+    }
+
+    fn switch_window(&mut self, window: Window) {
+        if self.window == window {
+            return;
+        }
+
+        if self.focused == Focused::Right && self.is_moving_in_window() {
+            self.log = String::from("Can't change windows while moving an item");
+            return;
+        }
+
+        // Keep the selection of the window we're leaving, so it's there when we come back
+        self.set_window_selected(Selected::Unfocused);
+        self.window = window;
+
+        match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                let len = self
+                    .playlists
+                    .get(playlist_idx)
+                    .map_or(0, |playlist| playlist.songs.len());
+                clamp_selection(&mut self.song_list_state, len);
+            }
+            Window::GlobalSongs => {
+                clamp_selection(&mut self.global_song_list_state, self.global_songs.len())
+            }
+            Window::DownloadManager => {
+                clamp_selection(&mut self.download_state, self.downloads.len())
+            }
+            Window::Queue => clamp_selection(
+                &mut self.queue_state,
+                self.song_queue.len() + self.pending_queue.len(),
+            ),
+            Window::ConfigurationMenu => {
+                if self.dlp_version.is_none() {
+                    self.check_dlp_version();
+                }
+            }
+            Window::Cleanup => clamp_selection(&mut self.cleanup_state, self.cleanup_report.len()),
+            Window::Integrity => {
+                clamp_selection(&mut self.integrity_state, self.integrity_report.len())
+            }
+            Window::History => {
+                clamp_selection(&mut self.history_state, self.save_data.history.len())
+            }
+            Window::Skipped => {
+                let len = self.frequently_skipped().len();
+                clamp_selection(&mut self.skipped_state, len)
+            }
+            Window::PartyQueue => clamp_selection(&mut self.party_state, self.party_requests.len()),
+            Window::Removed => {
+                clamp_selection(&mut self.removed_state, self.save_data.removed.len())
+            }
+            Window::DownloadHistory => clamp_selection(
+                &mut self.download_history_state,
+                self.save_data.download_history.len(),
+            ),
+            Window::Log => clamp_selection(&mut self.log_state, self.log_history.len()),
+            Window::PlaylistSync => {
+                let len = self.playlist_sync_changes();
+                clamp_selection(&mut self.playlist_sync_state, len)
+            }
+            Window::Journal => {
+                let len = self
+                    .playlist_list_state
+                    .selected()
+                    .and_then(|idx| self.save_data.playlists.get(idx))
+                    .map_or(0, |playlist| playlist.journal.len());
+                clamp_selection(&mut self.journal_state, len)
+            }
+        }
+
+        if self.focused == Focused::Right {
+            self.set_window_selected(Selected::Focused);
+        } else {
+            self.set_window_selected(Selected::Unfocused);
+        }
+    }
+
+    fn increase_volume(&mut self) {
+        let new_volume = self.sink.volume() + 0.05;
+        if new_volume >= 5.05 {
+            self.log = String::from("Volume can't be above 500%");
+        } else {
+            self.sink.set_volume(new_volume);
+            self.save_data.last_volume = new_volume;
+            self.announce(format!("Volume {:.0}%", new_volume * 100.));
+        }
+    }
+
+    fn decrease_volume(&mut self) {
+        let new_volume = self.sink.volume() - 0.05;
+        if new_volume < 0. {
+            self.log = String::from("Volume can't be negative");
+        } else {
+            self.sink.set_volume(new_volume);
+            self.save_data.last_volume = new_volume;
+            self.announce(format!("Volume {:.0}%", new_volume * 100.));
+        }
+    }
+
+    fn validate_input(&mut self) {
+        match self.mode {
+            Mode::Input(InputMode::AddPlaylist | InputMode::ClonePlaylist) => {
+                let text = self.text_area.lines()[0].trim();
+                let mut name_exists = false;
+                for playlist in &self.save_data.playlists {
+                    if playlist.name == text {
+                        name_exists = true;
+                        break;
+                    }
+                }
+
+                let bad_input = if text.is_empty() {
+                    String::from("Playlist name cannot be empty")
+                } else if name_exists {
+                    String::from("Playlist name cannot be same as existing playlist's name")
+                } else if text.len() > 64 {
+                    String::from("Playlist name cannot be longer than 64 characters")
+                } else {
+                    String::new()
+                };
+
+                let title = if self.mode == Mode::Input(InputMode::ClonePlaylist) {
+                    "Input name of the copy"
+                } else {
+                    "Input playlist name"
+                };
+                self.textarea_condition(
+                    !text.is_empty() && !name_exists && text.len() <= 64,
+                    String::from(title),
+                    bad_input,
+                );
+            }
+            Mode::Input(InputMode::AddSongToPlaylist) => {
+                let text = self.text_area.lines()[0].trim();
+                let mut name_exists = false;
+                for song in &self.save_data.songs {
+                    if song.name == text {
+                        name_exists = true;
+                        break;
+                    }
+                }
+
+                self.textarea_condition(
+                    name_exists,
+                    String::from("Input song name"),
+                    String::from("Song doesn't exist"),
+                );
+            }
+            Mode::Input(InputMode::AddGlobalSong) => {
+                let text = self.text_area.lines()[0].trim();
+                let mut name_exists = false;
+                for song in &self.save_data.songs {
+                    if song.name == text {
+                        name_exists = true;
+                        break;
+                    }
+                }
+
+                let bad_input = if text.is_empty() {
+                    String::from("Song name cannot be empty")
+                } else if name_exists {
+                    String::from("Song name cannot be same as existing song's name")
                 } else if text.len() > 64 {
                     String::from("Song name cannot be longer than 64 characters")
                 } else {
-                    String::new()
+                    String::new()
+                };
+
+                self.textarea_condition(
+                    !text.is_empty() && !name_exists && text.len() <= 64,
+                    String::from("Input song name"),
+                    bad_input,
+                );
+            }
+            Mode::Input(InputMode::ChooseFile(_) | InputMode::RelocateSong(_)) => {
+                let path = Path::new(&self.text_area.lines()[0]);
+                // TODO: Symlinks???
+                self.textarea_condition(
+                    path.exists() && path.is_file() && is_audio_file(path),
+                    String::from("Input file path"),
+                    String::from("File path is not pointing to an audio file"),
+                )
+            }
+            Mode::Input(InputMode::ReplaceSong(_)) => self.textarea_condition(
+                !self.text_area.lines()[0].trim().is_empty(),
+                String::from("YouTube link or what to search for instead"),
+                String::from("Can't be empty"),
+            ),
+            Mode::Input(InputMode::SpotifySearch) => self.textarea_condition(
+                !self.text_area.lines()[0].trim().is_empty(),
+                String::from("Search Spotify for tracks, albums and playlists"),
+                String::from("Type something to search for"),
+            ),
+            Mode::Input(InputMode::StreamLink) => self.textarea_condition(
+                super::is_valid_youtube_link(&link::normalize(&self.text_area.lines()[0])),
+                String::from("Input YouTube link to stream"),
+                String::from("Invalid YouTube link"),
+            ),
+            Mode::Input(InputMode::DownloadLink) => {
+                let link = link::normalize(&self.text_area.lines()[0]);
+                self.textarea_condition(
+                    super::is_valid_youtube_link(&link)
+                        || super::is_valid_youtube_playlist_link(&link)
+                        || super::is_valid_youtube_album_link(&link)
+                        || super::is_valid_metadata_link(&link)
+                        || validate_spotify_link(&link) != SpotifyLink::Invalid,
+                    String::from("Input Spotify/YouTube/Bandcamp/SoundCloud link"),
+                    String::from("Invalid Spotify/YouTube/Bandcamp/SoundCloud link"),
+                )
+            }
+            Mode::Input(InputMode::Filter) => {
+                let matches = self.filter_matches();
+                let selected = self.filtered_list_selection();
+                if let Some((first, _)) = matches.first() {
+                    if !matches.iter().any(|(idx, _)| Some(*idx) == selected) {
+                        self.select_filtered_item(*first);
+                    }
+                }
+
+                self.textarea_condition(
+                    !matches.is_empty(),
+                    String::from("Filter (up/down to pick a match)"),
+                    String::from("No matches"),
+                )
+            }
+            Mode::Input(InputMode::MoveToIndex | InputMode::SongPosition) => self
+                .textarea_condition(
+                    self.text_area.lines()[0]
+                        .parse::<usize>()
+                        .is_ok_and(|idx| idx >= 1),
+                    String::from("Move to position"),
+                    String::from("Not a position"),
+                ),
+            Mode::Input(InputMode::ExportFailures) => self.textarea_condition(
+                !self.text_area.lines()[0].trim().is_empty(),
+                String::from("Export failed downloads to"),
+                String::from("Input a file path"),
+            ),
+            Mode::Input(InputMode::ExportPlaylist) => self.textarea_condition(
+                playlist_io::Format::from_path(Path::new(&self.text_area.lines()[0])).is_some(),
+                String::from("Export playlist to"),
+                String::from("Use a .m3u, .xspf or .pls file"),
+            ),
+            Mode::Input(InputMode::ImportDirectory) => self.textarea_condition(
+                Path::new(&self.text_area.lines()[0]).is_dir(),
+                String::from("Input music directory to import"),
+                String::from("Not a directory"),
+            ),
+            Mode::Input(InputMode::ImportPlaylists(_)) => {
+                let text = &self.text_area.lines()[0].to_ascii_lowercase();
+                self.textarea_condition(
+                    text == "y" || text == "n",
+                    String::from("Create a playlist for every folder?"),
+                    String::from("Y/N only"),
+                )
+            }
+            Mode::Input(InputMode::GetDlp) => {
+                let text = &self.text_area.lines()[0].to_ascii_lowercase();
+                self.textarea_condition(
+                    text == "y" || text == "n",
+                    String::from("Download yt-dlp now?"),
+                    String::from("Y/N only"),
+                )
+            }
+            Mode::Input(InputMode::GetFfmpeg) => {
+                let text = &self.text_area.lines()[0].to_ascii_lowercase();
+                self.textarea_condition(
+                    text == "y" || text == "n",
+                    String::from("ffmpeg wasn't found, download it now?"),
+                    String::from("Y/N only"),
+                )
+            }
+            Mode::Input(InputMode::Rename) => {
+                let input = self.text_area.lines()[0].trim();
+                let (condition, bad_input) = if input.is_empty() {
+                    (false, "Name can't be empty")
+                } else if self.focused == Focused::Right
+                    && self.rename_target().as_deref() != Some(input)
+                    && self.save_data.songs.iter().any(|song| song.name == input)
+                {
+                    // Playlists refer to songs by name
+                    (false, "A song with this name already exists")
+                } else {
+                    (true, "")
+                };
+                let title = if self.focused == Focused::Left {
+                    "New playlist name"
+                } else {
+                    "New song name"
+                };
+                self.textarea_condition(condition, String::from(title), String::from(bad_input))
+            }
+            Mode::Input(InputMode::Notes(ref name)) => {
+                let title =
+                    format!("Notes for {name} (enter - new line, ctrl+s - save, esc - cancel)");
+                self.textarea_condition(true, title, String::new())
+            }
+            Mode::Input(InputMode::ResumeDownloads) => {
+                let text = &self.text_area.lines()[0].to_ascii_lowercase();
+                let pending = self.dlp.pending_downloads().len();
+                self.textarea_condition(
+                    text == "y" || text == "d" || text == "n",
+                    if pending > 0 {
+                        format!("Resume {pending} interrupted download(s)? (y - resume, d - delete them, n - ask next time)")
+                    } else {
+                        String::from("Delete files left by interrupted downloads? (d - delete, n - ask next time)")
+                    },
+                    String::from("Y/D/N only"),
+                )
+            }
+            Mode::Input(InputMode::DlpPath) => {
+                let path = Path::new(&self.text_area.lines()[0]);
+
+                #[cfg(target_os = "windows")]
+                let extension = "exe";
+
+                #[cfg(not(target_os = "windows"))]
+                let extension = "";
+
+                self.textarea_condition(
+                    path.exists()
+                        && path.is_file()
+                        && path.extension().unwrap_or_default() == extension,
+                    String::from("Input yt-dlp path"),
+                    String::from("File path is not pointing to a yt-dlp executable"),
+                )
+            }
+            Mode::Input(InputMode::SpotifyClientId) => self.textarea_condition(
+                self.text_area.lines()[0].len() == 32,
+                String::from("Input Spotify Client ID"),
+                String::from("Invalid Spotify Client ID"),
+            ),
+            Mode::Input(InputMode::NowPlayingPath) => {
+                let path = Path::new(&self.text_area.lines()[0]);
+                let parent = path.parent().unwrap_or(Path::new(""));
+                self.textarea_condition(
+                    path.as_os_str().is_empty()
+                        || ((parent.as_os_str().is_empty() || parent.is_dir()) && !path.is_dir()),
+                    String::from("File to write the current track to (empty - off)"),
+                    String::from("Directory of the file doesn't exist"),
+                )
+            }
+            Mode::Input(InputMode::DlpNiceness) => self.textarea_condition(
+                self.text_area.lines()[0]
+                    .parse::<u8>()
+                    .is_ok_and(|niceness| niceness <= 19),
+                String::from("Niceness of yt-dlp processes (0 - normal, 19 - lowest priority)"),
+                String::from("Not a number from 0 to 19"),
+            ),
+            Mode::Input(InputMode::MaxDlpProcesses) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<usize>().is_ok(),
+                String::from("Max yt-dlp processes at once (0 - no limit)"),
+                String::from("Not a number"),
+            ),
+            Mode::Input(InputMode::Cookies) => self.textarea_condition(
+                self.text_area.lines()[0].is_empty()
+                    || youtube::is_valid_cookies(&self.text_area.lines()[0]),
+                String::from("Browser like firefox or chrome:Profile 1, or a cookies.txt file (empty - none)"),
+                String::from("Not a supported browser or an existing file"),
+            ),
+            Mode::Input(InputMode::MusicDir) => self.textarea_condition(
+                self.text_area.lines()[0].is_empty()
+                    || Path::new(&self.text_area.lines()[0]).is_dir(),
+                String::from("Directory for downloaded songs (empty - quefi/songs)"),
+                String::from("Not a directory"),
+            ),
+            Mode::Input(InputMode::SpotifyMarket) => self.textarea_condition(
+                self.text_area.lines()[0].is_empty()
+                    || spotify::is_country_code(&self.text_area.lines()[0]),
+                String::from("Country code like US or PL, Spotify tracks are looked up there (empty - none)"),
+                String::from("Not a two-letter country code"),
+            ),
+            Mode::Input(InputMode::DownloadHook) => self.textarea_condition(
+                true,
+                String::from("Command run with the downloaded file's path (empty - nothing)"),
+                String::new(),
+            ),
+            Mode::Input(InputMode::FfmpegPath) => self.textarea_condition(
+                self.text_area.lines()[0].is_empty()
+                    || Path::new(&self.text_area.lines()[0]).is_file(),
+                String::from("Path to ffmpeg (empty - from PATH)"),
+                String::from("File doesn't exist"),
+            ),
+            Mode::Input(InputMode::MoveSongs(_)) => {
+                let text = &self.text_area.lines()[0].to_ascii_lowercase();
+                self.textarea_condition(
+                    text == "y" || text == "n",
+                    String::from("Move downloaded songs to the new directory?"),
+                    String::from("Y/N only"),
+                )
+            }
+            Mode::Input(InputMode::MaxParallelSongs) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<usize>().is_ok(),
+                String::from("Playlist songs downloaded at once (0 - no limit)"),
+                String::from("Not a number"),
+            ),
+            Mode::Input(InputMode::Language) => self.textarea_condition(
+                self.text_area.lines()[0].is_empty()
+                    || locale::parse(&self.text_area.lines()[0]).is_some(),
+                String::from("Language for sorting and numbers, like pl-PL (empty - system)"),
+                String::from("Not a valid language tag"),
+            ),
+            Mode::Input(InputMode::PlaylistDescription) => {
+                let len = self.text_area.lines()[0].chars().count();
+                self.textarea_condition(
+                    len <= MAX_DESCRIPTION_LENGTH,
+                    String::from("Describe the playlist (empty - no description)"),
+                    format!(
+                        "Description cannot be longer than {MAX_DESCRIPTION_LENGTH} characters"
+                    ),
+                )
+            }
+            Mode::Input(InputMode::StartupPlaylist) => {
+                let name = &self.text_area.lines()[0];
+                self.textarea_condition(
+                    name.is_empty() || self.playlists.iter().any(|playlist| &playlist.name == name),
+                    String::from("Playlist to start with (empty - the first one)"),
+                    String::from("Playlist doesn't exist"),
+                )
+            }
+            Mode::Input(InputMode::CacheSize) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<u64>().is_ok(),
+                String::from("Size limit of the cache in MB (0 - don't cache)"),
+                String::from("Not a number"),
+            ),
+            Mode::Input(InputMode::PartyPort) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<u16>().is_ok(),
+                String::from("Port for the party queue website (0 - off)"),
+                String::from("Not a port"),
+            ),
+            Mode::Input(InputMode::AutoSkipAfter) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<u32>().is_ok(),
+                String::from("Auto-skip songs skipped this many times (0 - never)"),
+                String::from("Not a number"),
+            ),
+            Mode::Input(InputMode::CleanupDays) => self.textarea_condition(
+                self.text_area.lines()[0].parse::<u32>().is_ok(),
+                String::from("Delete songs not played for this many days (0 - never)"),
+                String::from("Not a number"),
+            ),
+            Mode::Input(InputMode::SpotifyClientSecret) => self.textarea_condition(
+                self.text_area.lines()[0].len() == 32,
+                String::from("Input Spotify Client Secret"),
+                String::from("Invalid Spotify Client Secret"),
+            ),
+            _ => {
+                let mode = format!("{:?}", self.mode);
+                self.exit_input_mode();
+                self.show_error(AppError::NoInputHandler(mode));
+            }
+        }
+    }
+
+    fn textarea_condition(&mut self, condition: bool, title: String, bad_input: String) {
+        let title = match (self.save_data.high_contrast, condition) {
+            (true, true) => format!("✔ {title}"),
+            (true, false) => format!("✘ {title}"),
+            (false, _) => title,
+        };
+
+        if condition {
+            let block = Block::bordered()
+                .title(title)
+                .style(Style::default().light_green())
+                .border_set(border::THICK);
+            self.text_area.set_block(block);
+            self.valid_input = true;
+        } else {
+            let block = Block::bordered()
+                .title(title)
+                .title_bottom(bad_input)
+                .style(Style::default().light_red())
+                .border_set(border::THICK);
+            self.text_area.set_block(block);
+            self.valid_input = false;
+        }
+    }
+
+    async fn submit_input(&mut self) {
+        if !self.valid_input {
+            return;
+        }
+        self.log = String::from("Submitted input");
+        match &self.mode {
+            Mode::Input(InputMode::AddPlaylist) => {
+                let input = &self.text_area.lines()[0];
+                let was_empty = self.playlists.is_empty();
+
+                self.save_data.playlists.push(SerializablePlaylist {
+                    name: input.clone(),
+                    songs: Vec::new(),
+                    description: String::new(),
+                    audio_format: self.save_data.audio_format,
+                    journal: Vec::new(),
+                    spotify_id: String::new(),
+                });
+
+                self.playlists.push(Playlist {
+                    songs: Vec::new(),
+                    song_list_state: ListState::default().with_selected(Some(0)),
+                    selected: Selected::None,
+                    playing: false,
+                    name: input.clone(),
+                });
+                self.journal(self.save_data.playlists.len() - 1, PlaylistChange::Created);
+
+                if was_empty {
+                    select!(self.playlists, self.playlist_list_state, 0);
+                    self.see_songs_in_playlist();
+                }
+
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::ClonePlaylist) => {
+                let name = self.text_area.lines()[0].trim().to_string();
+                self.clone_playlist(name);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::AddSongToPlaylist) => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                let song_name = self.text_area.lines()[0].clone();
+                let was_empty = self.playlists[playlist_idx].songs.is_empty();
+
+                let mut song_path = String::new();
+                let mut favorite = false;
+                let mut duration = 0;
+                for song in &self.save_data.songs {
+                    if song.name == song_name {
+                        song_path = song.path.clone();
+                        favorite = song.favorite;
+                        duration = song.duration;
+                    }
+                }
+
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                let idx = if let Some(idx) = self.song_list_state.selected() {
+                    idx + 1
+                } else {
+                    0
+                };
+
+                self.save_data.playlists[playlist_idx]
+                    .songs
+                    .insert(idx, song_name.clone());
+                self.journal(playlist_idx, PlaylistChange::Added(song_name.clone(), idx));
+
+                self.playlists[playlist_idx].songs.insert(
+                    idx,
+                    Song {
+                        selected: Selected::None,
+                        name: song_name,
+                        path: song_path,
+                        playing: false,
+                        favorite,
+                        duration,
+                    },
+                );
+
+                if was_empty {
+                    select!(self.playlists[playlist_idx].songs, self.song_list_state, 0);
+                }
+
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::AddGlobalSong) => {
+                let input = self.text_area.lines()[0].clone();
+                self.text_area.move_cursor(CursorMove::Head);
+                self.text_area.delete_line_by_end();
+
+                self.mode = Mode::Input(InputMode::ChooseFile(input));
+                self.validate_input();
+            }
+            Mode::Input(InputMode::ChooseFile(song_name)) => {
+                let input = self.text_area.lines()[0].clone();
+                let was_empty = self.global_songs.is_empty();
+
+                if let Some(tags) = read_tags(&input) {
+                    self.song_tags.insert(input.clone(), tags);
+                }
+                let duration = probe_duration(&input);
+                self.global_songs.push(Song {
+                    selected: Selected::None,
+                    name: song_name.clone(),
+                    path: input.clone(),
+                    playing: false,
+                    favorite: false,
+                    duration,
+                });
+
+                self.save_data.songs.push(SerializableSong {
+                    name: song_name.clone(),
+                    duration,
+                    path: input,
+                    favorite: false,
+                    last_played: 0,
+                    url: String::new(),
+                    added: unix_timestamp(),
+                    play_count: 0,
+                    skips: 0,
+                    notes: String::new(),
+                });
+
+                if was_empty {
+                    select!(self.global_songs, self.global_song_list_state, 0);
+                }
+
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::RelocateSong(song_name)) => {
+                let song_name = song_name.clone();
+                let input = self.text_area.lines()[0].clone();
+                self.take_integrity_issue();
+                self.relocate_song(&song_name, input);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::ReplaceSong(song_name)) => {
+                let song_name = song_name.clone();
+                let input = self.text_area.lines()[0].trim().to_string();
+                self.exit_input_mode();
+                self.replace_song(song_name, &input);
+            }
+            Mode::Input(InputMode::DownloadLink) => {
+                let input = link::normalize(&self.text_area.lines()[0]);
+                self.download_link(&input);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::SpotifySearch) => {
+                let query = self.text_area.lines()[0].trim().to_string();
+                let id = self.new_download_id();
+                self.handle_link(id, SpotifyLink::Search(query));
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::StreamLink) => {
+                let url = link::normalize(&self.text_area.lines()[0]);
+                self.stream_link(url);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::Filter) => self.exit_input_mode(),
+            Mode::Input(InputMode::MoveToIndex) => {
+                let position = self.text_area.lines()[0].parse::<usize>().unwrap();
+                self.move_selected_to(position - 1);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::SongPosition) => {
+                let position = self.text_area.lines()[0].parse::<usize>().unwrap();
+                self.move_song_to_position(position - 1);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::ExportFailures) => {
+                let path = PathBuf::from(&self.text_area.lines()[0]);
+                self.export_failures(&path);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::ExportPlaylist) => {
+                let path = PathBuf::from(&self.text_area.lines()[0]);
+                self.export_playlist(&path);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::ImportDirectory) => {
+                let input = self.text_area.lines()[0].clone();
+                self.text_area.move_cursor(CursorMove::Head);
+                self.text_area.delete_line_by_end();
+
+                self.mode = Mode::Input(InputMode::ImportPlaylists(input));
+                self.validate_input();
+            }
+            Mode::Input(InputMode::ImportPlaylists(dir)) => {
+                let dir = dir.clone();
+                let create_playlists = self.text_area.lines()[0].eq_ignore_ascii_case("y");
+                self.import_directory(Path::new(&dir), create_playlists);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::GetDlp) => {
+                if &self.text_area.lines()[0] == "n" {
+                    self.exit_input_mode();
+                    return;
+                }
+
+                let client = self.client.clone();
+                self.spawn_task(async move { youtube::download_dlp(&client).await });
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::GetFfmpeg) => {
+                if self.text_area.lines()[0].eq_ignore_ascii_case("y") {
+                    let client = self.client.clone();
+                    self.spawn_task(async move { youtube::download_ffmpeg(&client).await });
+                }
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::FfmpegPath) => {
+                self.set_ffmpeg_path(self.text_area.lines()[0].clone());
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::SpotifyMarket) => {
+                let input = self.text_area.lines()[0].to_ascii_uppercase();
+                self.config.spotify_market.value = input.clone();
+                self.save_data.spotify_market = input;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::DownloadHook) => {
+                let input = self.text_area.lines()[0].trim().to_string();
+                self.config.download_hook.value = input.clone();
+                self.save_data.download_hook = input;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::Rename) => {
+                let new_name = self.text_area.lines()[0].trim().to_string();
+                if let Some(old_name) = self.rename_target() {
+                    if self.focused == Focused::Left {
+                        let idx = selected!(self, self.playlist_list_state);
+                        self.playlists[idx].name = new_name.clone();
+                        self.save_data.playlists[idx].name = new_name.clone();
+                        if self.save_data.startup_playlist == old_name {
+                            self.save_data.startup_playlist = new_name.clone();
+                            self.config.startup_playlist.value = new_name.clone();
+                        }
+                    } else {
+                        self.rename_song(&old_name, &new_name);
+                    }
+                    self.log = format!("Renamed {old_name} to {new_name}");
+                }
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::Notes(name)) => {
+                let name = name.clone();
+                let notes = self.text_area.lines().join("\n").trim_end().to_string();
+                if let Some(song) = self
+                    .save_data
+                    .songs
+                    .iter_mut()
+                    .find(|song| song.name == name)
+                {
+                    song.notes = notes;
+                }
+                self.exit_input_mode();
+                self.mode = Mode::Notes(name);
+            }
+            Mode::Input(InputMode::ResumeDownloads) => {
+                match self.text_area.lines()[0].to_ascii_lowercase().as_str() {
+                    "y" => self.resume_interrupted_downloads(),
+                    "d" => self.delete_interrupted_downloads(),
+                    _ => {}
+                }
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::DlpPath) => {
+                let input = self.text_area.lines()[0].clone();
+                self.set_dlp_path(input);
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::SpotifyClientId) => {
+                let input = self.text_area.lines()[0].clone();
+                self.config.spotify_client_id.value = input.clone();
+                self.save_data.spotify_client_id = input;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::NowPlayingPath) => {
+                let input = self.text_area.lines()[0].clone();
+                self.config.now_playing_path.value = input.clone();
+                self.save_data.now_playing_path = input;
+                self.write_now_playing();
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::DlpNiceness) => {
+                let niceness = self.text_area.lines()[0].parse::<u8>().unwrap();
+                self.config.dlp_niceness.value = niceness.to_string();
+                self.dlp.niceness = niceness;
+                self.save_data.dlp_niceness = niceness;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::MaxDlpProcesses) => {
+                let max = self.text_area.lines()[0].parse::<usize>().unwrap();
+                self.config.max_dlp_processes.value = max.to_string();
+                self.dlp.set_max_processes(max);
+                self.save_data.max_dlp_processes = max;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::Cookies) => {
+                let input = self.text_area.lines()[0].clone();
+                self.config.cookies.value = input.clone();
+                self.dlp.cookies = input.clone();
+                self.save_data.dlp_cookies = input;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::MusicDir) => {
+                let input = self.text_area.lines()[0].clone();
+                let old_dir = self.dlp.songs_dir.clone();
+                self.config.music_dir.value = input.clone();
+                self.dlp.songs_dir = songs_dir(&input);
+                self.save_data.music_dir = input;
+
+                let has_songs = self
+                    .save_data
+                    .songs
+                    .iter()
+                    .any(|song| Path::new(&song.path).starts_with(&old_dir));
+                if self.dlp.songs_dir != old_dir && has_songs {
+                    self.text_area.move_cursor(CursorMove::Head);
+                    self.text_area.delete_line_by_end();
+
+                    let old_dir = old_dir.to_string_lossy().to_string();
+                    self.mode = Mode::Input(InputMode::MoveSongs(old_dir));
+                    self.validate_input();
+                } else {
+                    self.exit_input_mode();
+                }
+            }
+            Mode::Input(InputMode::MoveSongs(old_dir)) => {
+                let old_dir = PathBuf::from(old_dir);
+                if self.text_area.lines()[0].eq_ignore_ascii_case("y") {
+                    self.move_songs(&old_dir);
+                }
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::MaxParallelSongs) => {
+                let max = self.text_area.lines()[0].parse::<usize>().unwrap();
+                self.config.max_parallel_songs.value = max.to_string();
+                // Songs that are already running or waiting keep the old limit
+                self.playlist_slots = Arc::new(Semaphore::new(youtube::max_permits(max)));
+                self.save_data.max_parallel_songs = max;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::Language) => {
+                let input = self.text_area.lines()[0].clone();
+                self.config.language.value = input.clone();
+                self.locale = Locale::new(&input);
+                self.save_data.language = input;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::PlaylistDescription) => {
+                let description = self.text_area.lines()[0].trim().to_string();
+                let idx = selected!(self, self.playlist_list_state);
+                self.save_data.playlists[idx].description = description;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::StartupPlaylist) => {
+                let name = self.text_area.lines()[0].clone();
+                self.config.startup_playlist.value = name.clone();
+                self.save_data.startup_playlist = name;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::CacheSize) => {
+                let size = self.text_area.lines()[0].parse::<u64>().unwrap();
+                self.config.cache_size.value = size.to_string();
+                self.save_data.cache_size = size;
+                self.cache.set_max_megabytes(size);
+                self.exit_input_mode();
+                self.trim_cache();
+            }
+            Mode::Input(InputMode::PartyPort) => {
+                let port = self.text_area.lines()[0].parse::<u16>().unwrap();
+                self.config.party_port.value = port.to_string();
+                self.save_data.party_port = port;
+                self.exit_input_mode();
+                self.start_party_server();
+            }
+            Mode::Input(InputMode::AutoSkipAfter) => {
+                let times = self.text_area.lines()[0].parse::<u32>().unwrap();
+                self.config.auto_skip_after.value = times.to_string();
+                self.save_data.auto_skip_after = times;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::CleanupDays) => {
+                let days = self.text_area.lines()[0].parse::<u32>().unwrap();
+                self.config.cleanup_days.value = days.to_string();
+                self.save_data.cleanup_days = days;
+                self.exit_input_mode();
+            }
+            Mode::Input(InputMode::SpotifyClientSecret) => {
+                let input = self.text_area.lines()[0].clone();
+                self.config.spotify_client_secret.value = input.clone();
+                self.save_data.spotify_client_secret = input;
+                self.text_area.clear_mask_char();
+                self.exit_input_mode();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn spotify_token_fresh(&self) -> bool {
+        !self.save_data.last_valid_token.is_empty()
+            && unix_timestamp() + TOKEN_EXPIRY_MARGIN < self.save_data.token_expires
+    }
+
+    // Shown in the configuration menu
+    pub(super) fn spotify_token_status(&self) -> String {
+        if self.save_data.last_valid_token.is_empty() {
+            return String::from("missing");
+        }
+
+        let now = unix_timestamp();
+        if now >= self.save_data.token_expires {
+            String::from("expired")
+        } else {
+            let minutes = (self.save_data.token_expires - now).div_ceil(60);
+            format!("valid, expires in {minutes}m")
+        }
+    }
+
+    fn handle_link(&mut self, download_id: DownloadId, link: SpotifyLink) {
+        if !self.spotify_token_fresh() {
+            self.recreate_spotify_token(download_id, link);
+            return;
+        }
+        let market = self.save_data.spotify_market.clone();
+
+        match link {
+            SpotifyLink::Playlist(id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingPlaylistInfo);
+                self.spawn_task(async move {
+                    fetch_playlist_info(download_id, &client, &id, &last_valid_token, &market).await
+                });
+            }
+            SpotifyLink::Sync(playlist_idx, id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingPlaylistInfo);
+                self.spawn_task(async move {
+                    spotify::fetch_synced_playlist(
+                        download_id,
+                        &client,
+                        playlist_idx,
+                        &id,
+                        &last_valid_token,
+                        &market,
+                    )
+                    .await
+                });
+            }
+            SpotifyLink::Album(id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingPlaylistInfo);
+                self.spawn_task(async move {
+                    spotify::fetch_album_info(download_id, &client, &id, &last_valid_token, &market)
+                        .await
+                });
+            }
+            SpotifyLink::Track(id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingTrackInfo);
+                self.spawn_task(async move {
+                    fetch_track_info(download_id, &client, &id, &last_valid_token, &market).await
+                });
+            }
+            SpotifyLink::Search(query) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::SearchingSpotify(query.clone()));
+                self.spawn_task(async move {
+                    spotify::search(download_id, &client, &query, &last_valid_token, &market).await
+                });
+            }
+            SpotifyLink::Episode(id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingTrackInfo);
+                self.spawn_task(async move {
+                    spotify::fetch_episode_info(
+                        download_id,
+                        &client,
+                        &id,
+                        &last_valid_token,
+                        &market,
+                    )
+                    .await
+                });
+            }
+            SpotifyLink::Show(id) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingPlaylistInfo);
+                self.spawn_task(async move {
+                    spotify::fetch_show_info(download_id, &client, &id, &last_valid_token, &market)
+                        .await
+                });
+            }
+            SpotifyLink::NewReleases(since) => {
+                let last_valid_token = self.save_data.last_valid_token.clone();
+                let client = self.client.clone();
+
+                self.downloads
+                    .insert(download_id, Download::FetchingNewReleases);
+                self.spawn_task(async move {
+                    fetch_new_releases(download_id, &client, &since, &last_valid_token, &market)
+                        .await
+                });
+            }
+            SpotifyLink::PlaylistPage(playlist_idx, url) => {
+                self.fetch_playlist_tracks(download_id, playlist_idx, url)
+            }
+            SpotifyLink::Invalid => unreachable!(),
+        }
+    }
+
+    // Returns the id the link is downloaded under
+    fn download_link(&mut self, url: &str) -> DownloadId {
+        let id = self.new_download_id();
+        self.downloads.insert(id, Download::Empty);
+
+        match validate_spotify_link(url) {
+            SpotifyLink::Invalid if super::is_valid_metadata_link(url) => {
+                self.fetch_link_metadata(id, url.to_string())
+            }
+            SpotifyLink::Invalid if super::is_valid_youtube_playlist_link(url) => {
+                self.fetch_youtube_playlist(id, url.to_string())
+            }
+            SpotifyLink::Invalid if super::is_valid_youtube_album_link(url) => {
+                self.fetch_youtube_album(id, url.to_string())
+            }
+            SpotifyLink::Invalid => self.download_youtube_link(id, url.to_string()),
+            link => self.handle_link(id, link),
+        }
+        id
+    }
+
+    fn fetch_link_metadata(&mut self, download_id: DownloadId, url: String) {
+        let dlp = self.dlp.clone();
+
+        self.downloads
+            .insert(download_id, Download::FetchingLinkMetadata);
+        self.spawn_task(async move { fetch_metadata(download_id, &dlp, &url).await });
+    }
+
+    fn fetch_youtube_playlist(&mut self, download_id: DownloadId, url: String) {
+        let dlp = self.dlp.clone();
+
+        self.downloads
+            .insert(download_id, Download::FetchingPlaylistInfo);
+        self.spawn_task(async move { fetch_playlist_entries(download_id, &dlp, &url).await });
+    }
+
+    fn fetch_youtube_album(&mut self, download_id: DownloadId, url: String) {
+        let client = self.client.clone();
+
+        self.downloads
+            .insert(download_id, Download::FetchingPlaylistInfo);
+        self.spawn_task(async move { fetch_album(download_id, &client, &url).await });
+    }
+
+    fn download_youtube_link(&mut self, download_id: DownloadId, url: String) {
+        let dlp = self.dlp.clone();
+        let name = String::from("Song from YT Link");
+        let download = PendingDownload {
+            name: name.clone(),
+            filename: make_safe_filename(&url),
+            link: url,
+            playlist: String::new(),
+            format: self.save_data.audio_format,
+        };
+
+        self.downloads
+            .insert(download_id, Download::DownloadingYoutubeSong);
+        self.spawn_download(async move {
+            download_song(
+                download_id,
+                &dlp,
+                download,
+                SearchFor::GlobalSong(name),
+                None,
+            )
+            .await
+        });
+    }
+
+    fn download_missing_songs(&mut self) {
+        if self.offline_blocked() {
+            return;
+        }
+        let Some(playlist_idx) = self.playlist_list_state.selected() else {
+            return;
+        };
+        if playlist_idx >= self.playlists.len() {
+            return;
+        }
+
+        let missing: Vec<String> = self.playlists[playlist_idx]
+            .songs
+            .iter()
+            .filter(|song| !Path::new(&song.path).exists())
+            .map(|song| song.name.clone())
+            .collect();
+
+        if missing.is_empty() {
+            self.log = String::from("No songs are missing from this playlist");
+            return;
+        }
+
+        let id = self.new_download_id();
+        self.downloads.insert(
+            id,
+            Download::ProcessingPlaylistSongs(ProcessingPlaylistSongs {
+                playlist_name: self.playlists[playlist_idx].name.clone(),
+                searching_songs: Vec::new(),
+                downloading_songs: Vec::new(),
+                total_to_search: missing.len(),
+                total_to_download: 0,
+                downloaded: 0,
+                searched: 0,
+                reused: 0,
+                failed: 0,
+                more_tracks: false,
+                to_tag: Vec::new(),
+            }),
+        );
+
+        for song_name in missing {
+            self.fetch_missing_song(id, song_name, true);
+        }
+    }
+
+    fn selected_song(&self) -> Option<&Song> {
+        match self.window {
+            Window::Songs => self
+                .playlist_list_state
+                .selected()
+                .and_then(|playlist_idx| {
+                    self.song_list_state
+                        .selected()
+                        .and_then(|idx| self.playlists.get(playlist_idx)?.songs.get(idx))
+                }),
+            Window::GlobalSongs => self
+                .global_song_list_state
+                .selected()
+                .and_then(|idx| self.global_songs.get(idx)),
+            _ => None,
+        }
+    }
+
+    // Returns whether there was a preview to stop
+    fn stop_preview(&mut self) -> bool {
+        let Some(preview) = self.preview.take() else {
+            return false;
+        };
+        preview.sink.stop();
+        if preview.resume {
+            self.sink.play();
+        }
+        true
+    }
+
+    // The queue is paused until the preview ends
+    fn start_preview(&mut self, sink: Sink) {
+        let resume = !self.sink.is_paused() && !self.sink.empty();
+        if resume {
+            self.sink.pause();
+        }
+        sink.set_volume(self.sink.volume());
+        self.preview = Some(Preview { sink, resume });
+    }
+
+    // Plays snippets from 25%, 50% and 75% of the song, pausing the main queue meanwhile
+    fn preview_selected_song(&mut self) {
+        if self.stop_preview() {
+            self.log = String::from("Stopped preview");
+            return;
+        }
+
+        // A preview decodes a second song next to the playing one
+        if self.save_data.low_memory {
+            self.log = String::from("Previews are off in low memory mode");
+            return;
+        }
+
+        let Some(song) = self.selected_song() else {
+            return;
+        };
+        let (name, path, known_duration) = (song.name.clone(), song.path.clone(), song.duration);
+        let Some(stream) = &self.stream else {
+            self.log = String::from("No audio output to preview on");
+            return;
+        };
+        let sink = Sink::connect_new(stream.mixer());
+
+        for percent in [25, 50, 75] {
+            let Ok(file) = File::open(&path) else {
+                self.log = format!("Failed to open {name}");
+                return;
+            };
+            let Ok(mut source) = Decoder::new(file) else {
+                self.log = format!("Failed to decode {name}");
+                return;
+            };
+
+            let duration = if known_duration > 0 {
+                Duration::from_secs(known_duration)
+            } else {
+                source.total_duration().unwrap_or(Duration::ZERO)
+            };
+            if duration.is_zero() {
+                self.log = format!("Duration of {name} isn't known, can't preview it");
+                return;
+            }
+
+            if source.try_seek(duration * percent / 100).is_err() {
+                self.log = format!("Failed to seek in {name}");
+                return;
+            }
+            sink.append(source.take_duration(PREVIEW_LENGTH));
+        }
+
+        self.start_preview(sink);
+        self.log = format!("Previewing {name}, press P to stop");
+    }
+
+    // Spotify's 30 second preview, to tell if it's the right track before downloading it
+    fn preview_spotify_track(&mut self, url: Option<String>) {
+        if self.stop_preview() {
+            self.log = String::from("Stopped preview");
+            return;
+        }
+        if self.save_data.low_memory {
+            self.log = String::from("Previews are off in low memory mode");
+            return;
+        }
+        let Some(url) = url else {
+            self.log = String::from("Spotify doesn't have a preview of this track");
+            return;
+        };
+
+        self.log = String::from("Fetching the Spotify preview...");
+        let client = self.client.clone();
+        self.spawn_task(async move { spotify::fetch_preview(&client, &url).await });
+    }
+
+    fn play_spotify_preview(&mut self, data: Vec<u8>) {
+        // Another preview could've been started while this one was fetched
+        if self.preview.is_some() {
+            return;
+        }
+        let Some(stream) = &self.stream else {
+            self.log = String::from("No audio output to preview on");
+            return;
+        };
+        let Ok(source) = Decoder::new(Cursor::new(data)) else {
+            self.log = String::from("Failed to decode the Spotify preview");
+            return;
+        };
+
+        let sink = Sink::connect_new(stream.mixer());
+        sink.append(source);
+        self.start_preview(sink);
+        self.log = String::from("Previewing on Spotify, press P to stop");
+    }
+
+    fn play_ui_sound(&mut self, sound: UiSound) {
+        if !self.save_data.ui_sounds {
+            return;
+        }
+        let Some(stream) = &self.stream else {
+            return;
+        };
+        let ui_sink = self
+            .ui_sink
+            .get_or_insert_with(|| Sink::connect_new(stream.mixer()));
+        ui_sink.set_volume(self.sink.volume() * UI_SOUND_GAIN);
+
+        // The fade keeps the tones from popping
+        let (frequency, length) = match sound {
+            UiSound::TrackChange => (1760.0, Duration::from_millis(30)),
+            UiSound::Error => (220.0, Duration::from_millis(200)),
+        };
+        ui_sink.append(
+            SineWave::new(frequency)
+                .take_duration(length)
+                .fade_in(Duration::from_millis(5)),
+        );
+    }
+
+    fn update_preview(&mut self) {
+        if !self
+            .preview
+            .as_ref()
+            .is_some_and(|preview| preview.sink.empty())
+        {
+            return;
+        }
+
+        if self.preview.take().is_some_and(|preview| preview.resume) {
+            self.sink.play();
+        }
+    }
+
+    fn repair_selected_song(&mut self) {
+        if self.offline_blocked() {
+            return;
+        }
+        let Some(song) = self.selected_song() else {
+            return;
+        };
+
+        if Path::new(&song.path).exists() {
+            self.log = format!("{} isn't missing", song.name);
+            return;
+        }
+
+        let song_name = song.name.clone();
+        self.log = format!("Re-downloading {song_name}");
+        self.redownload_song(song_name);
+    }
+
+    // For songs that got matched to the wrong video, the new one is downloaded in place of it
+    fn start_replacing_song(&mut self) {
+        if self.offline_blocked() {
+            return;
+        }
+        let Some(song) = self.selected_song() else {
+            return;
+        };
+
+        let song_name = song.name.clone();
+        self.text_area.insert_str(&song_name);
+        self.enter_input_mode(InputMode::ReplaceSong(song_name));
+    }
+
+    fn replace_song(&mut self, song_name: String, input: &str) {
+        let id = self.new_download_id();
+        self.replacing_songs.insert(song_name.clone());
+        self.log = format!("Replacing {song_name}");
+
+        let link = link::normalize(input);
+        if super::is_valid_youtube_link(&link) {
+            self.download_missing_song(id, song_name, link);
+            return;
+        }
+
+        self.downloads
+            .insert(id, Download::SearchingForSong(input.to_string()));
+        let client = self.client.clone();
+        let query = input.to_string();
+        self.spawn_download(async move {
+            search_ytmusic_choices(
+                id,
+                &client,
+                &query,
+                0,
+                SearchFilter::Songs,
+                SearchFor::MissingSong(song_name),
+            )
+            .await
+        });
+    }
+
+    // The cached search could point to a video that's gone, so this one searches again
+    fn redownload_song(&mut self, song_name: String) {
+        let id = self.new_download_id();
+        self.downloads
+            .insert(id, Download::SearchingForSong(song_name.clone()));
+        self.fetch_missing_song(id, song_name, false);
+    }
+
+    // Downloads from the stored URL, or searches for the song again if there isn't one
+    fn fetch_missing_song(&mut self, id: DownloadId, song_name: String, use_cache: bool) {
+        let (url, duration) = self
+            .save_data
+            .songs
+            .iter()
+            .find(|song| song.name == song_name)
+            .map(|song| (song.url.clone(), song.duration))
+            .unwrap_or_default();
+
+        if !url.is_empty() {
+            if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id)
+            {
+                processing.searched += 1;
+            }
+            self.download_missing_song(id, song_name, url);
+            return;
+        }
+
+        if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id) {
+            processing.searching_songs.push(song_name.clone());
+        }
+
+        let client = self.client.clone();
+        let cache = use_cache.then(|| self.cache.clone());
+        let query = song_name.clone();
+        self.spawn_download(async move {
+            // The length is known if the file was there once
+            let duration_ms = (duration * 1000) as u32;
+            search_ytmusic(
+                id,
+                &client,
+                cache.as_ref(),
+                &query,
+                duration_ms,
+                SearchFilter::Songs,
+                SearchFor::MissingSong(song_name),
+            )
+            .await
+        });
+    }
+
+    fn download_missing_song(&mut self, id: DownloadId, song_name: String, url: String) {
+        let filename = make_safe_filename(&song_name);
+        let format = self.save_data.audio_format;
+
+        // yt-dlp skips the download when the file is already there
+        if self.replacing_songs.remove(&song_name) {
+            let path = self
+                .dlp
+                .songs_dir
+                .join(format!("{}.{}", filename, format.extension()));
+            if let Err(err) = fs::remove_file(&path) {
+                if err.kind() != io::ErrorKind::NotFound {
+                    self.downloads.remove(&id);
+                    self.log = format!("Couldn't replace {song_name}: {err}");
+                    return;
+                }
+            }
+        }
+
+        if let Some(Download::ProcessingPlaylistSongs(processing)) = self.downloads.get_mut(&id) {
+            processing.downloading_songs.push(song_name.clone());
+            processing.total_to_download += 1;
+        } else {
+            self.downloads
+                .insert(id, Download::DownloadingSong(song_name.clone()));
+        }
+
+        let download = PendingDownload {
+            name: song_name.clone(),
+            filename,
+            link: url,
+            playlist: String::new(),
+            format,
+        };
+        let dlp = self.dlp.clone();
+
+        self.spawn_download(async move {
+            download_song(id, &dlp, download, SearchFor::MissingSong(song_name), None).await
+        });
+    }
+
+    // Songs that were being added to a playlist are put back into it once downloaded
+    fn resume_interrupted_downloads(&mut self) {
+        let pending = self.dlp.pending_downloads();
+        for file in youtube::partial_files(&self.dlp.songs_dir) {
+            let name = file.file_name().unwrap_or_default().to_string_lossy();
+            let is_resumed = pending
+                .iter()
+                .any(|download| name.starts_with(&format!("{}.", download.filename)));
+            if !is_resumed {
+                let _ = fs::remove_file(file);
+            }
+        }
+
+        for download in &pending {
+            let id = self.new_download_id();
+            if self
+                .save_data
+                .songs
+                .iter()
+                .any(|song| song.name == download.name)
+            {
+                self.download_missing_song(id, download.name.clone(), download.link.clone());
+                continue;
+            }
+
+            self.downloads
+                .insert(id, Download::DownloadingSong(download.name.clone()));
+            self.resumed_downloads.insert(id, download.clone());
+            let dlp = self.dlp.clone();
+            let download = download.clone();
+            self.spawn_download(async move {
+                let search_for = SearchFor::GlobalSong(download.name.clone());
+                download_song(id, &dlp, download, search_for, None).await
+            });
+        }
+        self.log = format!("Resuming {} download(s)", pending.len());
+    }
+
+    fn delete_interrupted_downloads(&mut self) {
+        let files = youtube::partial_files(&self.dlp.songs_dir);
+        let deleted = files
+            .iter()
+            .filter(|file| fs::remove_file(file).is_ok())
+            .count();
+        for download in self.dlp.pending_downloads() {
+            self.dlp.finish_pending(&download.filename);
+        }
+        self.log = format!("Deleted {deleted} file(s) left by interrupted downloads");
+    }
+
+    fn stream_link(&mut self, url: String) {
+        if let Some(data) = self.cache.get("streams", &url) {
+            self.streams.insert(url.clone(), data.into());
+            self.enqueue(
+                QueuedSong {
+                    from: QueuedFrom::Stream,
+                    name: format!("Stream: {url}"),
+                    path: url,
+                    duration: Duration::ZERO,
+                },
+                false,
+            );
+            return;
+        }
+
+        let id = self.new_download_id();
+        let dlp = self.dlp.clone();
+
+        self.downloads
+            .insert(id, Download::FetchingStream(url.clone()));
+        self.spawn_task(async move { stream_song(id, &dlp, &url).await });
+    }
+
+    pub(super) fn has_unsaved_changes(&self) -> bool {
+        self.checked_hash != self.saved_hash
+    }
+
+    // A crash or a kill only loses the changes of the last few seconds
+    pub(super) fn autosave(&mut self) {
+        if self.read_only || self.last_save_check.elapsed() < SAVE_CHECK_INTERVAL {
+            return;
+        }
+        self.last_save_check = Instant::now();
+
+        let hash = self.save_data.content_hash();
+        if hash != self.checked_hash {
+            self.checked_hash = hash;
+            self.changed_at = Instant::now();
+        } else if self.has_unsaved_changes() && self.changed_at.elapsed() >= AUTOSAVE_DELAY {
+            self.save();
+        }
+    }
+
+    // Returns whether it was saved
+    fn save(&mut self) -> bool {
+        match crate::save_data(&mut self.save_data) {
+            Ok(()) => {
+                self.saved_hash = self.save_data.content_hash();
+                self.checked_hash = self.saved_hash;
+                true
+            }
+            Err(err) => {
+                self.log = format!("Failed to save the library: {err}");
+                false
+            }
+        }
+    }
+
+    fn save_now(&mut self) {
+        if self.read_only {
+            self.log = String::from("Quefi is already running, changes made here won't be saved");
+        } else if self.save() {
+            self.log = String::from("Saved");
+        }
+    }
+
+    fn scan_inbox(&mut self) {
+        // Links stay in the inbox until quefi is started online
+        if self.read_only || self.offline || self.last_inbox_scan.elapsed() < INBOX_SCAN_INTERVAL {
+            return;
+        }
+        self.last_inbox_scan = Instant::now();
+
+        let inbox_dir = get_quefi_dir().join("inbox");
+        let entries = match fs::read_dir(&inbox_dir) {
+            Ok(entries) => entries,
+            Err(_) => return,
+        };
+
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let extension = path.extension().unwrap_or_default();
+            if !path.is_file() || (extension != "txt" && extension != "url") {
+                continue;
+            }
+
+            let contents = match fs::read_to_string(&path) {
+                Ok(contents) => contents,
+                Err(err) => {
+                    self.log = format!("Failed to read {}: {err}", path.display());
+                    continue;
+                }
+            };
+
+            let links = extract_links(&contents);
+            for link in &links {
+                self.download_link(link);
+            }
+
+            let processed_dir = inbox_dir.join("processed");
+            let moved = fs::create_dir_all(&processed_dir)
+                .and_then(|_| fs::rename(&path, processed_dir.join(entry.file_name())));
+
+            if let Err(err) = moved {
+                self.log = format!("Failed to move {} out of the inbox: {err}", path.display());
+            } else {
+                self.log = format!(
+                    "Queued {} link(s) from {}",
+                    links.len(),
+                    entry.file_name().to_string_lossy()
+                );
+            }
+        }
+    }
+
+    fn stop_playing_current(&mut self) {
+        match self.playing {
+            Playing::Playlist(idx, song_idx) if !self.playlists.is_empty() => {
+                self.playlists[idx].songs[song_idx].playing = false;
+                self.playlists[idx].playing = false;
+            }
+            Playing::GlobalSong(idx) if !self.global_songs.is_empty() => {
+                self.global_songs[idx].playing = false;
+            }
+            _ => {}
+        }
+        self.playing = Playing::None;
+        self.playlist_cursor = None;
+        self.global_cursor = None;
+        self.song_queue.clear();
+        self.sink.stop();
+        self.write_now_playing();
+    }
+
+    fn toggle_favorite(&mut self) {
+        if self.focused != Focused::Right {
+            return;
+        }
+
+        let name = match self.window {
+            Window::Songs => {
+                let playlist_idx = selected!(self, self.playlist_list_state);
+                self.song_list_state
+                    .selected()
+                    .and_then(|idx| self.playlists[playlist_idx].songs.get(idx))
+                    .map(|song| song.name.clone())
+            }
+            Window::GlobalSongs => self
+                .global_song_list_state
+                .selected()
+                .and_then(|idx| self.global_songs.get(idx))
+                .map(|song| song.name.clone()),
+            _ => None,
+        };
+
+        let Some(name) = name else {
+            return;
+        };
+
+        let Some(song) = self
+            .save_data
+            .songs
+            .iter_mut()
+            .find(|song| song.name == name)
+        else {
+            return;
+        };
+        song.favorite = !song.favorite;
+        let favorite = song.favorite;
+
+        let songs = self.global_songs.iter_mut().chain(
+            self.playlists
+                .iter_mut()
+                .flat_map(|playlist| &mut playlist.songs),
+        );
+        for song in songs.filter(|song| song.name == name) {
+            song.favorite = favorite;
+        }
+
+        self.log = if favorite {
+            format!("Added {name} to favorites")
+        } else {
+            format!("Removed {name} from favorites")
+        };
+    }
+
+    fn cleanup_candidates(&self) -> Vec<CleanupCandidate> {
+        if self.save_data.cleanup_days == 0 {
+            return Vec::new();
+        }
+
+        let songs_dir = &self.dlp.songs_dir;
+        let now = unix_timestamp();
+
+        self.save_data
+            .songs
+            .iter()
+            .filter(|song| !song.favorite && Path::new(&song.path).starts_with(songs_dir))
+            .filter_map(|song| {
+                // Songs that were never played count from the time they were downloaded
+                let last_played = if song.last_played != 0 {
+                    song.last_played
+                } else {
+                    fs::metadata(&song.path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()?
+                        .duration_since(UNIX_EPOCH)
+                        .ok()?
+                        .as_secs()
                 };
 
-                self.textarea_condition(
-                    !text.is_empty() && !name_exists && text.len() <= 64,
-                    String::from("Input song name"),
-                    bad_input,
-                );
-            }
-            Mode::Input(InputMode::ChooseFile(_)) => {
-                let path = Path::new(&self.text_area.lines()[0]);
-                // TODO: Symlinks??? More file formats???
-                self.textarea_condition(
-                    path.exists()
-                        && path.is_file()
-                        && path.extension().unwrap_or_default() == "mp3",
-                    String::from("Input file path"),
-                    String::from("File path is not pointing to a mp3 file"),
-                )
-            }
-            Mode::Input(InputMode::DownloadLink) => self.textarea_condition(
-                super::is_valid_youtube_link(&self.text_area.lines()[0])
-                    || validate_spotify_link(&self.text_area.lines()[0]) != SpotifyLink::Invalid,
-                String::from("Input Spotify/YouTube link"),
-                String::from("Invalid Spotify/YouTube link"),
-            ),
-            Mode::Input(InputMode::GetDlp) => {
-                let text = &self.text_area.lines()[0].to_ascii_lowercase();
-                self.textarea_condition(
-                    text == "y" || text == "n",
-                    String::from("Download yt-dlp now?"),
-                    String::from("Y/N only"),
-                )
+                let days = now.saturating_sub(last_played) / 86_400;
+                (days >= self.save_data.cleanup_days as u64).then(|| CleanupCandidate {
+                    name: song.name.clone(),
+                    path: song.path.clone(),
+                    days,
+                })
+            })
+            .collect()
+    }
+
+    fn journal(&mut self, playlist_idx: usize, change: PlaylistChange) {
+        if let Some(playlist) = self.save_data.playlists.get_mut(playlist_idx) {
+            playlist.journal.push(JournalEntry {
+                change,
+                time: unix_timestamp(),
+            });
+            let overflow = playlist.journal.len().saturating_sub(JOURNAL_LENGTH);
+            playlist.journal.drain(..overflow);
+        }
+    }
+
+    fn open_journal(&mut self) {
+        let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.save_data.playlists.get(idx))
+        else {
+            self.log = String::from("Select a playlist first");
+            return;
+        };
+        if self.is_moving_in_window() {
+            self.log = String::from("Can't change windows while moving an item");
+            return;
+        }
+        if playlist.journal.is_empty() {
+            self.log = format!("No changes to {} were recorded yet", playlist.name);
+            return;
+        }
+
+        self.journal_state.select_first();
+        self.switch_window(Window::Journal);
+    }
+
+    fn open_frequently_skipped(&mut self) {
+        if self.frequently_skipped().is_empty() {
+            self.log = String::from("No songs were skipped yet");
+            return;
+        }
+
+        self.skipped_state.select_first();
+        self.switch_window(Window::Skipped);
+    }
+
+    fn selected_skipped_song(&self) -> Option<String> {
+        self.skipped_state.selected().and_then(|idx| {
+            self.frequently_skipped()
+                .get(idx)
+                .map(|song| song.name.clone())
+        })
+    }
+
+    fn play_skipped_song(&mut self) {
+        let Some(name) = self.selected_skipped_song() else {
+            return;
+        };
+
+        if let Some(idx) = self.global_songs.iter().position(|song| song.name == name) {
+            self.stop_playing_current();
+            self.play_global_song(idx);
+        }
+    }
+
+    fn forgive_skips(&mut self) {
+        let Some(name) = self.selected_skipped_song() else {
+            return;
+        };
+
+        if let Some(song) = self
+            .save_data
+            .songs
+            .iter_mut()
+            .find(|song| song.name == name)
+        {
+            song.skips = 0;
+        }
+        let len = self.frequently_skipped().len();
+        clamp_selection(&mut self.skipped_state, len);
+        self.log = format!("Reset skips of {name}");
+    }
+
+    fn open_history(&mut self) {
+        if self.save_data.history.is_empty() {
+            self.log = String::from("Nothing was played yet");
+            return;
+        }
+
+        self.history_state.select_first();
+        self.switch_window(Window::History);
+    }
+
+    fn play_history_entry(&mut self) {
+        let Some(name) = self
+            .history_state
+            .selected()
+            .and_then(|idx| self.save_data.history.iter().rev().nth(idx))
+            .map(|entry| entry.name.clone())
+        else {
+            return;
+        };
+
+        match self.global_songs.iter().position(|song| song.name == name) {
+            Some(idx) => {
+                self.stop_playing_current();
+                self.play_global_song(idx);
             }
-            Mode::Input(InputMode::DlpPath) => {
-                let path = Path::new(&self.text_area.lines()[0]);
+            None => self.log = format!("{name} is no longer in the library"),
+        }
+    }
 
-                #[cfg(target_os = "windows")]
-                let extension = "exe";
+    fn open_download_history(&mut self) {
+        if self.save_data.download_history.is_empty() {
+            self.log = String::from("Nothing was downloaded yet");
+            return;
+        }
 
-                #[cfg(not(target_os = "windows"))]
-                let extension = "";
+        self.download_history_state.select_first();
+        self.switch_window(Window::DownloadHistory);
+    }
 
-                self.textarea_condition(
-                    path.exists()
-                        && path.is_file()
-                        && path.extension().unwrap_or_default() == extension,
-                    String::from("Input yt-dlp path"),
-                    String::from("File path is not pointing to a yt-dlp executable"),
-                )
+    fn play_downloaded_song(&mut self) {
+        let Some(path) = self
+            .download_history_state
+            .selected()
+            .and_then(|idx| self.save_data.download_history.iter().rev().nth(idx))
+            .map(|record| record.path.clone())
+        else {
+            return;
+        };
+
+        match self.global_songs.iter().position(|song| song.path == path) {
+            Some(idx) => {
+                self.stop_playing_current();
+                self.play_global_song(idx);
             }
-            Mode::Input(InputMode::SpotifyClientId) => self.textarea_condition(
-                self.text_area.lines()[0].len() == 32,
-                String::from("Input Spotify Client ID"),
-                String::from("Invalid Spotify Client ID"),
-            ),
-            Mode::Input(InputMode::SpotifyClientSecret) => self.textarea_condition(
-                self.text_area.lines()[0].len() == 32,
-                String::from("Input Spotify Client Secret"),
-                String::from("Invalid Spotify Client Secret"),
-            ),
-            _ => panic!("No input handler implemented for {:?}", self.mode),
+            None => self.log = format!("{path} is no longer in the library"),
         }
     }
 
-    fn textarea_condition(&mut self, condition: bool, title: String, bad_input: String) {
-        if condition {
-            let block = Block::bordered()
-                .title(title)
-                .style(Style::default().light_green())
-                .border_set(border::THICK);
-            self.text_area.set_block(block);
-            self.valid_input = true;
-        } else {
-            let block = Block::bordered()
-                .title(title)
-                .title_bottom(bad_input)
-                .style(Style::default().light_red())
-                .border_set(border::THICK);
-            self.text_area.set_block(block);
-            self.valid_input = false;
+    // Counts towards the session stats and keeps it in the download history
+    pub(super) fn record_download(&mut self, link: &str, path: &str) {
+        let size = file_size(path);
+        self.session_stats.downloaded_bytes += size;
+        self.save_data.download_history.push(DownloadRecord {
+            link: link.to_string(),
+            path: path.to_string(),
+            downloaded: unix_timestamp(),
+            size,
+        });
+
+        let overflow = self
+            .save_data
+            .download_history
+            .len()
+            .saturating_sub(HISTORY_LENGTH);
+        self.save_data.download_history.drain(..overflow);
+
+        if !self.save_data.download_hook.is_empty() {
+            let command = self.save_data.download_hook.clone();
+            let path = path.to_string();
+            self.spawn_task(async move { hook::run_download_hook(command, path).await });
         }
     }
 
-    async fn submit_input(&mut self) {
-        if !self.valid_input {
+    fn open_cleanup_report(&mut self) {
+        if self.save_data.cleanup_days == 0 {
+            self.log = String::from("Set the cleanup period in the configuration menu first");
             return;
         }
-        self.log = String::from("Submitted input");
-        match &self.mode {
-            Mode::Input(InputMode::AddPlaylist) => {
-                let input = &self.text_area.lines()[0];
-                let was_empty = self.playlists.is_empty();
 
-                self.save_data.playlists.push(SerializablePlaylist {
-                    name: input.clone(),
-                    songs: Vec::new(),
-                });
+        self.cleanup_report = self.cleanup_candidates();
+        self.cleanup_state.select_first();
+        self.switch_window(Window::Cleanup);
+    }
 
-                self.playlists.push(Playlist {
-                    songs: Vec::new(),
-                    selected: Selected::None,
-                    playing: false,
-                    name: input.clone(),
-                });
+    fn delete_cleanup_candidates(&mut self) {
+        let mut deleted = 0;
+        let mut freed = 0;
 
-                if was_empty {
-                    select!(self.playlists, self.playlist_list_state, 0);
-                    self.see_songs_in_playlist();
+        for candidate in &self.cleanup_report {
+            let size = file_size(&candidate.path);
+            match fs::remove_file(&candidate.path) {
+                Ok(()) => {
+                    deleted += 1;
+                    freed += size;
                 }
-
-                self.exit_input_mode();
+                Err(err) => self.log = format!("Failed to delete {}: {err}", candidate.name),
             }
-            Mode::Input(InputMode::AddSongToPlaylist) => {
-                let playlist_idx = self.playlist_list_state.selected().unwrap();
-                let song_name = self.text_area.lines()[0].clone();
-                let was_empty = self.playlists[playlist_idx].songs.is_empty();
+        }
 
-                let mut song_path = String::new();
-                for song in &self.save_data.songs {
-                    if song.name == song_name {
-                        song_path = song.path.clone();
-                    }
-                }
+        if deleted > 0 {
+            self.log = format!("Deleted {deleted} song(s), freed {} MiB", freed / 1_048_576);
+        }
+        self.cleanup_report.clear();
+    }
 
-                let playlist_idx = self.playlist_list_state.selected().unwrap();
-                let idx = if let Some(idx) = self.song_list_state.selected() {
-                    idx + 1
-                } else {
-                    0
+    fn check_integrity(&mut self) {
+        self.integrity_report = self
+            .save_data
+            .songs
+            .iter()
+            .filter_map(|song| {
+                let problem = match File::open(&song.path) {
+                    Ok(file) => match Decoder::new(file) {
+                        Ok(_) => return None,
+                        Err(err) => IntegrityProblem::Undecodable(err.to_string()),
+                    },
+                    Err(_) => IntegrityProblem::Missing,
                 };
 
-                self.save_data.playlists[playlist_idx]
-                    .songs
-                    .insert(idx, song_name.clone());
+                Some(IntegrityIssue {
+                    name: song.name.clone(),
+                    path: song.path.clone(),
+                    problem,
+                })
+            })
+            .collect();
+
+        self.log = format!(
+            "Found {} problem(s) in the library",
+            self.integrity_report.len()
+        );
+        self.integrity_state.select_first();
+        self.switch_window(Window::Integrity);
+    }
 
-                self.playlists[playlist_idx].songs.insert(
-                    idx,
-                    Song {
-                        selected: Selected::None,
-                        name: song_name,
-                        path: song_path,
-                        playing: false,
-                    },
+    fn take_integrity_issue(&mut self) -> Option<IntegrityIssue> {
+        let idx = self.integrity_state.selected()?;
+        if idx >= self.integrity_report.len() {
+            return None;
+        }
+
+        let issue = self.integrity_report.remove(idx);
+        clamp_selection(&mut self.integrity_state, self.integrity_report.len());
+        Some(issue)
+    }
+
+    // Takes the song out of the library and every playlist it's in
+    fn remove_song_from_library(&mut self, song_name: &str) {
+        let mut positions = Vec::new();
+        for playlist_idx in 0..self.playlists.len() {
+            while let Some(idx) = self.playlists[playlist_idx]
+                .songs
+                .iter()
+                .position(|song| song.name == song_name)
+            {
+                positions.push((self.playlists[playlist_idx].name.clone(), idx));
+                self.playlists[playlist_idx].songs.remove(idx);
+                self.save_data.playlists[playlist_idx].songs.remove(idx);
+                self.journal(
+                    playlist_idx,
+                    PlaylistChange::Removed(song_name.to_string(), idx),
                 );
+                self.fix_queue_after_song_removal(playlist_idx, idx);
+            }
+        }
 
-                if was_empty {
-                    select!(self.playlists[playlist_idx].songs, self.song_list_state, 0);
-                }
+        if let Some(idx) = self
+            .global_songs
+            .iter()
+            .position(|song| song.name == song_name)
+        {
+            self.remove_global_song(idx, positions);
+        }
+    }
 
-                self.exit_input_mode();
+    // The song stays restorable from the recently removed window, along with the
+    // playlist positions it was taken out of
+    fn remove_global_song(&mut self, idx: usize, positions: Vec<(String, usize)>) {
+        self.global_songs.remove(idx);
+        let song = self.save_data.songs.remove(idx);
+        self.save_data.removed.push(RemovedSong {
+            song,
+            positions,
+            removed: unix_timestamp(),
+        });
+
+        if let Playing::GlobalSong(playing_idx) = self.playing {
+            if playing_idx == idx {
+                self.playing = Playing::None;
             }
-            Mode::Input(InputMode::AddGlobalSong) => {
-                let input = self.text_area.lines()[0].clone();
-                self.text_area.move_cursor(CursorMove::Head);
-                self.text_area.delete_line_by_end();
+        }
+        self.fix_queue_after_global_song_removal(idx);
+    }
 
-                self.mode = Mode::Input(InputMode::ChooseFile(input));
-                self.validate_input();
+    fn set_song_duration(&mut self, song_name: &str, duration: u64) {
+        if let Some(song) = self
+            .save_data
+            .songs
+            .iter_mut()
+            .find(|song| song.name == song_name)
+        {
+            song.duration = duration;
+        }
+
+        let songs = self.global_songs.iter_mut().chain(
+            self.playlists
+                .iter_mut()
+                .flat_map(|playlist| &mut playlist.songs),
+        );
+        for song in songs.filter(|song| song.name == song_name) {
+            song.duration = duration;
+        }
+    }
+
+    fn relocate_song(&mut self, song_name: &str, path: String) {
+        if let Some(song) = self
+            .save_data
+            .songs
+            .iter_mut()
+            .find(|song| song.name == song_name)
+        {
+            song.path = path.clone();
+        }
+        self.set_song_duration(song_name, probe_duration(&path));
+
+        let songs = self.global_songs.iter_mut().chain(
+            self.playlists
+                .iter_mut()
+                .flat_map(|playlist| &mut playlist.songs),
+        );
+        for song in songs.filter(|song| song.name == song_name) {
+            song.path = path.clone();
+        }
+        self.load_tags(&path);
+        self.log = format!("Relocated {song_name}");
+    }
+
+    // Moves songs downloaded into the previous music directory to the current one
+    pub(super) fn move_songs(&mut self, old_dir: &Path) {
+        let mut moved = 0;
+        let mut failed = 0;
+
+        for idx in 0..self.save_data.songs.len() {
+            let old_path = self.save_data.songs[idx].path.clone();
+            let Ok(relative) = Path::new(&old_path).strip_prefix(old_dir) else {
+                continue;
+            };
+            let new_path = self.dlp.songs_dir.join(relative);
+            if move_file(Path::new(&old_path), &new_path).is_err() {
+                failed += 1;
+                continue;
             }
-            Mode::Input(InputMode::ChooseFile(song_name)) => {
-                let input = self.text_area.lines()[0].clone();
-                let was_empty = self.global_songs.is_empty();
 
+            let new_path = new_path.to_string_lossy().to_string();
+            self.save_data.songs[idx].path = new_path.clone();
+            let songs = self.global_songs.iter_mut().chain(
+                self.playlists
+                    .iter_mut()
+                    .flat_map(|playlist| &mut playlist.songs),
+            );
+            for song in songs.filter(|song| song.path == old_path) {
+                song.path = new_path.clone();
+            }
+            for record in &mut self.save_data.download_history {
+                if record.path == old_path {
+                    record.path = new_path.clone();
+                }
+            }
+            if let Some(tags) = self.song_tags.remove(&old_path) {
+                self.song_tags.insert(new_path, tags);
+            }
+            moved += 1;
+        }
+
+        self.log = if failed == 0 {
+            format!("Moved {moved} songs")
+        } else {
+            format!("Moved {moved} songs, {failed} couldn't be moved")
+        };
+    }
+
+    fn import_directory(&mut self, dir: &Path, create_playlists: bool) {
+        let mut found = Vec::new();
+        find_audio_files(dir, &mut found, &self.locale);
+
+        let was_empty = self.global_songs.is_empty();
+        let mut imported = 0;
+
+        for (folder, files) in found {
+            let mut playlist_songs = Vec::new();
+
+            for file in files {
+                let path = file.to_string_lossy().to_string();
+
+                // Importing the same folder twice shouldn't duplicate songs
+                if let Some(song) = self.save_data.songs.iter().find(|song| song.path == path) {
+                    playlist_songs.push(song.name.clone());
+                    continue;
+                }
+
+                let tags = read_tags(&path);
+                let stem = file.file_stem().unwrap_or_default().to_string_lossy();
+                let base_name = match &tags {
+                    Some(tags) => tags.label(&stem),
+                    None => stem.to_string(),
+                };
+
+                // Songs are looked up by name, so it has to be unique
+                let mut name = base_name.clone();
+                let mut copy = 2;
+                while self.save_data.songs.iter().any(|song| song.name == name) {
+                    name = format!("{base_name} ({copy})");
+                    copy += 1;
+                }
+
+                if let Some(tags) = tags {
+                    self.song_tags.insert(path.clone(), tags);
+                }
+                let duration = probe_duration(&path);
+                self.save_data.songs.push(SerializableSong {
+                    name: name.clone(),
+                    path: path.clone(),
+                    favorite: false,
+                    last_played: 0,
+                    url: String::new(),
+                    added: unix_timestamp(),
+                    play_count: 0,
+                    skips: 0,
+                    notes: String::new(),
+                    duration,
+                });
                 self.global_songs.push(Song {
                     selected: Selected::None,
-                    name: song_name.clone(),
-                    path: input.clone(),
+                    name: name.clone(),
+                    path,
                     playing: false,
+                    favorite: false,
+                    duration,
                 });
 
-                self.save_data.songs.push(SerializableSong {
-                    name: song_name.clone(),
-                    path: input,
-                });
-
-                if was_empty {
-                    select!(self.global_songs, self.global_song_list_state, 0);
-                }
-
-                self.exit_input_mode();
+                playlist_songs.push(name);
+                imported += 1;
             }
-            Mode::Input(InputMode::DownloadLink) => {
-                let link = validate_spotify_link(&self.text_area.lines()[0]);
-                let id = self.downloads.len() as u8;
 
-                self.downloads.insert(id, Download::Empty);
-                self.handle_link(id, link);
-                self.exit_input_mode();
+            if create_playlists {
+                let playlist_name = match folder.strip_prefix(dir) {
+                    Ok(relative) if !relative.as_os_str().is_empty() => {
+                        relative.to_string_lossy().to_string()
+                    }
+                    _ => dir
+                        .file_name()
+                        .unwrap_or(dir.as_os_str())
+                        .to_string_lossy()
+                        .to_string(),
+                };
+                self.add_imported_playlist(playlist_name, playlist_songs);
             }
-            Mode::Input(InputMode::GetDlp) => {
-                if &self.text_area.lines()[0] == "n" {
-                    self.exit_input_mode();
-                    return;
-                }
+        }
 
-                let client = self.client.clone();
-                self.join_handles.push(tokio::spawn(async move {
-                    youtube::download_dlp(&client).await
-                }));
-                self.exit_input_mode();
-            }
-            Mode::Input(InputMode::DlpPath) => {
-                let input = self.text_area.lines()[0].clone();
-                self.config.dlp_path.value = input.clone();
-                self.save_data.dlp_path = input;
-                self.exit_input_mode();
-            }
-            Mode::Input(InputMode::SpotifyClientId) => {
-                let input = self.text_area.lines()[0].clone();
-                self.config.spotify_client_id.value = input.clone();
-                self.save_data.spotify_client_id = input;
-                self.exit_input_mode();
-            }
-            Mode::Input(InputMode::SpotifyClientSecret) => {
-                let input = self.text_area.lines()[0].clone();
-                self.config.spotify_client_secret.value = input.clone();
-                self.save_data.spotify_client_secret = input;
-                self.text_area.clear_mask_char();
-                self.exit_input_mode();
-            }
-            _ => unreachable!(),
+        if was_empty && !self.global_songs.is_empty() {
+            select!(self.global_songs, self.global_song_list_state, 0);
         }
+        self.log = format!("Imported {imported} song(s) from {}", dir.display());
     }
 
-    fn handle_link(&mut self, download_id: u8, link: SpotifyLink) {
-        match link.clone() {
-            SpotifyLink::Playlist(id) => {
-                if self.save_data.last_valid_token.is_empty() {
-                    self.recreate_spotify_token(download_id, link);
-                    return;
-                }
+    fn add_imported_playlist(&mut self, name: String, song_names: Vec<String>) {
+        let songs = song_names
+            .iter()
+            .filter_map(|song_name| {
+                self.global_songs
+                    .iter()
+                    .find(|song| &song.name == song_name)
+                    .map(|song| Song {
+                        selected: Selected::None,
+                        playing: false,
+                        ..song.clone()
+                    })
+            })
+            .collect();
+
+        let was_empty = self.playlists.is_empty();
+        let playlist_idx = self.save_data.playlists.len();
+        self.save_data.playlists.push(SerializablePlaylist {
+            name: name.clone(),
+            songs: song_names.clone(),
+            description: String::new(),
+            audio_format: self.save_data.audio_format,
+            journal: Vec::new(),
+            spotify_id: String::new(),
+        });
+        self.journal(playlist_idx, PlaylistChange::Created);
+        for (idx, song_name) in song_names.into_iter().enumerate() {
+            self.journal(playlist_idx, PlaylistChange::Added(song_name, idx));
+        }
+        self.playlists.push(Playlist {
+            songs,
+            song_list_state: ListState::default().with_selected(Some(0)),
+            selected: Selected::None,
+            playing: false,
+            name,
+        });
+
+        if was_empty {
+            select!(self.playlists, self.playlist_list_state, 0);
+            self.see_songs_in_playlist();
+        }
+    }
 
-                let last_valid_token = self.save_data.last_valid_token.clone();
-                let client = self.client.clone();
+    fn load_tags(&mut self, path: &str) {
+        match read_tags(path) {
+            Some(tags) => self.song_tags.insert(path.to_string(), tags),
+            None => self.song_tags.remove(path),
+        };
+    }
 
-                self.downloads
-                    .insert(download_id, Download::FetchingPlaylistInfo);
-                self.join_handles.push(tokio::spawn(async move {
-                    fetch_playlist_info(download_id, &client, &id, &last_valid_token).await
-                }));
+    fn play_playlist(&mut self, playlist_idx: usize, idx: usize) {
+        self.playlist_cursor = Some((playlist_idx, idx));
+        self.fill_song_queue();
+        self.update_playing();
+        self.sink.play();
+    }
+
+    // Plays the global songs like a playlist, starting from the song at idx
+    fn play_global_song(&mut self, idx: usize) {
+        self.global_order = (0..self.global_songs.len()).collect();
+
+        let start = if self.shuffle {
+            self.global_order.swap(0, idx);
+            for i in (2..self.global_order.len()).rev() {
+                let j = 1 + random_index(i);
+                self.global_order.swap(i, j);
             }
-            SpotifyLink::Track(id) => {
-                if self.save_data.last_valid_token.is_empty() {
-                    self.recreate_spotify_token(download_id, link);
-                    return;
+            0
+        } else {
+            idx
+        };
+
+        self.global_cursor = Some(start);
+        self.fill_song_queue();
+        self.update_playing();
+        self.sink.play();
+    }
+
+    fn toggle_shuffle(&mut self) {
+        self.shuffle = !self.shuffle;
+        self.save_data.shuffle = self.shuffle;
+
+        // Only reshuffle the songs that didn't play yet
+        if let Some(order_idx) = self.global_cursor {
+            if order_idx < self.global_order.len() {
+                let mut rest = self.global_order.split_off(order_idx);
+                if self.shuffle {
+                    for i in (1..rest.len()).rev() {
+                        rest.swap(i, random_index(i + 1));
+                    }
+                } else {
+                    rest.sort_unstable();
                 }
+                self.global_order.extend(rest);
+            }
+        }
 
-                let last_valid_token = self.save_data.last_valid_token.clone();
-                let client = self.client.clone();
+        self.log = if self.shuffle {
+            String::from("Shuffle on")
+        } else {
+            String::from("Shuffle off")
+        };
+    }
 
-                self.downloads
-                    .insert(download_id, Download::FetchingTrackInfo);
-                self.join_handles.push(tokio::spawn(async move {
-                    fetch_track_info(download_id, &client, &id, &last_valid_token).await
-                }));
+    fn fix_queue_after_global_song_removal(&mut self, idx: usize) {
+        for queued in self.song_queue.iter_mut().chain(&mut self.pending_queue) {
+            if let QueuedFrom::GlobalSong(song_idx) = queued.from {
+                if song_idx == idx {
+                    queued.from = QueuedFrom::Manual;
+                } else if song_idx > idx {
+                    queued.from = QueuedFrom::GlobalSong(song_idx - 1);
+                }
             }
-            SpotifyLink::Invalid => {
-                let dlp_path = self.save_data.dlp_path.clone();
-                let input = self.text_area.lines()[0].clone();
+        }
 
-                self.downloads
-                    .insert(download_id, Download::DownloadingYoutubeSong);
-                self.join_handles.push(tokio::spawn(async move {
-                    download_song(
-                        download_id,
-                        &dlp_path,
-                        &input,
-                        &make_safe_filename(&input),
-                        SearchFor::GlobalSong(String::from("Song from YT Link")),
-                    )
-                    .await
-                }));
+        if let Some(order_idx) = self.global_cursor {
+            let removed_before = self.global_order[..order_idx.min(self.global_order.len())]
+                .iter()
+                .filter(|&&song_idx| song_idx == idx)
+                .count();
+            self.global_cursor = Some(order_idx - removed_before);
+        }
+
+        self.global_order.retain(|&song_idx| song_idx != idx);
+        for song_idx in &mut self.global_order {
+            if *song_idx > idx {
+                *song_idx -= 1;
+            }
+        }
+
+        if let Playing::GlobalSong(playing_idx) = self.playing {
+            if playing_idx > idx {
+                self.playing = Playing::GlobalSong(playing_idx - 1);
             }
         }
     }
 
-    fn stop_playing_current(&mut self) {
-        match self.playing {
-            Playing::Playlist(idx, song_idx) if !self.playlists.is_empty() => {
-                self.playlists[idx].songs[song_idx].playing = false;
-                self.playlists[idx].playing = false;
+    fn play_random(&mut self) {
+        let playlist_idx = selected!(self, self.playlist_list_state);
+
+        if self.window == Window::Songs && playlist_idx < self.playlists.len() {
+            let len = self.playlists[playlist_idx].songs.len();
+            if len == 0 {
+                self.log = String::from("Playlist is empty");
+                return;
             }
-            Playing::GlobalSong(idx) if !self.global_songs.is_empty() => {
-                self.global_songs[idx].playing = false;
+
+            self.stop_playing_current();
+            self.play_playlist(playlist_idx, random_index(len));
+        } else {
+            if self.global_songs.is_empty() {
+                self.log = String::from("There are no songs to pick from");
+                return;
             }
-            Playing::None => panic!("Tried to stop playing Playing::None"),
-            _ => {}
+
+            self.stop_playing_current();
+            self.play_global_song(random_index(self.global_songs.len()));
         }
-        self.playing = Playing::None;
-        self.song_queue.clear();
-        self.sink.stop();
     }
 
     fn play_current(&mut self) {
-        let playlist_idx = self.playlist_list_state.selected().unwrap();
+        let playlist_idx = selected!(self, self.playlist_list_state);
 
         if self.focused == Focused::Left {
             match self.playing {
@@ -941,21 +5856,14 @@ fn play_current(&mut self) {
                         return;
                     }
                 }
-                Playing::GlobalSong(_) => self.stop_playing_current(),
-                Playing::None => {}
+                Playing::GlobalSong(_) | Playing::None => self.stop_playing_current(),
             }
 
-            self.playlists[playlist_idx].songs[0].playing = true;
-            self.playlists[playlist_idx].playing = true;
-            self.playing = Playing::Playlist(playlist_idx, 0);
-            self.preload_songs(0);
-
-            self.last_queue_length = self.sink.len();
-            self.sink.play();
+            self.play_playlist(playlist_idx, 0);
         } else {
             match self.window {
                 Window::Songs => {
-                    let idx = self.song_list_state.selected().unwrap();
+                    let idx = selected!(self, self.song_list_state);
 
                     match self.playing {
                         Playing::Playlist(_, song_idx) => {
@@ -964,57 +5872,152 @@ fn play_current(&mut self) {
                                 return;
                             }
                         }
-                        Playing::GlobalSong(_) => self.stop_playing_current(),
-                        Playing::None => {}
+                        Playing::GlobalSong(_) | Playing::None => self.stop_playing_current(),
                     }
 
-                    self.playlists[playlist_idx].playing = true;
-                    self.playlists[playlist_idx].songs[idx].playing = true;
-
-                    self.playing = Playing::Playlist(playlist_idx, idx);
-                    self.preload_songs(idx);
-
-                    self.last_queue_length = self.sink.len();
-                    self.sink.play();
+                    self.play_playlist(playlist_idx, idx);
                 }
                 Window::GlobalSongs => {
-                    let idx = self.global_song_list_state.selected().unwrap();
+                    let idx = selected!(self, self.global_song_list_state);
 
                     match self.playing {
-                        Playing::Playlist(_, _) => self.stop_playing_current(),
+                        Playing::Playlist(_, _) | Playing::None => self.stop_playing_current(),
                         Playing::GlobalSong(playing_idx) => {
                             self.stop_playing_current();
                             if playing_idx == idx {
                                 return;
                             }
                         }
-                        Playing::None => {}
                     }
 
-                    self.global_songs[idx].playing = true;
-                    self.playing = Playing::GlobalSong(idx);
-                    self.song_queue.clear();
-                    self.play_path(
-                        &self.global_songs[idx].name.clone(),
-                        &self.global_songs[idx].path.clone(),
-                    );
-
-                    self.last_queue_length = self.sink.len();
-                    self.sink.play();
+                    self.play_global_song(idx);
+                }
+                Window::DownloadManager => self.toggle_downloads_paused(),
+                Window::Queue => {
+                    if let Some(idx) = self.queue_state.selected() {
+                        self.jump_to_queue_entry(idx);
+                    }
+                }
+                Window::Cleanup => self.delete_cleanup_candidates(),
+                Window::History => self.play_history_entry(),
+                Window::Journal => {}
+                Window::Skipped => self.play_skipped_song(),
+                Window::PartyQueue => self.approve_party_request(),
+                Window::Removed => self.restore_removed_song(),
+                Window::DownloadHistory => self.play_downloaded_song(),
+                Window::PlaylistSync => self.apply_playlist_sync(),
+                Window::Log => {}
+                Window::Integrity => {
+                    if let Some(issue) = self.take_integrity_issue() {
+                        self.redownload_song(issue.name);
+                    }
                 }
-                Window::DownloadManager => {}
                 Window::ConfigurationMenu => {
-                    if let Some(idx) = self.config_menu_state.selected() {
-                        match idx {
-                            0 => self.enter_input_mode(InputMode::DlpPath),
-                            1 => self.enter_input_mode(InputMode::SpotifyClientId),
-                            2 => {
-                                self.text_area.set_mask_char('*');
+                    let field_type = self.config_menu_state.selected().and_then(|idx| {
+                        self.config.fields().get(idx).map(|field| field.field_type)
+                    });
+
+                    match field_type {
+                        Some(ConfigFieldType::DlpPath) => self.enter_input_mode(InputMode::DlpPath),
+                        Some(ConfigFieldType::SpotifyClientId) => {
+                            self.enter_input_mode(InputMode::SpotifyClientId)
+                        }
+                        Some(ConfigFieldType::SpotifyClientSecret) => {
+                            self.text_area.set_mask_char('*');
 
-                                self.enter_input_mode(InputMode::SpotifyClientSecret)
-                            }
-                            _ => self.log = String::from("Index out of range for config menu"),
+                            self.enter_input_mode(InputMode::SpotifyClientSecret)
+                        }
+                        Some(ConfigFieldType::CleanupDays) => {
+                            self.enter_input_mode(InputMode::CleanupDays)
+                        }
+                        Some(ConfigFieldType::NowPlayingPath) => {
+                            self.enter_input_mode(InputMode::NowPlayingPath)
+                        }
+                        Some(ConfigFieldType::DlpNiceness) => {
+                            self.enter_input_mode(InputMode::DlpNiceness)
+                        }
+                        Some(ConfigFieldType::MaxDlpProcesses) => {
+                            self.enter_input_mode(InputMode::MaxDlpProcesses)
+                        }
+                        Some(ConfigFieldType::MaxParallelSongs) => {
+                            self.enter_input_mode(InputMode::MaxParallelSongs)
+                        }
+                        Some(ConfigFieldType::Cookies) => self.enter_input_mode(InputMode::Cookies),
+                        Some(ConfigFieldType::MusicDir) => {
+                            self.enter_input_mode(InputMode::MusicDir)
+                        }
+                        Some(ConfigFieldType::FfmpegPath) => {
+                            self.enter_input_mode(InputMode::FfmpegPath)
+                        }
+                        Some(ConfigFieldType::DownloadHook) => {
+                            self.enter_input_mode(InputMode::DownloadHook)
+                        }
+                        Some(ConfigFieldType::SpotifyMarket) => {
+                            self.enter_input_mode(InputMode::SpotifyMarket)
+                        }
+                        Some(ConfigFieldType::Language) => {
+                            self.enter_input_mode(InputMode::Language)
+                        }
+                        Some(ConfigFieldType::CacheSize) => {
+                            self.enter_input_mode(InputMode::CacheSize)
+                        }
+                        Some(ConfigFieldType::PartyPort) => {
+                            self.enter_input_mode(InputMode::PartyPort)
+                        }
+                        Some(ConfigFieldType::AutoSkipAfter) => {
+                            self.enter_input_mode(InputMode::AutoSkipAfter)
+                        }
+                        Some(ConfigFieldType::LowMemory) => {
+                            self.save_data.low_memory = !self.save_data.low_memory;
+                            self.config.low_memory.value = on_off(self.save_data.low_memory);
+                        }
+                        Some(ConfigFieldType::StartupWindow) => {
+                            self.save_data.startup_window = (self.save_data.startup_window + 1) % 5;
+                            self.config.startup_window.value =
+                                startup_window_name(self.save_data.startup_window);
                         }
+                        Some(ConfigFieldType::StartupPlaylist) => {
+                            self.enter_input_mode(InputMode::StartupPlaylist)
+                        }
+                        Some(ConfigFieldType::AudioFormat) => {
+                            self.save_data.audio_format = self.save_data.audio_format.next();
+                            self.config.audio_format.value =
+                                self.save_data.audio_format.name().to_string();
+                        }
+                        Some(ConfigFieldType::CheckForUpdates) => {
+                            self.save_data.check_for_updates = !self.save_data.check_for_updates;
+                            self.config.check_for_updates.value =
+                                on_off(self.save_data.check_for_updates);
+                        }
+                        Some(ConfigFieldType::UiSounds) => {
+                            self.save_data.ui_sounds = !self.save_data.ui_sounds;
+                            self.config.ui_sounds.value = on_off(self.save_data.ui_sounds);
+                        }
+                        Some(ConfigFieldType::ScreenReader) => {
+                            self.save_data.screen_reader = !self.save_data.screen_reader;
+                            self.config.screen_reader.value = on_off(self.save_data.screen_reader);
+                        }
+                        Some(ConfigFieldType::TrackNotification) => {
+                            self.save_data.track_notification =
+                                (self.save_data.track_notification + 1) % 3;
+                            self.config.track_notification.value =
+                                notification_name(self.save_data.track_notification);
+                        }
+                        Some(ConfigFieldType::NormalizeLoudness) => {
+                            self.save_data.normalize_loudness = !self.save_data.normalize_loudness;
+                            self.dlp.loudnorm = self.save_data.normalize_loudness;
+                            self.config.normalize_loudness.value =
+                                on_off(self.save_data.normalize_loudness);
+                        }
+                        Some(ConfigFieldType::ScrollTitles) => {
+                            self.save_data.scroll_titles = !self.save_data.scroll_titles;
+                            self.config.scroll_titles.value = on_off(self.save_data.scroll_titles);
+                        }
+                        Some(ConfigFieldType::HighContrast) => {
+                            self.save_data.high_contrast = !self.save_data.high_contrast;
+                            self.config.high_contrast.value = on_off(self.save_data.high_contrast);
+                        }
+                        None => self.log = String::from("Index out of range for config menu"),
                     }
                 }
             }
@@ -1023,6 +6026,7 @@ fn play_current(&mut self) {
 
     fn select_next(&mut self) {
         if self.focused == Focused::Left {
+            self.save_song_list_state();
             select_next!(
                 self.playlists,
                 self.playlist_list_state,
@@ -1032,7 +6036,7 @@ fn select_next(&mut self) {
         } else {
             match self.window {
                 Window::Songs => {
-                    let idx = self.playlist_list_state.selected().unwrap();
+                    let idx = selected!(self, self.playlist_list_state);
 
                     select_next!(
                         self.playlists[idx].songs,
@@ -1048,26 +6052,21 @@ fn select_next(&mut self) {
                     );
                 }
                 Window::DownloadManager => {}
+                Window::Queue => self.select_queue_entry(true),
+                Window::Cleanup => self.cleanup_state.select_next(),
+                Window::Integrity => self.integrity_state.select_next(),
+                Window::History => self.history_state.select_next(),
+                Window::Journal => self.journal_state.select_next(),
+                Window::Skipped => self.skipped_state.select_next(),
+                Window::PartyQueue => self.party_state.select_next(),
+                Window::Removed => self.removed_state.select_next(),
+                Window::DownloadHistory => self.download_history_state.select_next(),
+                Window::Log => self.log_state.select_next(),
+                Window::PlaylistSync => self.playlist_sync_state.select_next(),
                 Window::ConfigurationMenu => {
                     if let Some(idx) = self.config_menu_state.selected() {
-                        match idx {
-                            0 => {
-                                self.config.dlp_path.selected = Selected::None;
-                                self.config.spotify_client_id.selected = Selected::Focused;
-                                self.config_menu_state.select_next();
-                            }
-                            1 => {
-                                self.config.spotify_client_id.selected = Selected::None;
-                                self.config.spotify_client_secret.selected = Selected::Focused;
-                                self.config_menu_state.select_next();
-                            }
-                            2 => {
-                                self.config.spotify_client_secret.selected = Selected::None;
-                                self.config.dlp_path.selected = Selected::Focused;
-                                self.config_menu_state.select_first();
-                            }
-                            _ => panic!("Index out of range for config menu"),
-                        }
+                        let len = self.config.fields().len();
+                        self.select_config_field(idx, (idx + 1) % len);
                     }
                 }
             }
@@ -1076,6 +6075,7 @@ fn select_next(&mut self) {
 
     fn select_previous(&mut self) {
         if self.focused == Focused::Left {
+            self.save_song_list_state();
             select_previous!(
                 self.playlists,
                 self.playlist_list_state,
@@ -1085,7 +6085,7 @@ fn select_previous(&mut self) {
         } else {
             match self.window {
                 Window::Songs => {
-                    let idx = self.playlist_list_state.selected().unwrap();
+                    let idx = selected!(self, self.playlist_list_state);
 
                     select_previous!(
                         self.playlists[idx].songs,
@@ -1101,68 +6101,91 @@ fn select_previous(&mut self) {
                     );
                 }
                 Window::DownloadManager => {}
+                Window::Queue => self.select_queue_entry(false),
+                Window::Cleanup => self.cleanup_state.select_previous(),
+                Window::Integrity => self.integrity_state.select_previous(),
+                Window::History => self.history_state.select_previous(),
+                Window::Journal => self.journal_state.select_previous(),
+                Window::Skipped => self.skipped_state.select_previous(),
+                Window::PartyQueue => self.party_state.select_previous(),
+                Window::Removed => self.removed_state.select_previous(),
+                Window::DownloadHistory => self.download_history_state.select_previous(),
+                Window::Log => self.log_state.select_previous(),
+                Window::PlaylistSync => self.playlist_sync_state.select_previous(),
                 Window::ConfigurationMenu => {
                     if let Some(idx) = self.config_menu_state.selected() {
-                        match idx {
-                            0 => {
-                                self.config.dlp_path.selected = Selected::None;
-                                self.config.spotify_client_secret.selected = Selected::Focused;
-                                self.config_menu_state.select_last();
-                            }
-                            1 => {
-                                self.config.spotify_client_id.selected = Selected::None;
-                                self.config.dlp_path.selected = Selected::Focused;
-                                self.config_menu_state.select_previous();
-                            }
-                            2 => {
-                                self.config.spotify_client_secret.selected = Selected::None;
-                                self.config.spotify_client_id.selected = Selected::Focused;
-                                self.config_menu_state.select_previous();
-                            }
-                            _ => panic!("Index out of range for config menu"),
-                        }
+                        let len = self.config.fields().len();
+                        self.select_config_field(idx, (idx + len - 1) % len);
                     }
                 }
             }
         }
     }
 
-    fn play_path(&mut self, song_name: &str, path: &str) {
+    fn select_config_field(&mut self, idx: usize, new_idx: usize) {
+        if let Some(field) = self.config.field_mut(idx) {
+            field.selected = Selected::None;
+        }
+        if let Some(field) = self.config.field_mut(new_idx) {
+            field.selected = Selected::Focused;
+        }
+        self.config_menu_state.select(Some(new_idx));
+    }
+
+    fn play_path(&mut self, song_name: &str, path: &str, from: QueuedFrom) -> bool {
+        if from == QueuedFrom::Stream {
+            return match self.streams.get(path) {
+                Some(data) => {
+                    let data = Cursor::new(data.clone());
+                    self.append_source(song_name, path, from, data)
+                }
+                None => {
+                    self.log = format!("Stream of {path} is no longer available");
+                    false
+                }
+            };
+        }
+
         let file = match File::open(path) {
             Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                self.log = format!("{song_name} is missing, press R on it to re-download it");
+                return false;
+            }
             Err(err) => {
                 self.log = format!("Failed to open file: {}", err);
-                return;
+                return false;
             }
         };
 
-        let source = match Decoder::new(file) {
+        self.append_source(song_name, path, from, file)
+    }
+
+    fn append_source<R>(&mut self, song_name: &str, path: &str, from: QueuedFrom, data: R) -> bool
+    where
+        R: Read + Seek + Send + Sync + 'static,
+    {
+        let source = match Decoder::new(data) {
             Ok(source) => source,
             Err(err) => {
                 self.log = format!("Failed to decode file: {}", err);
-                return;
+                return false;
             }
         };
 
-        if let Some(duration) = source.total_duration() {
-            let queued_song = self.song_queue.last();
-            if let Some(last_song) = queued_song {
-                self.song_queue.push(QueuedSong {
-                    name: song_name.to_string(),
-                    song_idx: last_song.song_idx + 1,
-                    duration,
-                });
-            } else if let Playing::Playlist(_, idx) = self.playing {
-                self.song_queue.push(QueuedSong {
-                    name: song_name.to_string(),
-                    song_idx: idx,
-                    duration,
-                });
-            }
-        } else {
+        let duration = source.total_duration().unwrap_or_else(|| {
             self.log = String::from("Duration not known for a song in your playlist.");
-        }
+            Duration::ZERO
+        });
+
+        self.song_queue.push(QueuedSong {
+            from,
+            name: song_name.to_string(),
+            path: path.to_string(),
+            duration,
+        });
         self.sink.append(source);
+        true
     }
 
     fn add_item(&mut self) {
@@ -1170,8 +6193,27 @@ fn add_item(&mut self) {
             match self.window {
                 Window::Songs => self.enter_input_mode(InputMode::AddSongToPlaylist),
                 Window::GlobalSongs => self.enter_input_mode(InputMode::AddGlobalSong),
-                Window::DownloadManager => self.enter_input_mode(InputMode::DownloadLink),
-                Window::ConfigurationMenu => {}
+                Window::DownloadManager => self.enter_network_input_mode(InputMode::DownloadLink),
+                Window::Integrity => {
+                    if let Some(issue) = self
+                        .integrity_state
+                        .selected()
+                        .and_then(|idx| self.integrity_report.get(idx))
+                    {
+                        self.enter_input_mode(InputMode::RelocateSong(issue.name.clone()));
+                    }
+                }
+                Window::Skipped => self.forgive_skips(),
+                Window::Queue
+                | Window::ConfigurationMenu
+                | Window::Cleanup
+                | Window::History
+                | Window::Journal
+                | Window::PartyQueue
+                | Window::Removed
+                | Window::DownloadHistory
+                | Window::PlaylistSync
+                | Window::Log => {}
             }
         } else {
             self.enter_input_mode(InputMode::AddPlaylist);
@@ -1180,7 +6222,7 @@ fn add_item(&mut self) {
 
     fn remove_current(&mut self) {
         if self.focused == Focused::Left {
-            let idx = self.playlist_list_state.selected().unwrap();
+            let idx = selected!(self, self.playlist_list_state);
 
             self.log = format!("Remove playlist idx {idx}");
             self.playlists.remove(idx);
@@ -1189,6 +6231,7 @@ fn remove_current(&mut self) {
             if let Playing::Playlist(playing_idx, _) = self.playing {
                 if playing_idx == idx {
                     self.playing = Playing::None;
+                    self.playlist_cursor = None;
                 }
             }
 
@@ -1204,20 +6247,16 @@ fn remove_current(&mut self) {
         } else {
             match self.window {
                 Window::Songs => {
-                    let playlist_idx = self.playlist_list_state.selected().unwrap();
-                    let idx = self.song_list_state.selected().unwrap();
+                    let playlist_idx = selected!(self, self.playlist_list_state);
+                    let idx = selected!(self, self.song_list_state);
 
                     self.log = format!("Remove song idx {idx}");
 
-                    self.playlists[playlist_idx].songs.remove(idx);
+                    let song = self.playlists[playlist_idx].songs.remove(idx);
                     self.save_data.playlists[playlist_idx].songs.remove(idx);
+                    self.journal(playlist_idx, PlaylistChange::Removed(song.name, idx));
 
-                    if let Playing::Playlist(playlist_idx, playing_idx) = self.playing {
-                        if playing_idx == idx {
-                            self.playing = Playing::Playlist(playlist_idx, playing_idx - 1);
-                            self.preload_songs(playing_idx - 1);
-                        }
-                    }
+                    self.fix_queue_after_song_removal(playlist_idx, idx);
 
                     if !self.playlists[playlist_idx].songs.is_empty() {
                         if idx == self.playlists[playlist_idx].songs.len() {
@@ -1232,16 +6271,8 @@ fn remove_current(&mut self) {
                     }
                 }
                 Window::GlobalSongs => {
-                    let idx = self.global_song_list_state.selected().unwrap();
-
-                    self.global_songs.remove(idx);
-                    self.save_data.songs.remove(idx);
-
-                    if let Playing::GlobalSong(playing_idx) = self.playing {
-                        if playing_idx == idx {
-                            self.playing = Playing::None;
-                        }
-                    }
+                    let idx = selected!(self, self.global_song_list_state);
+                    self.remove_global_song(idx, Vec::new());
 
                     if !self.global_songs.is_empty() {
                         if idx == self.global_songs.len() {
@@ -1252,6 +6283,77 @@ fn remove_current(&mut self) {
                     }
                 }
                 Window::DownloadManager => {}
+                Window::Queue => {
+                    if let Some(idx) = self.queue_state.selected() {
+                        self.remove_queue_entry(idx);
+                    }
+                }
+                Window::Integrity => {
+                    if let Some(issue) = self.take_integrity_issue() {
+                        self.log = format!("Removed {} from the library", issue.name);
+                        self.remove_song_from_library(&issue.name);
+                    }
+                }
+                // The journal is append-only
+                Window::Journal => {}
+                Window::Removed => {
+                    if let Some(idx) = self.selected_removed_song() {
+                        let removed = self.save_data.removed.remove(idx);
+                        self.log = format!("{} can't be restored anymore", removed.song.name);
+                        clamp_selection(&mut self.removed_state, self.save_data.removed.len());
+                    }
+                }
+                Window::PartyQueue => {
+                    if let Some(idx) = self
+                        .party_state
+                        .selected()
+                        .filter(|&idx| idx < self.party_requests.len())
+                    {
+                        let submission = self.party_requests.remove(idx);
+                        self.log = format!("Rejected {}", submission.text);
+                        clamp_selection(&mut self.party_state, self.party_requests.len());
+                    }
+                }
+                Window::Skipped => {
+                    if let Some(name) = self.selected_skipped_song() {
+                        self.log = format!("Removed {name} from the library");
+                        self.remove_song_from_library(&name);
+                        let len = self.frequently_skipped().len();
+                        clamp_selection(&mut self.skipped_state, len);
+                    }
+                }
+                Window::History => {
+                    // The newest listen is shown first
+                    if let Some(idx) = self.history_state.selected() {
+                        if idx < self.save_data.history.len() {
+                            let idx = self.save_data.history.len() - 1 - idx;
+                            self.save_data.history.remove(idx);
+                        }
+                    }
+                }
+                Window::DownloadHistory => {
+                    // Same as the listening history, the newest is shown first
+                    let len = self.save_data.download_history.len();
+                    if let Some(idx) = self.download_history_state.selected() {
+                        if idx < len {
+                            self.save_data.download_history.remove(len - 1 - idx);
+                        }
+                    }
+                    clamp_selection(
+                        &mut self.download_history_state,
+                        self.save_data.download_history.len(),
+                    );
+                }
+                Window::PlaylistSync => self.leave_out_of_sync(),
+                Window::Log => {}
+                Window::Cleanup => {
+                    // Keep the song by taking it out of the report
+                    if let Some(idx) = self.cleanup_state.selected() {
+                        if idx < self.cleanup_report.len() {
+                            self.cleanup_report.remove(idx);
+                        }
+                    }
+                }
                 Window::ConfigurationMenu => {}
             }
         }
@@ -1260,6 +6362,13 @@ fn remove_current(&mut self) {
     pub(crate) fn init(&mut self) -> Result<(), Error> {
         let mut first = true;
 
+        for song in &mut self.save_data.songs {
+            // Songs added before durations were stored
+            if song.duration == 0 {
+                song.duration = probe_duration(&song.path);
+            }
+        }
+
         for playlist in &self.save_data.playlists {
             let songs = playlist
                 .songs
@@ -1272,6 +6381,8 @@ pub(crate) fn init(&mut self) -> Result<(), Error> {
                                 name: song.name.clone(),
                                 path: song.path.clone(),
                                 playing: false,
+                                favorite: song.favorite,
+                                duration: song.duration,
                             })
                         } else {
                             None
@@ -1282,6 +6393,7 @@ pub(crate) fn init(&mut self) -> Result<(), Error> {
 
             self.playlists.push(Playlist {
                 songs,
+                song_list_state: ListState::default().with_selected(Some(0)),
                 name: playlist.name.clone(),
                 selected: if first {
                     Selected::Focused
@@ -1295,37 +6407,171 @@ pub(crate) fn init(&mut self) -> Result<(), Error> {
         }
 
         for song in &self.save_data.songs {
+            if let Some(tags) = read_tags(&song.path) {
+                self.song_tags.insert(song.path.clone(), tags);
+            }
             self.global_songs.push(Song {
                 selected: Selected::None,
                 name: song.name.clone(),
                 path: song.path.clone(),
                 playing: false,
+                favorite: song.favorite,
+                duration: song.duration,
             });
         }
 
-        if !Path::new(&self.save_data.dlp_path).exists() {
-            self.enter_input_mode(InputMode::GetDlp);
+        // The files, yt-dlp processes and inbox belong to the instance that holds the lock
+        if self.read_only {
+            self.log = String::from("Quefi is already running, changes made here won't be saved");
+        } else {
+            let now = unix_timestamp();
+            self.save_data
+                .removed
+                .retain(|removed| now.saturating_sub(removed.removed) < REMOVED_KEEP_SECS);
+
+            if self.save_data.cleanup_days > 0 {
+                self.cleanup_report = self.cleanup_candidates();
+                if !self.cleanup_report.is_empty() {
+                    self.delete_cleanup_candidates();
+                }
+            }
+
+            if !self.offline {
+                self.start_party_server();
+            }
+            self.trim_cache();
+
+            if self.save_data.check_for_updates && !self.offline {
+                let client = self.client.clone();
+                self.spawn_task(async move { check_for_update(&client).await });
+            }
+
+            let killed = youtube::kill_leftover_processes(&self.save_data.dlp_path);
+            if killed > 0 {
+                self.log =
+                    format!("Killed {killed} yt-dlp process(es) left over from the last run");
+            }
+        }
+
+        if self.offline {
+            self.log = String::from("Offline, only songs in the library can be played");
+        } else if !Path::new(&self.save_data.dlp_path).exists() {
+            match env::var_os("PATH")
+                .and_then(|paths| youtube::find_in_paths(&paths, youtube::DLP_EXECUTABLE_NAME))
+            {
+                Some(path) => {
+                    self.set_dlp_path(path.to_string_lossy().to_string());
+                    self.log = format!("Using yt-dlp found at {}", path.display());
+                }
+                None => self.enter_input_mode(InputMode::GetDlp),
+            }
+        } else if Path::new(&self.save_data.dlp_path).is_file() && !self.ffmpeg_found() {
+            self.enter_input_mode(InputMode::GetFfmpeg);
+        } else if !self.read_only
+            && (!self.dlp.pending_downloads().is_empty()
+                || !youtube::partial_files(&self.dlp.songs_dir).is_empty())
+        {
+            self.enter_input_mode(InputMode::ResumeDownloads);
         }
 
         self.sink.set_volume(self.save_data.last_volume);
+        self.shuffle = self.save_data.shuffle;
         self.repeat = match self.save_data.last_repeat_mode {
             0 => Repeat::None,
             1 => Repeat::All,
             2 => Repeat::One,
             _ => return Err(Error::BadSerialization),
         };
+        self.open_startup_view();
+        self.play_start_playlist();
+        self.start_kiosk();
         Ok(())
     }
 
+    fn play_start_playlist(&mut self) {
+        let Some(name) = self.start_playlist.take() else {
+            return;
+        };
+        let Some(playlist_idx) = self
+            .playlists
+            .iter()
+            .position(|playlist| playlist.name == name)
+        else {
+            self.log = format!("Playlist {name} doesn't exist");
+            return;
+        };
+
+        // The startup playlist could be selected already
+        if let Some(playlist) = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.playlists.get_mut(idx))
+        {
+            playlist.selected = Selected::None;
+        }
+        select!(self.playlists, self.playlist_list_state, playlist_idx);
+        self.see_songs_in_playlist();
+        self.play_playlist(playlist_idx, 0);
+    }
+
+    fn open_startup_view(&mut self) {
+        if !self.save_data.startup_playlist.is_empty() {
+            match self
+                .playlists
+                .iter()
+                .position(|playlist| playlist.name == self.save_data.startup_playlist)
+            {
+                Some(playlist_idx) => {
+                    self.playlists[0].selected = Selected::None;
+                    select!(self.playlists, self.playlist_list_state, playlist_idx);
+                    self.see_songs_in_playlist();
+                }
+                None => {
+                    self.log = format!(
+                        "Startup playlist {} doesn't exist",
+                        self.save_data.startup_playlist
+                    )
+                }
+            }
+        }
+
+        let window = match self.save_data.startup_window {
+            1 => Window::Songs,
+            2 => Window::GlobalSongs,
+            3 => Window::DownloadManager,
+            4 => Window::Queue,
+            _ => return,
+        };
+        self.switch_window(window);
+        // Focusing the right side needs a playlist to take the focus from
+        if !self.playlists.is_empty() {
+            self.select_right_window();
+        }
+    }
+
     fn enter_input_mode(&mut self, input_mode: InputMode) {
         self.mode = Mode::Input(input_mode);
         self.validate_input();
     }
 
+    fn enter_network_input_mode(&mut self, input_mode: InputMode) {
+        if !self.offline_blocked() {
+            self.enter_input_mode(input_mode);
+        }
+    }
+
+    // Started with --offline, tells why nothing happened
+    fn offline_blocked(&mut self) -> bool {
+        if self.offline {
+            self.log = String::from("Can't do that offline, restart quefi without --offline");
+        }
+        self.offline
+    }
+
     fn exit_input_mode(&mut self) {
-        // Delete everything from the text area
-        self.text_area.move_cursor(CursorMove::Head);
-        self.text_area.delete_line_by_end();
+        // Delete everything from the text area, notes can span multiple lines
+        self.text_area.select_all();
+        self.text_area.cut();
 
         self.mode = Mode::Normal;
     }
@@ -66,6 +66,20 @@ macro_rules! select {
     };
 }
 
+// The selected index of a list, when there's none the error is shown and the function returns
+#[macro_export]
+macro_rules! selected {
+    ($app:expr, $state:expr) => {
+        match $state.selected() {
+            Some(idx) => idx,
+            None => {
+                $app.show_error(AppError::NothingSelected);
+                return Default::default();
+            }
+        }
+    };
+}
+
 #[macro_export]
 macro_rules! moving_warning {
     ($item:expr, $log:expr) => {
@@ -75,3 +89,22 @@ macro_rules! moving_warning {
         }
     };
 }
+
+#[macro_export]
+macro_rules! move_to {
+    ($vec:expr, $state:expr, $save_data_vec:expr, $new_idx:expr) => {
+        if let Some(idx) = $state.selected() {
+            if idx < $vec.len() && $vec[idx].selected == Selected::Moving {
+                let new_idx = $new_idx.min($vec.len() - 1);
+
+                let item = $vec.remove(idx);
+                $vec.insert(new_idx, item);
+                let item = $save_data_vec.remove(idx);
+                $save_data_vec.insert(new_idx, item);
+
+                $state.select(Some(new_idx));
+                return true;
+            }
+        }
+    };
+}
@@ -0,0 +1,2489 @@
+use super::{
+    App, Download, Focused, Hint, InputMode, Mode, Playing, QueuedFrom, QueuedSong, Selected,
+    SerializablePlaylist, SerializableSong, Song, Window,
+};
+use crate::{
+    party,
+    spotify::{self, PlaylistInfo, TrackInfo},
+    update::Update,
+    youtube::{
+        self, AudioFormat, SearchFilter, SearchResult, YoutubePlaylist, YoutubePlaylistEntry,
+    },
+    Cli, Error, SaveData, SearchFor, TaskResult, TaskReturn,
+};
+use clap::Parser;
+use ratatui::{
+    backend::TestBackend,
+    crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+    Terminal,
+};
+use rodio::{queue::SourcesQueueOutput, Sink};
+use std::{
+    env, fs,
+    net::IpAddr,
+    path::PathBuf,
+    time::{Duration, Instant},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
+
+struct Harness<'a> {
+    app: App<'a>,
+    terminal: Terminal<TestBackend>,
+    // Keeps the sink's queue alive, nothing reads from it
+    _output: SourcesQueueOutput,
+}
+
+impl Harness<'_> {
+    fn new(width: u16, height: u16) -> Self {
+        Harness::with_data(
+            SaveData {
+                // Any existing path, so the app doesn't ask to download yt-dlp
+                dlp_path: String::from("."),
+                ..SaveData::default()
+            },
+            width,
+            height,
+        )
+    }
+
+    // Most actions expect a playlist to be selected
+    fn with_playlist(width: u16, height: u16) -> Self {
+        Harness::with_data(
+            SaveData {
+                dlp_path: String::from("."),
+                playlists: vec![playlist("Liked", &[])],
+                ..SaveData::default()
+            },
+            width,
+            height,
+        )
+    }
+
+    fn with_data(mut data: SaveData, width: u16, height: u16) -> Self {
+        init_test_dirs();
+        let (sink, output) = Sink::new();
+        // Rendered numbers would otherwise depend on the machine's locale
+        if data.language.is_empty() {
+            data.language = String::from("en");
+        }
+
+        let mut app = App::with_sink(data, sink, None);
+        app.init().unwrap();
+
+        Harness {
+            app,
+            terminal: Terminal::new(TestBackend::new(width, height)).unwrap(),
+            _output: output,
+        }
+    }
+
+    async fn press(&mut self, code: KeyCode) -> bool {
+        self.app.handle_key(KeyEvent::from(code)).await
+    }
+
+    async fn type_text(&mut self, text: &str) {
+        for char in text.chars() {
+            self.press(KeyCode::Char(char)).await;
+        }
+    }
+
+    // Waits for the result of a task without handling it, results of other tasks are dropped
+    async fn task_result(&mut self, wanted: impl Fn(&TaskResult) -> bool) -> TaskResult {
+        loop {
+            let result = self.app.task_results.recv().await.unwrap().unwrap();
+            self.app.running_tasks -= 1;
+            if wanted(&result) {
+                return result;
+            }
+        }
+    }
+
+    fn render(&mut self) -> String {
+        self.terminal
+            .draw(|frame| frame.render_widget(&mut self.app, frame.area()))
+            .unwrap();
+
+        let buffer = self.terminal.backend().buffer();
+        let mut screen = String::new();
+        for y in 0..buffer.area.height {
+            for x in 0..buffer.area.width {
+                screen.push_str(buffer[(x, y)].symbol());
+            }
+            screen.push('\n');
+        }
+        screen
+    }
+}
+
+// Tests must never touch the library in the real data directory
+fn init_test_dirs() {
+    crate::init_dirs(None, true);
+}
+
+// A second of silent 128 kbps MPEG-1 Layer III frames
+fn silent_mp3(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("quefi-test-{}-{name}.mp3", std::process::id()));
+    let mut frame = vec![0u8; 417];
+    frame[..4].copy_from_slice(&[0xFF, 0xFB, 0x90, 0x00]);
+
+    fs::write(&path, frame.repeat(40)).unwrap();
+    path
+}
+
+fn song(name: &str, path: &str) -> SerializableSong {
+    SerializableSong {
+        name: String::from(name),
+        path: String::from(path),
+        ..SerializableSong::default()
+    }
+}
+
+fn playlist(name: &str, songs: &[&str]) -> SerializablePlaylist {
+    SerializablePlaylist {
+        name: String::from(name),
+        songs: songs.iter().map(|song| song.to_string()).collect(),
+        ..SerializablePlaylist::default()
+    }
+}
+
+#[tokio::test]
+async fn renders_help() {
+    let mut harness = Harness::new(100, 60);
+
+    harness.press(KeyCode::Char('y')).await;
+    assert_eq!(harness.app.mode, Mode::Help);
+    assert!(harness.render().contains("display this text"));
+
+    harness.press(KeyCode::Char('y')).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert!(!harness.render().contains("display this text"));
+}
+
+#[tokio::test]
+async fn quits() {
+    let mut harness = Harness::new(80, 24);
+    assert!(harness.press(KeyCode::Char('q')).await);
+}
+
+#[tokio::test]
+async fn adds_playlist() {
+    let mut harness = Harness::new(80, 24);
+
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Road trip").await;
+    assert!(harness.render().contains("Road trip"));
+
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert_eq!(harness.app.playlists.len(), 1);
+    assert_eq!(harness.app.save_data.playlists[0].name, "Road trip");
+    assert!(harness.render().contains("Road trip"));
+}
+
+#[tokio::test]
+async fn navigates_playlists() {
+    let mut harness = Harness::new(80, 24);
+
+    for name in ["First", "Second", "Third"] {
+        harness.press(KeyCode::Char('a')).await;
+        harness.type_text(name).await;
+        harness.press(KeyCode::Enter).await;
+    }
+
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(0));
+    harness.press(KeyCode::Char('j')).await;
+    harness.press(KeyCode::Down).await;
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(2));
+    harness.press(KeyCode::Char('k')).await;
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(1));
+
+    harness.press(KeyCode::Char('l')).await;
+    assert_eq!(harness.app.focused, Focused::Right);
+    harness.press(KeyCode::Char('g')).await;
+    assert_eq!(harness.app.window, Window::GlobalSongs);
+    assert!(harness.render().contains("Global song manager"));
+}
+
+#[tokio::test]
+async fn plays_global_song() {
+    let mut harness = Harness::with_playlist(80, 24);
+    let path = silent_mp3("play");
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Silence").await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text(&path.to_string_lossy()).await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.global_songs.len(), 1);
+
+    harness.press(KeyCode::Enter).await;
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(harness.app.playing, Playing::GlobalSong(0));
+    assert_eq!(harness.app.song_queue[0].name, "Silence");
+    assert!(harness.render().contains("00 Silence"));
+}
+
+#[tokio::test]
+async fn renders_empty_library() {
+    let mut harness = Harness::new(80, 24);
+    assert!(harness.render().contains("Songs"));
+}
+
+#[tokio::test]
+async fn renders_narrow_terminal() {
+    let mut harness = Harness::new(20, 10);
+    harness.app.log = String::from("A log message longer than the terminal");
+    harness.render();
+}
+
+#[tokio::test]
+async fn renders_large_library() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.app.global_songs = (0..10_000)
+        .map(|idx| Song {
+            selected: Selected::None,
+            name: format!("Song {idx}"),
+            path: format!("{idx}.mp3"),
+            playing: false,
+            favorite: false,
+            duration: 0,
+        })
+        .collect();
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('k')).await;
+    assert_eq!(harness.app.global_song_list_state.selected(), Some(9_999));
+
+    let screen = harness.render();
+    assert!(screen.contains("Song 9999"));
+    assert!(!screen.contains("Song 0 "));
+
+    harness.press(KeyCode::Char('j')).await;
+    let screen = harness.render();
+    assert!(screen.contains("Song 0 "));
+    assert!(!screen.contains("Song 9999"));
+
+    // Items of the last frame are reused until something they show changes
+    assert_eq!(harness.render(), screen);
+    harness.app.global_songs[1].favorite = true;
+    assert!(harness.render().contains("★ Song 1 "));
+}
+
+#[tokio::test]
+async fn reports_missing_songs() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Gone"])],
+            songs: vec![song("Gone", "/nonexistent/gone.mp3")],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('L')).await;
+    assert_eq!(harness.app.window, Window::Integrity);
+    assert!(harness.render().contains("Gone (missing)"));
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('n')).await;
+    assert!(harness.app.integrity_report.is_empty());
+    assert!(harness.app.global_songs.is_empty());
+    assert!(harness.app.playlists[0].songs.is_empty());
+    assert!(harness.app.save_data.playlists[0].songs.is_empty());
+}
+
+#[tokio::test]
+async fn truncates_long_titles() {
+    let mut harness = Harness::new(50, 24);
+    harness.app.song_queue.push(QueuedSong {
+        from: QueuedFrom::Manual,
+        name: "A very long song title that doesn't fit into the player".repeat(2),
+        path: String::new(),
+        duration: Duration::ZERO,
+    });
+
+    let screen = harness.render();
+    assert!(screen.contains("++ A very long song titl…"));
+    assert!(screen.contains("50%"));
+}
+
+#[tokio::test]
+async fn imports_directory() {
+    let dir = env::temp_dir().join(format!("quefi-test-{}-import", std::process::id()));
+    fs::create_dir_all(dir.join("Album")).unwrap();
+    fs::rename(silent_mp3("import-a"), dir.join("Intro.mp3")).unwrap();
+    fs::rename(silent_mp3("import-b"), dir.join("Album").join("Outro.mp3")).unwrap();
+
+    let mut harness = Harness::new(80, 24);
+    harness.press(KeyCode::Char('I')).await;
+    harness.type_text(&dir.to_string_lossy()).await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("y").await;
+    harness.press(KeyCode::Enter).await;
+    fs::remove_dir_all(&dir).unwrap();
+
+    let names: Vec<&str> = harness
+        .app
+        .global_songs
+        .iter()
+        .map(|song| song.name.as_str())
+        .collect();
+    assert_eq!(names, ["Intro", "Outro"]);
+
+    let playlists: Vec<&str> = harness
+        .app
+        .playlists
+        .iter()
+        .map(|playlist| playlist.name.as_str())
+        .collect();
+    assert_eq!(playlists.len(), 2);
+    assert!(playlists.contains(&"Album"));
+    assert_eq!(harness.app.save_data.playlists[0].songs.len(), 1);
+}
+
+#[tokio::test]
+async fn moves_playlist_to_position() {
+    let mut harness = Harness::new(80, 24);
+
+    for name in ["First", "Second", "Third", "Fourth"] {
+        harness.press(KeyCode::Char('a')).await;
+        harness.type_text(name).await;
+        harness.press(KeyCode::Enter).await;
+    }
+
+    harness.press(KeyCode::Char('k')).await;
+    harness.press(KeyCode::Char('m')).await;
+    harness.press(KeyCode::Home).await;
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(0));
+
+    harness.press(KeyCode::Char('M')).await;
+    harness.type_text("3").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(2));
+
+    let names: Vec<&str> = harness
+        .app
+        .save_data
+        .playlists
+        .iter()
+        .map(|playlist| playlist.name.as_str())
+        .collect();
+    assert_eq!(names, ["First", "Second", "Fourth", "Third"]);
+}
+
+#[test]
+fn accepts_youtube_link_forms() {
+    for link in [
+        "https://www.youtube.com/watch?v=dQw4w9WgXcQ",
+        "https://music.youtube.com/watch?v=dQw4w9WgXcQ&list=RDAMVM",
+        "https://m.youtube.com/watch?v=dQw4w9WgXcQ",
+        "https://youtube.com/shorts/dQw4w9WgXcQ?feature=share",
+        "https://youtu.be/dQw4w9WgXcQ?si=abc",
+    ] {
+        assert!(super::is_valid_youtube_link(link), "{link}");
+    }
+    assert!(!super::is_valid_youtube_link(
+        "https://music.youtube.com/shorts/dQw4w9WgXcQ"
+    ));
+
+    assert!(super::is_valid_youtube_album_link(&crate::link::normalize(
+        "https://music.youtube.com/browse/MPREb_4pL8gzRtw1p?si=abc"
+    )));
+    assert!(!super::is_valid_youtube_album_link(
+        "https://music.youtube.com/browse/UCabc"
+    ));
+}
+
+#[tokio::test]
+async fn offers_to_repair_missing_song() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Gone"])],
+            songs: vec![SerializableSong {
+                url: String::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ"),
+                ..song("Gone", "/nonexistent/gone.mp3")
+            }],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Enter).await;
+    assert!(harness.app.log.contains("press R"));
+
+    harness.press(KeyCode::Char('R')).await;
+    assert_eq!(harness.app.downloads.len(), 1);
+}
+
+#[tokio::test]
+async fn filters_global_songs() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.app.global_songs = [
+        "Blue Monday",
+        "Everlong",
+        "Bohemian Rhapsody",
+        "Black Hole Sun",
+    ]
+    .into_iter()
+    .map(|name| Song {
+        selected: Selected::None,
+        name: String::from(name),
+        path: String::new(),
+        playing: false,
+        favorite: false,
+        duration: 0,
+    })
+    .collect();
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('/')).await;
+    harness.type_text("bhs").await;
+
+    let screen = harness.render();
+    assert!(screen.contains("Black Hole Sun"));
+    assert!(screen.contains("Bohemian Rhapsody"));
+    assert!(!screen.contains("Everlong"));
+    assert!(!screen.contains("Blue Monday"));
+    assert_eq!(harness.app.global_song_list_state.selected(), Some(2));
+
+    harness.press(KeyCode::Down).await;
+    assert_eq!(harness.app.global_song_list_state.selected(), Some(3));
+
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert_eq!(harness.app.global_song_list_state.selected(), Some(3));
+    assert!(harness.render().contains("Everlong"));
+}
+
+#[tokio::test]
+async fn sorts_global_songs() {
+    let songs = [("Bravo", 30), ("alpha", 200), ("Charlie", 100)]
+        .into_iter()
+        .map(|(name, duration)| SerializableSong {
+            duration,
+            ..song(name, &format!("/nonexistent/{name}.mp3"))
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &[])],
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+    let names = |app: &App| -> Vec<String> {
+        app.save_data
+            .songs
+            .iter()
+            .map(|song| song.name.clone())
+            .collect()
+    };
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('O')).await;
+    assert_eq!(names(&harness.app), ["alpha", "Bravo", "Charlie"]);
+    assert_eq!(harness.app.global_songs[0].name, "alpha");
+    // The selected song stays selected
+    assert_eq!(harness.app.global_song_list_state.selected(), Some(1));
+
+    harness.press(KeyCode::Char('O')).await;
+    assert_eq!(names(&harness.app), ["Bravo", "Charlie", "alpha"]);
+    assert_eq!(harness.app.log, "Sorted by duration");
+}
+
+#[tokio::test]
+async fn shows_song_durations() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Long", "Short"])],
+            songs: [("Long", 3_725), ("Short", 95)]
+                .into_iter()
+                .map(|(name, duration)| SerializableSong {
+                    duration,
+                    ..song(name, &format!("/nonexistent/{name}.mp3"))
+                })
+                .collect(),
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    let screen = harness.render();
+    let long = screen.lines().find(|line| line.contains("Long")).unwrap();
+    assert!(long.trim_end().ends_with("1:02:05│"));
+    assert!(screen.contains("Total: 1:03:40"));
+}
+
+#[tokio::test]
+async fn shows_time_left_in_playlist() {
+    let paths: Vec<PathBuf> = ["left-a", "left-b"].into_iter().map(silent_mp3).collect();
+    let songs = ["A", "B"]
+        .into_iter()
+        .zip(&paths)
+        .map(|(name, path)| SerializableSong {
+            duration: 90,
+            ..song(name, &path.to_string_lossy())
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["A", "B"])],
+            songs,
+            ..SaveData::default()
+        },
+        120,
+        24,
+    );
+
+    harness.press(KeyCode::Enter).await;
+    harness.app.sink.pause();
+    for path in &paths {
+        fs::remove_file(path).unwrap();
+    }
+
+    // The current song's 90 seconds plus the one after it
+    let screen = harness.render();
+    assert!(screen.contains("Playlist: 3:00 left"));
+    let liked = screen.lines().find(|line| line.contains("Liked")).unwrap();
+    assert!(liked.contains("3:00"));
+}
+
+#[tokio::test]
+async fn sorts_and_formats_by_language() {
+    let songs = ["Żaba", "Zebra", "Łódź", "Lato"]
+        .into_iter()
+        .map(|name| SerializableSong {
+            duration: 65,
+            ..song(name, &format!("/nonexistent/{name}.mp3"))
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &[])],
+            songs,
+            language: String::from("pl"),
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('O')).await;
+    let names: Vec<&str> = harness
+        .app
+        .save_data
+        .songs
+        .iter()
+        .map(|song| song.name.as_str())
+        .collect();
+    assert_eq!(names, ["Lato", "Łódź", "Zebra", "Żaba"]);
+
+    harness.press(KeyCode::Char('c')).await;
+    harness.app.config_menu_state.select(Some(10));
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("ar-EG").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.save_data.language, "ar-EG");
+    harness.press(KeyCode::Char('g')).await;
+    assert!(harness.render().contains("١:٠٥"));
+}
+
+#[tokio::test]
+async fn screen_reader_mode_uses_plain_text() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &[])],
+            screen_reader: true,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    let screen = harness.render();
+    assert!(!screen.contains('│') && !screen.contains('►'));
+    assert!(screen.contains("> Liked"));
+    assert!(screen.contains("Nothing playing"));
+
+    harness.press(KeyCode::Char(' ')).await;
+    assert_eq!(harness.app.log, "Paused");
+    harness.press(KeyCode::Char('r')).await;
+    assert_eq!(harness.app.log, "Repeat all");
+    harness.press(KeyCode::Char('i')).await;
+    assert_eq!(harness.app.log, "Volume 55%");
+}
+
+#[tokio::test]
+async fn records_listening_history() {
+    let mut harness = Harness::with_playlist(80, 24);
+    let path = silent_mp3("history");
+
+    harness.press(KeyCode::Char('H')).await;
+    assert_eq!(harness.app.log, "Nothing was played yet");
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Silence").await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text(&path.to_string_lossy()).await;
+    harness.press(KeyCode::Enter).await;
+    harness.press(KeyCode::Enter).await;
+
+    assert_eq!(harness.app.save_data.history.len(), 1);
+    assert_eq!(harness.app.save_data.history[0].name, "Silence");
+    assert_eq!(harness.app.save_data.songs[0].play_count, 1);
+
+    harness.press(KeyCode::Char('H')).await;
+    assert_eq!(harness.app.window, Window::History);
+    let screen = harness.render();
+    assert!(screen.contains("Listening history"));
+    assert!(screen.contains("just now  Silence (1 play)"));
+
+    fs::remove_file(&path).unwrap();
+
+    harness.press(KeyCode::Char('n')).await;
+    assert!(harness.app.save_data.history.is_empty());
+}
+
+#[tokio::test]
+async fn edits_song_notes() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &[])],
+            songs: vec![song("Ballad", "/nonexistent/Ballad.mp3")],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('N')).await;
+    assert_eq!(harness.app.mode, Mode::Notes(String::from("Ballad")));
+    assert!(harness.render().contains("No notes yet"));
+
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Capo 2").await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("Am F C G").await;
+    harness
+        .app
+        .handle_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL))
+        .await;
+    assert_eq!(harness.app.save_data.songs[0].notes, "Capo 2\nAm F C G");
+    assert_eq!(harness.app.mode, Mode::Notes(String::from("Ballad")));
+
+    let screen = harness.render();
+    assert!(screen.contains("Capo 2") && screen.contains("Am F C G"));
+
+    harness.press(KeyCode::Esc).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+}
+
+#[tokio::test]
+async fn journals_playlist_changes() {
+    let songs = ["A", "B", "C"]
+        .into_iter()
+        .map(|name| song(name, &format!("/nonexistent/{name}.mp3")))
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["A", "B", "C"])],
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('J')).await;
+    assert_eq!(harness.app.log, "No changes to Liked were recorded yet");
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('m')).await;
+    harness.press(KeyCode::Char('j')).await;
+    harness.press(KeyCode::Char('j')).await;
+    harness.press(KeyCode::Char('m')).await;
+    harness.press(KeyCode::Char('n')).await;
+    assert_eq!(harness.app.save_data.playlists[0].songs, ["B", "C"]);
+
+    harness.press(KeyCode::Char('J')).await;
+    assert_eq!(harness.app.window, Window::Journal);
+    let screen = harness.render();
+    // Newest first
+    let removed = screen.find("Removed A from #3").unwrap();
+    let moved = screen.find("Moved A from #1 to #3").unwrap();
+    assert!(removed < moved);
+}
+
+#[tokio::test]
+async fn auto_skips_frequently_skipped_songs() {
+    let paths: Vec<PathBuf> = ["skip-b", "skip-a", "skip-c"]
+        .into_iter()
+        .map(silent_mp3)
+        .collect();
+    let songs = ["B", "A", "C"]
+        .into_iter()
+        .zip(&paths)
+        .map(|(name, path)| SerializableSong {
+            skips: if name == "A" { 3 } else { 0 },
+            ..song(name, &path.to_string_lossy())
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["B", "A", "C"])],
+            songs,
+            auto_skip_after: 2,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Enter).await;
+    for path in &paths {
+        fs::remove_file(path).unwrap();
+    }
+
+    let queued: Vec<&str> = harness
+        .app
+        .song_queue
+        .iter()
+        .map(|queued| queued.name.as_str())
+        .collect();
+    assert_eq!(queued, ["B", "C"]);
+
+    harness.press(KeyCode::Char('F')).await;
+    assert_eq!(harness.app.window, Window::Skipped);
+    assert!(harness.render().contains("A (3 skips)"));
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('a')).await;
+    assert_eq!(harness.app.save_data.songs[1].skips, 0);
+}
+
+#[tokio::test]
+async fn renames_songs_everywhere() {
+    let songs = ["Untitled", "Other"]
+        .into_iter()
+        .map(|name| song(name, &format!("/nonexistent/{name}.mp3")))
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Untitled"])],
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+    // Renaming starts with the old name typed in
+    let clear = |app: &mut App| {
+        app.text_area.select_all();
+        app.text_area.cut();
+    };
+
+    harness.press(KeyCode::F(2)).await;
+    assert_eq!(harness.app.text_area.lines()[0], "Liked");
+    clear(&mut harness.app);
+    harness.type_text("Favorites").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.save_data.playlists[0].name, "Favorites");
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::F(2)).await;
+    clear(&mut harness.app);
+    harness.type_text("Other").await;
+    harness.press(KeyCode::Enter).await;
+    // Taken by another song
+    assert_eq!(harness.app.mode, Mode::Input(InputMode::Rename));
+
+    clear(&mut harness.app);
+    harness.type_text("Sunrise").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert_eq!(harness.app.save_data.songs[0].name, "Sunrise");
+    assert_eq!(harness.app.save_data.playlists[0].songs, ["Sunrise"]);
+    assert_eq!(harness.app.playlists[0].songs[0].name, "Sunrise");
+}
+
+#[tokio::test]
+async fn kiosk_mode_plays_playlist_and_locks_library() {
+    let path = silent_mp3("kiosk");
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &[]), playlist("Party", &["Song"])],
+            songs: vec![song("Song", &path.to_string_lossy())],
+            kiosk_playlist: String::from("Party"),
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(harness.app.playing, Playing::Playlist(1, 0));
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(1));
+
+    harness.press(KeyCode::Char('n')).await;
+    assert_eq!(harness.app.playlists.len(), 2);
+    assert_eq!(harness.app.log, "Disabled in kiosk mode");
+}
+
+#[tokio::test]
+async fn starts_offline_playing_playlist_from_command_line() {
+    let cli = Cli::try_parse_from([
+        "quefi",
+        "--volume",
+        "80",
+        "--playlist",
+        "Road",
+        "--offline",
+        "--portable",
+    ])
+    .unwrap();
+    crate::init_dirs(cli.data_dir.clone(), cli.portable);
+    assert_eq!(cli.volume, Some(80));
+    assert!(Cli::try_parse_from(["quefi", "--volume", "501"]).is_err());
+    assert!(Cli::try_parse_from(["quefi", "add"]).is_err());
+
+    let path = silent_mp3("start_playlist");
+    let data = SaveData {
+        dlp_path: String::from("."),
+        language: String::from("en"),
+        playlists: vec![playlist("Liked", &[]), playlist("Road", &["Song"])],
+        songs: vec![song("Song", &path.to_string_lossy())],
+        ..SaveData::default()
+    };
+    let (sink, _output) = Sink::new();
+    let mut app = App::with_sink(data, sink, None);
+    app.offline = cli.offline;
+    app.start_playlist = cli.playlist;
+    app.init().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(app.playing, Playing::Playlist(1, 0));
+    assert_eq!(app.playlist_list_state.selected(), Some(1));
+    assert!(app.party_server.is_none());
+
+    app.handle_key(KeyEvent::from(KeyCode::Char('G'))).await;
+    assert_eq!(app.mode, Mode::Normal);
+    assert_eq!(
+        app.log,
+        "Can't do that offline, restart quefi without --offline"
+    );
+}
+
+#[tokio::test]
+async fn playlist_from_command_line_replaces_startup_playlist() {
+    let path = silent_mp3("both_playlists");
+    let data = SaveData {
+        dlp_path: String::from("."),
+        startup_playlist: String::from("Chill"),
+        playlists: vec![
+            playlist("Liked", &[]),
+            playlist("Chill", &[]),
+            playlist("Road", &["Song"]),
+        ],
+        songs: vec![song("Song", &path.to_string_lossy())],
+        ..SaveData::default()
+    };
+    init_test_dirs();
+    let (sink, _output) = Sink::new();
+    let mut app = App::with_sink(data, sink, None);
+    app.start_playlist = Some(String::from("Road"));
+    app.init().unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(app.playing, Playing::Playlist(2, 0));
+    let selected: Vec<&str> = app
+        .playlists
+        .iter()
+        .filter(|playlist| playlist.selected != Selected::None)
+        .map(|playlist| playlist.name.as_str())
+        .collect();
+    assert_eq!(selected, ["Road"]);
+}
+
+#[tokio::test]
+async fn duplicates_playlist() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Party", &["B", "A"])],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('B')).await;
+    assert_eq!(harness.app.text_area.lines()[0], "Party (copy)");
+    harness.press(KeyCode::Enter).await;
+
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert_eq!(harness.app.save_data.playlists[1].name, "Party (copy)");
+    assert_eq!(harness.app.save_data.playlists[1].songs, ["B", "A"]);
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(1));
+
+    // The copy can't take a name that's already used
+    harness.press(KeyCode::Char('B')).await;
+    harness.app.text_area.select_all();
+    harness.app.text_area.cut();
+    harness.type_text("Party").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.playlists.len(), 2);
+}
+
+#[tokio::test]
+async fn reviews_party_queue_requests() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.app.party_requests.push(party::Submission {
+        text: String::from("Never Gonna Give You Up"),
+        from: IpAddr::from([127, 0, 0, 1]),
+    });
+    harness.press(KeyCode::Char('Q')).await;
+    harness.press(KeyCode::Char('l')).await;
+    assert!(harness
+        .render()
+        .contains("Never Gonna Give You Up  (from 127.0.0.1)"));
+
+    harness.press(KeyCode::Char('n')).await;
+    assert!(harness.app.party_requests.is_empty());
+    assert_eq!(harness.app.log, "Rejected Never Gonna Give You Up");
+}
+
+#[tokio::test]
+async fn queues_approved_party_links() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.app.party_requests.push(party::Submission {
+        text: String::from("https://youtube.com/watch?v=dQw4w9WgXcQ"),
+        from: "127.0.0.1".parse().unwrap(),
+    });
+    harness.press(KeyCode::Char('Q')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Enter).await;
+    assert!(harness.app.party_requests.is_empty());
+
+    // The approved id is the one the link downloads under
+    let id = *harness.app.party_downloads.iter().next().unwrap();
+    assert!(harness.app.downloads.contains_key(&id));
+
+    let path = harness
+        .app
+        .dlp
+        .songs_dir
+        .join("Never Gonna Give You Up.mp3");
+    fs::create_dir_all(&harness.app.dlp.songs_dir).unwrap();
+    let silent = silent_mp3("party");
+    fs::copy(&silent, &path).unwrap();
+    fs::remove_file(silent).unwrap();
+
+    harness.app.handle_result(Ok(TaskReturn::SongDownloaded(
+        id,
+        SearchFor::GlobalSong(String::from("Never Gonna Give You Up")),
+        String::from("https://youtube.com/watch?v=dQw4w9WgXcQ"),
+    )));
+    assert!(harness.app.party_downloads.is_empty());
+    assert_eq!(harness.app.song_queue[0].name, "Never Gonna Give You Up");
+    fs::remove_file(path).unwrap();
+}
+
+#[tokio::test]
+async fn shows_available_update() {
+    let mut harness = Harness::new(100, 24);
+    harness.press(KeyCode::Char('U')).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+
+    harness.app.update = Some(Update {
+        version: String::from("v9.0.0"),
+        changelog: String::from("Faster downloads"),
+    });
+    assert!(harness.render().contains("v9.0.0 available"));
+
+    harness.press(KeyCode::Char('U')).await;
+    assert!(harness.render().contains("Faster downloads"));
+    harness.press(KeyCode::Esc).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+}
+
+#[tokio::test]
+async fn describes_playlists() {
+    let mut harness = Harness::with_playlist(80, 24);
+
+    harness.press(KeyCode::Char('N')).await;
+    assert_eq!(
+        harness.app.mode,
+        Mode::Input(InputMode::PlaylistDescription)
+    );
+    harness.type_text("Road trip").await;
+    harness.press(KeyCode::Enter).await;
+
+    assert_eq!(harness.app.save_data.playlists[0].description, "Road trip");
+    let screen = harness.render();
+    assert!(screen.contains("Description"));
+    assert!(screen.contains("Road trip"));
+
+    // Only shown while the playlists are focused
+    harness.press(KeyCode::Char('l')).await;
+    assert!(!harness.render().contains("Road trip"));
+}
+
+#[tokio::test]
+async fn picks_download_format_per_playlist() {
+    let mut harness = Harness::with_playlist(80, 24);
+
+    harness.press(KeyCode::Char('A')).await;
+    harness.press(KeyCode::Char('A')).await;
+    assert_eq!(
+        harness.app.save_data.playlists[0].audio_format,
+        AudioFormat::Opus
+    );
+    assert_eq!(
+        harness.app.log,
+        "Songs downloaded into Liked will be opus (best quality)"
+    );
+    assert_eq!(AudioFormat::Opus.extension(), "opus");
+
+    // Copies download the same way as the original
+    harness.press(KeyCode::Char('B')).await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(
+        harness.app.save_data.playlists[1].audio_format,
+        AudioFormat::Opus
+    );
+}
+
+#[tokio::test]
+async fn restores_recently_removed_songs() {
+    let songs = ["B", "A", "C"]
+        .into_iter()
+        .map(|name| SerializableSong {
+            skips: if name == "A" { 3 } else { 0 },
+            ..song(name, &format!("/nonexistent/{name}.mp3"))
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["B", "A", "C"])],
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('F')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('n')).await;
+    assert_eq!(harness.app.save_data.playlists[0].songs, ["B", "C"]);
+    assert_eq!(harness.app.save_data.songs.len(), 2);
+
+    harness.press(KeyCode::Char('Z')).await;
+    assert_eq!(harness.app.window, Window::Removed);
+    assert!(harness
+        .render()
+        .contains("A  (removed just now, was in 1 playlist)"));
+
+    harness.press(KeyCode::Enter).await;
+    assert!(harness.app.save_data.removed.is_empty());
+    assert_eq!(harness.app.save_data.songs[2].name, "A");
+    assert_eq!(harness.app.save_data.playlists[0].songs, ["B", "A", "C"]);
+    let names: Vec<&str> = harness.app.playlists[0]
+        .songs
+        .iter()
+        .map(|song| song.name.as_str())
+        .collect();
+    assert_eq!(names, ["B", "A", "C"]);
+}
+
+#[tokio::test]
+async fn moves_song_to_typed_position() {
+    let songs = ["A", "B", "C", "D"]
+        .into_iter()
+        .map(|name| song(name, &format!("/nonexistent/{name}.mp3")))
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["A", "B", "C", "D"])],
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('M')).await;
+    assert_eq!(harness.app.mode, Mode::Input(InputMode::SongPosition));
+    assert_eq!(harness.app.text_area.lines()[0], "1");
+
+    harness.app.text_area.select_all();
+    harness.app.text_area.cut();
+    harness.type_text("3").await;
+    harness.press(KeyCode::Enter).await;
+
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert_eq!(
+        harness.app.save_data.playlists[0].songs,
+        ["B", "C", "A", "D"]
+    );
+    let names: Vec<&str> = harness.app.playlists[0]
+        .songs
+        .iter()
+        .map(|song| song.name.as_str())
+        .collect();
+    assert_eq!(names, ["B", "C", "A", "D"]);
+    assert_eq!(harness.app.song_list_state.selected(), Some(2));
+}
+
+#[tokio::test]
+async fn opens_configured_startup_view() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("First", &[]), playlist("Second", &[])],
+            startup_window: 2,
+            startup_playlist: String::from("Second"),
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    assert_eq!(harness.app.playlist_list_state.selected(), Some(1));
+    assert_eq!(harness.app.window, Window::GlobalSongs);
+    assert_eq!(harness.app.focused, Focused::Right);
+
+    // Going back to the playlists lands on the startup playlist
+    harness.press(KeyCode::Char('h')).await;
+    assert_eq!(harness.app.playlists[1].selected, Selected::Focused);
+    assert_eq!(harness.app.playlists[0].selected, Selected::None);
+}
+
+#[tokio::test]
+async fn low_memory_mode_loads_one_song_at_a_time() {
+    let paths: Vec<PathBuf> = ["low-memory-a", "low-memory-b", "low-memory-c"]
+        .into_iter()
+        .map(silent_mp3)
+        .collect();
+    let songs = ["A", "B", "C"]
+        .into_iter()
+        .zip(&paths)
+        .map(|(name, path)| song(name, &path.to_string_lossy()))
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["A", "B", "C"])],
+            songs,
+            low_memory: true,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Enter).await;
+    for path in &paths {
+        fs::remove_file(path).unwrap();
+    }
+    assert_eq!(harness.app.song_queue.len(), 1);
+    assert_eq!(harness.app.sink.len(), 1);
+
+    harness.press(KeyCode::Char('s')).await;
+    assert!(harness.render().contains("Songs loaded in the player: 1"));
+}
+
+#[tokio::test]
+async fn imports_youtube_playlists() {
+    assert!(super::is_valid_youtube_playlist_link(
+        &crate::link::normalize(
+            "https://music.youtube.com/playlist?list=PLx0sYbCqOb8TBPRdmBHs5Iftvv9TPboYG&si=abc"
+        )
+    ));
+    assert!(!super::is_valid_youtube_playlist_link(
+        "https://www.youtube.com/playlist"
+    ));
+
+    let songs = ["Intro", "Outro"]
+        .into_iter()
+        .map(|name| SerializableSong {
+            url: format!("https://youtube.com/watch?v={name:-<11}"),
+            ..song(name, &format!("/nonexistent/{name}.mp3"))
+        })
+        .collect();
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            songs,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    let entry = |id: &str, title: &str| YoutubePlaylistEntry {
+        id: String::from(id),
+        title: String::from(title),
+    };
+    harness.app.handle_result(Ok(TaskReturn::YoutubePlaylist(
+        0,
+        YoutubePlaylist {
+            title: String::from("Mixtape"),
+            entries: vec![
+                entry("Outro------", "Outro (Official Video)"),
+                entry("xxxxxxxxxxx", "[Private video]"),
+                entry("Intro------", "Intro"),
+            ],
+        },
+    )));
+
+    // Both videos were already downloaded, so nothing is left to do
+    assert!(harness.app.downloads.is_empty());
+    assert_eq!(harness.app.save_data.playlists[0].name, "Mixtape");
+    assert_eq!(harness.app.save_data.playlists[0].songs, ["Outro", "Intro"]);
+}
+
+#[tokio::test]
+async fn jumps_to_config_field_from_error_hint() {
+    let mut harness = Harness::with_playlist(120, 24);
+
+    harness.app.handle_result(Err(Error::SpotifyCredentials));
+    assert!(harness.render().contains(
+        "Spotify didn't accept the client ID/secret (b - set the Spotify client ID/secret)"
+    ));
+
+    harness.press(KeyCode::Char('b')).await;
+    assert_eq!(harness.app.window, Window::ConfigurationMenu);
+    assert_eq!(harness.app.focused, Focused::Right);
+    assert_eq!(harness.app.config_menu_state.selected(), Some(1));
+    assert_eq!(
+        harness.app.config.spotify_client_id.selected,
+        Selected::Focused
+    );
+
+    // The hint goes away with the message it was for
+    harness.app.log = String::from("Something else");
+    assert!(!harness.render().contains("b - set"));
+}
+
+#[tokio::test]
+async fn limits_parallel_playlist_songs() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            max_parallel_songs: 1,
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    // A song that's still being downloaded holds the only slot
+    let permit = harness.app.playlist_slots.clone().acquire_owned().await;
+    harness
+        .app
+        .spawn_playlist_task(async { Ok(TaskReturn::DlpDownloaded) });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(harness.app.task_results.is_empty());
+
+    drop(permit);
+    assert!(matches!(
+        harness.task_result(|_| true).await,
+        Ok(TaskReturn::DlpDownloaded)
+    ));
+}
+
+#[tokio::test]
+async fn pauses_and_resumes_downloads() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.press(KeyCode::Char('d')).await;
+    harness.press(KeyCode::Char('l')).await;
+
+    harness.press(KeyCode::Enter).await;
+    assert!(harness.render().contains("(paused)"));
+    harness
+        .app
+        .spawn_download(async { Ok(TaskReturn::DlpDownloaded) });
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(harness.app.task_results.is_empty());
+
+    harness.press(KeyCode::Enter).await;
+    assert!(!harness.render().contains("(paused)"));
+    assert!(matches!(
+        harness.task_result(|_| true).await,
+        Ok(TaskReturn::DlpDownloaded)
+    ));
+}
+
+#[tokio::test]
+async fn downloads_in_configured_format() {
+    let mut harness = Harness::new(100, 40);
+    harness.press(KeyCode::Char('c')).await;
+    harness.app.config_menu_state.select(Some(22));
+    harness.app.focused = Focused::Right;
+
+    harness.press(KeyCode::Enter).await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.save_data.audio_format, AudioFormat::Opus);
+    assert!(harness
+        .render()
+        .contains("Download format: opus (best quality)"));
+
+    // New playlists start with it
+    harness.app.focused = Focused::Left;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Road trip").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(
+        harness.app.save_data.playlists[0].audio_format,
+        AudioFormat::Opus
+    );
+
+    harness.app.handle_result(Ok(TaskReturn::SongDownloaded(
+        0,
+        SearchFor::GlobalSong(String::from("Song")),
+        String::from("https://youtube.com/watch?v=dQw4w9WgXcQ"),
+    )));
+    assert!(harness.app.save_data.songs[0].path.ends_with("Song.opus"));
+}
+
+#[tokio::test]
+async fn adds_songs_in_other_formats() {
+    let path = env::temp_dir().join("quefi-test-format.m4a");
+    fs::write(&path, b"").unwrap();
+    let mut harness = Harness::with_playlist(80, 24);
+
+    harness.press(KeyCode::Char('g')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text("Song").await;
+    harness.press(KeyCode::Enter).await;
+
+    harness.type_text(path.to_str().unwrap()).await;
+    assert!(harness.app.valid_input);
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.global_songs[0].path, path.to_str().unwrap());
+
+    assert_eq!(AudioFormat::Flac.next(), AudioFormat::M4a);
+    assert_eq!(AudioFormat::M4a.extension(), "m4a");
+}
+
+#[tokio::test]
+async fn sets_cookies_for_yt_dlp() {
+    let mut harness = Harness::with_playlist(100, 40);
+    harness.press(KeyCode::Char('c')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.app.config_menu_state.select(Some(23));
+
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("netscape").await;
+    assert!(!harness.app.valid_input);
+
+    harness.press(KeyCode::Esc).await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("chrome:Profile 1").await;
+    assert!(harness.app.valid_input);
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.dlp.cookies, "chrome:Profile 1");
+    assert_eq!(harness.app.save_data.dlp_cookies, "chrome:Profile 1");
+}
+
+#[tokio::test]
+async fn picks_search_result_before_downloading() {
+    let mut harness = Harness::with_playlist(100, 24);
+    let result = |id: &str, title: &str| SearchResult {
+        video_id: String::from(id),
+        duration_ms: 200_000,
+        title: String::from(title),
+        artist: String::from("Artist"),
+    };
+
+    harness.app.handle_result(Ok(TaskReturn::SearchResults(
+        0,
+        vec![
+            result("aaaaaaaaaaa", "Song (Live)"),
+            result("bbbbbbbbbbb", "Song"),
+        ],
+        SearchFor::GlobalSong(String::from("Song")),
+    )));
+    assert_eq!(harness.app.mode, Mode::PickResult);
+    let screen = harness.render();
+    assert!(screen.contains("Results for Song"));
+    assert!(screen.contains("Song (Live) - Artist (3:20)"));
+
+    harness.press(KeyCode::Down).await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert!(matches!(
+        harness.app.downloads.get(&0),
+        Some(super::Download::DownloadingSong(name)) if name == "Song"
+    ));
+
+    // Cancelling drops the download
+    harness.app.handle_result(Ok(TaskReturn::SearchResults(
+        1,
+        vec![result("ccccccccccc", "Other")],
+        SearchFor::GlobalSong(String::from("Other")),
+    )));
+    harness.press(KeyCode::Esc).await;
+    assert!(!harness.app.downloads.contains_key(&1));
+}
+
+#[tokio::test]
+async fn replaces_wrongly_matched_song() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Song"])],
+            songs: vec![SerializableSong {
+                url: String::from("https://www.youtube.com/watch?v=aaaaaaaaaaa"),
+                ..song("Song", "/nonexistent/song.mp3")
+            }],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('W')).await;
+    assert_eq!(harness.app.text_area.lines()[0], "Song");
+
+    // A corrected query shows the results to pick from
+    harness.type_text(" studio version").await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&0),
+        Some(super::Download::SearchingForSong(query)) if query == "Song studio version"
+    ));
+    assert!(harness.app.replacing_songs.contains("Song"));
+
+    // A link is downloaded straight away
+    harness.app.downloads.clear();
+    harness.press(KeyCode::Char('W')).await;
+    harness.app.text_area.select_all();
+    harness.app.text_area.cut();
+    harness
+        .type_text("https://www.youtube.com/watch?v=bbbbbbbbbbb")
+        .await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&1),
+        Some(super::Download::DownloadingSong(name)) if name == "Song"
+    ));
+    assert!(harness.app.replacing_songs.is_empty());
+}
+
+#[tokio::test]
+async fn moves_songs_to_new_music_directory() {
+    let dir = env::temp_dir().join(format!("quefi-test-{}-music", std::process::id()));
+    let old_dir = dir.join("old");
+    let new_dir = dir.join("new");
+    fs::create_dir_all(&old_dir).unwrap();
+    fs::create_dir_all(&new_dir).unwrap();
+    let old_path = old_dir.join("Song.mp3");
+    fs::write(&old_path, b"").unwrap();
+
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            music_dir: old_dir.to_string_lossy().to_string(),
+            playlists: vec![playlist("Liked", &["Song"])],
+            songs: vec![song("Song", &old_path.to_string_lossy())],
+            ..SaveData::default()
+        },
+        100,
+        40,
+    );
+    harness.press(KeyCode::Char('c')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.app.config_menu_state.select(Some(24));
+
+    harness.press(KeyCode::Enter).await;
+    harness.type_text(&new_dir.to_string_lossy()).await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.dlp.songs_dir, new_dir);
+    harness.type_text("y").await;
+    harness.press(KeyCode::Enter).await;
+
+    let new_path = new_dir.join("Song.mp3");
+    let moved = new_path.is_file() && !old_path.exists();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(moved);
+    assert_eq!(
+        harness.app.save_data.songs[0].path,
+        new_path.to_str().unwrap()
+    );
+    assert_eq!(
+        harness.app.playlists[0].songs[0].path,
+        new_path.to_str().unwrap()
+    );
+    assert_eq!(harness.app.log, "Moved 1 songs");
+}
+
+#[tokio::test]
+async fn keeps_download_history() {
+    let mut harness = Harness::with_playlist(120, 40);
+    harness.press(KeyCode::Char('Y')).await;
+    assert_eq!(harness.app.log, "Nothing was downloaded yet");
+
+    harness.app.handle_result(Ok(TaskReturn::SongDownloaded(
+        0,
+        SearchFor::GlobalSong(String::from("Song")),
+        String::from("https://youtube.com/watch?v=dQw4w9WgXcQ"),
+    )));
+    harness.press(KeyCode::Char('Y')).await;
+    assert_eq!(harness.app.window, Window::DownloadHistory);
+    let screen = harness.render();
+    assert!(screen.contains("Download history"));
+    assert!(screen.contains("Song.mp3 (0 B)  from https://youtube.co"));
+
+    harness.app.focused = Focused::Right;
+    harness.press(KeyCode::Char('n')).await;
+    assert!(harness.app.save_data.download_history.is_empty());
+}
+
+#[tokio::test]
+async fn lists_failed_playlist_songs() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            ..SaveData::default()
+        },
+        100,
+        30,
+    );
+    let entry = |id: &str, title: &str| YoutubePlaylistEntry {
+        id: String::from(id),
+        title: String::from(title),
+    };
+    harness.app.handle_result(Ok(TaskReturn::YoutubePlaylist(
+        0,
+        YoutubePlaylist {
+            title: String::from("Mixtape"),
+            entries: vec![
+                entry("aaaaaaaaaaa", "Gone"),
+                entry("bbbbbbbbbbb", "Blocked"),
+            ],
+        },
+    )));
+
+    let failed = |name: &str, slot: usize, reason: &str| {
+        Err(Error::SongFailed(
+            0,
+            SearchFor::Playlist(0, String::from(name), slot),
+            String::from(reason),
+        ))
+    };
+    harness
+        .app
+        .handle_result(failed("Gone", 0, "Video unavailable"));
+    harness.app.handle_result(failed(
+        "Blocked",
+        1,
+        "The uploader has not made this video available in your country",
+    ));
+    assert!(harness.app.downloads.is_empty());
+    assert_eq!(
+        harness.app.log,
+        "Finished Mixtape, 2 failed (listed in the download manager)"
+    );
+
+    harness.press(KeyCode::Char('d')).await;
+    let screen = harness.render();
+    assert!(screen.contains("Failed to download for Mixtape (2):"));
+    assert!(screen.contains("Gone: Video unavailable"));
+
+    let path = env::temp_dir().join(format!("quefi-test-{}-failures.txt", std::process::id()));
+    harness.press(KeyCode::Char('X')).await;
+    harness.app.text_area.select_all();
+    harness.app.text_area.cut();
+    harness.type_text(&path.to_string_lossy()).await;
+    harness.press(KeyCode::Enter).await;
+    let contents = fs::read_to_string(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+    assert!(contents.starts_with("Mixtape\n  Gone: Video unavailable\n  Blocked: "));
+}
+
+#[tokio::test]
+async fn never_reuses_download_ids() {
+    let mut harness = Harness::with_playlist(100, 30);
+    harness.press(KeyCode::Char('d')).await;
+    harness.app.focused = Focused::Right;
+
+    for _ in 0..2 {
+        harness.press(KeyCode::Char('a')).await;
+        harness
+            .type_text("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+            .await;
+        harness.press(KeyCode::Enter).await;
+    }
+    assert_eq!(harness.app.downloads.keys().collect::<Vec<_>>(), [&0, &1]);
+
+    // The first one failing used to free its id for the next download
+    harness.app.handle_result(Err(Error::SongFailed(
+        0,
+        SearchFor::GlobalSong(String::from("Song")),
+        String::from("Video unavailable"),
+    )));
+    harness.press(KeyCode::Char('a')).await;
+    harness
+        .type_text("https://www.youtube.com/watch?v=dQw4w9WgXcQ")
+        .await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.downloads.keys().collect::<Vec<_>>(), [&1, &2]);
+}
+
+#[tokio::test]
+async fn removes_failed_fetches_from_downloads() {
+    let mut harness = Harness::new(80, 24);
+    let url = String::from("https://www.youtube.com/watch?v=dQw4w9WgXcQ");
+    harness.app.mode = Mode::Input(InputMode::StreamLink);
+    harness.type_text(&url).await;
+    harness.press(KeyCode::Enter).await;
+    let id = *harness.app.downloads.keys().next().unwrap();
+
+    harness.app.handle_result(Err(Error::Stream(id, url)));
+    assert!(harness.app.downloads.is_empty());
+    assert_eq!(harness.app.session_stats.failed_downloads, 1);
+    // Errors that have nothing to do with downloads aren't counted
+    harness.app.handle_result(Err(Error::NoNowPlayingFile));
+    assert_eq!(harness.app.session_stats.failed_downloads, 1);
+
+    // Bandcamp and SoundCloud songs have their metadata fetched first
+    let url = String::from("https://artist.bandcamp.com/track/song");
+    harness.press(KeyCode::Char('d')).await;
+    harness.app.focused = Focused::Right;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text(&url).await;
+    harness.press(KeyCode::Enter).await;
+    let id = *harness.app.downloads.keys().next().unwrap();
+    assert!(matches!(
+        harness.app.downloads[&id],
+        Download::FetchingLinkMetadata
+    ));
+    harness.app.handle_result(Err(Error::Metadata(id, url)));
+    assert!(harness.app.downloads.is_empty());
+
+    let url =
+        String::from("https://www.youtube.com/playlist?list=PLFgquLnL59alCl_2TQvOiD5Vgm1hCaGSI");
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text(&url).await;
+    harness.press(KeyCode::Enter).await;
+    let id = *harness.app.downloads.keys().next().unwrap();
+    assert!(matches!(
+        harness.app.downloads[&id],
+        Download::FetchingPlaylistInfo
+    ));
+    harness
+        .app
+        .handle_result(Err(Error::YoutubePlaylist(id, url)));
+    assert!(harness.app.downloads.is_empty());
+
+    // Albums are listed by the same kind of download
+    let url = "https://music.youtube.com/browse/UCabc";
+    let result = youtube::fetch_album(3, &harness.app.client, url).await;
+    assert!(matches!(result, Err(Error::YoutubePlaylist(3, _))));
+
+    // A search that can't reach YT Music, through a proxy nothing listens on
+    let closed = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let proxy = format!("http://{}", closed.local_addr().unwrap());
+    drop(closed);
+    let client = reqwest::Client::builder()
+        .proxy(reqwest::Proxy::all(proxy).unwrap())
+        .build()
+        .unwrap();
+    harness
+        .app
+        .downloads
+        .insert(4, Download::SearchingForSong(String::from("Song")));
+    let result = youtube::search_ytmusic_choices(
+        4,
+        &client,
+        "Song",
+        0,
+        SearchFilter::Songs,
+        SearchFor::GlobalSong(String::from("Song")),
+    )
+    .await;
+    assert!(matches!(result, Err(Error::SongFailed(4, ..))));
+    harness.app.handle_result(result);
+    assert!(harness.app.downloads.is_empty());
+}
+
+#[tokio::test]
+async fn forgets_failed_downloads() {
+    let mut harness = Harness::new(80, 24);
+    // Other tests would be asked to resume it if it was in the data directory
+    let pending_path =
+        env::temp_dir().join(format!("quefi-test-{}-failed.pending", std::process::id()));
+    harness.app.dlp.pending_path = pending_path.clone();
+
+    // yt-dlp that can't be started, and one that exits with an error
+    for program in ["quefi-test-missing-yt-dlp", "false"] {
+        harness.app.dlp.path = String::from(program);
+        let download = youtube::PendingDownload {
+            name: String::from("Failed Song"),
+            filename: String::from("Failed Song"),
+            link: String::from("https://youtube.com/watch?v=dQw4w9WgXcQ"),
+            playlist: String::new(),
+            format: AudioFormat::Mp3,
+        };
+        let result = youtube::download_song(
+            0,
+            &harness.app.dlp,
+            download,
+            SearchFor::GlobalSong(String::from("Failed Song")),
+            None,
+        )
+        .await;
+        assert!(result.is_err());
+        assert!(harness.app.dlp.pending_downloads().is_empty());
+        assert!(!pending_path.exists());
+    }
+}
+
+#[tokio::test]
+async fn resumes_interrupted_downloads_into_their_playlist() {
+    let mut harness = Harness::with_playlist(80, 24);
+    harness.app.dlp.pending_path =
+        env::temp_dir().join(format!("quefi-test-{}-resume.pending", std::process::id()));
+    harness.app.dlp.path = String::from("quefi-test-missing-yt-dlp");
+    let link = String::from("https://youtube.com/watch?v=dQw4w9WgXcQ");
+    harness.app.dlp.start_pending(youtube::PendingDownload {
+        name: String::from("Interrupted Song"),
+        filename: String::from("Interrupted Song"),
+        link: link.clone(),
+        playlist: String::from("Liked"),
+        format: AudioFormat::Opus,
+    });
+
+    harness.app.mode = Mode::Input(InputMode::ResumeDownloads);
+    harness.type_text("y").await;
+    harness.press(KeyCode::Enter).await;
+    let (&id, resumed) = harness.app.resumed_downloads.iter().next().unwrap();
+    assert_eq!(resumed.playlist, "Liked");
+    assert_eq!(resumed.format, AudioFormat::Opus);
+
+    // Failing again doesn't offer it on the next start
+    let failed = harness.task_result(|result| result.is_err()).await;
+    assert!(matches!(failed, Err(Error::DlpMissing)));
+    assert!(harness.app.dlp.pending_downloads().is_empty());
+
+    let path = harness.app.dlp.songs_dir.join("Interrupted Song.opus");
+    fs::copy(silent_mp3("resumed"), &path).unwrap();
+    harness.app.handle_result(Ok(TaskReturn::SongDownloaded(
+        id,
+        SearchFor::GlobalSong(String::from("Interrupted Song")),
+        link,
+    )));
+    fs::remove_file(&path).unwrap();
+    assert_eq!(
+        harness.app.save_data.playlists[0].songs,
+        ["Interrupted Song"]
+    );
+    assert_eq!(harness.app.save_data.songs[0].path, path.to_string_lossy());
+}
+
+#[tokio::test]
+async fn verifies_yt_dlp_download() {
+    let sum = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+    let checksums = format!("{sum}  yt-dlp\n0000  yt-dlp.exe\n");
+    assert!(youtube::checksum_matches(&checksums, "yt-dlp", b"abc"));
+    assert!(!youtube::checksum_matches(&checksums, "yt-dlp", b"abd"));
+    assert!(!youtube::checksum_matches(&checksums, "yt-dlp.exe", b"abc"));
+
+    let mut harness = Harness::new(100, 40);
+    harness.app.dlp_version = Some(String::from("2025.09.05"));
+    harness.press(KeyCode::Char('c')).await;
+    assert!(harness.render().contains("yt-dlp 2025.09.05"));
+}
+
+#[tokio::test]
+async fn sets_up_ffmpeg_for_yt_dlp() {
+    let mut harness = Harness::with_playlist(100, 40);
+    harness.app.log = String::from("yt-dlp couldn't find ffmpeg");
+    harness.app.hint = Some((Hint::InstallFfmpeg, harness.app.log.clone()));
+    harness.press(KeyCode::Char('b')).await;
+    assert!(harness
+        .render()
+        .contains("ffmpeg wasn't found, download it now?"));
+    harness.press(KeyCode::Esc).await;
+
+    harness.press(KeyCode::Char('c')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.app.config_menu_state.select(Some(25));
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("/nonexistent/ffmpeg").await;
+    assert!(!harness.app.valid_input);
+
+    harness.press(KeyCode::Esc).await;
+    harness
+        .app
+        .handle_result(Ok(TaskReturn::FfmpegDownloaded(PathBuf::from(
+            "/opt/ffmpeg",
+        ))));
+    assert_eq!(harness.app.dlp.ffmpeg_path, "/opt/ffmpeg");
+    assert_eq!(harness.app.save_data.ffmpeg_path, "/opt/ffmpeg");
+    assert!(harness.render().contains("ffmpeg path: /opt/ffmpeg"));
+}
+
+#[cfg(not(target_os = "windows"))]
+#[tokio::test]
+async fn runs_command_after_download() {
+    let mut harness = Harness::with_playlist(100, 40);
+    harness.press(KeyCode::Char('c')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.app.config_menu_state.select(Some(26));
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("test -f").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.save_data.download_hook, "test -f");
+
+    let path = env::temp_dir().join(format!("quefi-test-{}-hook.mp3", std::process::id()));
+    fs::write(&path, b"").unwrap();
+    harness
+        .app
+        .record_download("https://youtu.be/a", &path.to_string_lossy());
+    let result = harness.task_result(|_| true).await;
+    fs::remove_file(&path).unwrap();
+    assert!(matches!(result, Ok(TaskReturn::DownloadHookRan)));
+
+    // The file's gone now, so the command fails
+    harness
+        .app
+        .record_download("https://youtu.be/a", &path.to_string_lossy());
+    let result = harness.task_result(|_| true).await;
+    harness.app.handle_result(result);
+    assert!(harness
+        .app
+        .log
+        .starts_with("Post-download command failed for "));
+}
+
+#[tokio::test]
+async fn imports_every_page_of_spotify_playlist() {
+    let mut harness = Harness::with_playlist(100, 40);
+    let track = |name: &str| TrackInfo {
+        duration_ms: 200_000,
+        query: format!("Artist - {name}"),
+        name: String::from(name),
+        artist: String::from("Artist"),
+        album: None,
+        track_number: 0,
+        cover_url: None,
+        filter: SearchFilter::Songs,
+        preview_url: None,
+    };
+
+    harness.app.handle_result(Ok(TaskReturn::PlaylistInfo(
+        7,
+        PlaylistInfo {
+            tracks: vec![track("First page")],
+            name: String::from("Long playlist"),
+            next: Some(String::from("http://127.0.0.1:1/tracks?offset=100")),
+            spotify_id: Some(String::from("37i9dQZF1DXcBWIGoYBM5M")),
+        },
+    )));
+    let playlist_idx = harness.app.playlists.len() - 1;
+    assert_eq!(
+        harness.app.save_data.playlists[playlist_idx].spotify_id,
+        "37i9dQZF1DXcBWIGoYBM5M"
+    );
+    let processing = |app: &App| match app.downloads.get(&7) {
+        Some(super::Download::ProcessingPlaylistSongs(processing)) => {
+            (processing.total_to_search, processing.more_tracks)
+        }
+        _ => panic!("The import isn't running"),
+    };
+    assert_eq!(processing(&harness.app), (1, true));
+
+    harness.app.handle_result(Ok(TaskReturn::PlaylistTracks(
+        7,
+        playlist_idx,
+        vec![track("Second page"), track("Also second page")],
+        None,
+    )));
+    assert_eq!(processing(&harness.app), (3, false));
+    assert_eq!(harness.app.playlists[playlist_idx].songs.len(), 3);
+
+    // A page that can't be fetched doesn't hold up the rest of the import
+    harness.app.downloads.insert(
+        8,
+        super::Download::ProcessingPlaylistSongs(super::ProcessingPlaylistSongs {
+            playlist_name: String::from("Other"),
+            searching_songs: Vec::new(),
+            downloading_songs: Vec::new(),
+            total_to_download: 0,
+            total_to_search: 0,
+            downloaded: 0,
+            searched: 0,
+            reused: 0,
+            failed: 0,
+            more_tracks: true,
+            to_tag: Vec::new(),
+        }),
+    );
+    let result = harness
+        .task_result(|result| matches!(result, Err(Error::PlaylistTracks(7, _))))
+        .await;
+    harness.app.handle_result(match result {
+        Err(Error::PlaylistTracks(_, reason)) => Err(Error::PlaylistTracks(8, reason)),
+        result => result,
+    });
+    assert!(!harness.app.downloads.contains_key(&8));
+    assert!(harness
+        .app
+        .log
+        .starts_with("Couldn't fetch the rest of the playlist"));
+}
+
+#[tokio::test]
+async fn refreshes_token_for_playlist_page() {
+    let mut harness = Harness::with_playlist(10, 4);
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!(
+        "http://{}/v1/playlists/abc/tracks?offset=100",
+        listener.local_addr().unwrap()
+    );
+    // The token expires between the pages, the same page is asked for again once it's refreshed
+    let server = tokio::spawn(async move {
+        let mut tokens = Vec::new();
+        for response in [
+            "HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+            "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: 24\r\nConnection: close\r\n\r\n{\"items\":[],\"next\":null}",
+        ] {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = Vec::new();
+            let mut buf = [0; 1024];
+            while !request.ends_with(b"\r\n\r\n") {
+                let read = stream.read(&mut buf).await.unwrap();
+                request.extend_from_slice(&buf[..read]);
+            }
+            let request = String::from_utf8(request).unwrap();
+            tokens.extend(
+                request
+                    .lines()
+                    .find_map(|line| line.strip_prefix("authorization: Bearer "))
+                    .map(String::from),
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+        tokens
+    });
+
+    let client = reqwest::Client::new();
+    let result = spotify::fetch_playlist_tracks(9, &client, 0, &url, "expired").await;
+    let link = spotify::SpotifyLink::PlaylistPage(0, url.clone());
+    assert!(matches!(&result, Err(Error::SpotifyBadAuth(9, failed)) if *failed == link));
+
+    harness.app.downloads.insert(
+        9,
+        super::Download::ProcessingPlaylistSongs(super::ProcessingPlaylistSongs {
+            playlist_name: String::from("Playlist"),
+            searching_songs: Vec::new(),
+            downloading_songs: Vec::new(),
+            total_to_download: 0,
+            total_to_search: 0,
+            downloaded: 0,
+            searched: 0,
+            reused: 0,
+            failed: 0,
+            more_tracks: true,
+            to_tag: Vec::new(),
+        }),
+    );
+    harness.app.handle_result(Ok(TaskReturn::Token(
+        9,
+        String::from("fresh"),
+        crate::unix_timestamp() + 3600,
+        link.clone(),
+    )));
+    let result = harness
+        .task_result(|result| matches!(result, Ok(TaskReturn::PlaylistTracks(9, ..))))
+        .await;
+    assert!(
+        matches!(&result, Ok(TaskReturn::PlaylistTracks(9, 0, tracks, None)) if tracks.is_empty())
+    );
+    assert_eq!(server.await.unwrap(), ["expired", "fresh"]);
+
+    // The import keeps its progress while a new token is made
+    harness
+        .app
+        .handle_result(Err(Error::SpotifyBadAuth(9, link)));
+    assert!(matches!(
+        harness.app.downloads.get(&9),
+        Some(super::Download::ProcessingPlaylistSongs(_))
+    ));
+}
+
+#[tokio::test]
+async fn syncs_imported_spotify_playlist() {
+    let track = |name: &str| TrackInfo {
+        duration_ms: 200_000,
+        query: format!("Artist - {name}"),
+        name: String::from(name),
+        artist: String::from("Artist"),
+        album: None,
+        track_number: 0,
+        cover_url: None,
+        filter: SearchFilter::Songs,
+        preview_url: None,
+    };
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![SerializablePlaylist {
+                spotify_id: String::from("37i9dQZF1DXcBWIGoYBM5M"),
+                ..playlist("Imported", &["Kept", "Gone", "Added by hand"])
+            }],
+            songs: ["Kept", "Gone", "Added by hand"]
+                .map(|name| song(name, &format!("/nonexistent/{name}.mp3")))
+                .into(),
+            ..SaveData::default()
+        },
+        100,
+        24,
+    );
+
+    harness
+        .app
+        .show_playlist_sync(0, vec![track("Kept"), track("New")]);
+    let screen = harness.render();
+    assert!(screen.contains("+ Artist - New"));
+    assert!(screen.contains("- Gone"));
+    assert!(screen.contains("- Added by hand"));
+
+    // Songs added by hand can be kept
+    harness.press(KeyCode::Char('l')).await;
+    harness.press(KeyCode::Char('j')).await;
+    harness.press(KeyCode::Char('j')).await;
+    harness.press(KeyCode::Char('n')).await;
+    harness.press(KeyCode::Enter).await;
+
+    assert_eq!(
+        harness.app.save_data.playlists[0].songs,
+        ["Kept", "Added by hand", ""]
+    );
+    assert!(harness
+        .app
+        .log
+        .starts_with("Synced Imported, removed 1 song(s)"));
+    assert_eq!(harness.app.window, Window::Songs);
+}
+
+#[tokio::test]
+async fn refreshes_spotify_token_before_it_expires() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            last_valid_token: String::from("token"),
+            token_expires: crate::unix_timestamp() + 10,
+            ..SaveData::default()
+        },
+        100,
+        30,
+    );
+    harness.app.window = Window::DownloadManager;
+    harness.app.focused = Focused::Right;
+    let link = "https://open.spotify.com/track/4aawyAB9vmqN3uQ7FjRGTy";
+
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text(link).await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&0),
+        Some(super::Download::FetchingSpotifyToken)
+    ));
+
+    harness.app.save_data.token_expires = crate::unix_timestamp() + 3600;
+    harness.press(KeyCode::Char('a')).await;
+    harness.type_text(link).await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&1),
+        Some(super::Download::FetchingTrackInfo)
+    ));
+}
+
+#[tokio::test]
+async fn shows_spotify_token_status_in_configuration_menu() {
+    let mut harness = Harness::with_playlist(100, 40);
+    harness.press(KeyCode::Char('c')).await;
+    assert!(harness.render().contains("Spotify token: missing"));
+
+    harness.app.save_data.last_valid_token = String::from("token");
+    harness.app.save_data.token_expires = crate::unix_timestamp() + 3600;
+    assert!(harness
+        .render()
+        .contains("Spotify token: valid, expires in 60m"));
+
+    harness.app.save_data.token_expires = crate::unix_timestamp() - 1;
+    assert!(harness.render().contains("Spotify token: expired"));
+}
+
+#[tokio::test]
+async fn sets_spotify_market() {
+    let mut harness = Harness::with_playlist(100, 40);
+    harness.press(KeyCode::Char('c')).await;
+    harness.press(KeyCode::Char('l')).await;
+    harness.app.config_menu_state.select(Some(27));
+    assert!(harness.render().contains("Spotify market: None"));
+
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("Poland").await;
+    assert!(!harness.app.valid_input);
+
+    harness.press(KeyCode::Esc).await;
+    harness.press(KeyCode::Enter).await;
+    harness.type_text("pl").await;
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(harness.app.save_data.spotify_market, "PL");
+    assert!(harness.render().contains("Spotify market: PL"));
+}
+
+#[tokio::test]
+async fn imports_spotify_search_results() {
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            last_valid_token: String::from("token"),
+            token_expires: crate::unix_timestamp() + 3600,
+            ..SaveData::default()
+        },
+        100,
+        30,
+    );
+    harness.press(KeyCode::Char('G')).await;
+    harness.type_text("discovery").await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&0),
+        Some(super::Download::SearchingSpotify(query)) if query == "discovery"
+    ));
+
+    harness.app.handle_result(Ok(TaskReturn::SpotifySearch(
+        0,
+        String::from("discovery"),
+        vec![
+            spotify::SearchItem {
+                link: spotify::SpotifyLink::Track(String::from("0DiWol3AO6WpXZgp0goxAV")),
+                name: String::from("One More Time"),
+                by: String::from("Daft Punk"),
+                preview_url: None,
+            },
+            spotify::SearchItem {
+                link: spotify::SpotifyLink::Album(String::from("2noRn2Aes5aoNVsU6iWThc")),
+                name: String::from("Discovery"),
+                by: String::from("Daft Punk"),
+                preview_url: None,
+            },
+        ],
+    )));
+    assert!(!harness.app.downloads.contains_key(&0));
+    assert_eq!(harness.app.mode, Mode::PickSpotifyResult);
+    let screen = harness.render();
+    assert!(screen.contains("Spotify results for discovery"));
+    assert!(screen.contains("Album: Discovery - Daft Punk"));
+
+    harness.press(KeyCode::Down).await;
+    harness.press(KeyCode::Enter).await;
+    assert!(matches!(
+        harness.app.downloads.get(&1),
+        Some(super::Download::FetchingPlaylistInfo)
+    ));
+    // The track is still there to be imported
+    assert_eq!(harness.app.mode, Mode::PickSpotifyResult);
+    assert!(harness
+        .render()
+        .contains("Track: One More Time - Daft Punk"));
+
+    harness.press(KeyCode::Esc).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+    assert!(harness.app.spotify_search.is_none());
+}
+
+#[tokio::test]
+async fn previews_spotify_track_before_downloading() {
+    let mut harness = Harness::new(100, 30);
+    harness.app.handle_result(Ok(TaskReturn::TrackInfo(
+        0,
+        TrackInfo {
+            duration_ms: 200_000,
+            query: String::from("Artist - Song"),
+            name: String::from("Song"),
+            artist: String::from("Artist"),
+            album: None,
+            track_number: 0,
+            cover_url: None,
+            filter: SearchFilter::Songs,
+            preview_url: Some(String::from("http://127.0.0.1:1/preview.mp3")),
+        },
+    )));
+    harness.app.handle_result(Ok(TaskReturn::SearchResults(
+        0,
+        vec![SearchResult {
+            video_id: String::from("aaaaaaaaaaa"),
+            duration_ms: 200_000,
+            title: String::from("Song"),
+            artist: String::from("Artist"),
+        }],
+        SearchFor::GlobalSong(String::from("Song")),
+    )));
+    assert_eq!(harness.app.mode, Mode::PickResult);
+    assert!(harness.render().contains("P - preview"));
+
+    harness.press(KeyCode::Char('P')).await;
+    assert_eq!(harness.app.log, "Fetching the Spotify preview...");
+    let result = harness
+        .task_result(|result| matches!(result, Err(Error::SpotifyPreview(_))))
+        .await;
+    harness.app.handle_result(result);
+    assert!(harness
+        .app
+        .log
+        .starts_with("Couldn't fetch the Spotify preview: "));
+
+    // Without an audio output there's nothing to play it on
+    harness
+        .app
+        .handle_result(Ok(TaskReturn::SpotifyPreview(Vec::new())));
+    assert_eq!(harness.app.log, "No audio output to preview on");
+    assert_eq!(harness.app.mode, Mode::PickResult);
+}
+
+#[tokio::test]
+async fn shows_unsaved_changes() {
+    let mut harness = Harness::with_playlist(100, 30);
+    assert!(!harness.render().contains("unsaved changes"));
+
+    harness.press(KeyCode::Char('S')).await;
+    // Changes are only looked for every few seconds
+    harness.app.autosave();
+    assert!(!harness.app.has_unsaved_changes());
+    harness.app.last_save_check = Instant::now() - Duration::from_secs(3);
+    harness.app.autosave();
+    assert!(harness.app.has_unsaved_changes());
+    assert!(harness.render().contains("unsaved changes"));
+
+    harness.app.read_only = true;
+    harness
+        .app
+        .handle_key(KeyEvent::new(KeyCode::Char('s'), KeyModifiers::CONTROL))
+        .await;
+    assert_eq!(
+        harness.app.log,
+        "Quefi is already running, changes made here won't be saved"
+    );
+}
+
+#[test]
+fn keeps_test_files_next_to_the_executable() {
+    init_test_dirs();
+    let exe_dir = env::current_exe().unwrap().parent().unwrap().join("quefi");
+    assert!(crate::is_portable());
+    assert_eq!(crate::get_quefi_dir(), exe_dir);
+    assert_eq!(crate::get_cache_dir(), exe_dir.join("cache"));
+    assert_eq!(crate::get_config_dir(), exe_dir);
+    assert_eq!(
+        youtube::downloaded_dlp_path(),
+        PathBuf::from(youtube::DLP_EXECUTABLE_NAME)
+    );
+}
+
+#[tokio::test]
+async fn keeps_log_history() {
+    let mut harness = Harness::new(100, 30);
+    harness.app.log = String::from("Downloading Song");
+    harness.app.record_log();
+    harness.app.record_log();
+    harness.app.handle_result(Err(Error::YtMusic));
+    harness.app.log = String::from("Downloaded Song");
+
+    harness.press(KeyCode::Char('V')).await;
+    assert_eq!(harness.app.window, Window::Log);
+    let entries: Vec<(&str, bool)> = harness
+        .app
+        .log_history
+        .iter()
+        .rev()
+        .take(3)
+        .map(|entry| (entry.text.as_str(), entry.error))
+        .collect();
+    assert_eq!(
+        entries,
+        [
+            ("Downloaded Song", false),
+            ("Failed to search YT Music", true),
+            ("Downloading Song", false),
+        ]
+    );
+
+    // Newest first
+    let screen = harness.render();
+    let error = screen.find("Failed to search YT Music").unwrap();
+    assert!(screen.find("Downloaded Song").unwrap() < error);
+    assert!(error < screen.find("Downloading Song").unwrap());
+
+    for idx in 0..600 {
+        harness.app.log = format!("Message {idx}");
+        harness.app.record_log();
+    }
+    assert_eq!(harness.app.log_history.len(), 500);
+    assert_eq!(harness.app.log_history[0].text, "Message 100");
+}
+
+#[tokio::test]
+async fn shows_bugs_in_a_popup_instead_of_crashing() {
+    let mut harness = Harness::with_playlist(100, 30);
+    harness.app.playlist_list_state.select(None);
+    harness.press(KeyCode::Enter).await;
+    assert_eq!(
+        harness.app.app_error,
+        Some(super::AppError::NothingSelected)
+    );
+    let screen = harness.render();
+    assert!(screen.contains("Something went wrong"));
+    assert!(screen.contains("Nothing is selected in the list"));
+
+    // Other keys wait for the popup to be closed
+    harness.press(KeyCode::Char('q')).await;
+    assert!(harness.app.app_error.is_some());
+    harness.press(KeyCode::Esc).await;
+    assert!(harness.app.app_error.is_none());
+    harness.app.playlist_list_state.select(Some(0));
+
+    harness.app.downloads.insert(7, super::Download::Empty);
+    harness.press(KeyCode::Char('d')).await;
+    assert!(harness.render().contains("Starting..."));
+    harness.app.handle_result(Ok(TaskReturn::SongDownloaded(
+        7,
+        SearchFor::Playlist(0, String::from("Song"), 0),
+        String::new(),
+    )));
+    assert_eq!(
+        harness.app.app_error,
+        Some(super::AppError::NotPlaylistImport(7))
+    );
+    harness.press(KeyCode::Esc).await;
+
+    harness
+        .app
+        .spawn_task(async { panic!("Unexpected response") });
+    let result = harness.app.task_results.recv().await.unwrap();
+    harness.app.finish_task(result);
+    assert_eq!(harness.app.running_tasks, 0);
+    assert!(matches!(
+        harness.app.app_error,
+        Some(super::AppError::TaskPanicked(ref err)) if err.contains("Unexpected response")
+    ));
+    assert!(harness.render().contains("A background task crashed"));
+}
+
+#[tokio::test]
+async fn finishes_tasks_before_quitting() {
+    let mut harness = Harness::new(100, 30);
+    harness.app.spawn_task(std::future::pending());
+
+    assert!(!harness.press(KeyCode::Char('q')).await);
+    assert_eq!(harness.app.mode, Mode::Quitting);
+    assert!(harness.render().contains("1 task(s) left"));
+    harness.press(KeyCode::Esc).await;
+    assert_eq!(harness.app.mode, Mode::Normal);
+
+    harness.press(KeyCode::Char('q')).await;
+    harness.press(KeyCode::Char('a')).await;
+    assert!(harness.render().contains("Stopping the downloads..."));
+    // Can't go back once they're stopped
+    harness.press(KeyCode::Esc).await;
+    assert_eq!(harness.app.mode, Mode::Quitting);
+
+    let result = harness.app.task_results.recv().await.unwrap();
+    assert!(matches!(result, Ok(Err(Error::Cancelled))));
+    harness.app.finish_task(result);
+    assert_eq!(harness.app.log, "Stopping the downloads...");
+    // What the run loop quits on
+    assert_eq!(harness.app.mode, Mode::Quitting);
+    assert_eq!(harness.app.running_tasks, 0);
+}
+
+#[tokio::test]
+async fn finished_tasks_wake_up_the_run_loop() {
+    let mut harness = Harness::new(80, 24);
+    harness.app.spawn_task(async { Err(Error::YtMusic) });
+
+    // The result itself comes through the channel the run loop waits on
+    let result = harness.app.task_results.recv().await.unwrap();
+    harness.app.finish_task(result);
+    assert_eq!(harness.app.running_tasks, 0);
+    assert_eq!(harness.app.log, "Failed to search YT Music");
+}
+
+#[tokio::test]
+async fn ticks_slowly_while_nothing_plays() {
+    let path = silent_mp3("tick");
+    let mut harness = Harness::with_data(
+        SaveData {
+            dlp_path: String::from("."),
+            playlists: vec![playlist("Liked", &["Song"])],
+            songs: vec![song("Song", &path.to_string_lossy())],
+            ..SaveData::default()
+        },
+        80,
+        24,
+    );
+    assert_eq!(harness.app.tick_interval(), Duration::from_secs(1));
+
+    harness.press(KeyCode::Enter).await;
+    fs::remove_file(&path).unwrap();
+    assert_eq!(harness.app.playing, Playing::Playlist(0, 0));
+    assert_eq!(harness.app.tick_interval(), Duration::from_millis(100));
+
+    harness.press(KeyCode::Char(' ')).await;
+    assert_eq!(harness.app.tick_interval(), Duration::from_secs(1));
+
+    // Titles that don't fit keep scrolling while paused
+    harness.app.save_data.scroll_titles = true;
+    harness.render();
+    assert_eq!(harness.app.tick_interval(), Duration::from_secs(1));
+    harness.app.song_queue[0].name = "Song ".repeat(20);
+    harness.render();
+    assert_eq!(harness.app.tick_interval(), Duration::from_millis(100));
+}
@@ -1,19 +1,37 @@
-use std::time::Duration;
+use std::{collections::HashMap, mem, time::Duration};
 
-use crate::app::{App, Mode, Playlist, Selected, Song};
+use crate::{
+    app::{
+        App, DownloadRecord, Focused, HistoryEntry, JournalEntry, LogEntry, Mode, Playlist,
+        PlaylistChange, RemovedSong, Selected, SerializableSong, Song,
+    },
+    locale::Locale,
+    party::Submission,
+    spotify::{SearchItem, SpotifyLink},
+    tags::SongTags,
+    unix_timestamp,
+    util::civil_date,
+    youtube::SearchResult,
+};
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    style::Stylize,
+    style::{Style, Stylize},
     symbols::border,
-    widgets::{Block, List, ListItem, Paragraph, StatefulWidget, Widget},
+    text::{Line, Span},
+    widgets::{Block, List, ListItem, ListState, Paragraph, StatefulWidget, Widget, Wrap},
 };
 
-use super::{ConfigField, ConfigFieldType, Download, Repeat, Window};
+use super::{
+    ConfigField, ConfigFieldType, Download, InputMode, IntegrityProblem, Playing, QueuedFrom,
+    Repeat, Window,
+};
 
 impl Widget for &mut App<'_> {
     fn render(self, area: Rect, buf: &mut Buffer) {
-        if let Mode::Input(_) = self.mode {
+        // Notes get the whole window to be edited in, instead of the one line input
+        let editing_notes = matches!(self.mode, Mode::Input(InputMode::Notes(_)));
+        if matches!(self.mode, Mode::Input(_)) && !editing_notes {
             let [header_area, main_area, input_area, player_area, log_area] = Layout::vertical([
                 Constraint::Length(1),
                 Constraint::Fill(1),
@@ -27,7 +45,7 @@ fn render(self, area: Rect, buf: &mut Buffer) {
                 Layout::horizontal([Constraint::Percentage(20), Constraint::Fill(1)])
                     .areas(main_area);
 
-            App::render_header(header_area, buf);
+            self.render_header(header_area, buf);
             self.render_playlists(playlist_area, buf);
             self.render_window(main_area, buf);
             self.text_area.render(input_area, buf);
@@ -46,9 +64,13 @@ fn render(self, area: Rect, buf: &mut Buffer) {
                 Layout::horizontal([Constraint::Percentage(20), Constraint::Fill(1)])
                     .areas(main_area);
 
-            App::render_header(header_area, buf);
+            self.render_header(header_area, buf);
             self.render_playlists(playlist_area, buf);
-            self.render_window(main_area, buf);
+            if editing_notes {
+                self.text_area.render(main_area, buf);
+            } else {
+                self.render_window(main_area, buf);
+            }
             self.render_player(player_area, buf);
             self.render_log(log_area, buf);
         }
@@ -56,21 +78,93 @@ fn render(self, area: Rect, buf: &mut Buffer) {
 }
 
 impl App<'_> {
+    fn is_filtering(&self, focused: Focused) -> bool {
+        self.mode == Mode::Input(InputMode::Filter) && self.focused == focused
+    }
+
     fn render_playlists(&mut self, area: Rect, buf: &mut Buffer) {
-        let block = Block::bordered()
-            .title("Playlists")
-            .border_set(border::PLAIN);
+        let block = bordered(self.save_data.screen_reader).title("Playlists");
+
+        let description = self
+            .playlist_list_state
+            .selected()
+            .and_then(|idx| self.save_data.playlists.get(idx))
+            .map_or("", |playlist| playlist.description.as_str());
+        let area = if self.focused == Focused::Left && !description.is_empty() && area.width > 2 {
+            // Roughly how many lines the description wraps to, up to 4 of them
+            let width = (area.width - 2) as usize;
+            let lines = description.chars().count().div_ceil(width).min(4) as u16;
+            let [list_area, info_area] =
+                Layout::vertical([Constraint::Fill(1), Constraint::Length(lines + 2)]).areas(area);
 
-        StatefulWidget::render(
-            List::new(&self.playlists).block(block),
+            Paragraph::new(description)
+                .wrap(Wrap { trim: true })
+                .block(bordered(self.save_data.screen_reader).title("Description"))
+                .render(info_area, buf);
+            list_area
+        } else {
+            area
+        };
+
+        let plain = self.save_data.screen_reader;
+        let high_contrast = self.save_data.high_contrast;
+        let width = block.inner(area).width as usize;
+        let matches = self
+            .is_filtering(Focused::Left)
+            .then(|| self.filter_matches());
+        render_filtered(
+            &self.playlists,
+            matches.as_deref(),
+            |playlist, matched| {
+                styled_item(
+                    playlist_item(playlist, matched, width, &self.locale, plain),
+                    playlist.selected,
+                    playlist.playing,
+                    high_contrast,
+                )
+            },
+            block,
             area,
             buf,
             &mut self.playlist_list_state,
         );
     }
 
+    // Size of the streamed songs held in memory
+    fn stream_memory(&self) -> u64 {
+        self.streams.values().map(|data| data.len() as u64).sum()
+    }
+
+    // Remaining time of the current song and the ones after it in the playing playlist
+    fn playlist_time_left(&self) -> Option<Duration> {
+        let Playing::Playlist(playlist_idx, song_idx) = self.playing else {
+            return None;
+        };
+        let total: u64 = self
+            .playlists
+            .get(playlist_idx)?
+            .songs
+            .iter()
+            .skip(song_idx)
+            .map(|song| song.duration)
+            .sum();
+        Some(Duration::from_secs(total).saturating_sub(self.sink.get_pos()))
+    }
+
     fn render_player(&mut self, area: Rect, buf: &mut Buffer) {
-        let block = Block::bordered().title("Player").border_set(border::PLAIN);
+        let mut block = bordered(self.save_data.screen_reader).title("Player");
+        if let Some(left) = self.playlist_time_left() {
+            block = block.title_bottom(
+                Line::from(format!(" Playlist: {} left ", self.locale.duration(left)))
+                    .right_aligned(),
+            );
+        }
+
+        self.title_scrolls = false;
+        if self.save_data.screen_reader {
+            self.render_plain_player(block, area, buf);
+            return;
+        }
 
         let repeat_symbol = match self.repeat {
             Repeat::All => "🔁",
@@ -78,6 +172,7 @@ fn render_player(&mut self, area: Rect, buf: &mut Buffer) {
             Repeat::None => "  ",
         };
         let pause_symbol = if self.sink.is_paused() { "||" } else { ">>" };
+        let shuffle_symbol = if self.shuffle { "🔀" } else { "  " };
 
         let remaining_time = if !self.song_queue.is_empty() {
             let remaining = self.song_queue[0]
@@ -93,31 +188,52 @@ fn render_player(&mut self, area: Rect, buf: &mut Buffer) {
         };
 
         let remaining_song_time: Duration;
-        let title: &str;
+        let title: String;
         let num: String;
         if !self.song_queue.is_empty() {
             remaining_song_time = self.song_queue[0]
                 .duration
                 .saturating_sub(self.sink.get_pos());
-            title = &self.song_queue[0].name;
+            title = match self.song_tags.get(&self.song_queue[0].path) {
+                Some(tags) => tags.label(&self.song_queue[0].name),
+                None => self.song_queue[0].name.clone(),
+            };
 
-            let song_idx = self.song_queue[0].song_idx;
-            num = format!("{song_idx:02}");
+            num = match self.song_queue[0].from {
+                QueuedFrom::Playlist(_, idx) | QueuedFrom::GlobalSong(idx) => {
+                    self.locale.padded(idx as u64, 2)
+                }
+                QueuedFrom::Manual => String::from("++"),
+                QueuedFrom::Stream => String::from("~~"),
+            };
         } else {
-            title = "";
+            title = String::new();
             num = String::from("XX");
             remaining_song_time = Duration::from_secs(0);
         }
 
-        let remaining_time_str = format_duration(remaining_song_time);
-        let progress_width = area.as_size().width - 7 - remaining_time_str.len() as u16;
+        let remaining_time_str = self.locale.duration(remaining_song_time);
+        let progress_width = area
+            .as_size()
+            .width
+            .saturating_sub(7 + remaining_time_str.len() as u16);
         let progress = (progress_width as f32 * (1. - remaining_time)).floor() as usize;
         let inverted_progress = (progress_width as f32 * remaining_time).ceil() as usize;
 
+        // Everything else on the first line takes up 28 columns
+        let title_width = (area.as_size().width as usize).saturating_sub(28);
+        let title = if self.save_data.scroll_titles {
+            self.title_scrolls = title.chars().count() > title_width;
+            let step = (self.title_shown_at.elapsed().as_millis() / MARQUEE_STEP_MS) as usize;
+            marquee(&title, title_width, step)
+        } else {
+            truncate(&title, title_width)
+        };
+
         Paragraph::new(format!(
-            "{num} {title}{}{repeat_symbol} 🔈{:.0}% {} \n{pause_symbol} {}{} {} ",
+            "{num} {title}{}{shuffle_symbol}{repeat_symbol} 🔈{:.0}% {} \n{pause_symbol} {}{} {} ",
             // Spaces until other information won't fit
-            " ".repeat((area.as_size().width - 26 - title.len() as u16) as usize),
+            " ".repeat(title_width.saturating_sub(title.chars().count())),
             // Volume percentage
             self.sink.volume() * 100.,
             // Volume
@@ -133,30 +249,128 @@ fn render_player(&mut self, area: Rect, buf: &mut Buffer) {
         .render(area, buf);
     }
 
+    // Spells out what the symbols and bars of the player show
+    fn render_plain_player(&self, block: Block, area: Rect, buf: &mut Buffer) {
+        let mut status = Vec::new();
+        if self.shuffle {
+            status.push(String::from("shuffle"));
+        }
+        match self.repeat {
+            Repeat::All => status.push(String::from("repeat all")),
+            Repeat::One => status.push(String::from("repeat one")),
+            Repeat::None => {}
+        }
+        status.push(format!("volume {:.0}%", self.sink.volume() * 100.));
+
+        let text = match self.song_queue.first() {
+            Some(queued) => {
+                let title = match self.song_tags.get(&queued.path) {
+                    Some(tags) => tags.label(&queued.name),
+                    None => queued.name.clone(),
+                };
+                status.insert(
+                    0,
+                    String::from(if self.sink.is_paused() {
+                        "Paused"
+                    } else {
+                        "Playing"
+                    }),
+                );
+                format!(
+                    "{title}\n{}, {} of {}",
+                    status.join(", "),
+                    self.locale.duration(self.sink.get_pos()),
+                    self.locale.duration(queued.duration),
+                )
+            }
+            None => format!("Nothing playing\n{}", status.join(", ")),
+        };
+
+        Paragraph::new(text).block(block).render(area, buf);
+    }
+
     fn render_window(&mut self, area: Rect, buf: &mut Buffer) {
-        let block = Block::bordered()
+        let block = bordered(self.save_data.screen_reader)
             .title(match self.window {
                 Window::Songs => "Songs",
                 Window::GlobalSongs => "Global song manager",
                 Window::DownloadManager => "Download manager",
                 Window::ConfigurationMenu => "Configuration menu",
+                Window::Queue => "Queue",
+                Window::Cleanup => "Cleanup report",
+                Window::Integrity => "Library integrity check",
+                Window::History => "Listening history",
+                Window::Journal => "Playlist changes",
+                Window::Skipped => "Frequently skipped songs",
+                Window::PartyQueue => "Party queue",
+                Window::Removed => "Recently removed",
+                Window::DownloadHistory => "Download history",
+                Window::PlaylistSync => "Changes on Spotify",
+                Window::Log => "Log",
             })
-            .title_bottom("q - quit   y - help")
-            .border_set(border::PLAIN);
+            .title_bottom("q - quit   y - help");
 
-        if self.mode == Mode::Help {
+        if let Some(err) = &self.app_error {
+            Paragraph::new(format!(
+                "\n  {err}\n\n  This is a bug in quefi, the session keeps going.\n  Details are in quefi/logs/."
+            ))
+            .wrap(Wrap { trim: false })
+            .block(
+                block
+                    .title("Something went wrong")
+                    .title_bottom(Line::from("enter/esc - close").right_aligned()),
+            )
+            .render(area, buf);
+        } else if self.mode == Mode::Help {
             Paragraph::new(concat!(
                 "",
-                "\n  q - quit the program",
+                "\n  q - quit the program, after the downloads finish",
                 "\n  y - display this text",
+                "\n  s - show session statistics",
+                "\n  ctrl+s - save changes now (they're also saved a few seconds after)",
                 "\n  r - toggle repeating",
-                "\n  enter - play song/playlist",
+                "\n  S - toggle shuffling global songs",
+                "\n  enter - play song/playlist (global songs play one after another)",
                 "\n  space - pause song/playlist",
                 "\n  a - add song/playlist",
                 "\n  n - remove song/playlist",
+                "\n  D - download songs missing from playlist",
+                "\n  R - re-download the selected song if it's missing",
+                "\n  W - replace the selected song with another video",
+                "\n  P - preview the selected song (10s at 25/50/75%)",
+                "\n  t - stream a YouTube link without downloading it",
+                "\n  L - check the library for missing or broken files",
+                "\n  I - import a music directory",
+                "\n  home/end - move item being moved to the top/bottom",
+                "\n  M - move item being moved, or the selected song, to a position",
+                "\n  / - filter the focused list",
+                "\n  O - sort songs by name/duration/date added/play count",
                 "\n  f - skip song",
+                "\n  z - play a random song",
+                "\n  v - toggle favorite",
+                "\n  C - show songs to clean up",
+                "\n  x - play selected song next",
+                "\n  e - add selected song to the queue",
                 "\n  g - open global song manager",
                 "\n  d - open download manager",
+                "\n  w - open queue",
+                "\n  H - show listening history",
+                "\n  J - show changes made to the selected playlist",
+                "\n  E - export the selected playlist to M3U/XSPF/PLS",
+                "\n  B - duplicate the selected playlist",
+                "\n  A - change the format songs are downloaded into the playlist in",
+                "\n  Q - review songs requested through the party queue",
+                "\n  U - show what changed in a new quefi version",
+                "\n  Z - show songs removed in the last 30 days",
+                "\n  Y - show download history",
+                "\n  V - show the log, with errors of past downloads",
+                "\n  K - sync the selected playlist with Spotify",
+                "\n  G - search Spotify for tracks, albums and playlists to import",
+                "\n  X - export playlist songs that failed to download",
+                "\n  b - fix the error in the log, when it shows how",
+                "\n  F - show frequently skipped songs",
+                "\n  F2 - rename the selected playlist or song",
+                "\n  N - show/edit notes of the selected song or playlist's description",
                 "\n  u/i - decrease/increase volume",
                 "\n  o/p - seek backward/forward 5 seconds",
                 "\n  left/right - select the left/right window",
@@ -167,36 +381,709 @@ fn render_window(&mut self, area: Rect, buf: &mut Buffer) {
             ))
             .block(block)
             .render(area, buf);
+        } else if let Mode::Notes(name) = &self.mode {
+            let notes = self.song_notes(name);
+            let text = if notes.is_empty() {
+                String::from("No notes yet")
+            } else {
+                notes.to_string()
+            };
+            Paragraph::new(text)
+                .wrap(Wrap { trim: false })
+                .block(
+                    block
+                        .title(format!("Notes - {name}"))
+                        .title_bottom(Line::from("a - edit   N - close").right_aligned()),
+                )
+                .render(area, buf);
+        } else if let (Mode::Changelog, Some(update)) = (&self.mode, &self.update) {
+            let changelog = if update.changelog.trim().is_empty() {
+                "No changelog was published for this release"
+            } else {
+                update.changelog.trim()
+            };
+            Paragraph::new(changelog)
+                .wrap(Wrap { trim: false })
+                .block(
+                    block
+                        .title(format!("What's new in quefi {}", update.version))
+                        .title_bottom(Line::from("U - close").right_aligned()),
+                )
+                .render(area, buf);
+        } else if let (Mode::PickResult, Some(choice)) = (&self.mode, self.result_choices.first()) {
+            let selected = self.result_choice_state.selected();
+            let plain = self.save_data.screen_reader;
+            let results: Vec<(usize, &SearchResult)> = choice.results.iter().enumerate().collect();
+
+            render_visible(
+                &results,
+                |&(idx, result)| {
+                    let state = if selected == Some(idx) {
+                        Selected::Focused
+                    } else {
+                        Selected::None
+                    };
+                    ListItem::from(format!(
+                        "{}{} - {} ({})",
+                        selected_prefix(state, plain),
+                        result.title,
+                        result.artist,
+                        self.locale
+                            .duration(Duration::from_millis(result.duration_ms as u64))
+                    ))
+                },
+                block
+                    .title(format!("Results for {}", choice.search_for.song_name()))
+                    .title_bottom(
+                        Line::from(
+                            // Songs from Spotify can have a preview
+                            if self
+                                .spotify_tracks
+                                .contains_key(choice.search_for.song_name())
+                            {
+                                "P - preview   enter - download   esc - cancel the download"
+                            } else {
+                                "enter - download   esc - cancel the download"
+                            },
+                        )
+                        .right_aligned(),
+                    ),
+                area,
+                buf,
+                &mut self.result_choice_state,
+            );
+        } else if let (Mode::PickSpotifyResult, Some(search)) = (&self.mode, &self.spotify_search) {
+            let selected = self.spotify_search_state.selected();
+            let plain = self.save_data.screen_reader;
+            let items: Vec<(usize, &SearchItem)> = search.items.iter().enumerate().collect();
+
+            render_visible(
+                &items,
+                |&(idx, item)| {
+                    let state = if selected == Some(idx) {
+                        Selected::Focused
+                    } else {
+                        Selected::None
+                    };
+                    let kind = match item.link {
+                        SpotifyLink::Album(_) => "Album",
+                        SpotifyLink::Playlist(_) => "Playlist",
+                        _ => "Track",
+                    };
+                    ListItem::from(format!(
+                        "{}{kind}: {} - {}",
+                        selected_prefix(state, plain),
+                        item.name,
+                        item.by
+                    ))
+                },
+                block
+                    .title(format!("Spotify results for {}", search.query))
+                    .title_bottom(
+                        Line::from("P - preview   enter - import   esc - close").right_aligned(),
+                    ),
+                area,
+                buf,
+                &mut self.spotify_search_state,
+            );
+        } else if self.mode == Mode::Stats {
+            let stats = &self.session_stats;
+            Paragraph::new(format!(
+                "\n  Tracks played: {}\n  Listening time: {}\n  Data downloaded: {}\n  Failed downloads: {}\n\n  Songs loaded in the player: {}\n  Streams in memory: {} ({})\n\n  r - reset   s - close",
+                self.locale.number(stats.tracks_played),
+                self.locale.duration(stats.listening_time),
+                format_bytes(stats.downloaded_bytes),
+                stats.failed_downloads,
+                self.sink.len(),
+                self.streams.len(),
+                format_bytes(self.stream_memory()),
+            ))
+            .block(block)
+            .render(area, buf);
+        } else if self.mode == Mode::Quitting {
+            let (text, keys) = if self.cancel.is_cancelled() {
+                (String::from("\n  Stopping the downloads..."), "")
+            } else {
+                (
+                    format!(
+                        "\n  Finishing downloads...\n\n  {} task(s) left, quefi quits once they're done.\n  Stopped yt-dlp downloads can be resumed on the next start.",
+                        self.running_tasks
+                    ),
+                    "a - stop them and quit   esc - keep using quefi",
+                )
+            };
+            Paragraph::new(text)
+                .block(
+                    block
+                        .title("Quitting")
+                        .title_bottom(Line::from(keys).right_aligned()),
+                )
+                .render(area, buf);
         } else {
             match self.window {
                 Window::Songs => {
-                    let playlist_idx = self.playlist_list_state.selected().unwrap();
-                    StatefulWidget::render(
-                        List::new(&self.playlists[playlist_idx].songs).block(block),
+                    let songs = self
+                        .playlist_list_state
+                        .selected()
+                        .and_then(|idx| self.playlists.get(idx))
+                        .map_or(&[][..], |playlist| &playlist.songs[..]);
+                    let high_contrast = self.save_data.high_contrast;
+                    let width = block.inner(area).width as usize;
+                    let matches = self
+                        .is_filtering(Focused::Right)
+                        .then(|| self.filter_matches());
+                    self.song_items.begin_frame(self.item_view(width));
+                    render_filtered(
+                        songs,
+                        matches.as_deref(),
+                        |song, matched| {
+                            let tags = self.song_tags.get(&song.path);
+                            self.song_items.get(song, tags, matched, || {
+                                styled_item(
+                                    song_item(
+                                        song,
+                                        tags,
+                                        matched,
+                                        width,
+                                        &self.locale,
+                                        self.save_data.screen_reader,
+                                    ),
+                                    song.selected,
+                                    song.playing,
+                                    high_contrast,
+                                )
+                            })
+                        },
+                        block.title_bottom(total_duration(songs, &self.locale)),
                         area,
                         buf,
                         &mut self.song_list_state,
                     );
                 }
-                Window::GlobalSongs => StatefulWidget::render(
-                    List::new(&self.global_songs).block(block),
-                    area,
-                    buf,
-                    &mut self.global_song_list_state,
-                ),
-                Window::DownloadManager => StatefulWidget::render(
-                    List::new(self.downloads.values()).block(block),
-                    area,
-                    buf,
-                    &mut self.download_state,
-                ),
+                Window::GlobalSongs => {
+                    let high_contrast = self.save_data.high_contrast;
+                    let width = block.inner(area).width as usize;
+                    let matches = self
+                        .is_filtering(Focused::Right)
+                        .then(|| self.filter_matches());
+                    self.song_items.begin_frame(self.item_view(width));
+                    render_filtered(
+                        &self.global_songs,
+                        matches.as_deref(),
+                        |song, matched| {
+                            let tags = self.song_tags.get(&song.path);
+                            self.song_items.get(song, tags, matched, || {
+                                styled_item(
+                                    song_item(
+                                        song,
+                                        tags,
+                                        matched,
+                                        width,
+                                        &self.locale,
+                                        self.save_data.screen_reader,
+                                    ),
+                                    song.selected,
+                                    song.playing,
+                                    high_contrast,
+                                )
+                            })
+                        },
+                        block.title_bottom(total_duration(&self.global_songs, &self.locale)),
+                        area,
+                        buf,
+                        &mut self.global_song_list_state,
+                    );
+                }
+                Window::DownloadManager => {
+                    let block = if *self.downloads_paused.borrow() {
+                        block
+                            .title("(paused)")
+                            .title_bottom("enter - resume downloads")
+                    } else {
+                        block.title_bottom("enter - pause downloads")
+                    };
+                    let block = if self.failed_imports.is_empty() {
+                        block
+                    } else {
+                        block.title_bottom("X - export failures")
+                    };
+
+                    let mut items: Vec<ListItem> =
+                        self.downloads.values().map(ListItem::from).collect();
+                    for import in &self.failed_imports {
+                        let songs: Vec<String> = import
+                            .songs
+                            .iter()
+                            .map(|(name, reason)| format!("  {name}: {reason}"))
+                            .collect();
+                        items.push(ListItem::from(format!(
+                            "Failed to download for {} ({}):\n{}",
+                            import.playlist_name,
+                            import.songs.len(),
+                            songs.join("\n")
+                        )));
+                    }
+                    StatefulWidget::render(
+                        List::new(items).block(block),
+                        area,
+                        buf,
+                        &mut self.download_state,
+                    )
+                }
+                Window::Queue => {
+                    let len = self.song_queue.len() + self.pending_queue.len();
+                    if self.queue_state.selected().unwrap_or(0) >= len {
+                        self.queue_state.select(Some(len.saturating_sub(1)));
+                        self.queue_moving = false;
+                    }
+
+                    let selected = self.queue_state.selected();
+                    let items = self
+                        .song_queue
+                        .iter()
+                        .chain(&self.pending_queue)
+                        .enumerate()
+                        .map(|(idx, queued)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else if self.queue_moving {
+                                Selected::Moving
+                            } else {
+                                Selected::Focused
+                            };
+
+                            let plain = self.save_data.screen_reader;
+                            let mut prefix = selected_prefix(state, plain);
+                            if idx == 0 {
+                                prefix.push_str(if plain { "playing: " } else { "🔈 " });
+                            }
+
+                            styled_item(
+                                ListItem::from(format!("{}{}", prefix, queued.name)),
+                                state,
+                                idx == 0,
+                                self.save_data.high_contrast,
+                            )
+                        })
+                        .collect::<Vec<_>>();
+
+                    StatefulWidget::render(
+                        List::new(items).block(block),
+                        area,
+                        buf,
+                        &mut self.queue_state,
+                    );
+                }
+                Window::Cleanup => {
+                    let selected = self.cleanup_state.selected();
+                    let items = self
+                        .cleanup_report
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, candidate)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            ListItem::from(format!(
+                                "{}{} (not played for {} days)",
+                                selected_prefix(state, self.save_data.screen_reader),
+                                candidate.name,
+                                candidate.days
+                            ))
+                        })
+                        .collect::<Vec<_>>();
+
+                    let block = if items.is_empty() {
+                        block.title_bottom("Nothing to clean up")
+                    } else {
+                        block.title_bottom("enter - delete these files   n - keep song")
+                    };
+
+                    StatefulWidget::render(
+                        List::new(items).block(block),
+                        area,
+                        buf,
+                        &mut self.cleanup_state,
+                    );
+                }
+                Window::Integrity => {
+                    let selected = self.integrity_state.selected();
+                    let items = self
+                        .integrity_report
+                        .iter()
+                        .enumerate()
+                        .map(|(idx, issue)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            let problem = match &issue.problem {
+                                IntegrityProblem::Missing => String::from("missing"),
+                                IntegrityProblem::Undecodable(err) => {
+                                    format!("can't be decoded: {err}")
+                                }
+                            };
+
+                            ListItem::from(format!(
+                                "{}{} ({problem})\n  {}",
+                                selected_prefix(state, self.save_data.screen_reader),
+                                issue.name,
+                                issue.path,
+                            ))
+                        })
+                        .collect::<Vec<_>>();
+
+                    let block = if items.is_empty() {
+                        block.title_bottom("No problems found")
+                    } else {
+                        block.title_bottom("enter - re-download   a - relocate   n - remove")
+                    };
+
+                    StatefulWidget::render(
+                        List::new(items).block(block),
+                        area,
+                        buf,
+                        &mut self.integrity_state,
+                    );
+                }
+                Window::History => {
+                    let play_counts: HashMap<&str, u32> = self
+                        .save_data
+                        .songs
+                        .iter()
+                        .map(|song| (song.name.as_str(), song.play_count))
+                        .collect();
+                    // Newest first
+                    let entries: Vec<(usize, &HistoryEntry)> =
+                        self.save_data.history.iter().rev().enumerate().collect();
+                    let selected = self.history_state.selected();
+                    let plain = self.save_data.screen_reader;
+                    let now = unix_timestamp();
+
+                    render_visible(
+                        &entries,
+                        |&(idx, entry)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            let plays = match play_counts.get(entry.name.as_str()) {
+                                Some(&count) => format!(
+                                    " ({} {})",
+                                    self.locale.number(count),
+                                    if count == 1 { "play" } else { "plays" }
+                                ),
+                                None => String::new(),
+                            };
+                            ListItem::from(format!(
+                                "{}{}  {}{plays}",
+                                selected_prefix(state, plain),
+                                format_played(entry.played, now, &self.locale),
+                                entry.name,
+                            ))
+                        },
+                        block.title_bottom("enter - play   n - remove from history"),
+                        area,
+                        buf,
+                        &mut self.history_state,
+                    );
+                }
+                Window::Skipped => {
+                    // The list borrows self, so the state is put back after rendering
+                    let mut skipped_state = mem::take(&mut self.skipped_state);
+                    let songs: Vec<(usize, &SerializableSong)> =
+                        self.frequently_skipped().into_iter().enumerate().collect();
+                    let selected = skipped_state.selected();
+                    let plain = self.save_data.screen_reader;
+
+                    render_visible(
+                        &songs,
+                        |&(idx, song)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            ListItem::from(format!(
+                                "{}{} ({} {})",
+                                selected_prefix(state, plain),
+                                song.name,
+                                self.locale.number(song.skips),
+                                if song.skips == 1 { "skip" } else { "skips" }
+                            ))
+                        },
+                        block.title_bottom(
+                            "enter - play   a - reset skips   n - remove from library",
+                        ),
+                        area,
+                        buf,
+                        &mut skipped_state,
+                    );
+                    self.skipped_state = skipped_state;
+                }
+                Window::Removed => {
+                    // Newest first
+                    let removed: Vec<(usize, &RemovedSong)> =
+                        self.save_data.removed.iter().rev().enumerate().collect();
+                    let selected = self.removed_state.selected();
+                    let plain = self.save_data.screen_reader;
+                    let now = unix_timestamp();
+
+                    render_visible(
+                        &removed,
+                        |&(idx, removed)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            let playlists = match removed.positions.len() {
+                                0 => String::new(),
+                                1 => String::from(", was in 1 playlist"),
+                                count => {
+                                    format!(
+                                        ", was in {} playlists",
+                                        self.locale.number(count as u64)
+                                    )
+                                }
+                            };
+                            ListItem::from(format!(
+                                "{}{}  (removed {}{playlists})",
+                                selected_prefix(state, plain),
+                                removed.song.name,
+                                format_played(removed.removed, now, &self.locale),
+                            ))
+                        },
+                        block.title_bottom("enter - restore   n - forget"),
+                        area,
+                        buf,
+                        &mut self.removed_state,
+                    );
+                }
+                Window::DownloadHistory => {
+                    // Newest first
+                    let records: Vec<(usize, &DownloadRecord)> = self
+                        .save_data
+                        .download_history
+                        .iter()
+                        .rev()
+                        .enumerate()
+                        .collect();
+                    let selected = self.download_history_state.selected();
+                    let plain = self.save_data.screen_reader;
+                    let now = unix_timestamp();
+
+                    render_visible(
+                        &records,
+                        |&(idx, record)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            ListItem::from(format!(
+                                "{}{}  {} ({})  from {}",
+                                selected_prefix(state, plain),
+                                format_played(record.downloaded, now, &self.locale),
+                                record.path,
+                                format_bytes(record.size),
+                                record.link,
+                            ))
+                        },
+                        block.title_bottom("enter - play   n - forget"),
+                        area,
+                        buf,
+                        &mut self.download_history_state,
+                    );
+                }
+                Window::PlaylistSync => {
+                    let changes: Vec<(usize, String)> = self
+                        .playlist_sync
+                        .iter()
+                        .flat_map(|sync| {
+                            let added = sync
+                                .added
+                                .iter()
+                                .map(|track| format!("+ {} - {}", track.artist, track.name));
+                            let removed = sync.removed.iter().map(|name| format!("- {name}"));
+                            added.chain(removed)
+                        })
+                        .enumerate()
+                        .collect();
+                    let selected = self.playlist_sync_state.selected();
+                    let plain = self.save_data.screen_reader;
+
+                    render_visible(
+                        &changes,
+                        |(idx, change)| {
+                            let state = if selected != Some(*idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+                            ListItem::from(format!("{}{change}", selected_prefix(state, plain)))
+                        },
+                        block.title_bottom("enter - apply   n - leave out"),
+                        area,
+                        buf,
+                        &mut self.playlist_sync_state,
+                    );
+                }
+                Window::Log => {
+                    // Newest first
+                    let entries: Vec<(usize, &LogEntry)> =
+                        self.log_history.iter().rev().enumerate().collect();
+                    let selected = self.log_state.selected();
+                    let plain = self.save_data.screen_reader;
+                    let now = unix_timestamp();
+
+                    render_visible(
+                        &entries,
+                        |&(idx, entry)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            let item = ListItem::from(format!(
+                                "{}{}  {}{}",
+                                selected_prefix(state, plain),
+                                format_played(entry.time, now, &self.locale),
+                                if entry.error && plain { "Error: " } else { "" },
+                                entry.text,
+                            ));
+                            if entry.error && !plain {
+                                item.red()
+                            } else {
+                                item
+                            }
+                        },
+                        block.title_bottom("older entries are in quefi/logs/"),
+                        area,
+                        buf,
+                        &mut self.log_state,
+                    );
+                }
+                Window::PartyQueue => {
+                    let requests: Vec<(usize, &Submission)> =
+                        self.party_requests.iter().enumerate().collect();
+                    let selected = self.party_state.selected();
+                    let plain = self.save_data.screen_reader;
+
+                    render_visible(
+                        &requests,
+                        |&(idx, submission)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            ListItem::from(format!(
+                                "{}{}  (from {})",
+                                selected_prefix(state, plain),
+                                submission.text,
+                                submission.from
+                            ))
+                        },
+                        block.title_bottom("enter - approve and download   n - reject"),
+                        area,
+                        buf,
+                        &mut self.party_state,
+                    );
+                }
+                Window::Journal => {
+                    let journal = match self
+                        .playlist_list_state
+                        .selected()
+                        .and_then(|idx| self.save_data.playlists.get(idx))
+                    {
+                        Some(playlist) => playlist.journal.as_slice(),
+                        None => &[],
+                    };
+                    // Newest first
+                    let entries: Vec<(usize, &JournalEntry)> =
+                        journal.iter().rev().enumerate().collect();
+                    let selected = self.journal_state.selected();
+                    let plain = self.save_data.screen_reader;
+                    let now = unix_timestamp();
+
+                    render_visible(
+                        &entries,
+                        |&(idx, entry)| {
+                            let state = if selected != Some(idx) {
+                                Selected::None
+                            } else if self.focused == Focused::Left {
+                                Selected::Unfocused
+                            } else {
+                                Selected::Focused
+                            };
+
+                            ListItem::from(format!(
+                                "{}{}  {}",
+                                selected_prefix(state, plain),
+                                format_played(entry.time, now, &self.locale),
+                                describe_change(&entry.change, &self.locale),
+                            ))
+                        },
+                        block,
+                        area,
+                        buf,
+                        &mut self.journal_state,
+                    );
+                }
                 Window::ConfigurationMenu => StatefulWidget::render(
-                    List::new([
-                        &self.config.dlp_path,
-                        &self.config.spotify_client_id,
-                        &self.config.spotify_client_secret,
-                    ])
-                    .block(block),
+                    List::new(self.config.fields().into_iter().map(|field| {
+                        styled_item(
+                            config_item(field, self.save_data.screen_reader),
+                            field.selected,
+                            false,
+                            self.save_data.high_contrast,
+                        )
+                    }))
+                    .block(
+                        match self.dlp_version.as_deref() {
+                            Some(version) if !version.is_empty() => {
+                                block.title_bottom(format!("yt-dlp {version}"))
+                            }
+                            _ => block,
+                        }
+                        .title_bottom(
+                            Line::from(format!("Spotify token: {}", self.spotify_token_status()))
+                                .right_aligned(),
+                        ),
+                    ),
                     area,
                     buf,
                     &mut self.config_menu_state,
@@ -205,58 +1092,432 @@ fn render_window(&mut self, area: Rect, buf: &mut Buffer) {
         }
     }
 
+    fn item_view(&self, width: usize) -> ItemView {
+        ItemView {
+            width,
+            plain: self.save_data.screen_reader,
+            high_contrast: self.save_data.high_contrast,
+            language: self.save_data.language.clone(),
+        }
+    }
+
     fn render_log(&mut self, area: Rect, buf: &mut Buffer) {
-        Paragraph::new(self.log.as_str())
-            .reversed()
-            .render(area, buf);
+        let log = match self.current_hint() {
+            Some(hint) => format!("{} ({})", self.log, hint.text()),
+            None => self.log.clone(),
+        };
+        Paragraph::new(log).reversed().render(area, buf);
     }
 
-    fn render_header(area: Rect, buf: &mut Buffer) {
-        Paragraph::new(format!("Quefi v{}", env!("CARGO_PKG_VERSION")))
-            .bold()
-            .centered()
-            .render(area, buf);
+    fn render_header(&self, area: Rect, buf: &mut Buffer) {
+        let mut header = format!("Quefi v{}", env!("CARGO_PKG_VERSION"));
+        if let Some(update) = &self.update {
+            header.push_str(&format!(" ({} available, U - changelog)", update.version));
+        }
+        if self.has_unsaved_changes() {
+            header.push_str(" - unsaved changes");
+        }
+        Paragraph::new(header).bold().centered().render(area, buf);
     }
 }
 
-#[inline(always)]
-fn format_duration(duration: Duration) -> String {
-    let minutes = duration.as_secs() / 60;
-    let seconds = duration.as_secs() % 60;
-    format!("{}:{:02}", minutes, seconds)
+// Box drawing characters get read out by screen readers, so the borders are blank in that mode
+fn bordered<'a>(plain: bool) -> Block<'a> {
+    Block::bordered().border_set(if plain { border::EMPTY } else { border::PLAIN })
 }
 
-impl From<&Playlist> for ListItem<'_> {
-    fn from(value: &Playlist) -> Self {
-        let mut prefix = match value.selected {
-            Selected::None => String::from("   "),
-            Selected::Moving => String::from("⇅  "),
-            Selected::Focused => String::from("►  "),
-            Selected::Unfocused => String::from("⇨  "),
-        };
+fn total_duration(songs: &[Song], locale: &Locale) -> Line<'static> {
+    let total: u64 = songs.iter().map(|song| song.duration).sum();
+    Line::from(format!(
+        " Total: {} ",
+        locale.duration(Duration::from_secs(total))
+    ))
+    .right_aligned()
+}
 
-        if value.playing {
-            prefix.push_str("🔈 ");
+fn describe_change(change: &PlaylistChange, locale: &Locale) -> String {
+    // Positions are shown 1-based
+    let position = |idx: usize| locale.number(idx as u64 + 1);
+    match change {
+        PlaylistChange::Created => String::from("Playlist created"),
+        PlaylistChange::Added(name, idx) => format!("Added {name} at #{}", position(*idx)),
+        PlaylistChange::Removed(name, idx) => format!("Removed {name} from #{}", position(*idx)),
+        PlaylistChange::Moved(name, from, to) => {
+            format!(
+                "Moved {name} from #{} to #{}",
+                position(*from),
+                position(*to)
+            )
         }
+        PlaylistChange::Sorted(criterion) => format!("Sorted by {criterion}"),
+    }
+}
 
-        ListItem::from(format!("{}{}", prefix, value.name))
+// Recent listens are shown relative to now, older ones by their (UTC) date
+fn format_played(played: u64, now: u64, locale: &Locale) -> String {
+    let ago = now.saturating_sub(played);
+    if ago < 60 {
+        return String::from("just now");
+    }
+    if ago < 3600 {
+        return format!("{} min ago", locale.number(ago / 60));
     }
+    if ago < 86_400 {
+        return format!("{} h ago", locale.number(ago / 3600));
+    }
+
+    let (year, month, day) = civil_date(played);
+    format!(
+        "{}-{}-{}",
+        locale.padded(year as u64, 4),
+        locale.padded(month as u64, 2),
+        locale.padded(day as u64, 2)
+    )
 }
 
-impl From<&Song> for ListItem<'_> {
-    fn from(value: &Song) -> Self {
-        let mut prefix = match value.selected {
-            Selected::None => String::from("   "),
-            Selected::Moving => String::from("⇅  "),
-            Selected::Focused => String::from("►  "),
-            Selected::Unfocused => String::from("⇨  "),
-        };
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024. && unit < UNITS.len() - 1 {
+        size /= 1024.;
+        unit += 1;
+    }
+
+    if unit == 0 {
+        format!("{bytes} B")
+    } else {
+        format!("{size:.1} {}", UNITS[unit])
+    }
+}
+
+fn selected_prefix(selected: Selected, plain: bool) -> String {
+    if plain {
+        return String::from(match selected {
+            Selected::None => "  ",
+            Selected::Moving => "moving: ",
+            Selected::Focused => "> ",
+            Selected::Unfocused => "- ",
+        });
+    }
+
+    match selected {
+        Selected::None => String::from("   "),
+        Selected::Moving => String::from("⇅  "),
+        Selected::Focused => String::from("►  "),
+        Selected::Unfocused => String::from("⇨  "),
+    }
+}
+
+const MARQUEE_STEP_MS: u128 = 300;
 
-        if value.playing {
-            prefix.push_str("🔈 ");
+fn truncate(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+    if width == 0 {
+        return String::new();
+    }
+
+    let mut truncated: String = text.chars().take(width - 1).collect();
+    truncated.push('…');
+    truncated
+}
+
+// Scrolls text that doesn't fit by one character every step, wrapping around with a gap
+fn marquee(text: &str, width: usize, step: usize) -> String {
+    let len = text.chars().count();
+    if len <= width {
+        return text.to_string();
+    }
+
+    let looped: Vec<char> = text.chars().chain("   ".chars()).collect();
+    (0..width)
+        .map(|idx| looped[(step + idx) % looped.len()])
+        .collect()
+}
+
+// Builds only the items that fit in the area, formatting every song of a big library each
+// frame is what made scrolling slow
+fn render_visible<'a, T>(
+    items: &'a [T],
+    to_item: impl FnMut(&'a T) -> ListItem<'a>,
+    block: Block,
+    area: Rect,
+    buf: &mut Buffer,
+    state: &mut ListState,
+) {
+    if items.is_empty() {
+        state.select(None);
+    }
+
+    let height = block.inner(area).height as usize;
+    let selected = state
+        .selected()
+        .map(|idx| idx.min(items.len().saturating_sub(1)));
+
+    let mut offset = state.offset().min(items.len().saturating_sub(height));
+    if let Some(selected) = selected {
+        if selected < offset {
+            offset = selected;
+        } else if height > 0 && selected >= offset + height {
+            offset = selected + 1 - height;
         }
+    }
+
+    let end = (offset + height).min(items.len());
+    let mut visible_state = ListState::default().with_selected(selected.map(|idx| idx - offset));
+
+    StatefulWidget::render(
+        List::new(items[offset..end].iter().map(to_item)).block(block),
+        area,
+        buf,
+        &mut visible_state,
+    );
 
-        ListItem::from(format!("{}{}", prefix, value.name))
+    state.select(selected);
+    *state.offset_mut() = offset;
+}
+
+// Renders only the items matching the filter, if the list is being filtered
+fn render_filtered<'a, T>(
+    items: &'a [T],
+    matches: Option<&'a [(usize, Vec<usize>)]>,
+    mut to_item: impl FnMut(&'a T, &'a [usize]) -> ListItem<'a>,
+    block: Block,
+    area: Rect,
+    buf: &mut Buffer,
+    state: &mut ListState,
+) {
+    let Some(matches) = matches else {
+        render_visible(items, |item| to_item(item, &[]), block, area, buf, state);
+        return;
+    };
+
+    let selected = state
+        .selected()
+        .and_then(|selected| matches.iter().position(|(idx, _)| *idx == selected));
+    let mut filtered_state = ListState::default().with_selected(selected);
+
+    render_visible(
+        matches,
+        |(idx, matched)| to_item(&items[*idx], matched),
+        block,
+        area,
+        buf,
+        &mut filtered_state,
+    );
+}
+
+// Highlights the chars matched by the filter
+fn highlighted<'a>(prefix: String, label: &str, matched: &[usize]) -> Line<'a> {
+    let span = |text: String, is_match: bool| {
+        if is_match {
+            Span::styled(text, Style::default().yellow().bold())
+        } else {
+            Span::raw(text)
+        }
+    };
+
+    let mut spans = vec![Span::raw(prefix)];
+    let mut run = String::new();
+    let mut run_matched = false;
+
+    for (idx, char) in label.chars().enumerate() {
+        let is_match = matched.binary_search(&idx).is_ok();
+        if is_match != run_matched && !run.is_empty() {
+            spans.push(span(mem::take(&mut run), run_matched));
+        }
+        run_matched = is_match;
+        run.push(char);
+    }
+    spans.push(span(run, run_matched));
+
+    Line::from(spans)
+}
+
+// In high contrast mode, states are also told apart by text modifiers, not just by the symbols
+fn styled_item(item: ListItem, selected: Selected, playing: bool, high_contrast: bool) -> ListItem {
+    if !high_contrast {
+        return item;
+    }
+
+    let mut style = match selected {
+        Selected::None => Style::default(),
+        Selected::Moving => Style::default().bold().underlined(),
+        Selected::Focused => Style::default().bold().reversed(),
+        Selected::Unfocused => Style::default().underlined(),
+    };
+    if playing {
+        style = style.italic();
+    }
+    item.style(style)
+}
+
+fn playlist_item<'a>(
+    playlist: &Playlist,
+    matched: &[usize],
+    width: usize,
+    locale: &Locale,
+    plain: bool,
+) -> ListItem<'a> {
+    let mut prefix = selected_prefix(playlist.selected, plain);
+
+    if playlist.playing {
+        prefix.push_str(if plain { "playing: " } else { "🔈 " });
+    }
+
+    // The name is more important than the duration in the narrow playlist list
+    let total: u64 = playlist.songs.iter().map(|song| song.duration).sum();
+    let needed = Line::from(prefix.as_str()).width()
+        + Line::from(playlist.name.as_str()).width()
+        + locale.duration(Duration::from_secs(total)).len()
+        + 1;
+    if total == 0 || needed > width {
+        return ListItem::from(highlighted(prefix, &playlist.name, matched));
+    }
+    ListItem::from(with_duration(
+        prefix,
+        &playlist.name,
+        matched,
+        total,
+        width,
+        locale,
+    ))
+}
+
+// The duration goes to the right edge, the label gets truncated to make space for it
+fn with_duration<'a>(
+    prefix: String,
+    label: &str,
+    matched: &[usize],
+    seconds: u64,
+    width: usize,
+    locale: &Locale,
+) -> Line<'a> {
+    let duration = Span::raw(locale.duration(Duration::from_secs(seconds)));
+    let prefix_width = Line::from(prefix.as_str()).width();
+    let label_width = width.saturating_sub(prefix_width + duration.width() + 1);
+    let mut line = highlighted(prefix, &truncate(label, label_width), matched);
+
+    let padding = width.saturating_sub(line.width() + duration.width()).max(1);
+    line.push_span(Span::raw(" ".repeat(padding)));
+    line.push_span(duration);
+    line
+}
+
+fn song_item<'a>(
+    song: &Song,
+    tags: Option<&SongTags>,
+    matched: &[usize],
+    width: usize,
+    locale: &Locale,
+    plain: bool,
+) -> ListItem<'a> {
+    let mut prefix = selected_prefix(song.selected, plain);
+
+    if song.favorite {
+        prefix.push_str(if plain { "favorite: " } else { "★ " });
+    }
+
+    if song.playing {
+        prefix.push_str(if plain { "playing: " } else { "🔈 " });
+    }
+
+    let label = match tags {
+        Some(tags) => tags.label(&song.name),
+        None => song.name.clone(),
+    };
+    if song.duration == 0 {
+        return ListItem::from(highlighted(prefix, &label, matched));
+    }
+    ListItem::from(with_duration(
+        prefix,
+        &label,
+        matched,
+        song.duration,
+        width,
+        locale,
+    ))
+}
+
+// How every item of a list is shown, changing it builds all of them again
+#[derive(PartialEq)]
+pub(super) struct ItemView {
+    width: usize,
+    plain: bool,
+    high_contrast: bool,
+    // Durations are written in it
+    language: String,
+}
+
+// Everything else a song's item shows
+struct SongItemKey {
+    name: String,
+    tags: Option<SongTags>,
+    selected: Selected,
+    favorite: bool,
+    playing: bool,
+    duration: u64,
+    matched: Vec<usize>,
+}
+
+impl SongItemKey {
+    fn new(song: &Song, tags: Option<&SongTags>, matched: &[usize]) -> Self {
+        SongItemKey {
+            name: song.name.clone(),
+            tags: tags.cloned(),
+            selected: song.selected,
+            favorite: song.favorite,
+            playing: song.playing,
+            duration: song.duration,
+            matched: matched.to_vec(),
+        }
+    }
+
+    fn matches(&self, song: &Song, tags: Option<&SongTags>, matched: &[usize]) -> bool {
+        self.name == song.name
+            && self.tags.as_ref() == tags
+            && self.selected == song.selected
+            && self.favorite == song.favorite
+            && self.playing == song.playing
+            && self.duration == song.duration
+            && self.matched == matched
+    }
+}
+
+// Items of the songs shown in the last frame, by path. A song's item is only built again once
+// something it shows changed, so the playback ticks and scrolling don't format every row again
+#[derive(Default)]
+pub(super) struct SongItems {
+    view: Option<ItemView>,
+    last: HashMap<String, (SongItemKey, ListItem<'static>)>,
+    current: HashMap<String, (SongItemKey, ListItem<'static>)>,
+}
+
+impl SongItems {
+    fn begin_frame(&mut self, view: ItemView) {
+        self.last = mem::take(&mut self.current);
+        if self.view.as_ref() != Some(&view) {
+            self.last.clear();
+            self.view = Some(view);
+        }
+    }
+
+    fn get(
+        &mut self,
+        song: &Song,
+        tags: Option<&SongTags>,
+        matched: &[usize],
+        build: impl FnOnce() -> ListItem<'static>,
+    ) -> ListItem<'static> {
+        let (key, item) = match self.last.remove(&song.path) {
+            Some((key, item)) if key.matches(song, tags, matched) => (key, item),
+            _ => (SongItemKey::new(song, tags, matched), build()),
+        };
+        self.current.insert(song.path.clone(), (key, item.clone()));
+        item
     }
 }
 
@@ -264,10 +1525,12 @@ impl From<&Download> for ListItem<'_> {
     fn from(value: &Download) -> Self {
         match value {
             Download::ProcessingPlaylistSongs(processing) => ListItem::from(format!(
-                "Searching songs for {} ({}/{}):\n{}\nDownloading songs for {} ({}/{}):\n{}",
+                "Searching songs for {} ({}/{}{}):\n{}\nDownloading songs for {} ({}/{}, {} already in the library):\n{}",
                 processing.playlist_name,
                 processing.searched,
                 processing.total_to_search,
+                // More pages of the playlist are on the way
+                if processing.more_tracks { "+" } else { "" },
                 {
                     let mut songs = processing
                         .searching_songs
@@ -285,6 +1548,7 @@ fn from(value: &Download) -> Self {
                 processing.playlist_name,
                 processing.downloaded,
                 processing.total_to_download,
+                processing.reused,
                 {
                     let mut songs = processing
                         .downloading_songs
@@ -303,37 +1567,105 @@ fn from(value: &Download) -> Self {
             Download::FetchingSpotifyToken => ListItem::from("Fetching Spotify token..."),
             Download::FetchingPlaylistInfo => ListItem::from("Fetching playlist info..."),
             Download::FetchingTrackInfo => ListItem::from("Fetching track info..."),
+            Download::SearchingSpotify(query) => {
+                ListItem::from(format!("Searching Spotify for {query}..."))
+            }
+            Download::WritingTags(writing) => ListItem::from(format!(
+                "Writing tags for {} ({}/{})",
+                writing.playlist_name, writing.written, writing.total
+            )),
+            Download::FetchingNewReleases => {
+                ListItem::from("Checking followed artists for new releases...")
+            }
+            Download::FetchingLinkMetadata => ListItem::from("Fetching song metadata..."),
             Download::SearchingForSong(query) => {
                 ListItem::from(format!("Searching for {}...", query))
             }
+            Download::PickingResult(name) => {
+                ListItem::from(format!("Waiting for a search result to be picked for {name}"))
+            }
             Download::DownloadingSong(name) => ListItem::from(format!("Downloading {}...", name)),
             Download::DownloadingYoutubeSong => ListItem::from("Downloading song from YouTube..."),
-            Download::Empty => panic!("Tried to display empty download"), // TODO: check if it always crashes
+            Download::FetchingStream(url) => ListItem::from(format!("Fetching stream of {url}...")),
+            // Replaced as soon as the link is handled
+            Download::Empty => ListItem::from("Starting..."),
         }
     }
 }
 
-impl From<&ConfigField> for ListItem<'_> {
-    fn from(value: &ConfigField) -> Self {
-        let prefix = match value.selected {
-            Selected::None => String::from("   "),
-            Selected::Moving => String::from("⇅  "),
-            Selected::Focused => String::from("►  "),
-            Selected::Unfocused => String::from("⇨  "),
-        };
+fn config_item<'a>(value: &ConfigField, plain: bool) -> ListItem<'a> {
+    let prefix = selected_prefix(value.selected, plain);
 
-        let name = match value.field_type {
-            ConfigFieldType::DlpPath => "DLP path: ",
-            ConfigFieldType::SpotifyClientId => "Spotify client ID: ",
-            ConfigFieldType::SpotifyClientSecret => "Spotify client secret: ",
-        };
+    let name = match value.field_type {
+        ConfigFieldType::DlpPath => "DLP path: ",
+        ConfigFieldType::SpotifyClientId => "Spotify client ID: ",
+        ConfigFieldType::SpotifyClientSecret => "Spotify client secret: ",
+        ConfigFieldType::HighContrast => "High contrast mode: ",
+        ConfigFieldType::CleanupDays => "Delete songs not played for (days, 0 - never): ",
+        ConfigFieldType::NowPlayingPath => "Now playing file: ",
+        ConfigFieldType::ScrollTitles => "Scroll long titles: ",
+        ConfigFieldType::DlpNiceness => "yt-dlp niceness (0 - normal, 19 - lowest): ",
+        ConfigFieldType::MaxDlpProcesses => "Max yt-dlp processes at once (0 - no limit): ",
+        ConfigFieldType::NormalizeLoudness => "Normalize loudness of downloads (ffmpeg): ",
+        ConfigFieldType::Language => "Language for sorting and numbers: ",
+        ConfigFieldType::ScreenReader => "Screen reader mode: ",
+        ConfigFieldType::TrackNotification => "Notify on track change: ",
+        ConfigFieldType::AutoSkipAfter => "Auto-skip songs skipped (times, 0 - never): ",
+        ConfigFieldType::UiSounds => "UI sounds (track change, errors): ",
+        ConfigFieldType::PartyPort => "Party queue port (0 - off): ",
+        ConfigFieldType::CacheSize => "Cache size limit (MB, 0 - off): ",
+        ConfigFieldType::CheckForUpdates => "Check for quefi updates on launch: ",
+        ConfigFieldType::StartupWindow => "Start on: ",
+        ConfigFieldType::StartupPlaylist => "Start with playlist: ",
+        ConfigFieldType::LowMemory => "Low memory mode (no preloading or previews): ",
+        ConfigFieldType::MaxParallelSongs => "Playlist songs downloaded at once (0 - no limit): ",
+        ConfigFieldType::AudioFormat => "Download format: ",
+        ConfigFieldType::Cookies => "Cookies for restricted videos (browser or file): ",
+        ConfigFieldType::MusicDir => "Music directory: ",
+        ConfigFieldType::FfmpegPath => "ffmpeg path: ",
+        ConfigFieldType::DownloadHook => "Run after each download: ",
+        ConfigFieldType::SpotifyMarket => "Spotify market: ",
+    };
 
-        let value = match value.field_type {
-            ConfigFieldType::DlpPath => &value.value,
-            ConfigFieldType::SpotifyClientId => &value.value,
-            ConfigFieldType::SpotifyClientSecret => "********************************",
-        };
+    let value = match value.field_type {
+        ConfigFieldType::DlpPath => &value.value,
+        ConfigFieldType::SpotifyClientId => &value.value,
+        ConfigFieldType::SpotifyClientSecret if plain => "hidden",
+        ConfigFieldType::SpotifyClientSecret => "********************************",
+        ConfigFieldType::HighContrast => &value.value,
+        ConfigFieldType::CleanupDays => &value.value,
+        ConfigFieldType::NowPlayingPath if value.value.is_empty() => "Off",
+        ConfigFieldType::NowPlayingPath => &value.value,
+        ConfigFieldType::ScrollTitles => &value.value,
+        ConfigFieldType::DlpNiceness => &value.value,
+        ConfigFieldType::MaxDlpProcesses => &value.value,
+        ConfigFieldType::NormalizeLoudness => &value.value,
+        ConfigFieldType::Language if value.value.is_empty() => "System",
+        ConfigFieldType::Language => &value.value,
+        ConfigFieldType::ScreenReader => &value.value,
+        ConfigFieldType::TrackNotification => &value.value,
+        ConfigFieldType::AutoSkipAfter => &value.value,
+        ConfigFieldType::UiSounds => &value.value,
+        ConfigFieldType::PartyPort => &value.value,
+        ConfigFieldType::CacheSize => &value.value,
+        ConfigFieldType::CheckForUpdates => &value.value,
+        ConfigFieldType::StartupWindow => &value.value,
+        ConfigFieldType::StartupPlaylist if value.value.is_empty() => "First",
+        ConfigFieldType::StartupPlaylist => &value.value,
+        ConfigFieldType::LowMemory => &value.value,
+        ConfigFieldType::MaxParallelSongs => &value.value,
+        ConfigFieldType::AudioFormat => &value.value,
+        ConfigFieldType::Cookies if value.value.is_empty() => "None",
+        ConfigFieldType::Cookies => &value.value,
+        ConfigFieldType::MusicDir if value.value.is_empty() => "quefi/songs",
+        ConfigFieldType::MusicDir => &value.value,
+        ConfigFieldType::FfmpegPath if value.value.is_empty() => "From PATH",
+        ConfigFieldType::FfmpegPath => &value.value,
+        ConfigFieldType::DownloadHook if value.value.is_empty() => "Nothing",
+        ConfigFieldType::DownloadHook => &value.value,
+        ConfigFieldType::SpotifyMarket if value.value.is_empty() => "None",
+        ConfigFieldType::SpotifyMarket => &value.value,
+    };
 
-        ListItem::from(prefix + name + value)
-    }
+    ListItem::from(prefix + name + value)
 }
@@ -0,0 +1,136 @@
+use std::{
+    fs::{self, File},
+    hash::{DefaultHasher, Hash, Hasher},
+    io,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+/// Data that can be fetched again, like streamed tracks. Unlike songs/, files in here
+/// get removed, least recently used first, once they take up more than the size cap.
+#[derive(Clone)]
+pub(crate) struct Cache {
+    dir: PathBuf,
+    max_bytes: u64,
+}
+
+impl Cache {
+    pub(crate) fn new(dir: PathBuf, max_megabytes: u64) -> Self {
+        Cache {
+            dir,
+            max_bytes: max_megabytes * 1024 * 1024,
+        }
+    }
+
+    pub(crate) fn set_max_megabytes(&mut self, max_megabytes: u64) {
+        self.max_bytes = max_megabytes * 1024 * 1024;
+    }
+
+    // Kind keeps different kinds of data, e.g. "streams", in their own directory
+    fn path(&self, kind: &str, key: &str) -> PathBuf {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        self.dir
+            .join(kind)
+            .join(format!("{:016x}", hasher.finish()))
+    }
+
+    pub(crate) fn get(&self, kind: &str, key: &str) -> Option<Vec<u8>> {
+        let path = self.path(kind, key);
+        let data = fs::read(&path).ok()?;
+        // Modification time is what tells the least recently used files apart
+        if let Ok(file) = File::options().write(true).open(&path) {
+            let _ = file.set_modified(SystemTime::now());
+        }
+        Some(data)
+    }
+
+    // A size cap of 0 turns the cache off
+    pub(crate) fn put(&self, kind: &str, key: &str, data: &[u8]) -> io::Result<()> {
+        if (data.len() as u64) > self.max_bytes {
+            return Ok(());
+        }
+
+        let path = self.path(kind, key);
+        fs::create_dir_all(path.parent().unwrap())?;
+        fs::write(&path, data)?;
+        self.trim()?;
+        Ok(())
+    }
+
+    /// Removes the least recently used files until the cache fits in the size cap,
+    /// returns the amount of bytes freed
+    pub(crate) fn trim(&self) -> io::Result<u64> {
+        let mut files = Vec::new();
+        collect_files(&self.dir, &mut files)?;
+
+        let mut size: u64 = files.iter().map(|(_, len, _)| len).sum();
+        if size <= self.max_bytes {
+            return Ok(0);
+        }
+
+        files.sort_by_key(|&(_, _, modified)| modified);
+        let mut freed = 0;
+        for (path, len, _) in files {
+            if size <= self.max_bytes {
+                break;
+            }
+            fs::remove_file(&path)?;
+            size -= len;
+            freed += len;
+        }
+        Ok(freed)
+    }
+}
+
+fn collect_files(dir: &Path, files: &mut Vec<(PathBuf, u64, SystemTime)>) -> io::Result<()> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    for entry in entries.flatten() {
+        let Ok(metadata) = entry.metadata() else {
+            continue;
+        };
+        if metadata.is_dir() {
+            collect_files(&entry.path(), files)?;
+        } else {
+            let modified = metadata.modified().unwrap_or(SystemTime::UNIX_EPOCH);
+            files.push((entry.path(), metadata.len(), modified));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Cache;
+    use std::{env, fs, process, thread, time::Duration};
+
+    #[test]
+    fn trims_cache_least_recently_used_first() {
+        let dir = env::temp_dir().join(format!("quefi-test-{}-cache", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let mut cache = Cache::new(dir.clone(), 1);
+        let half = vec![0u8; 512 * 1024];
+
+        cache.put("streams", "old", &half).unwrap();
+        cache.put("streams", "new", &half).unwrap();
+        // Reading it makes it the most recently used one
+        thread::sleep(Duration::from_millis(20));
+        assert!(cache.get("streams", "old").is_some());
+        thread::sleep(Duration::from_millis(20));
+        cache.put("streams", "newest", &half).unwrap();
+
+        assert!(cache.get("streams", "old").is_some());
+        assert!(cache.get("streams", "new").is_none());
+        assert!(cache.get("streams", "newest").is_some());
+
+        cache.set_max_megabytes(0);
+        cache.trim().unwrap();
+        assert!(cache.get("streams", "newest").is_none());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
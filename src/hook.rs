@@ -0,0 +1,44 @@
+use crate::{Error, TaskResult, TaskReturn};
+use std::process::Stdio;
+use tokio::process::Command;
+
+/// Runs the user's post-download command with the downloaded file as its last argument
+pub(crate) async fn run_download_hook(command: String, path: String) -> TaskResult {
+    let failed = |reason: String| Error::DownloadHook(path.clone(), reason);
+    let output = shell(&command, &path)
+        .stdin(Stdio::null())
+        .output()
+        .await
+        .map_err(|err| failed(err.to_string()))?;
+
+    if !output.status.success() {
+        // The last thing it printed is usually what went wrong
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        let reason = stderr
+            .lines()
+            .rev()
+            .map(str::trim)
+            .find(|line| !line.is_empty())
+            .map(String::from)
+            .unwrap_or_else(|| output.status.to_string());
+        return Err(failed(reason));
+    }
+    Ok(TaskReturn::DownloadHookRan)
+}
+
+// Going through the shell lets the command use quotes, pipes and the like
+#[cfg(not(target_os = "windows"))]
+fn shell(command: &str, path: &str) -> Command {
+    let mut shell = Command::new("sh");
+    // sh takes the argument after the script as $0, the path is $1
+    shell.args(["-c", &format!("{command} \"$1\""), "sh", path]);
+    shell
+}
+
+#[cfg(target_os = "windows")]
+fn shell(command: &str, path: &str) -> Command {
+    let mut shell = Command::new("cmd");
+    shell.creation_flags(0x08000000); // Create no window
+    shell.arg("/C").raw_arg(format!("{command} \"{path}\""));
+    shell
+}
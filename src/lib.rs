@@ -0,0 +1,1086 @@
+use app::{App, DownloadRecord, HistoryEntry, RemovedSong, SerializablePlaylist, SerializableSong};
+use clap::{Parser, Subcommand};
+use lock::{Lock, LockError};
+use ratatui::{
+    backend::{Backend, CrosstermBackend},
+    crossterm::{
+        terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+        ExecutableCommand,
+    },
+    Terminal,
+};
+use serde::{Deserialize, Serialize};
+use spotify::{PlaylistInfo, SearchItem, SpotifyLink, TrackInfo};
+use std::{
+    collections::BTreeMap,
+    env,
+    fmt::{self, Display, Formatter},
+    fs::{create_dir_all, read_to_string, rename, write},
+    hash::{DefaultHasher, Hash, Hasher},
+    io::{self, stdout, ErrorKind},
+    mem,
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing_appender::rolling::Rotation;
+use update::Update;
+use youtube::{AudioFormat, SearchResult, SongMetadata, YoutubePlaylist};
+
+mod app;
+mod cache;
+mod hook;
+mod link;
+mod locale;
+mod lock;
+mod party;
+mod playlist_io;
+mod spotify;
+mod status;
+mod tags;
+mod update;
+mod util;
+mod youtube;
+
+#[doc(hidden)]
+pub use app::bench;
+
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SaveData {
+    // Missing in files older than versioning, see migrate
+    #[serde(default)]
+    version: u32,
+    dlp_path: String,
+    last_volume: f32,
+    last_repeat_mode: u8,
+    #[serde(default)]
+    shuffle: bool,
+    playlists: Vec<SerializablePlaylist>,
+    songs: Vec<SerializableSong>,
+    spotify_client_id: String,
+    spotify_client_secret: String,
+    last_valid_token: String,
+    // Unix time the token stops working at
+    #[serde(default)]
+    token_expires: u64,
+    #[serde(default)]
+    high_contrast: bool,
+    #[serde(default)]
+    cleanup_days: u32,
+    #[serde(default)]
+    now_playing_path: String,
+    #[serde(default)]
+    scroll_titles: bool,
+    #[serde(default)]
+    dlp_niceness: u8,
+    #[serde(default)]
+    max_dlp_processes: usize,
+    // Songs of an imported playlist searched for/downloaded at once, 0 - no limit
+    #[serde(default = "default_max_parallel_songs")]
+    max_parallel_songs: usize,
+    #[serde(default)]
+    normalize_loudness: bool,
+    #[serde(default)]
+    language: String,
+    #[serde(default)]
+    screen_reader: bool,
+    // 0 - off, 1 - terminal bell, 2 - OSC 9 desktop notification
+    #[serde(default)]
+    track_notification: u8,
+    #[serde(default)]
+    history: Vec<HistoryEntry>,
+    // Songs skipped this many times are skipped automatically, 0 - never
+    #[serde(default)]
+    auto_skip_after: u32,
+    // Older versions kept the login here, it's moved to its own file when loaded
+    #[serde(default, skip_serializing)]
+    spotify_refresh_token: String,
+    #[serde(default)]
+    last_release_check: u64,
+    #[serde(default)]
+    ui_sounds: bool,
+    #[serde(default)]
+    kiosk_playlist: String,
+    #[serde(default)]
+    party_port: u16,
+    // In megabytes, 0 - don't cache anything
+    #[serde(default = "default_cache_size")]
+    cache_size: u64,
+    #[serde(default)]
+    check_for_updates: bool,
+    // 0 - playlists, 1 - songs of the playlist, 2 - all songs, 3 - download manager, 4 - queue
+    #[serde(default)]
+    startup_window: u8,
+    // Empty - the first playlist
+    #[serde(default)]
+    startup_playlist: String,
+    // For devices like the Raspberry Pi Zero, keeps as little audio in memory as possible
+    #[serde(default)]
+    low_memory: bool,
+    // Passed to yt-dlp for age-restricted and members-only videos, empty - none
+    #[serde(default)]
+    dlp_cookies: String,
+    // Songs that aren't downloaded into a playlist, new playlists start with it too
+    #[serde(default)]
+    audio_format: AudioFormat,
+    // Where downloaded songs go, empty - quefi/songs
+    #[serde(default)]
+    music_dir: String,
+    // Empty - ffmpeg from PATH
+    #[serde(default)]
+    ffmpeg_path: String,
+    // Run with the file path after every download, empty - nothing
+    #[serde(default)]
+    download_hook: String,
+    // Country code sent with Spotify requests, empty - none
+    #[serde(default)]
+    spotify_market: String,
+    #[serde(default)]
+    download_history: Vec<DownloadRecord>,
+    // Songs removed from the library in the last 30 days, oldest first
+    #[serde(default)]
+    removed: Vec<RemovedSong>,
+    // Where ${NAME} at the start of a song path points to, e.g. a NAS mount.
+    // An environment variable with the same name wins, for machines that mount it elsewhere
+    #[serde(default)]
+    path_roots: BTreeMap<String, String>,
+    // Roots that were found on load, paths under them are saved with the placeholder again
+    #[serde(skip)]
+    resolved_roots: Vec<(String, String)>,
+}
+
+fn default_cache_size() -> u64 {
+    512
+}
+
+fn default_max_parallel_songs() -> usize {
+    4
+}
+
+impl Default for SaveData {
+    fn default() -> Self {
+        SaveData {
+            version: SAVE_VERSION,
+            dlp_path: String::new(),
+            last_volume: 0.5,
+            last_repeat_mode: 0,
+            shuffle: false,
+            playlists: Vec::new(),
+            songs: Vec::new(),
+            spotify_client_id: String::new(),
+            spotify_client_secret: String::new(),
+            last_valid_token: String::new(),
+            token_expires: 0,
+            high_contrast: false,
+            cleanup_days: 0,
+            now_playing_path: String::new(),
+            scroll_titles: false,
+            dlp_niceness: 0,
+            max_dlp_processes: 0,
+            max_parallel_songs: default_max_parallel_songs(),
+            normalize_loudness: false,
+            language: String::new(),
+            screen_reader: false,
+            track_notification: 0,
+            history: Vec::new(),
+            auto_skip_after: 0,
+            spotify_refresh_token: String::new(),
+            last_release_check: 0,
+            ui_sounds: false,
+            kiosk_playlist: String::new(),
+            party_port: 0,
+            cache_size: default_cache_size(),
+            check_for_updates: false,
+            startup_window: 0,
+            startup_playlist: String::new(),
+            low_memory: false,
+            dlp_cookies: String::new(),
+            audio_format: AudioFormat::default(),
+            music_dir: String::new(),
+            ffmpeg_path: String::new(),
+            download_hook: String::new(),
+            spotify_market: String::new(),
+            download_history: Vec::new(),
+            removed: Vec::new(),
+            path_roots: BTreeMap::new(),
+            resolved_roots: Vec::new(),
+        }
+    }
+}
+
+type TaskResult = Result<TaskReturn, Error>;
+// Never reused, a late result of a removed download can't be taken for a newer one
+pub(crate) type DownloadId = u32;
+
+#[derive(Debug)]
+pub(crate) enum TaskReturn {
+    SearchResult(DownloadId, SearchResult, SearchFor),
+    SearchResults(DownloadId, Vec<SearchResult>, SearchFor),
+    // The token, and the unix time it expires at
+    Token(DownloadId, String, u64, SpotifyLink),
+    PlaylistInfo(DownloadId, PlaylistInfo),
+    // Another page of a long Spotify playlist, with the link to the next one
+    PlaylistTracks(DownloadId, PlaylistIdx, Vec<TrackInfo>, Option<String>),
+    // Every track an imported playlist has on Spotify now
+    PlaylistSync(DownloadId, PlaylistIdx, Vec<TrackInfo>),
+    SongDownloaded(DownloadId, SearchFor, String),
+    SongStreamed(DownloadId, String, Vec<u8>),
+    LinkMetadata(DownloadId, String, SongMetadata),
+    YoutubePlaylist(DownloadId, YoutubePlaylist),
+    TrackInfo(DownloadId, TrackInfo),
+    // What a search on Spotify found for the query
+    SpotifySearch(DownloadId, String, Vec<SearchItem>),
+    // MP3 data of a track's preview
+    SpotifyPreview(Vec<u8>),
+    NewReleases(DownloadId, Vec<TrackInfo>),
+    UpdateChecked(Option<Update>),
+    TagsWritten(DownloadId, Vec<String>),
+    DlpDownloaded,
+    // Empty if yt-dlp didn't say
+    DlpVersion(String),
+    FfmpegDownloaded(PathBuf),
+    DownloadHookRan,
+}
+
+type PlaylistIdx = usize;
+type SongName = String;
+type SongIdx = usize;
+
+#[derive(Debug)]
+pub(crate) enum SearchFor {
+    // TODO: PlaylistIdx may be inaccurate when a new playlist is added, fix would be needed!
+    Playlist(PlaylistIdx, SongName, SongIdx),
+    GlobalSong(SongName),
+    MissingSong(SongName),
+}
+
+impl SearchFor {
+    fn song_name(&self) -> &str {
+        match self {
+            SearchFor::Playlist(_, name, _)
+            | SearchFor::GlobalSong(name)
+            | SearchFor::MissingSong(name) => name,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub(crate) enum Error {
+    SpotifyBadAuth(DownloadId, SpotifyLink),
+    Http(reqwest::Error),
+    Io(std::io::Error),
+    BadSerialization,
+    YtMusic,
+    // Download that fetched the stream, the link
+    Stream(DownloadId, String),
+    Metadata(DownloadId, String),
+    YoutubePlaylist(DownloadId, String),
+    NoNowPlayingFile,
+    SpotifyLogin,
+    SpotifyCredentials,
+    DlpMissing,
+    // A song that won't download, with the reason
+    SongFailed(DownloadId, SearchFor, String),
+    DlpDownload(String),
+    FfmpegDownload(String),
+    // Path of the downloaded file, what went wrong
+    DownloadHook(String, String),
+    PlaylistTracks(DownloadId, String),
+    SpotifyPreview(String),
+    // The task was stopped while quitting
+    Cancelled,
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::Io(err)
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    fn from(err: reqwest::Error) -> Self {
+        Error::Http(err)
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Http(err) => write!(f, "HTTP Error: {err}"),
+            Self::Io(err) => write!(f, "IO Error: {err}"),
+            Self::BadSerialization => write!(f, "Couldn't deserialize the repeat mode"),
+            Self::YtMusic => write!(f, "Failed to search YT Music"),
+            Self::Stream(_, link) => write!(f, "Failed to stream {link}"),
+            Self::Metadata(_, link) => write!(f, "Failed to get metadata of {link}"),
+            Self::YoutubePlaylist(_, link) => write!(f, "Failed to list the videos of {link}"),
+            Self::SpotifyLogin => write!(
+                f,
+                "Spotify didn't accept the login, run `quefi spotify-login` again"
+            ),
+            Self::SpotifyCredentials => {
+                write!(f, "Spotify didn't accept the client ID/secret")
+            }
+            Self::DlpMissing => write!(f, "yt-dlp wasn't found at the configured path"),
+            Self::DlpDownload(reason) => write!(f, "Couldn't install yt-dlp: {reason}"),
+            Self::FfmpegDownload(reason) => write!(f, "Couldn't install ffmpeg: {reason}"),
+            Self::PlaylistTracks(_, reason) => {
+                write!(f, "Couldn't fetch the rest of the playlist: {reason}")
+            }
+            Self::SpotifyPreview(reason) => {
+                write!(f, "Couldn't fetch the Spotify preview: {reason}")
+            }
+            Self::Cancelled => write!(f, "Cancelled"),
+            Self::DownloadHook(path, reason) => {
+                write!(f, "Post-download command failed for {path}: {reason}")
+            }
+            Self::SongFailed(_, search_for, reason) => {
+                write!(f, "Failed to download {}: {reason}", search_for.song_name())
+            }
+            Self::NoNowPlayingFile => write!(
+                f,
+                "Set the now playing file in the configuration menu to use quefi status"
+            ),
+            &Self::SpotifyBadAuth(..) => {
+                panic!("Tried to display Error::SpotifyBadAuth");
+            }
+        }
+    }
+}
+
+#[derive(Parser)]
+#[command(
+    version,
+    about = "Music player for the terminal, downloads from YouTube and Spotify"
+)]
+pub(crate) struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    /// Open the library without saving any changes, even while another quefi uses it
+    #[arg(long, global = true)]
+    read_only: bool,
+    /// Keep everything in quefi/ next to the executable
+    #[arg(long, global = true)]
+    portable: bool,
+    /// Keep the library, songs, settings and cache in this directory
+    #[arg(long, global = true, value_name = "DIR", conflicts_with = "portable")]
+    data_dir: Option<PathBuf>,
+    /// Volume to start at, in percent
+    #[arg(long, value_parser = clap::value_parser!(u16).range(0..=500))]
+    pub(crate) volume: Option<u16>,
+    /// Start playing this playlist right away
+    #[arg(long, value_name = "NAME")]
+    pub(crate) playlist: Option<String>,
+    /// Don't download, stream, check for updates or start the party queue
+    #[arg(long)]
+    pub(crate) offline: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print what's playing in the running quefi
+    Status {
+        /// Keep printing a new line whenever it changes
+        #[arg(long)]
+        follow: bool,
+    },
+    /// Download links in the running quefi, through the inbox
+    Add {
+        #[arg(required = true, value_name = "LINK")]
+        links: Vec<String>,
+    },
+    /// Log in to Spotify to import private playlists and new releases
+    SpotifyLogin,
+}
+
+// Where quefi keeps its files, decided once before anything is read or written
+static DIRS: OnceLock<Dirs> = OnceLock::new();
+
+struct Dirs {
+    data: PathBuf,
+    cache: PathBuf,
+    // Where config.toml is, so settings can be kept with the other dotfiles
+    config: PathBuf,
+    // Everything is in quefi/ next to the executable
+    portable: bool,
+}
+
+impl Dirs {
+    fn new(data_dir: Option<PathBuf>, portable: bool) -> Self {
+        if let Some(data) = data_dir {
+            return Dirs {
+                cache: data.join("cache"),
+                config: data.clone(),
+                data,
+                portable: false,
+            };
+        }
+
+        let exe = match std::env::current_exe() {
+            Ok(exe) => exe,
+            Err(err) => panic!("Failed to get executable file. {err}"),
+        };
+        let next_to_exe = exe.parent().unwrap().join("quefi");
+
+        // A quefi/ that's already there keeps being used, so updating doesn't lose the library
+        let portable = portable || next_to_exe.exists();
+        match (
+            portable,
+            dirs::data_dir(),
+            dirs::cache_dir(),
+            dirs::config_dir(),
+        ) {
+            (false, Some(data), Some(cache), Some(config)) => Dirs {
+                data: data.join("quefi"),
+                cache: cache.join("quefi"),
+                config: config.join("quefi"),
+                portable: false,
+            },
+            _ => Dirs {
+                cache: next_to_exe.join("cache"),
+                config: next_to_exe.clone(),
+                data: next_to_exe,
+                portable: true,
+            },
+        }
+    }
+}
+
+fn init_dirs(data_dir: Option<PathBuf>, portable: bool) {
+    let _ = DIRS.set(Dirs::new(data_dir, portable));
+}
+
+fn dirs() -> &'static Dirs {
+    DIRS.get_or_init(|| Dirs::new(None, false))
+}
+
+/// Data directory of the platform (like ~/.local/share/quefi), or quefi/ next to the executable
+pub(crate) fn get_quefi_dir() -> PathBuf {
+    dirs().data.clone()
+}
+
+pub(crate) fn get_cache_dir() -> PathBuf {
+    dirs().cache.clone()
+}
+
+/// Config directory of the platform (like ~/.config/quefi), where config.toml is
+pub(crate) fn get_config_dir() -> PathBuf {
+    dirs().config.clone()
+}
+
+pub(crate) fn is_portable() -> bool {
+    dirs().portable
+}
+
+pub(crate) fn songs_dir(music_dir: &str) -> PathBuf {
+    if music_dir.is_empty() {
+        get_quefi_dir().join("songs")
+    } else {
+        PathBuf::from(music_dir)
+    }
+}
+
+// Bumped with a migration step whenever old data.json files can't be read as they are
+const SAVE_VERSION: u32 = 2;
+// Fields of SaveData that are kept in config.toml instead of data.json
+const CONFIG_KEYS: [&str; 30] = [
+    "dlp_path",
+    "spotify_client_id",
+    "spotify_client_secret",
+    "high_contrast",
+    "cleanup_days",
+    "now_playing_path",
+    "scroll_titles",
+    "dlp_niceness",
+    "max_dlp_processes",
+    "normalize_loudness",
+    "language",
+    "screen_reader",
+    "track_notification",
+    "auto_skip_after",
+    "ui_sounds",
+    "party_port",
+    "cache_size",
+    "check_for_updates",
+    "startup_window",
+    "startup_playlist",
+    "low_memory",
+    "max_parallel_songs",
+    "audio_format",
+    "dlp_cookies",
+    "music_dir",
+    "ffmpeg_path",
+    "download_hook",
+    "spotify_market",
+    "kiosk_playlist",
+    "path_roots",
+];
+// A week of logs
+const LOG_FILES: usize = 7;
+pub(crate) const NEW_RELEASES_PLAYLIST: &str = "New Releases";
+// A week
+pub(crate) const RELEASE_CHECK_INTERVAL: u64 = 7 * 86_400;
+
+pub(crate) fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+impl SaveData {
+    // Tells if anything changed since the last save, without keeping a copy of the data around
+    pub(crate) fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        serde_json::to_string(self)
+            .unwrap_or_default()
+            .hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn root(&self, name: &str) -> Option<String> {
+        env::var(name)
+            .ok()
+            .filter(|root| !root.is_empty())
+            .or_else(|| self.path_roots.get(name).cloned())
+            .map(|root| root.trim_end_matches(['/', '\\']).to_string())
+    }
+
+    fn song_paths_mut(&mut self) -> impl Iterator<Item = &mut String> {
+        self.songs
+            .iter_mut()
+            .chain(self.removed.iter_mut().map(|removed| &mut removed.song))
+            .map(|song| &mut song.path)
+    }
+
+    // The rest of quefi only sees real paths, placeholders without a root are left as they are
+    fn expand_paths(&mut self) {
+        let mut names: Vec<String> = self.path_roots.keys().cloned().collect();
+        for song in self
+            .songs
+            .iter()
+            .chain(self.removed.iter().map(|removed| &removed.song))
+        {
+            if let Some(name) = util::path_placeholder(&song.path) {
+                if !names.iter().any(|other| other == name) {
+                    names.push(name.to_string());
+                }
+            }
+        }
+        self.resolved_roots = names
+            .into_iter()
+            .filter_map(|name| self.root(&name).map(|root| (name, root)))
+            .collect();
+
+        let roots = self.resolved_roots.clone();
+        for path in self.song_paths_mut() {
+            let Some(name) = util::path_placeholder(path) else {
+                continue;
+            };
+            if let Some((_, root)) = roots.iter().find(|(other, _)| other == name) {
+                *path = format!("{root}{}", &path[name.len() + 3..]);
+            }
+        }
+    }
+
+    fn collapse_paths(&mut self) {
+        let roots = mem::take(&mut self.resolved_roots);
+        for path in self.song_paths_mut() {
+            if let Some(collapsed) = util::collapse_path(path, &roots) {
+                *path = collapsed;
+            }
+        }
+        self.resolved_roots = roots;
+    }
+}
+
+pub(crate) fn save_data(data: &mut SaveData) -> io::Result<()> {
+    let (config, library) = serialize_save_data(data)?;
+    let config_dir = get_config_dir();
+    create_dir_all(&config_dir)?;
+    replace_file(&config_dir.join("config.toml"), config)?;
+    replace_file(&get_quefi_dir().join("data.json"), library)
+}
+
+// Contents of config.toml and data.json
+fn serialize_save_data(data: &mut SaveData) -> io::Result<(String, String)> {
+    data.collapse_paths();
+    let serde_json::Value::Object(mut library) = serde_json::to_value(&data).unwrap() else {
+        unreachable!();
+    };
+    data.expand_paths();
+
+    let config: serde_json::Map<String, serde_json::Value> = CONFIG_KEYS
+        .iter()
+        .filter_map(|&key| library.remove_entry(key))
+        .collect();
+    let config = toml::to_string(&config).map_err(io::Error::other)?;
+    Ok((config, serde_json::to_string(&library).unwrap()))
+}
+
+// Renamed over the old file, so a crash while writing doesn't leave half of it
+fn replace_file(path: &Path, contents: String) -> io::Result<()> {
+    let mut temp = path.as_os_str().to_owned();
+    temp.push(".tmp");
+    write(&temp, contents)?;
+    rename(temp, path)
+}
+
+// The running instance picks the links up from the inbox like any other dropped file
+fn add_to_inbox(links: &[String]) -> Result<(), String> {
+    let path = get_quefi_dir().join("inbox").join(format!(
+        "added-{}-{}.txt",
+        unix_timestamp(),
+        std::process::id()
+    ));
+    write(&path, links.join("\n"))
+        .map_err(|err| format!("Could not write {}: {err}", path.display()))?;
+
+    println!("Added {} link(s) to the inbox", links.len());
+    Ok(())
+}
+
+// Links a Spotify account, so its private playlists can be imported and new releases of the
+// artists it follows get downloaded weekly
+async fn spotify_login(data: &mut SaveData) -> Result<(), String> {
+    if data.spotify_client_id.is_empty() {
+        return Err(String::from(
+            "Set the Spotify client ID in the configuration menu first",
+        ));
+    }
+
+    let login = spotify::Login::new();
+    println!(
+        "Add {} to the redirect URIs of your Spotify app, then open:",
+        spotify::REDIRECT_URI
+    );
+    println!("{}", login.authorize_url(&data.spotify_client_id));
+
+    // Something else could be using the port, pasting the address works without it
+    let code = match tokio::net::TcpListener::bind(spotify::REDIRECT_ADDRESS).await {
+        Ok(listener) => {
+            println!("Waiting for you to allow access...");
+            login.wait_for_redirect(listener).await
+        }
+        Err(_) => {
+            println!(
+                "After allowing access, the browser fails to load a page. Paste its address here:"
+            );
+            let mut input = String::new();
+            io::stdin()
+                .read_line(&mut input)
+                .map_err(|err| format!("Could not read the address: {err}"))?;
+            login.code_from_address(input.trim())
+        }
+    }
+    .map_err(|err| err.to_string())?;
+
+    let client = reqwest::Client::new();
+    let refresh_token = spotify::log_in(&client, &data.spotify_client_id, &login, &code)
+        .await
+        .map_err(|err| err.to_string())?;
+    spotify::store_refresh_token(&refresh_token)
+        .map_err(|err| format!("Could not save the login: {err}"))?;
+    // Tokens without a user can't see private playlists
+    data.last_valid_token.clear();
+    // The first check picks up the last week
+    data.last_release_check = unix_timestamp().saturating_sub(RELEASE_CHECK_INTERVAL);
+
+    println!("Logged in, private playlists can be imported now and new releases will show up in the \"{NEW_RELEASES_PLAYLIST}\" playlist");
+    Ok(())
+}
+
+fn parse_config(contents: &str) -> Result<serde_json::Map<String, serde_json::Value>, String> {
+    let table: toml::Table = toml::from_str(contents).map_err(|err| err.to_string())?;
+    let serde_json::Value::Object(mut config) =
+        serde_json::to_value(table).map_err(|err| err.to_string())?
+    else {
+        unreachable!();
+    };
+    // Anything else would override the library
+    config.retain(|key, _| CONFIG_KEYS.contains(&key.as_str()));
+    Ok(config)
+}
+
+// Settings in config.toml win over the ones of data.json files written before it existed
+fn parse_save_data(
+    contents: &str,
+    config: serde_json::Map<String, serde_json::Value>,
+) -> Result<SaveData, String> {
+    let mut value: serde_json::Value =
+        serde_json::from_str(contents).map_err(|err| err.to_string())?;
+    migrate(&mut value)?;
+
+    let serde_json::Value::Object(fields) = &mut value else {
+        unreachable!();
+    };
+    fields.extend(config);
+    // A deleted config.toml, or settings left out of it, start from the defaults
+    let serde_json::Value::Object(defaults) =
+        serde_json::to_value(SaveData::default()).map_err(|err| err.to_string())?
+    else {
+        unreachable!();
+    };
+    for (key, default) in defaults {
+        if CONFIG_KEYS.contains(&key.as_str()) {
+            fields.entry(key).or_insert(default);
+        }
+    }
+    serde_json::from_value(value).map_err(|err| err.to_string())
+}
+
+// Brings data.json from an older quefi up to SAVE_VERSION, one version at a time
+fn migrate(value: &mut serde_json::Value) -> Result<(), String> {
+    let version = value
+        .get("version")
+        .and_then(serde_json::Value::as_u64)
+        .unwrap_or(0);
+    if version > SAVE_VERSION as u64 {
+        return Err(format!(
+            "it's from a newer quefi (data version {version}, this one reads up to {SAVE_VERSION})"
+        ));
+    }
+    let Some(fields) = value.as_object_mut() else {
+        return Err(String::from("it isn't a JSON object"));
+    };
+
+    if version < 1 {
+        // Before versioning, fields were sometimes added without a default
+        let serde_json::Value::Object(defaults) =
+            serde_json::to_value(SaveData::default()).map_err(|err| err.to_string())?
+        else {
+            unreachable!();
+        };
+        for (key, default) in defaults {
+            fields.entry(key).or_insert(default);
+        }
+    }
+    // Version 2 moved the settings to config.toml, they're split off on the next save
+
+    fields.insert(String::from("version"), SAVE_VERSION.into());
+    Ok(())
+}
+
+fn load_data() -> SaveData {
+    let dir = get_quefi_dir();
+    if let Err(err) = create_dir_all(dir.join("songs")) {
+        if err.kind() != ErrorKind::AlreadyExists {
+            panic!("Could not create quefi/songs/ in the directory of the quefi executable file: {err}");
+        }
+    }
+    if let Err(err) = create_dir_all(dir.join("inbox")) {
+        if err.kind() != ErrorKind::AlreadyExists {
+            panic!("Could not create quefi/inbox/ in the directory of the quefi executable file: {err}");
+        }
+    }
+    let config_path = get_config_dir().join("config.toml");
+    let config = match read_to_string(&config_path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == ErrorKind::NotFound => String::new(),
+        Err(err) => panic!("Could not read {}: {err}", config_path.display()),
+    };
+    let config = match parse_config(&config) {
+        Ok(config) => config,
+        Err(err) => {
+            // Starting with the default settings would overwrite it on exit
+            eprintln!("Could not load {}: {err}", config_path.display());
+            eprintln!("The file was left as it is, fix or move it away to start quefi again.");
+            std::process::exit(1);
+        }
+    };
+
+    // A first start still uses a config.toml written by hand
+    let (contents, first_start) = match read_to_string(dir.join("data.json")) {
+        Ok(contents) => (contents, false),
+        Err(err) if err.kind() == ErrorKind::NotFound => (String::from("{}"), true),
+        Err(err) => panic!("Could not read quefi/data.json: {err}"),
+    };
+    let mut data = match parse_save_data(&contents, config) {
+        Ok(data) => data,
+        Err(err) => {
+            // Starting with empty data would overwrite the library on exit
+            eprintln!("Could not load quefi/data.json: {err}");
+            eprintln!("The file was left as it is, fix or move it away to start quefi again.");
+            std::process::exit(1);
+        }
+    };
+    data.expand_paths();
+    if first_start {
+        if let Err(err) = save_data(&mut data) {
+            panic!("Could not write quefi/data.json: {err}");
+        }
+    }
+    if !data.spotify_refresh_token.is_empty()
+        && spotify::store_refresh_token(&data.spotify_refresh_token).is_ok()
+    {
+        data.spotify_refresh_token.clear();
+    }
+    data
+}
+
+pub(crate) fn make_safe_filename(input: &str) -> String {
+    let input = util::UNSAFE_FILENAME_CHARS.replace_all(input, "_");
+    let input = util::EDGE_DOTS.replace_all(input.as_ref(), "_");
+
+    let mut result = input.into_owned();
+    if util::RESERVED_FILENAME.is_match(result.as_str()) {
+        result.push('_');
+    }
+
+    result
+}
+
+// Daily files in quefi/logs/, everything that was in the log line and the errors behind it
+fn init_logging() {
+    let appender = tracing_appender::rolling::Builder::new()
+        .rotation(Rotation::DAILY)
+        .filename_prefix("quefi")
+        .filename_suffix("log")
+        .max_log_files(LOG_FILES)
+        .build(get_quefi_dir().join("logs"));
+    match appender {
+        Ok(appender) => tracing_subscriber::fmt()
+            .with_writer(appender)
+            .with_target(false)
+            .init(),
+        Err(err) => eprintln!("Could not open quefi/logs/, not logging to a file: {err}"),
+    }
+}
+
+fn init_terminal() -> io::Result<Terminal<impl Backend>> {
+    enable_raw_mode()?;
+    stdout().execute(EnterAlternateScreen)?;
+
+    let terminal = Terminal::new(CrosstermBackend::new(stdout()))?;
+    Ok(terminal)
+}
+
+fn restore_terminal() -> io::Result<()> {
+    disable_raw_mode()?;
+    stdout().execute(LeaveAlternateScreen)?;
+
+    Ok(())
+}
+
+/// What the quefi executable runs, it's in the library so the benchmarks can build on it
+pub async fn run() -> Result<(), impl fmt::Debug> {
+    run_cli(Cli::parse()).await
+}
+
+async fn run_cli(cli: Cli) -> Result<(), Error> {
+    init_dirs(cli.data_dir.clone(), cli.portable);
+
+    match &cli.command {
+        Some(Command::Status { follow }) => {
+            let data = load_data();
+            if let Err(err) = status::print_status(&data, *follow) {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Add { links }) => {
+            if let Err(err) = add_to_inbox(links) {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::SpotifyLogin) => {
+            // Saving the token while quefi runs would be overwritten by its own save on exit
+            let _lock = match Lock::acquire() {
+                Ok(lock) => Some(lock),
+                Err(LockError::Held(pid)) => {
+                    eprintln!("Close quefi (PID {pid}) before logging in");
+                    std::process::exit(1);
+                }
+                Err(LockError::Starting) => {
+                    eprintln!("Quefi is starting, close it before logging in");
+                    std::process::exit(1);
+                }
+                Err(LockError::Io(_)) => None,
+            };
+
+            let mut data = load_data();
+            if let Err(err) = spotify_login(&mut data).await {
+                eprintln!("{err}");
+                std::process::exit(1);
+            }
+            if let Err(err) = save_data(&mut data) {
+                eprintln!("Could not save quefi/data.json: {err}");
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        None => {}
+    }
+
+    let mut read_only = cli.read_only;
+    let _lock = match Lock::acquire() {
+        Ok(lock) => Some(lock),
+        Err(LockError::Held(_) | LockError::Starting) if read_only => None,
+        Err(LockError::Held(pid)) => {
+            eprintln!("Quefi is already running (PID {pid}) and using the same data directory.");
+            eprintln!("Use `quefi add <link>...` to download links in the running instance,");
+            eprintln!("or `quefi --read-only` to open the library without saving any changes.");
+            std::process::exit(1);
+        }
+        Err(LockError::Starting) => {
+            eprintln!("Another quefi is starting and using the same data directory.");
+            eprintln!("Use `quefi --read-only` to open the library without saving any changes.");
+            std::process::exit(1);
+        }
+        Err(LockError::Io(err)) => {
+            eprintln!("Could not create quefi/quefi.lock, opening read-only: {err}");
+            read_only = true;
+            None
+        }
+    };
+    // Only read after the lock, so the data can't change before this instance saves over it
+    let mut data = load_data();
+
+    init_logging();
+    // Bugs left to panic would otherwise leave the terminal in raw mode
+    let default_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        // Tasks run on the runtime's other threads, their panics are shown in quefi instead
+        if std::thread::current().name() != Some("main") {
+            tracing::error!("{info}");
+            return;
+        }
+        let _ = restore_terminal();
+        default_hook(info);
+    }));
+    let terminal = init_terminal()?;
+    if let Some(volume) = cli.volume {
+        data.last_volume = volume as f32 / 100.;
+    }
+    let mut app = App::new(data);
+    app.read_only = read_only;
+    app.offline = cli.offline;
+    app.start_playlist = cli.playlist;
+
+    app.init()?;
+    app.run(terminal).await?;
+
+    let saved = if app.read_only {
+        Ok(())
+    } else {
+        save_data(&mut app.save_data)
+    };
+    restore_terminal()?;
+    saved?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        parse_config, parse_save_data, serialize_save_data, SaveData, SerializableSong,
+        SAVE_VERSION,
+    };
+    use crate::youtube::AudioFormat;
+    use serde_json::Map;
+
+    #[test]
+    fn migrates_old_save_data() {
+        // Written before versioning, without fields that didn't have a default
+        let data = parse_save_data(
+            r#"{"dlp_path": "yt-dlp", "last_volume": 0.3, "playlists": [], "songs": []}"#,
+            Map::new(),
+        )
+        .unwrap();
+        assert_eq!(data.version, SAVE_VERSION);
+        assert_eq!(data.dlp_path, "yt-dlp");
+        assert_eq!(data.last_volume, 0.3);
+        assert_eq!(data.last_repeat_mode, 0);
+        assert!(data.last_valid_token.is_empty());
+
+        let newer = format!(r#"{{"version": {}}}"#, SAVE_VERSION + 1);
+        assert!(matches!(
+            parse_save_data(&newer, Map::new()),
+            Err(err) if err.starts_with("it's from a newer quefi")
+        ));
+        assert!(parse_save_data(r#"{"songs": 5}"#, Map::new()).is_err());
+        assert!(parse_save_data("not json", Map::new()).is_err());
+    }
+
+    #[test]
+    fn keeps_settings_in_config_toml() {
+        let mut data = SaveData {
+            dlp_path: String::from("/usr/bin/yt-dlp"),
+            cache_size: 100,
+            audio_format: AudioFormat::Opus,
+            last_volume: 0.3,
+            ..SaveData::default()
+        };
+        data.path_roots
+            .insert(String::from("NAS"), String::from("/mnt/nas"));
+        let (config, library) = serialize_save_data(&mut data).unwrap();
+        assert!(config.contains("dlp_path = \"/usr/bin/yt-dlp\""));
+        assert!(config.contains("audio_format = \"opus\""));
+        assert!(config.contains("[path_roots]\nNAS = \"/mnt/nas\""));
+        assert!(!config.contains("last_volume"));
+        assert!(!library.contains("dlp_path"));
+
+        let mut config =
+            parse_config(&config.replace("cache_size = 100", "cache_size = 50")).unwrap();
+        let data = parse_save_data(&library, config.clone()).unwrap();
+        assert_eq!(data.cache_size, 50);
+        assert_eq!(data.audio_format, AudioFormat::Opus);
+        assert_eq!(data.path_roots["NAS"], "/mnt/nas");
+        assert_eq!(data.last_volume, 0.3);
+
+        // Settings left out of config.toml are the defaults, and it can't change the library
+        config.remove("dlp_path");
+        let data = parse_save_data(&library, config).unwrap();
+        assert!(data.dlp_path.is_empty());
+        let config = parse_config("last_volume = 1.0\nhigh_contrast = true").unwrap();
+        let data = parse_save_data(&library, config).unwrap();
+        assert!(data.high_contrast);
+        assert_eq!(data.last_volume, 0.3);
+        assert!(parse_config("cache_size = ").is_err());
+    }
+
+    #[test]
+    fn resolves_path_placeholders() {
+        let song = |path: &str| {
+            let mut song = SerializableSong::default();
+            song.path = String::from(path);
+            song
+        };
+        let mut data = SaveData {
+            songs: vec![
+                song("${QUEFI_TEST_NAS}/a.mp3"),
+                song("${QUEFI_TEST_NOWHERE}/b.mp3"),
+                song("/mnt/nasty/c.mp3"),
+            ],
+            path_roots: [(String::from("QUEFI_TEST_NAS"), String::from("/mnt/nas/"))].into(),
+            ..SaveData::default()
+        };
+
+        data.expand_paths();
+        let paths: Vec<&str> = data.songs.iter().map(|song| song.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "/mnt/nas/a.mp3",
+                "${QUEFI_TEST_NOWHERE}/b.mp3",
+                "/mnt/nasty/c.mp3"
+            ]
+        );
+
+        // Songs added under a root are saved with the placeholder too
+        data.songs.push(song("/mnt/nas/new/d.mp3"));
+        data.collapse_paths();
+        let paths: Vec<&str> = data.songs.iter().map(|song| song.path.as_str()).collect();
+        assert_eq!(
+            paths,
+            [
+                "${QUEFI_TEST_NAS}/a.mp3",
+                "${QUEFI_TEST_NOWHERE}/b.mp3",
+                "/mnt/nasty/c.mp3",
+                "${QUEFI_TEST_NAS}/new/d.mp3"
+            ]
+        );
+    }
+}
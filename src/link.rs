@@ -0,0 +1,99 @@
+// Query parameters added by share buttons and trackers, they don't change what a link points to
+const TRACKING_PARAMS: [&str; 8] = [
+    "si", "feature", "pp", "fbclid", "gclid", "igshid", "ref", "context",
+];
+
+fn is_tracking_param(param: &str) -> bool {
+    let key = param.split('=').next().unwrap_or_default();
+    key.is_empty() || key.starts_with("utm_") || TRACKING_PARAMS.contains(&key)
+}
+
+/// Turns a pasted link into the form the link validation expects.
+/// Returns the trimmed input unchanged if it isn't a http(s) link.
+pub(crate) fn normalize(link: &str) -> String {
+    let link = link.trim();
+    let Some(rest) = link
+        .strip_prefix("https://")
+        .or_else(|| link.strip_prefix("http://"))
+    else {
+        return link.to_string();
+    };
+
+    let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+    let mut params: Vec<String> = query
+        .split('&')
+        .filter(|param| !is_tracking_param(param))
+        .map(String::from)
+        .collect();
+
+    let path = if let Some(id) = path.strip_prefix("youtu.be/") {
+        params.insert(0, format!("v={id}"));
+        String::from("www.youtube.com/watch")
+    } else if let Some(id) = [
+        "youtube.com/shorts/",
+        "www.youtube.com/shorts/",
+        "m.youtube.com/shorts/",
+    ]
+    .iter()
+    .find_map(|prefix| path.strip_prefix(prefix))
+    {
+        params.insert(0, format!("v={}", id.trim_end_matches('/')));
+        String::from("www.youtube.com/watch")
+    } else if let Some(rest) = path
+        .strip_prefix("music.youtube.com/")
+        .or_else(|| path.strip_prefix("m.youtube.com/"))
+    {
+        format!("www.youtube.com/{rest}")
+    } else if let Some((_, rest)) = path
+        .strip_prefix("open.spotify.com/intl-")
+        .and_then(|rest| rest.split_once('/'))
+    {
+        // Localized share links, like open.spotify.com/intl-de/track/...
+        format!("open.spotify.com/{rest}")
+    } else {
+        path.to_string()
+    };
+
+    // The video id has to come first, share links sometimes put other parameters before it
+    params.sort_by_key(|param| !param.starts_with("v="));
+
+    if params.is_empty() {
+        format!("https://{path}")
+    } else {
+        format!("https://{path}?{}", params.join("&"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize;
+
+    #[test]
+    fn normalizes_links() {
+        assert_eq!(
+            normalize("  https://youtu.be/dQw4w9WgXcQ?si=AbCdEf \n"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(
+            normalize("https://music.youtube.com/watch?feature=share&v=dQw4w9WgXcQ&t=42"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ&t=42"
+        );
+        assert_eq!(
+            normalize("https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"),
+            "https://open.spotify.com/track/4uLU6hMCjMI75M1A2tKUQC"
+        );
+        assert_eq!(
+            normalize("https://artist.bandcamp.com/track/song?utm_source=share&from=embed"),
+            "https://artist.bandcamp.com/track/song?from=embed"
+        );
+        assert_eq!(
+            normalize("https://youtube.com/shorts/dQw4w9WgXcQ?feature=share"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(
+            normalize("https://m.youtube.com/watch?v=dQw4w9WgXcQ"),
+            "https://www.youtube.com/watch?v=dQw4w9WgXcQ"
+        );
+        assert_eq!(normalize(" not a link "), "not a link");
+    }
+}
@@ -0,0 +1,82 @@
+use fixed_decimal::FixedDecimal;
+use icu_collator::{Collator, CollatorOptions};
+use icu_decimal::{options::FixedDecimalFormatterOptions, FixedDecimalFormatter};
+use icu_provider::DataLocale;
+use std::{cmp::Ordering, path::Path, time::Duration};
+
+pub(crate) struct Locale {
+    collator: Collator,
+    decimal: FixedDecimalFormatter,
+}
+
+// Turns POSIX names like pl_PL.UTF-8 into BCP 47 tags, returns None for C/POSIX or garbage
+pub(crate) fn parse(name: &str) -> Option<DataLocale> {
+    let name = name.split(['.', '@']).next().unwrap_or_default();
+    if name.is_empty() || name == "C" || name == "POSIX" {
+        return None;
+    }
+    name.replace('_', "-").parse().ok()
+}
+
+impl Locale {
+    // Empty language means the system locale, anything unparsable falls back to root collation
+    pub(crate) fn new(language: &str) -> Self {
+        let locale = if language.is_empty() {
+            sys_locale::get_locale().and_then(|name| parse(&name))
+        } else {
+            parse(language)
+        }
+        .unwrap_or_default();
+
+        Locale {
+            collator: Collator::try_new(&locale, CollatorOptions::new())
+                .or_else(|_| Collator::try_new(&DataLocale::default(), CollatorOptions::new()))
+                .unwrap(),
+            decimal: FixedDecimalFormatter::try_new(
+                &locale,
+                FixedDecimalFormatterOptions::default(),
+            )
+            .or_else(|_| {
+                FixedDecimalFormatter::try_new(
+                    &DataLocale::default(),
+                    FixedDecimalFormatterOptions::default(),
+                )
+            })
+            .unwrap(),
+        }
+    }
+
+    pub(crate) fn compare(&self, a: &str, b: &str) -> Ordering {
+        self.collator.compare(a, b)
+    }
+
+    pub(crate) fn compare_paths(&self, a: &Path, b: &Path) -> Ordering {
+        self.compare(&a.to_string_lossy(), &b.to_string_lossy())
+    }
+
+    pub(crate) fn number(&self, number: impl Into<FixedDecimal>) -> String {
+        self.decimal.format_to_string(&number.into())
+    }
+
+    // Zero-padded to at least the given amount of digits, used for indices and times
+    pub(crate) fn padded(&self, number: u64, digits: i16) -> String {
+        self.decimal
+            .format_to_string(&FixedDecimal::from(number).padded_start(digits))
+    }
+
+    pub(crate) fn duration(&self, duration: Duration) -> String {
+        let hours = duration.as_secs() / 3600;
+        let minutes = duration.as_secs() / 60 % 60;
+        let seconds = duration.as_secs() % 60;
+        if hours > 0 {
+            format!(
+                "{}:{}:{}",
+                self.number(hours),
+                self.padded(minutes, 2),
+                self.padded(seconds, 2)
+            )
+        } else {
+            format!("{}:{}", self.number(minutes), self.padded(seconds, 2))
+        }
+    }
+}
@@ -0,0 +1,162 @@
+use crate::{get_quefi_dir, youtube};
+use std::{
+    fs::{self, OpenOptions},
+    io::{self, ErrorKind, Write},
+    path::{Path, PathBuf},
+    process,
+    time::{Duration, SystemTime},
+};
+
+const LOCK_FILE: &str = "quefi.lock";
+
+// More than enough for an instance to write its PID into the lock it just created
+const PID_WRITE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Held while quefi runs, so a second instance doesn't overwrite data.json with its own saves
+pub(crate) struct Lock {
+    path: PathBuf,
+}
+
+pub(crate) enum LockError {
+    // PID of the instance that holds the lock
+    Held(u32),
+    // Another instance created the lock and didn't write its PID into it yet
+    Starting,
+    Io(io::Error),
+}
+
+impl Lock {
+    pub(crate) fn acquire() -> Result<Lock, LockError> {
+        let dir = get_quefi_dir();
+        fs::create_dir_all(&dir).map_err(LockError::Io)?;
+        Lock::acquire_at(dir.join(LOCK_FILE))
+    }
+
+    fn acquire_at(path: PathBuf) -> Result<Lock, LockError> {
+        // Second attempt is after removing a lock left behind by a crash
+        for _ in 0..2 {
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(mut file) => {
+                    write!(file, "{}", process::id()).map_err(LockError::Io)?;
+                    return Ok(Lock { path });
+                }
+                Err(err) if err.kind() == ErrorKind::AlreadyExists => {
+                    let pid = fs::read_to_string(&path)
+                        .ok()
+                        .and_then(|contents| contents.trim().parse::<u32>().ok());
+                    match pid {
+                        Some(pid) if is_quefi_running(pid) => return Err(LockError::Held(pid)),
+                        None if !is_abandoned(&path) => return Err(LockError::Starting),
+                        _ => fs::remove_file(&path).map_err(LockError::Io)?,
+                    }
+                }
+                Err(err) => return Err(LockError::Io(err)),
+            }
+        }
+
+        Err(LockError::Io(io::Error::from(ErrorKind::AlreadyExists)))
+    }
+}
+
+impl Drop for Lock {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+// A lock without a PID is only left behind by a crash right after creating it
+fn is_abandoned(path: &Path) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .is_some_and(|age| age > PID_WRITE_TIMEOUT)
+}
+
+// The pid could've been reused by a different program after a crash
+fn is_quefi_running(pid: u32) -> bool {
+    let name = std::env::current_exe()
+        .ok()
+        .and_then(|exe| {
+            exe.file_stem()
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| String::from("quefi"));
+
+    pid != process::id() && youtube::is_process_named(pid, &name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Lock, LockError};
+    use std::{
+        env, fs,
+        path::PathBuf,
+        process::{self, Command},
+        time::{Duration, SystemTime},
+    };
+
+    fn lock_path(name: &str) -> PathBuf {
+        let path = env::temp_dir().join(format!("quefi-test-{}-{name}.lock", process::id()));
+        let _ = fs::remove_file(&path);
+        path
+    }
+
+    #[test]
+    fn refuses_second_instance() {
+        let path = lock_path("held");
+        // Stands in for another instance, ps shows the name of this executable in its command
+        let name = env::current_exe().unwrap();
+        let name = name.file_stem().unwrap().to_string_lossy();
+        let mut other = Command::new("sh")
+            .args(["-c", "sleep 30; :", &name])
+            .spawn()
+            .unwrap();
+        fs::write(&path, other.id().to_string()).unwrap();
+
+        let result = Lock::acquire_at(path.clone());
+        other.kill().unwrap();
+        other.wait().unwrap();
+        assert!(matches!(result, Err(LockError::Held(pid)) if pid == other.id()));
+        assert_eq!(fs::read_to_string(&path).unwrap(), other.id().to_string());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn takes_over_abandoned_lock() {
+        let path = lock_path("crashed");
+        // Higher than any pid Linux or macOS hands out
+        fs::write(&path, "999999999").unwrap();
+        let lock = Lock::acquire_at(path.clone()).ok().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            process::id().to_string()
+        );
+        drop(lock);
+        assert!(!path.exists());
+
+        // Crashed before writing its pid
+        let path = lock_path("empty");
+        let file = fs::File::create(&path).unwrap();
+        file.set_modified(SystemTime::now() - Duration::from_secs(60))
+            .unwrap();
+        drop(file);
+        let _lock = Lock::acquire_at(path.clone()).ok().unwrap();
+        assert_eq!(
+            fs::read_to_string(&path).unwrap(),
+            process::id().to_string()
+        );
+    }
+
+    #[test]
+    fn waits_for_starting_instance() {
+        let path = lock_path("starting");
+        fs::write(&path, "").unwrap();
+        assert!(matches!(
+            Lock::acquire_at(path.clone()),
+            Err(LockError::Starting)
+        ));
+        assert!(path.exists());
+        fs::remove_file(path).unwrap();
+    }
+}
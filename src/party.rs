@@ -0,0 +1,251 @@
+use std::{io, net::IpAddr, sync::Arc, time::Duration};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::{
+        mpsc::{error::TrySendError, Sender},
+        Semaphore,
+    },
+    time::timeout,
+};
+
+// Guests only send a link or a search query, anything bigger isn't one
+const MAX_REQUEST_SIZE: usize = 16 * 1024;
+const MAX_SUBMISSION_LENGTH: usize = 300;
+// The server is open to the whole network, so nobody can keep it busy or fill up the queue
+const MAX_CONNECTIONS: usize = 32;
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Submissions waiting for the host, guests are told to try later once there are this many
+pub(crate) const MAX_PENDING: usize = 50;
+
+const FORM_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Quefi party queue</title>
+</head>
+<body>
+<h1>Request a song</h1>
+<form method="post" action="/">
+<input name="q" placeholder="YouTube/Spotify link or song name" autofocus required>
+<button>Send</button>
+</form>
+</body>
+</html>
+"#;
+
+const SENT_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Quefi party queue</title>
+</head>
+<body>
+<h1>Sent!</h1>
+<p>The host will approve it soon.</p>
+<a href="/">Request another song</a>
+</body>
+</html>
+"#;
+
+const BUSY_PAGE: &str = r#"<!DOCTYPE html>
+<html>
+<head>
+<meta charset="utf-8">
+<meta name="viewport" content="width=device-width, initial-scale=1">
+<title>Quefi party queue</title>
+</head>
+<body>
+<h1>Too many requests</h1>
+<p>The host has a lot of songs to go through, try again in a while.</p>
+<a href="/">Request another song</a>
+</body>
+</html>
+"#;
+
+/// A link or search query sent by a guest, waiting for the host to approve it
+#[derive(Debug)]
+pub(crate) struct Submission {
+    pub(crate) text: String,
+    pub(crate) from: IpAddr,
+}
+
+pub(crate) async fn serve(listener: TcpListener, sender: Sender<Submission>) {
+    let connections = Arc::new(Semaphore::new(MAX_CONNECTIONS));
+    loop {
+        let Ok((stream, addr)) = listener.accept().await else {
+            continue;
+        };
+        // Dropping the stream closes connections over the limit
+        let Ok(permit) = connections.clone().try_acquire_owned() else {
+            continue;
+        };
+        let sender = sender.clone();
+        tokio::spawn(async move {
+            let _ = handle_connection(stream, addr.ip(), sender).await;
+            drop(permit);
+        });
+    }
+}
+
+async fn handle_connection(
+    mut stream: TcpStream,
+    from: IpAddr,
+    sender: Sender<Submission>,
+) -> io::Result<()> {
+    let Ok(request) = timeout(READ_TIMEOUT, read_request(&mut stream)).await else {
+        return respond(&mut stream, "408 Request Timeout", "Request timeout").await;
+    };
+    let Some((head, body)) = request? else {
+        return respond(&mut stream, "400 Bad Request", "Bad request").await;
+    };
+
+    let mut request_line = head.lines().next().unwrap_or_default().split(' ');
+    let method = request_line.next().unwrap_or_default();
+    let path = request_line.next().unwrap_or_default();
+
+    match (method, path) {
+        ("GET", "/") => respond(&mut stream, "200 OK", FORM_PAGE).await,
+        ("POST", "/") => {
+            let text = form_value(&body, "q").unwrap_or_default();
+            let text = text.trim();
+            if text.is_empty() || text.chars().count() > MAX_SUBMISSION_LENGTH {
+                return respond(&mut stream, "400 Bad Request", FORM_PAGE).await;
+            }
+
+            let submission = Submission {
+                text: text.to_string(),
+                from,
+            };
+            match sender.try_send(submission) {
+                Err(TrySendError::Full(_)) => {
+                    respond(&mut stream, "503 Service Unavailable", BUSY_PAGE).await
+                }
+                _ => respond(&mut stream, "200 OK", SENT_PAGE).await,
+            }
+        }
+        _ => respond(&mut stream, "404 Not Found", "Not found").await,
+    }
+}
+
+// Returns the head and body, or None when the request is too big or cut off
+async fn read_request(stream: &mut TcpStream) -> io::Result<Option<(String, String)>> {
+    let mut request = Vec::new();
+    let mut buf = [0; 4096];
+
+    loop {
+        let read = stream.read(&mut buf).await?;
+        if read == 0 {
+            return Ok(None);
+        }
+        request.extend_from_slice(&buf[..read]);
+        if request.len() > MAX_REQUEST_SIZE {
+            return Ok(None);
+        }
+
+        let Some(head_end) = request.windows(4).position(|window| window == b"\r\n\r\n") else {
+            continue;
+        };
+        let head = String::from_utf8_lossy(&request[..head_end]).into_owned();
+        let content_length = head
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, value)| value.trim().parse::<usize>().ok())
+            .unwrap_or(0);
+
+        let body = &request[head_end + 4..];
+        if body.len() >= content_length {
+            let body = String::from_utf8_lossy(&body[..content_length]).into_owned();
+            return Ok(Some((head, body)));
+        }
+    }
+}
+
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+/// Decodes a field of an application/x-www-form-urlencoded body
+pub(crate) fn form_value(body: &str, name: &str) -> Option<String> {
+    body.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| decode_form_component(value))
+    })
+}
+
+fn decode_form_component(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut idx = 0;
+
+    while idx < bytes.len() {
+        match bytes[idx] {
+            b'+' => decoded.push(b' '),
+            b'%' if idx + 2 < bytes.len() => {
+                let hex = std::str::from_utf8(&bytes[idx + 1..idx + 3]).unwrap_or_default();
+                match u8::from_str_radix(hex, 16) {
+                    Ok(byte) => {
+                        decoded.push(byte);
+                        idx += 2;
+                    }
+                    Err(_) => decoded.push(b'%'),
+                }
+            }
+            byte => decoded.push(byte),
+        }
+        idx += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::serve;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+        sync::mpsc,
+    };
+
+    #[tokio::test]
+    async fn receives_queue_submissions() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (sender, mut receiver) = mpsc::channel(1);
+        let server = tokio::spawn(serve(listener, sender));
+
+        let submit = |body: &'static str| async move {
+            let mut stream = TcpStream::connect(addr).await.unwrap();
+            stream
+                .write_all(
+                    format!(
+                        "POST / HTTP/1.1\r\nHost: quefi\r\nContent-Type: application/x-www-form-urlencoded\r\nContent-Length: {}\r\n\r\n{body}",
+                        body.len()
+                    )
+                    .as_bytes(),
+                )
+                .await
+                .unwrap();
+            let mut response = String::new();
+            stream.read_to_string(&mut response).await.unwrap();
+            response
+        };
+        let response = submit("q=Never+Gonna+Give%20You+Up").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        // Guests are turned away while the queue is full
+        let response = submit("q=Sandstorm").await;
+        assert!(response.starts_with("HTTP/1.1 503 Service Unavailable"));
+
+        let submission = receiver.recv().await.unwrap();
+        assert_eq!(submission.text, "Never Gonna Give You Up");
+        server.abort();
+    }
+}
@@ -0,0 +1,156 @@
+use std::{fmt::Write, path::Path};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub(crate) enum Format {
+    M3u,
+    Xspf,
+    Pls,
+}
+
+pub(crate) struct Entry<'a> {
+    pub(crate) title: &'a str,
+    // Absolute path of the audio file
+    pub(crate) path: &'a str,
+    // In seconds, 0 when unknown
+    pub(crate) duration: u64,
+}
+
+impl Format {
+    pub(crate) fn from_path(path: &Path) -> Option<Format> {
+        let extension = path.extension()?.to_string_lossy().to_ascii_lowercase();
+        match extension.as_str() {
+            "m3u" | "m3u8" => Some(Format::M3u),
+            "xspf" => Some(Format::Xspf),
+            "pls" => Some(Format::Pls),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn serialize(self, name: &str, entries: &[Entry]) -> String {
+        match self {
+            Format::M3u => m3u(name, entries),
+            Format::Xspf => xspf(name, entries),
+            Format::Pls => pls(entries),
+        }
+    }
+}
+
+fn m3u(name: &str, entries: &[Entry]) -> String {
+    let mut out = format!("#EXTM3U\n#PLAYLIST:{name}\n");
+    for entry in entries {
+        // -1 is how extended M3U says the length is unknown
+        let duration = match entry.duration {
+            0 => -1,
+            duration => duration as i64,
+        };
+        let _ = writeln!(out, "#EXTINF:{duration},{}\n{}", entry.title, entry.path);
+    }
+    out
+}
+
+fn xspf(name: &str, entries: &[Entry]) -> String {
+    let mut out = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    out.push_str("<playlist version=\"1\" xmlns=\"http://xspf.org/ns/0/\">\n");
+    let _ = writeln!(out, "  <title>{}</title>\n  <trackList>", escape_xml(name));
+    for entry in entries {
+        out.push_str("    <track>\n");
+        let _ = writeln!(
+            out,
+            "      <location>{}</location>",
+            escape_xml(&file_uri(entry.path))
+        );
+        let _ = writeln!(out, "      <title>{}</title>", escape_xml(entry.title));
+        // XSPF durations are in milliseconds
+        if entry.duration > 0 {
+            let _ = writeln!(out, "      <duration>{}</duration>", entry.duration * 1000);
+        }
+        out.push_str("    </track>\n");
+    }
+    out.push_str("  </trackList>\n</playlist>\n");
+    out
+}
+
+fn pls(entries: &[Entry]) -> String {
+    let mut out = String::from("[playlist]\n");
+    for (idx, entry) in entries.iter().enumerate() {
+        let number = idx + 1;
+        let length = match entry.duration {
+            0 => -1,
+            duration => duration as i64,
+        };
+        let _ = writeln!(
+            out,
+            "File{number}={}\nTitle{number}={}\nLength{number}={length}",
+            entry.path, entry.title
+        );
+    }
+    let _ = write!(out, "NumberOfEntries={}\nVersion=2\n", entries.len());
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+// XSPF locations are URIs, Windows paths need the drive letter after an extra slash
+fn file_uri(path: &str) -> String {
+    let path = path.replace('\\', "/");
+    let mut uri = String::from("file://");
+    if !path.starts_with('/') {
+        uri.push('/');
+    }
+    for byte in path.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' | b':' => {
+                uri.push(byte as char)
+            }
+            _ => {
+                let _ = write!(uri, "%{byte:02X}");
+            }
+        }
+    }
+    uri
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Entry, Format};
+    use std::path::Path;
+
+    #[test]
+    fn exports_playlist_formats() {
+        let entries = [
+            Entry {
+                title: "Rock & Roll",
+                path: "/music/a b.mp3",
+                duration: 125,
+            },
+            Entry {
+                title: "Unknown",
+                path: "/music/c.mp3",
+                duration: 0,
+            },
+        ];
+
+        let m3u = Format::M3u.serialize("Mix", &entries);
+        assert!(m3u.starts_with("#EXTM3U\n"));
+        assert!(m3u.contains("#EXTINF:125,Rock & Roll\n/music/a b.mp3\n"));
+        assert!(m3u.contains("#EXTINF:-1,Unknown\n"));
+
+        let xspf = Format::Xspf.serialize("Mix", &entries);
+        assert!(xspf.contains("<location>file:///music/a%20b.mp3</location>"));
+        assert!(xspf.contains("<title>Rock &amp; Roll</title>"));
+        assert!(xspf.contains("<duration>125000</duration>"));
+
+        let pls = Format::Pls.serialize("Mix", &entries);
+        assert!(pls.contains("File1=/music/a b.mp3\nTitle1=Rock & Roll\nLength1=125\n"));
+        assert!(pls.ends_with("NumberOfEntries=2\nVersion=2\n"));
+
+        assert_eq!(Format::from_path(Path::new("mix.M3U8")), Some(Format::M3u));
+        assert_eq!(Format::from_path(Path::new("mix.txt")), None);
+    }
+}
@@ -1,7 +1,32 @@
-use reqwest::Client;
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::{Client, Url};
+use ring::{
+    digest::{digest, SHA256},
+    rand::{SecureRandom, SystemRandom},
+};
 use serde::Deserialize;
+use std::{collections::HashSet, fs, io};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpListener,
+};
 
-use crate::{Error, TaskResult, TaskReturn};
+use crate::{
+    get_quefi_dir, unix_timestamp, youtube::SearchFilter, DownloadId, Error, PlaylistIdx,
+    TaskResult, TaskReturn,
+};
+
+// Has to be added to the redirect URIs of the Spotify app, `quefi spotify-login` listens on it
+pub const REDIRECT_URI: &str = "http://127.0.0.1:8888/callback";
+pub const REDIRECT_ADDRESS: &str = "127.0.0.1:8888";
+
+// Followed artists for new releases, the user's own playlists for imports
+const SCOPES: &str = "user-follow-read playlist-read-private playlist-read-collaborative";
+
+// Kept out of data.json, which gets shared and backed up far more often
+const REFRESH_TOKEN_FILE: &str = "spotify.token";
+// Episodes and shows count as unavailable without a market, used when none is set
+const FALLBACK_MARKET: &str = "US";
 
 #[derive(Debug, Deserialize)]
 struct ApiPlaylistMetadata {
@@ -12,6 +37,9 @@ struct ApiPlaylistMetadata {
 #[derive(Debug, Deserialize)]
 struct ApiTracks {
     items: Vec<ApiTrackItem>,
+    // Link to the next page, the API only gives up to 100 tracks at once
+    #[serde(default)]
+    next: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,6 +52,29 @@ struct ApiTrackMetadata {
     name: String,
     artists: Vec<ApiArtist>,
     duration_ms: u32,
+    #[serde(default)]
+    track_number: u32,
+    #[serde(default)]
+    disc_number: u32,
+    // Missing on tracks listed by an album
+    #[serde(default)]
+    album: Option<ApiTrackAlbum>,
+    // 30 seconds of the track, Spotify doesn't have one for every track
+    #[serde(default)]
+    preview_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ApiTrackAlbum {
+    name: String,
+    // Largest first
+    #[serde(default)]
+    images: Vec<ApiImage>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+struct ApiImage {
+    url: String,
 }
 
 #[derive(Debug, Deserialize, Clone)]
@@ -34,97 +85,311 @@ struct ApiArtist {
 #[derive(Debug, Deserialize)]
 struct ApiTokenResponse {
     access_token: String,
+    // Seconds, an hour for Spotify
+    #[serde(default)]
+    expires_in: u64,
+    #[serde(default)]
+    refresh_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiFollowedArtists {
+    artists: ApiArtistPage,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiArtistPage {
+    items: Vec<ApiFollowedArtist>,
+    cursors: ApiCursors,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiFollowedArtist {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiCursors {
+    after: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAlbums {
+    items: Vec<ApiAlbum>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAlbum {
+    id: String,
+    // YYYY-MM-DD, or just YYYY-MM or YYYY for old releases
+    release_date: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAlbumTracks {
+    items: Vec<ApiTrackMetadata>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiAlbumInfo {
+    name: String,
+    // Largest first
+    #[serde(default)]
+    images: Vec<ApiImage>,
+    tracks: ApiAlbumTracks,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiEpisode {
+    name: String,
+    duration_ms: u32,
+    // Largest first
+    #[serde(default)]
+    images: Vec<ApiImage>,
+    // Missing on episodes listed by a show
+    #[serde(default)]
+    show: Option<ApiShowName>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiShowName {
+    name: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiShow {
+    name: String,
+    episodes: ApiEpisodes,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiEpisodes {
+    // Episodes that aren't available in the market are null
+    items: Vec<Option<ApiEpisode>>,
+    #[serde(default)]
+    next: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiSearch {
+    #[serde(default)]
+    tracks: ApiSearchPage,
+    #[serde(default)]
+    albums: ApiSearchPage,
+    #[serde(default)]
+    playlists: ApiSearchPage,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ApiSearchPage {
+    // Spotify sometimes lists playlists that are gone as null
+    items: Vec<Option<ApiSearchItem>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiSearchItem {
+    id: String,
+    name: String,
+    // Only tracks and albums have artists, only playlists have an owner
+    #[serde(default)]
+    artists: Vec<ApiArtist>,
+    #[serde(default)]
+    owner: Option<ApiOwner>,
+    #[serde(default)]
+    preview_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ApiOwner {
+    #[serde(default)]
+    display_name: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct TrackInfo {
-    // TODO: Use the duration to make searches more accurate
-    _duration_ms: u32,
+    pub duration_ms: u32,
     pub query: String,
     pub name: String,
+    pub artist: String,
+    pub album: Option<String>,
+    // 0 if unknown
+    pub track_number: u32,
+    pub cover_url: Option<String>,
+    pub filter: SearchFilter,
+    pub preview_url: Option<String>,
 }
 
 #[derive(Debug)]
 pub struct PlaylistInfo {
     pub tracks: Vec<TrackInfo>,
     pub name: String,
+    // Where the rest of the tracks are, if there are more than fit in one page
+    pub next: Option<String>,
+    // Kept with the imported playlist, so it can be synced later
+    pub spotify_id: Option<String>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum SpotifyLink {
     Track(String),
     Playlist(String),
+    Album(String),
+    Episode(String),
+    Show(String),
+    // Not a link anyone pastes, fetches an imported playlist again to sync it
+    Sync(PlaylistIdx, String),
+    // Not a link either, searches Spotify for tracks, albums and playlists
+    Search(String),
+    // Neither is this, fetches the next page of a playlist being imported
+    PlaylistPage(PlaylistIdx, String),
+    // Nor this, checks the followed artists for releases since the date
+    NewReleases(String),
     Invalid,
 }
 
+/// A track, album or playlist found by searching Spotify, imported through its link
+#[derive(Debug)]
+pub struct SearchItem {
+    pub link: SpotifyLink,
+    pub name: String,
+    // Artists of tracks and albums, the owner of playlists
+    pub by: String,
+    // Only tracks can have one
+    pub preview_url: Option<String>,
+}
+
 pub fn validate_spotify_link(link: &str) -> SpotifyLink {
-    if let Some(track_id) = link.strip_prefix("https://open.spotify.com/track/") {
-        if let Some((id, _)) = track_id.split_once('?') {
-            SpotifyLink::Track(id.to_string())
-        } else {
-            SpotifyLink::Track(track_id.to_string())
-        }
-    } else if let Some(playlist_id) = link.strip_prefix("https://open.spotify.com/playlist/") {
-        if let Some((id, _)) = playlist_id.split_once('?') {
-            SpotifyLink::Playlist(id.to_string())
-        } else {
-            SpotifyLink::Playlist(playlist_id.to_string())
-        }
-    } else {
-        SpotifyLink::Invalid
+    // URIs copied from the desktop app, like spotify:track:ID
+    let parts = match link.strip_prefix("spotify:") {
+        Some(uri) => uri.split_once(':'),
+        None => link
+            .strip_prefix("https://open.spotify.com/")
+            .map(|path| {
+                // Localized share links, like open.spotify.com/intl-de/track/...
+                path.strip_prefix("intl-")
+                    .and_then(|rest| rest.split_once('/'))
+                    .map_or(path, |(_, rest)| rest)
+            })
+            .and_then(|path| path.split_once('/')),
+    };
+    let Some((kind, id)) = parts else {
+        return SpotifyLink::Invalid;
+    };
+    let id = id.split_once('?').map_or(id, |(id, _)| id);
+    if id.is_empty() || !id.chars().all(|char| char.is_ascii_alphanumeric()) {
+        return SpotifyLink::Invalid;
+    }
+    let id = id.to_string();
+
+    match kind {
+        "track" => SpotifyLink::Track(id),
+        "playlist" => SpotifyLink::Playlist(id),
+        "album" => SpotifyLink::Album(id),
+        "episode" => SpotifyLink::Episode(id),
+        "show" => SpotifyLink::Show(id),
+        _ => SpotifyLink::Invalid,
     }
 }
 
+// Spotify answers with what's available in the market, relinking tracks to the local release.
+// The pages after the first keep the market in their links
+fn with_market(url: String, market: &str) -> String {
+    if market.is_empty() {
+        return url;
+    }
+    let separator = if url.contains('?') { '&' } else { '?' };
+    format!("{url}{separator}market={market}")
+}
+
+// ISO 3166-1 alpha-2, what Spotify takes as a market
+pub fn is_country_code(text: &str) -> bool {
+    text.len() == 2 && text.chars().all(|char| char.is_ascii_alphabetic())
+}
+
 fn transform_track_metadata(metadata: ApiTrackMetadata) -> TrackInfo {
-    TrackInfo {
-        query: format!(
-            "{} - {}",
-            metadata
-                .artists
-                .into_iter()
-                .map(|artist| artist.name)
-                .collect::<Vec<String>>()
-                .join(", "),
-            &metadata.name
+    let artist = metadata
+        .artists
+        .into_iter()
+        .map(|artist| artist.name)
+        .collect::<Vec<String>>()
+        .join(", ");
+
+    let (album, cover_url) = match metadata.album {
+        Some(album) => (
+            Some(album.name),
+            album.images.into_iter().next().map(|image| image.url),
         ),
+        None => (None, None),
+    };
+
+    TrackInfo {
+        query: format!("{} - {}", artist, &metadata.name),
         name: metadata.name,
-        _duration_ms: metadata.duration_ms,
+        artist,
+        album,
+        track_number: metadata.track_number,
+        cover_url,
+        duration_ms: metadata.duration_ms,
+        filter: SearchFilter::Songs,
+        preview_url: metadata.preview_url,
     }
 }
 
-pub async fn fetch_track_info(id: u8, client: &Client, track_id: &str, token: &str) -> TaskResult {
-    let url = format!("https://api.spotify.com/v1/tracks/{}", track_id);
+// The show stands in for both the artist and the album
+fn transform_episode(episode: ApiEpisode, show: &str) -> TrackInfo {
+    TrackInfo {
+        query: format!("{show} - {}", episode.name),
+        name: episode.name,
+        artist: show.to_string(),
+        album: Some(show.to_string()),
+        track_number: 0,
+        cover_url: episode.images.into_iter().next().map(|image| image.url),
+        duration_ms: episode.duration_ms,
+        filter: SearchFilter::Episodes,
+        preview_url: None,
+    }
+}
 
-    let result = client.get(&url).bearer_auth(token).send().await;
+pub async fn fetch_track_info(
+    id: DownloadId,
+    client: &Client,
+    track_id: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let url = with_market(
+        format!("https://api.spotify.com/v1/tracks/{}", track_id),
+        market,
+    );
 
-    match result {
-        Ok(res) => {
-            let metadata: ApiTrackMetadata = res.json().await?;
-            Ok(TaskReturn::TrackInfo(
-                id,
-                transform_track_metadata(metadata),
-            ))
-        }
-        Err(err) => {
-            if err.status().unwrap().as_u16() == 401 {
-                Err(Error::SpotifyBadAuth(
-                    id,
-                    SpotifyLink::Track(track_id.to_string()),
-                ))
-            } else {
-                Err(Error::Http(err))
-            }
-        }
+    // A failed request has no status, only a response says the token was refused
+    let res = client.get(&url).bearer_auth(token).send().await?;
+    if res.status().as_u16() == 401 {
+        return Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::Track(track_id.to_string()),
+        ));
     }
+
+    let metadata: ApiTrackMetadata = res.error_for_status()?.json().await?;
+    Ok(TaskReturn::TrackInfo(
+        id,
+        transform_track_metadata(metadata),
+    ))
 }
 
+const TRACK_FIELDS: &str = "name,artists(name),duration_ms,track_number,album(name,images)";
+
 pub async fn fetch_playlist_info(
-    id: u8,
+    id: DownloadId,
     client: &Client,
     playlist_id: &str,
     token: &str,
+    market: &str,
 ) -> TaskResult {
-    let url = format!("https://api.spotify.com/v1/playlists/{}?fields=name,tracks.items(track(name,artists(name),duration_ms))", playlist_id);
+    let url = with_market(format!("https://api.spotify.com/v1/playlists/{}?fields=name,tracks(next,items(track({TRACK_FIELDS})))", playlist_id), market);
 
     let result = client.get(&url).bearer_auth(token).send().await;
 
@@ -148,6 +413,8 @@ pub async fn fetch_playlist_info(
                         .map(|track| transform_track_metadata(track.track))
                         .collect::<Vec<TrackInfo>>(),
                     name: metadata.name,
+                    next: metadata.tracks.next,
+                    spotify_id: Some(playlist_id.to_string()),
                 },
             ))
         }
@@ -155,8 +422,311 @@ pub async fn fetch_playlist_info(
     }
 }
 
+// Imported like a playlist, the album's tracks don't say which album they're from
+pub async fn fetch_album_info(
+    id: DownloadId,
+    client: &Client,
+    album_id: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let url = with_market(
+        format!("https://api.spotify.com/v1/albums/{album_id}"),
+        market,
+    );
+    let res = client.get(&url).bearer_auth(token).send().await?;
+    if res.status().as_u16() == 401 {
+        return Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::Album(album_id.to_string()),
+        ));
+    }
+    let album: ApiAlbumInfo = res.error_for_status()?.json().await?;
+
+    // Albums rarely have more than one page, so they're all fetched before importing
+    let mut items = album.tracks.items;
+    let mut next = album.tracks.next;
+    while let Some(url) = next {
+        let page: ApiAlbumTracks = client
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        items.extend(page.items);
+        next = page.next;
+    }
+    items.sort_by_key(|track| (track.disc_number, track.track_number));
+
+    let cover_url = album.images.into_iter().next().map(|image| image.url);
+    let tracks = items
+        .into_iter()
+        .map(|track| TrackInfo {
+            album: Some(album.name.clone()),
+            cover_url: cover_url.clone(),
+            ..transform_track_metadata(track)
+        })
+        .collect();
+    Ok(TaskReturn::PlaylistInfo(
+        id,
+        PlaylistInfo {
+            tracks,
+            name: album.name,
+            next: None,
+            spotify_id: None,
+        },
+    ))
+}
+
+pub async fn fetch_episode_info(
+    id: DownloadId,
+    client: &Client,
+    episode_id: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let market = if market.is_empty() {
+        FALLBACK_MARKET
+    } else {
+        market
+    };
+    let url = format!("https://api.spotify.com/v1/episodes/{episode_id}?market={market}");
+    let res = client.get(&url).bearer_auth(token).send().await?;
+    if res.status().as_u16() == 401 {
+        return Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::Episode(episode_id.to_string()),
+        ));
+    }
+
+    let mut episode: ApiEpisode = res.error_for_status()?.json().await?;
+    let show = episode
+        .show
+        .take()
+        .map(|show| show.name)
+        .unwrap_or_default();
+    Ok(TaskReturn::TrackInfo(id, transform_episode(episode, &show)))
+}
+
+// Imported like an album, every episode is fetched before importing
+pub async fn fetch_show_info(
+    id: DownloadId,
+    client: &Client,
+    show_id: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let market = if market.is_empty() {
+        FALLBACK_MARKET
+    } else {
+        market
+    };
+    let url = format!("https://api.spotify.com/v1/shows/{show_id}?market={market}");
+    let res = client.get(&url).bearer_auth(token).send().await?;
+    if res.status().as_u16() == 401 {
+        return Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::Show(show_id.to_string()),
+        ));
+    }
+    let show: ApiShow = res.error_for_status()?.json().await?;
+
+    let mut items = show.episodes.items;
+    let mut next = show.episodes.next;
+    while let Some(url) = next {
+        let page: ApiEpisodes = client
+            .get(&url)
+            .bearer_auth(token)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        items.extend(page.items);
+        next = page.next;
+    }
+
+    let tracks = items
+        .into_iter()
+        .flatten()
+        .map(|episode| transform_episode(episode, &show.name))
+        .collect();
+    Ok(TaskReturn::PlaylistInfo(
+        id,
+        PlaylistInfo {
+            tracks,
+            name: show.name,
+            next: None,
+            spotify_id: None,
+        },
+    ))
+}
+
+// Results per type, so tracks don't push out the albums and playlists
+const SEARCH_LIMIT: &str = "5";
+
+pub async fn search(
+    id: DownloadId,
+    client: &Client,
+    query: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let mut params = vec![
+        ("q", query),
+        ("type", "track,album,playlist"),
+        ("limit", SEARCH_LIMIT),
+    ];
+    if !market.is_empty() {
+        params.push(("market", market));
+    }
+
+    let res = client
+        .get("https://api.spotify.com/v1/search")
+        .query(&params)
+        .bearer_auth(token)
+        .send()
+        .await?;
+    if res.status().as_u16() == 401 {
+        return Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::Search(query.to_string()),
+        ));
+    }
+    let found: ApiSearch = res.error_for_status()?.json().await?;
+
+    let pages = [
+        (
+            found.tracks,
+            SpotifyLink::Track as fn(String) -> SpotifyLink,
+        ),
+        (found.albums, SpotifyLink::Album),
+        (found.playlists, SpotifyLink::Playlist),
+    ];
+    let items = pages
+        .into_iter()
+        .flat_map(|(page, link)| {
+            page.items.into_iter().flatten().map(move |item| {
+                let by = match item.owner {
+                    Some(owner) => owner.display_name.unwrap_or_default(),
+                    None => item
+                        .artists
+                        .into_iter()
+                        .map(|artist| artist.name)
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                };
+                SearchItem {
+                    link: link(item.id),
+                    name: item.name,
+                    by,
+                    preview_url: item.preview_url,
+                }
+            })
+        })
+        .collect();
+    Ok(TaskReturn::SpotifySearch(id, query.to_string(), items))
+}
+
+// The preview is a short MP3 that doesn't need a token
+pub async fn fetch_preview(client: &Client, url: &str) -> TaskResult {
+    let result: Result<Vec<u8>, reqwest::Error> = async {
+        let res = client.get(url).send().await?.error_for_status()?;
+        Ok(res.bytes().await?.to_vec())
+    }
+    .await;
+
+    match result {
+        Ok(data) => Ok(TaskReturn::SpotifyPreview(data)),
+        Err(err) => Err(Error::SpotifyPreview(err.to_string())),
+    }
+}
+
+// Every track of an imported playlist, to see what changed since it was imported
+pub async fn fetch_synced_playlist(
+    id: DownloadId,
+    client: &Client,
+    playlist_idx: PlaylistIdx,
+    playlist_id: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    let mut url = with_market(
+        format!("https://api.spotify.com/v1/playlists/{playlist_id}/tracks?limit=100"),
+        market,
+    );
+    let mut tracks = Vec::new();
+    loop {
+        let res = client
+            .get(format!("{url}&fields=next,items(track({TRACK_FIELDS}))"))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        if res.status().as_u16() == 401 {
+            return Err(Error::SpotifyBadAuth(
+                id,
+                SpotifyLink::Sync(playlist_idx, playlist_id.to_string()),
+            ));
+        }
+
+        let page: ApiTracks = res.error_for_status()?.json().await?;
+        tracks.extend(
+            page.items
+                .into_iter()
+                .map(|track| transform_track_metadata(track.track)),
+        );
+        match page.next {
+            Some(next) => url = next,
+            None => break,
+        }
+    }
+    Ok(TaskReturn::PlaylistSync(id, playlist_idx, tracks))
+}
+
+// One page after the first, the link comes from the previous page
+pub async fn fetch_playlist_tracks(
+    id: DownloadId,
+    client: &Client,
+    playlist_idx: PlaylistIdx,
+    url: &str,
+    token: &str,
+) -> TaskResult {
+    let result: Result<Option<ApiTracks>, reqwest::Error> = async {
+        let res = client
+            .get(format!("{url}&fields=next,items(track({TRACK_FIELDS}))"))
+            .bearer_auth(token)
+            .send()
+            .await?;
+        // The token can expire in the middle of a long import
+        if res.status().as_u16() == 401 {
+            return Ok(None);
+        }
+        res.error_for_status()?.json().await.map(Some)
+    }
+    .await;
+
+    match result {
+        Ok(None) => Err(Error::SpotifyBadAuth(
+            id,
+            SpotifyLink::PlaylistPage(playlist_idx, url.to_string()),
+        )),
+        Ok(Some(page)) => Ok(TaskReturn::PlaylistTracks(
+            id,
+            playlist_idx,
+            page.items
+                .into_iter()
+                .map(|track| transform_track_metadata(track.track))
+                .collect(),
+            page.next,
+        )),
+        Err(err) => Err(Error::PlaylistTracks(id, err.to_string())),
+    }
+}
+
 pub async fn create_token(
-    id: u8,
+    id: DownloadId,
     client: &Client,
     client_id: &str,
     client_secret: &str,
@@ -168,9 +738,380 @@ pub async fn create_token(
         .form(&[("grant_type", "client_credentials")])
         .send()
         .await?;
+    if matches!(res.status().as_u16(), 400 | 401) {
+        return Err(Error::SpotifyCredentials);
+    }
+
+    let token: ApiTokenResponse = res.json().await?;
+    Ok(TaskReturn::Token(
+        id,
+        token.access_token,
+        unix_timestamp() + token.expires_in,
+        link,
+    ))
+}
+
+/// Authorization code with PKCE, the code is only worth something to whoever has the verifier
+pub struct Login {
+    pub verifier: String,
+    // Sent to Spotify and back, so a redirect quefi didn't ask for is told apart
+    pub state: String,
+}
+
+impl Login {
+    pub fn new() -> Login {
+        Login {
+            verifier: random_string(64),
+            state: random_string(16),
+        }
+    }
+
+    pub fn authorize_url(&self, client_id: &str) -> String {
+        Url::parse_with_params(
+            "https://accounts.spotify.com/authorize",
+            &[
+                ("client_id", client_id),
+                ("response_type", "code"),
+                ("redirect_uri", REDIRECT_URI),
+                ("scope", SCOPES),
+                ("state", &self.state),
+                ("code_challenge_method", "S256"),
+                ("code_challenge", &code_challenge(&self.verifier)),
+            ],
+        )
+        .unwrap()
+        .to_string()
+    }
+
+    // A bare code works too, in case the address was cut off when pasting it
+    pub fn code_from_address(&self, address: &str) -> Result<String, Error> {
+        let Ok(url) = Url::parse(address) else {
+            return Ok(address.to_string());
+        };
+        let param = |name: &str| {
+            url.query_pairs()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value.into_owned())
+        };
+
+        if param("state").is_none_or(|state| state != self.state) {
+            return Err(Error::SpotifyLogin);
+        }
+        param("code").ok_or(Error::SpotifyLogin)
+    }
+
+    // Answers the browser once Spotify redirects it back, returns the code it brought
+    pub async fn wait_for_redirect(&self, listener: TcpListener) -> Result<String, Error> {
+        loop {
+            let (mut stream, _) = listener.accept().await?;
+            let mut request = Vec::new();
+            let mut buf = [0; 4096];
+            while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buf).await?;
+                if read == 0 || request.len() > 16 * 1024 {
+                    break;
+                }
+                request.extend_from_slice(&buf[..read]);
+            }
+
+            let request = String::from_utf8_lossy(&request);
+            let path = request.split(' ').nth(1).unwrap_or_default();
+            // Browsers ask for a favicon too
+            if !path.starts_with("/callback") {
+                respond(&mut stream, "404 Not Found", "Not found").await?;
+                continue;
+            }
+
+            let code = self.code_from_address(&format!("http://{REDIRECT_ADDRESS}{path}"));
+            let body = match code {
+                Ok(_) => "Logged in, you can close this page and go back to quefi",
+                Err(_) => "Spotify didn't log you in, try `quefi spotify-login` again",
+            };
+            respond(&mut stream, "200 OK", body).await?;
+            return code;
+        }
+    }
+}
+
+async fn respond(stream: &mut tokio::net::TcpStream, status: &str, body: &str) -> io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+fn random_string(bytes: usize) -> String {
+    let mut random = vec![0; bytes];
+    SystemRandom::new().fill(&mut random).unwrap();
+    URL_SAFE_NO_PAD.encode(random)
+}
+
+pub(crate) fn code_challenge(verifier: &str) -> String {
+    URL_SAFE_NO_PAD.encode(digest(&SHA256, verifier.as_bytes()))
+}
+
+// Takes the code from the URL Spotify redirected to, returns the refresh token
+pub async fn log_in(
+    client: &Client,
+    client_id: &str,
+    login: &Login,
+    code: &str,
+) -> Result<String, Error> {
+    let res = client
+        .post("https://accounts.spotify.com/api/token")
+        .form(&[
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", REDIRECT_URI),
+            ("client_id", client_id),
+            ("code_verifier", &login.verifier),
+        ])
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(Error::SpotifyLogin);
+    }
 
     let token: ApiTokenResponse = res.json().await?;
-    Ok(TaskReturn::Token(id, token.access_token, link))
+    token.refresh_token.ok_or(Error::SpotifyLogin)
+}
+
+pub fn read_refresh_token() -> Option<String> {
+    fs::read_to_string(get_quefi_dir().join(REFRESH_TOKEN_FILE))
+        .ok()
+        .map(|token| token.trim().to_string())
+        .filter(|token| !token.is_empty())
+}
+
+// Only the user can read it
+pub fn store_refresh_token(token: &str) -> io::Result<()> {
+    let path = get_quefi_dir().join(REFRESH_TOKEN_FILE);
+    #[cfg(not(target_os = "windows"))]
+    {
+        use std::{io::Write, os::unix::fs::OpenOptionsExt, os::unix::fs::PermissionsExt};
+
+        let mut file = fs::OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .mode(0o600)
+            .open(&path)?;
+        // The mode only applies to new files
+        file.set_permissions(fs::Permissions::from_mode(0o600))?;
+        file.write_all(token.as_bytes())
+    }
+    #[cfg(target_os = "windows")]
+    fs::write(path, token)
+}
+
+// Spotify can hand out a new refresh token with the access token, the old one stops working then
+async fn refresh_access_token(client: &Client, client_id: &str) -> Result<ApiTokenResponse, Error> {
+    let refresh_token = read_refresh_token().ok_or(Error::SpotifyLogin)?;
+    let res = client
+        .post("https://accounts.spotify.com/api/token")
+        .form(&[
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.as_str()),
+            ("client_id", client_id),
+        ])
+        .send()
+        .await?;
+    if !res.status().is_success() {
+        return Err(Error::SpotifyLogin);
+    }
+
+    let token: ApiTokenResponse = res.json().await?;
+    if let Some(refresh_token) = &token.refresh_token {
+        store_refresh_token(refresh_token)?;
+    }
+    Ok(token)
+}
+
+// Same as create_token, but the token can also read the logged in user's private playlists
+pub async fn create_user_token(
+    id: DownloadId,
+    client: &Client,
+    client_id: &str,
+    link: SpotifyLink,
+) -> TaskResult {
+    let token = refresh_access_token(client, client_id).await?;
+    Ok(TaskReturn::Token(
+        id,
+        token.access_token,
+        unix_timestamp() + token.expires_in,
+        link,
+    ))
+}
+
+// Tracks of albums and singles that the followed artists released on or after the given date
+pub async fn fetch_new_releases(
+    id: DownloadId,
+    client: &Client,
+    since: &str,
+    token: &str,
+    market: &str,
+) -> TaskResult {
+    // Checking takes a request per artist and album, so the token can expire along the way
+    let bad_auth = || Error::SpotifyBadAuth(id, SpotifyLink::NewReleases(since.to_string()));
+
+    let mut artists = Vec::new();
+    let mut after = None;
+    loop {
+        let mut url = String::from("https://api.spotify.com/v1/me/following?type=artist&limit=50");
+        if let Some(after) = &after {
+            url.push_str(&format!("&after={after}"));
+        }
+        let res = client.get(&url).bearer_auth(token).send().await?;
+        if res.status().as_u16() == 401 {
+            return Err(bad_auth());
+        }
+        let page: ApiFollowedArtists = res.error_for_status()?.json().await?;
+
+        artists.extend(page.artists.items.into_iter().map(|artist| artist.id));
+        after = page.artists.cursors.after;
+        if after.is_none() {
+            break;
+        }
+    }
+
+    // Collaborations show up under every artist on them
+    let mut albums = HashSet::new();
+    for artist in artists {
+        let url = with_market(format!("https://api.spotify.com/v1/artists/{artist}/albums?include_groups=album,single&limit=20"), market);
+        let res = client.get(&url).bearer_auth(token).send().await?;
+        if res.status().as_u16() == 401 {
+            return Err(bad_auth());
+        }
+        let page: ApiAlbums = res.error_for_status()?.json().await?;
+
+        albums.extend(
+            page.items
+                .into_iter()
+                .filter(|album| album.release_date.as_str() >= since)
+                .map(|album| album.id),
+        );
+    }
+
+    let mut tracks = Vec::new();
+    for album in albums {
+        let url = with_market(
+            format!("https://api.spotify.com/v1/albums/{album}/tracks?limit=50"),
+            market,
+        );
+        let res = client.get(&url).bearer_auth(token).send().await?;
+        if res.status().as_u16() == 401 {
+            return Err(bad_auth());
+        }
+        let page: ApiAlbumTracks = res.error_for_status()?.json().await?;
+
+        tracks.extend(page.items.into_iter().map(transform_track_metadata));
+    }
+
+    Ok(TaskReturn::NewReleases(id, tracks))
 }
 
-// TODO: Make a function to access all track of playlist (fetch_playlist_info only lists the first 100)
+#[cfg(test)]
+mod tests {
+    use super::{code_challenge, validate_spotify_link, Login, SpotifyLink};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::{TcpListener, TcpStream},
+    };
+
+    #[tokio::test]
+    async fn takes_spotify_login_code_from_redirect() {
+        assert_eq!(
+            code_challenge("quefi-test-verifier-0123456789-abcdefghijklmnopqrstuvwxyz"),
+            "pbGqemAlbImTLUtOObaEMYSlOeY0BlCaOs3aMozWVck"
+        );
+
+        let login = Login::new();
+        let url = login.authorize_url("client");
+        assert!(url.contains("code_challenge_method=S256"));
+        assert!(url.contains(&format!("state={}", login.state)));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let state = login.state.clone();
+        let browser = tokio::spawn(async move {
+            // The favicon request comes first, the login waits for the redirect
+            for path in [
+                String::from("/favicon.ico"),
+                format!("/callback?code=abc&state={state}"),
+            ] {
+                let mut stream = TcpStream::connect(addr).await.unwrap();
+                let request = format!("GET {path} HTTP/1.1\r\nHost: 127.0.0.1\r\n\r\n");
+                stream.write_all(request.as_bytes()).await.unwrap();
+                let mut response = String::new();
+                stream.read_to_string(&mut response).await.unwrap();
+            }
+        });
+        assert_eq!(login.wait_for_redirect(listener).await.unwrap(), "abc");
+        browser.await.unwrap();
+
+        // Someone else's redirect isn't taken
+        assert!(login
+            .code_from_address("http://127.0.0.1:8888/callback?code=abc&state=other")
+            .is_err());
+        assert!(login
+            .code_from_address("http://127.0.0.1:8888/callback?code=abc")
+            .is_err());
+        assert_eq!(login.code_from_address("abc").unwrap(), "abc");
+    }
+
+    #[test]
+    fn recognizes_spotify_album_links() {
+        assert_eq!(
+            validate_spotify_link("https://open.spotify.com/album/4aawyAB9vmqN3uQ7FjRGTy?si=x"),
+            SpotifyLink::Album(String::from("4aawyAB9vmqN3uQ7FjRGTy"))
+        );
+        assert_eq!(
+            validate_spotify_link("https://open.spotify.com/artist/4aawyAB9vmqN3uQ7FjRGTy"),
+            SpotifyLink::Invalid
+        );
+    }
+
+    #[test]
+    fn recognizes_spotify_uris_and_localized_links() {
+        assert_eq!(
+            validate_spotify_link("spotify:track:4uLU6hMCjMI75M1A2tKUQC"),
+            SpotifyLink::Track(String::from("4uLU6hMCjMI75M1A2tKUQC"))
+        );
+        assert_eq!(
+            validate_spotify_link("spotify:playlist:37i9dQZF1DXcBWIGoYBM5M"),
+            SpotifyLink::Playlist(String::from("37i9dQZF1DXcBWIGoYBM5M"))
+        );
+        assert_eq!(
+            validate_spotify_link(
+                "https://open.spotify.com/intl-de/track/4uLU6hMCjMI75M1A2tKUQC?si=abc"
+            ),
+            SpotifyLink::Track(String::from("4uLU6hMCjMI75M1A2tKUQC"))
+        );
+        assert_eq!(
+            validate_spotify_link("spotify:track:"),
+            SpotifyLink::Invalid
+        );
+        assert_eq!(
+            validate_spotify_link("spotify:user:someone"),
+            SpotifyLink::Invalid
+        );
+    }
+
+    #[test]
+    fn recognizes_spotify_podcast_links() {
+        assert_eq!(
+            validate_spotify_link("https://open.spotify.com/episode/512ojhOuo1ktJprKbVcKyQ?si=x"),
+            SpotifyLink::Episode(String::from("512ojhOuo1ktJprKbVcKyQ"))
+        );
+        assert_eq!(
+            validate_spotify_link("https://open.spotify.com/show/38bS44xjbVVZ3No3ByF1dJ"),
+            SpotifyLink::Show(String::from("38bS44xjbVVZ3No3ByF1dJ"))
+        );
+        assert_eq!(
+            validate_spotify_link("https://open.spotify.com/show"),
+            SpotifyLink::Invalid
+        );
+    }
+}
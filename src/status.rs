@@ -0,0 +1,57 @@
+use crate::{Error, SaveData};
+use serde_json::{json, Value};
+use std::{fs, thread, time::Duration};
+
+const POLL_INTERVAL: Duration = Duration::from_millis(500);
+
+// Turns the now playing file into the JSON waybar/i3status custom modules expect
+fn status_line(contents: &str) -> String {
+    let info: Value = serde_json::from_str(contents).unwrap_or_else(|_| json!({}));
+    let status = info["status"].as_str().unwrap_or("stopped");
+
+    if status == "stopped" {
+        return json!({ "text": "", "tooltip": "Not playing", "class": "stopped", "alt": "stopped" })
+            .to_string();
+    }
+
+    let name = info["name"].as_str().unwrap_or_default();
+    let title = info["title"].as_str().unwrap_or(name);
+    let text = match info["artist"].as_str() {
+        Some(artist) => format!("{artist} - {title}"),
+        None => title.to_string(),
+    };
+    let tooltip = match info["album"].as_str() {
+        Some(album) => format!("{text}\n{album}"),
+        None => text.clone(),
+    };
+    let text = if status == "paused" {
+        format!("⏸ {text}")
+    } else {
+        text
+    };
+
+    json!({ "text": text, "tooltip": tooltip, "class": status, "alt": status }).to_string()
+}
+
+pub(crate) fn print_status(data: &SaveData, follow: bool) -> Result<(), Error> {
+    if data.now_playing_path.is_empty() {
+        return Err(Error::NoNowPlayingFile);
+    }
+
+    let mut last_line = String::new();
+    loop {
+        let contents = fs::read_to_string(&data.now_playing_path).unwrap_or_default();
+        let line = status_line(&contents);
+
+        // Status bars redraw on every line, so only print when something changed
+        if line != last_line {
+            println!("{line}");
+            last_line = line;
+        }
+
+        if !follow {
+            return Ok(());
+        }
+        thread::sleep(POLL_INTERVAL);
+    }
+}
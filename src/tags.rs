@@ -0,0 +1,94 @@
+use std::{fs::File, path::Path, time::Duration};
+use symphonia::core::{
+    formats::FormatOptions,
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey},
+    probe::{Hint, ProbeResult},
+};
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub(crate) struct SongTags {
+    pub(crate) title: Option<String>,
+    pub(crate) artist: Option<String>,
+    pub(crate) album: Option<String>,
+}
+
+impl SongTags {
+    fn apply(&mut self, revision: &MetadataRevision) {
+        for tag in revision.tags() {
+            let value = tag.value.to_string();
+            if value.trim().is_empty() {
+                continue;
+            }
+
+            match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => self.title = Some(value),
+                Some(StandardTagKey::Artist) => self.artist = Some(value),
+                Some(StandardTagKey::Album) => self.album = Some(value),
+                _ => {}
+            }
+        }
+    }
+
+    // Falls back to the name the song was added with when the file has no title
+    pub(crate) fn label(&self, name: &str) -> String {
+        let Some(title) = &self.title else {
+            return name.to_string();
+        };
+
+        let mut label = match &self.artist {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.clone(),
+        };
+        if let Some(album) = &self.album {
+            label.push_str(&format!(" ({album})"));
+        }
+        label
+    }
+}
+
+fn probe(path: &str) -> Option<ProbeResult> {
+    let file = File::open(path).ok()?;
+    let stream = MediaSourceStream::new(Box::new(file), Default::default());
+
+    let mut hint = Hint::new();
+    if let Some(extension) = Path::new(path).extension().and_then(|ext| ext.to_str()) {
+        hint.with_extension(extension);
+    }
+
+    symphonia::default::get_probe()
+        .format(
+            &hint,
+            stream,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()
+}
+
+pub(crate) fn read_tags(path: &str) -> Option<SongTags> {
+    let mut probed = probe(path)?;
+
+    let mut tags = SongTags::default();
+    // ID3 tags are read while probing, Vorbis comments and MP4 atoms belong to the container
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|log| log.current()) {
+        tags.apply(revision);
+    }
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.apply(revision);
+    }
+
+    if tags.title.is_none() && tags.artist.is_none() && tags.album.is_none() {
+        return None;
+    }
+    Some(tags)
+}
+
+// Only known when the container stores the length, reading through the whole file would be too slow
+pub(crate) fn read_duration(path: &str) -> Option<Duration> {
+    let probed = probe(path)?;
+    let params = &probed.format.default_track()?.codec_params;
+    let time = params.time_base?.calc_time(params.n_frames?);
+
+    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+}
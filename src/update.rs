@@ -0,0 +1,51 @@
+use crate::{youtube::fetch_latest_release, TaskResult, TaskReturn};
+use reqwest::Client;
+
+/// A newer release of quefi than the one that's running
+#[derive(Debug)]
+pub(crate) struct Update {
+    pub(crate) version: String,
+    pub(crate) changelog: String,
+}
+
+pub(crate) async fn check_for_update(client: &Client) -> TaskResult {
+    // Failing to check isn't worth bothering the user with, it's tried again on the next launch
+    let update = match fetch_latest_release(client, "nieboczek/quefi").await {
+        Ok(release) if is_newer(&release.tag_name, env!("CARGO_PKG_VERSION")) => Some(Update {
+            version: release.tag_name,
+            changelog: release.body,
+        }),
+        _ => None,
+    };
+    Ok(TaskReturn::UpdateChecked(update))
+}
+
+// Tags look like v0.7.1, anything that doesn't is never newer
+pub(crate) fn is_newer(tag: &str, current: &str) -> bool {
+    fn parse(version: &str) -> Option<(u64, u64, u64)> {
+        let mut parts = version.trim_start_matches('v').splitn(3, '.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next()?.parse().ok()?;
+        // Pre-release suffixes like -beta.1 are ignored
+        let patch = parts.next()?.split(['-', '+']).next()?.parse().ok()?;
+        Some((major, minor, patch))
+    }
+
+    match (parse(tag), parse(current)) {
+        (Some(tag), Some(current)) => tag > current,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_newer;
+
+    #[test]
+    fn compares_release_versions() {
+        assert!(is_newer("v0.8.0", "0.7.1"));
+        assert!(is_newer("v0.7.10", "0.7.9"));
+        assert!(!is_newer("v0.7.1", "0.7.1"));
+        assert!(!is_newer("nightly", "0.7.1"));
+    }
+}
@@ -0,0 +1,121 @@
+use regex::Regex;
+use std::sync::LazyLock;
+
+pub(crate) static YOUTUBE_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^https?://(((www\.|music\.|m\.)?youtube\.com/watch\?v=[\w-]{11}(&.*)?)|((www\.|m\.)?youtube\.com/shorts/[\w-]{11}/?(\?.*)?)|(youtu\.be/[\w-]{11}(\?.*)?))$",
+    )
+    .unwrap()
+});
+
+pub(crate) static YOUTUBE_PLAYLIST_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https?://(www\.|music\.|m\.)?youtube\.com/playlist\?list=[\w-]+(&.*)?$").unwrap()
+});
+
+// YT Music album/release pages, the id is what its track list is browsed by
+pub(crate) static YOUTUBE_ALBUM_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(r"^https?://(www\.|music\.)?youtube\.com/browse/(MPREb_[\w-]+)/?(\?.*)?$").unwrap()
+});
+
+pub(crate) static METADATA_LINK: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new(
+        r"^https?://(([\w-]+\.bandcamp\.com/track/[\w-]+)|((www\.|m\.)?soundcloud\.com/[\w-]+/[\w-]+))/?(\?.*)?$",
+    )
+    .unwrap()
+});
+
+pub(crate) static UNSAFE_FILENAME_CHARS: LazyLock<Regex> = LazyLock::new(|| {
+    Regex::new("[<>:\"/\\\\|?*\u{0000}-\u{001F}\u{007F}\u{0080}-\u{009F}]+").unwrap()
+});
+
+pub(crate) static EDGE_DOTS: LazyLock<Regex> = LazyLock::new(|| Regex::new("^\\.+|\\.+$").unwrap());
+
+pub(crate) static RESERVED_FILENAME: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new("^(con|prn|aux|nul|com\\d|lpt\\d)$").unwrap());
+
+pub(crate) static DURATION: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^(\d+:)*\d+:\d+$").unwrap());
+
+pub(crate) static PATH_PLACEHOLDER: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"^\$\{([A-Za-z_][A-Za-z0-9_]*)\}").unwrap());
+
+// Name of the ${NAME} placeholder a path starts with
+pub(crate) fn path_placeholder(path: &str) -> Option<&str> {
+    PATH_PLACEHOLDER
+        .captures(path)
+        .and_then(|captures| captures.get(1))
+        .map(|name| name.as_str())
+}
+
+// Turns a path under one of the roots back into ${NAME}/..., the longest root wins
+pub(crate) fn collapse_path(path: &str, roots: &[(String, String)]) -> Option<String> {
+    roots
+        .iter()
+        .filter(|(_, root)| {
+            path.strip_prefix(root.as_str())
+                .is_some_and(|rest| rest.is_empty() || rest.starts_with(['/', '\\']))
+        })
+        .max_by_key(|(_, root)| root.len())
+        .map(|(name, root)| format!("${{{name}}}{}", &path[root.len()..]))
+}
+
+// Case-insensitive subsequence match, returns the positions of the matched chars in text
+pub(crate) fn fuzzy_match(pattern: &str, text: &str) -> Option<Vec<usize>> {
+    let mut pattern = pattern.chars().flat_map(char::to_lowercase).peekable();
+    let mut positions = Vec::new();
+
+    for (idx, char) in text.chars().enumerate() {
+        let Some(&wanted) = pattern.peek() else {
+            break;
+        };
+        if char.to_lowercase().eq([wanted]) {
+            positions.push(idx);
+            pattern.next();
+        }
+    }
+
+    pattern.peek().is_none().then_some(positions)
+}
+
+// Unix timestamp to a (UTC) year, month and day, from Howard Hinnant's date algorithms
+pub(crate) fn civil_date(timestamp: u64) -> (i64, i64, i64) {
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_idx = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_idx + 2) / 5 + 1;
+    let month = if month_idx < 10 {
+        month_idx + 3
+    } else {
+        month_idx - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (year, month, day)
+}
+
+// Lowercase letters and digits only, so "Song (Remix)" and "song - remix" compare equal
+pub(crate) fn normalize_title(text: &str) -> String {
+    text.chars()
+        .filter(|char| char.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::normalize_title;
+
+    #[test]
+    fn matches_titles_loosely() {
+        assert_eq!(
+            normalize_title("Song (Remix)"),
+            normalize_title("song - remix")
+        );
+        assert_eq!(normalize_title("Żółć!"), "żółć");
+        assert_ne!(normalize_title("Song 2"), normalize_title("Song"));
+    }
+}
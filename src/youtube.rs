@@ -1,15 +1,30 @@
-use crate::{get_quefi_dir, Error, SearchFor, TaskResult, TaskReturn};
-use regex::Regex;
+use crate::{
+    cache::Cache, get_quefi_dir, is_portable, spotify::TrackInfo, unix_timestamp, util, DownloadId,
+    Error, SearchFor, TaskResult, TaskReturn,
+};
 use reqwest::Client;
+use ring::digest::{digest, SHA256};
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Value};
 use std::{
+    collections::HashSet,
+    ffi::OsStr,
     fmt::Write,
-    io,
-    process::Stdio,
+    fs, io,
+    path::{Path, PathBuf},
+    process::{Output, Stdio},
+    sync::{Arc, Mutex},
     time::{SystemTime, UNIX_EPOCH},
 };
-use tokio::{fs::File, io::copy, process::Command};
+use tokio::{
+    fs::File,
+    io::copy,
+    process::{Child, Command},
+    sync::{OwnedSemaphorePermit, Semaphore},
+};
+
+#[cfg(target_os = "windows")]
+use std::os::windows::process::CommandExt;
 
 #[cfg(not(target_os = "windows"))]
 use tokio::fs::OpenOptions;
@@ -20,25 +35,514 @@
 #[cfg(not(target_os = "windows"))]
 pub const DLP_EXECUTABLE_NAME: &str = "yt-dlp";
 
+// Published with every yt-dlp release
+const DLP_CHECKSUMS: &str = "SHA2-256SUMS";
+
+#[cfg(target_os = "windows")]
+pub const FFMPEG_EXECUTABLE_NAME: &str = "ffmpeg.exe";
+
+#[cfg(not(target_os = "windows"))]
+pub const FFMPEG_EXECUTABLE_NAME: &str = "ffmpeg";
+
+// Builds of ffmpeg made for yt-dlp, the checksums come with every release
+const FFMPEG_CHECKSUMS: &str = "checksums.sha256";
+
 #[cfg(target_os = "windows")]
 async fn create_file() -> io::Result<File> {
-    File::create("yt-dlp.exe").await
+    File::create(downloaded_dlp_path()).await
 }
 
 #[cfg(not(target_os = "windows"))]
 async fn create_file() -> io::Result<File> {
-    OpenOptions::new()
+    use std::os::unix::fs::PermissionsExt;
+
+    let file = OpenOptions::new()
         .read(true)
         .write(true)
         .create(true)
-        //.mode(0o744) // this might affect A LOT
-        .open("yt-dlp")
-        .await
+        .truncate(true)
+        .mode(0o744)
+        .open(downloaded_dlp_path())
+        .await?;
+    // The mode only applies to new files, an older download could've been overwritten
+    file.set_permissions(std::fs::Permissions::from_mode(0o744))
+        .await?;
+    Ok(file)
+}
+
+/// yt-dlp or ffmpeg installed by a package manager, so it doesn't have to be downloaded.
+/// Paths is the value of the PATH environment variable
+pub(crate) fn find_in_paths(paths: &OsStr, name: &str) -> Option<PathBuf> {
+    std::env::split_paths(paths)
+        .map(|dir| dir.join(name))
+        .find(|path| path.is_file())
+}
+
+// Pids of the running yt-dlp processes, so they can be killed if quefi crashes
+const PID_FILE: &str = "dlp.pids";
+
+// Downloads that didn't finish yet, so they can be resumed if quefi crashes
+const PENDING_FILE: &str = "downloads.pending";
+
+// Browsers yt-dlp can read cookies from
+const COOKIE_BROWSERS: [&str; 9] = [
+    "brave", "chrome", "chromium", "edge", "firefox", "opera", "safari", "vivaldi", "whale",
+];
+
+// Left behind in songs/ by yt-dlp or ffmpeg when they get interrupted
+const PARTIAL_SUFFIXES: [&str; 4] = [".part", ".ytdl", ".temp", ".loudnorm.mp3"];
+
+const SEARCH_CACHE: &str = "searches";
+
+// A month, after that the song is searched for again in case a better upload showed up
+const SEARCH_CACHE_TTL: u64 = 30 * 86_400;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingDownload {
+    pub name: String,
+    pub filename: String,
+    pub link: String,
+    // Playlist the song was downloaded for, empty when it only goes to the global songs
+    #[serde(default)]
+    pub playlist: String,
+    #[serde(default)]
+    pub format: AudioFormat,
+}
+
+/// What yt-dlp converts downloads to, playlists can pick something other than the default
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum AudioFormat {
+    // yt-dlp's default quality, about 128 kbps
+    #[default]
+    Mp3,
+    Mp3High,
+    Opus,
+    Flac,
+    M4a,
+}
+
+impl AudioFormat {
+    pub(crate) fn extension(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 | AudioFormat::Mp3High => "mp3",
+            AudioFormat::Opus => "opus",
+            AudioFormat::Flac => "flac",
+            AudioFormat::M4a => "m4a",
+        }
+    }
+
+    // Flac ignores --audio-quality, so it isn't passed for it
+    fn quality(self) -> Option<&'static str> {
+        match self {
+            AudioFormat::Mp3 | AudioFormat::Flac | AudioFormat::M4a => None,
+            AudioFormat::Mp3High => Some("320K"),
+            AudioFormat::Opus => Some("0"),
+        }
+    }
+
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            AudioFormat::Mp3 => "mp3 (default)",
+            AudioFormat::Mp3High => "mp3 320 kbps",
+            AudioFormat::Opus => "opus (best quality)",
+            AudioFormat::Flac => "flac (lossless)",
+            AudioFormat::M4a => "m4a (no conversion for most videos)",
+        }
+    }
+
+    pub(crate) fn next(self) -> AudioFormat {
+        match self {
+            AudioFormat::Mp3 => AudioFormat::Mp3High,
+            AudioFormat::Mp3High => AudioFormat::Opus,
+            AudioFormat::Opus => AudioFormat::Flac,
+            AudioFormat::Flac => AudioFormat::M4a,
+            AudioFormat::M4a => AudioFormat::Mp3,
+        }
+    }
+}
+
+/// Failures of yt-dlp that have a known fix
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum DlpProblem {
+    FfmpegMissing,
+    // YouTube refuses old yt-dlp versions
+    Forbidden,
+    // Age-restricted and members-only videos
+    SignInRequired,
+}
+
+impl DlpProblem {
+    fn from_stderr(stderr: &[u8]) -> Option<DlpProblem> {
+        let stderr = String::from_utf8_lossy(stderr);
+        if stderr.contains("ffmpeg not found") {
+            Some(DlpProblem::FfmpegMissing)
+        } else if stderr.contains("HTTP Error 403") {
+            Some(DlpProblem::Forbidden)
+        } else if stderr.contains("Sign in to confirm")
+            || stderr.contains("members-only")
+            || stderr.contains("Join this channel")
+        {
+            Some(DlpProblem::SignInRequired)
+        } else {
+            None
+        }
+    }
+}
+
+/// Spawns yt-dlp with the configured priority, running at most the configured amount at once
+#[derive(Clone)]
+pub struct Dlp {
+    pub path: String,
+    pub niceness: u8,
+    // Level-match downloaded songs with ffmpeg's loudnorm
+    pub loudnorm: bool,
+    // A cookies.txt file or a browser to take cookies from, like "firefox" or "chrome:Profile 1"
+    pub cookies: String,
+    // Where downloaded songs go
+    pub songs_dir: PathBuf,
+    // Empty when ffmpeg is taken from PATH
+    pub ffmpeg_path: String,
+    slots: Arc<Semaphore>,
+    running: Arc<Mutex<HashSet<u32>>>,
+    pending: Arc<Mutex<Vec<PendingDownload>>>,
+    // downloads.pending in the data directory
+    pub pending_path: PathBuf,
+    // Last known problem of a failed process, until the app shows it
+    problem: Arc<Mutex<Option<DlpProblem>>>,
+}
+
+// Gives back the process slot and forgets the pid once the process is done
+struct DlpGuard {
+    pid: Option<u32>,
+    running: Arc<Mutex<HashSet<u32>>>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for DlpGuard {
+    fn drop(&mut self) {
+        if let Some(pid) = self.pid {
+            let mut running = self.running.lock().unwrap();
+            running.remove(&pid);
+            write_pids(&running);
+        }
+    }
+}
+
+impl Dlp {
+    pub fn new(
+        path: String,
+        niceness: u8,
+        max_processes: usize,
+        loudnorm: bool,
+        cookies: String,
+        songs_dir: PathBuf,
+        ffmpeg_path: String,
+    ) -> Self {
+        let pending_path = get_quefi_dir().join(PENDING_FILE);
+        Dlp {
+            path,
+            niceness,
+            loudnorm,
+            cookies,
+            songs_dir,
+            ffmpeg_path,
+            slots: Arc::new(Semaphore::new(max_permits(max_processes))),
+            running: Arc::new(Mutex::new(HashSet::new())),
+            pending: Arc::new(Mutex::new(read_pending(&pending_path))),
+            pending_path,
+            problem: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    pub(crate) fn take_problem(&self) -> Option<DlpProblem> {
+        self.problem.lock().unwrap().take()
+    }
+
+    fn report_failure(&self, output: &Output) {
+        if let Some(problem) = DlpProblem::from_stderr(&output.stderr) {
+            *self.problem.lock().unwrap() = Some(problem);
+        }
+    }
+
+    pub fn pending_downloads(&self) -> Vec<PendingDownload> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    pub(crate) fn start_pending(&self, download: PendingDownload) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|other| other.filename != download.filename);
+        pending.push(download);
+        write_pending(&self.pending_path, &pending);
+    }
+
+    pub fn finish_pending(&self, filename: &str) {
+        let mut pending = self.pending.lock().unwrap();
+        pending.retain(|download| download.filename != filename);
+        write_pending(&self.pending_path, &pending);
+    }
+
+    // Only a download stopped by quitting stays pending, a failed one would fail again on every start
+    async fn run_pending(
+        &self,
+        args: &[&str],
+        download: &PendingDownload,
+    ) -> Result<(Output, DlpGuard), Error> {
+        self.start_pending(download.clone());
+        let finished = async {
+            let (child, guard) = self.spawn(args, Some(&self.songs_dir)).await?;
+            Ok((child.wait_with_output().await?, guard))
+        }
+        .await;
+        self.finish_pending(&download.filename);
+        finished
+    }
+
+    // Processes that are already running keep their slots
+    pub fn set_max_processes(&mut self, max_processes: usize) {
+        self.slots = Arc::new(Semaphore::new(max_permits(max_processes)));
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn command(&self, program: &str) -> Command {
+        if self.niceness == 0 {
+            return Command::new(program);
+        }
+
+        // ffmpeg processes started by yt-dlp inherit the niceness
+        let mut command = Command::new("nice");
+        command.args(["-n", &self.niceness.to_string(), program]);
+        command
+    }
+
+    #[cfg(target_os = "windows")]
+    fn command(&self, program: &str) -> Command {
+        let mut command = Command::new(program);
+        if self.niceness == 0 {
+            command.creation_flags(0x08000000); // Create no window
+        } else {
+            command.creation_flags(0x08000000 | 0x00004000); // Create no window, below normal priority
+        }
+        command
+    }
+
+    fn ffmpeg(&self) -> &str {
+        if self.ffmpeg_path.is_empty() {
+            FFMPEG_EXECUTABLE_NAME
+        } else {
+            &self.ffmpeg_path
+        }
+    }
+
+    async fn spawn(&self, args: &[&str], dir: Option<&Path>) -> Result<(Child, DlpGuard), Error> {
+        let mut all_args = Vec::with_capacity(args.len() + 4);
+        if !self.cookies.is_empty() {
+            if Path::new(&self.cookies).is_file() {
+                all_args.push("--cookies");
+            } else {
+                all_args.push("--cookies-from-browser");
+            }
+            all_args.push(self.cookies.as_str());
+        }
+        if !self.ffmpeg_path.is_empty() {
+            all_args.extend(["--ffmpeg-location", self.ffmpeg_path.as_str()]);
+        }
+        all_args.extend_from_slice(args);
+
+        match self.spawn_program(&self.path, &all_args, dir).await {
+            Err(Error::Io(err)) if err.kind() == io::ErrorKind::NotFound => Err(Error::DlpMissing),
+            result => result,
+        }
+    }
+
+    async fn spawn_program(
+        &self,
+        program: &str,
+        args: &[&str],
+        dir: Option<&Path>,
+    ) -> Result<(Child, DlpGuard), Error> {
+        let permit = self.slots.clone().acquire_owned().await.unwrap();
+
+        let mut command = self.command(program);
+        if let Some(dir) = dir {
+            command.current_dir(dir);
+        }
+        let child = command
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .args(args)
+            .spawn()?;
+
+        let pid = child.id();
+        if let Some(pid) = pid {
+            let mut running = self.running.lock().unwrap();
+            running.insert(pid);
+            write_pids(&running);
+        }
+
+        Ok((
+            child,
+            DlpGuard {
+                pid,
+                running: self.running.clone(),
+                _permit: permit,
+            },
+        ))
+    }
+}
+
+// The last error yt-dlp printed, like "Video unavailable"
+fn failure_reason(output: &Output) -> String {
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    stderr
+        .lines()
+        .rev()
+        .find_map(|line| line.strip_prefix("ERROR: "))
+        .map(|error| {
+            // Most start with the site and video id, like "[youtube] dQw4w9WgXcQ: "
+            match error
+                .strip_prefix('[')
+                .and_then(|rest| rest.split_once(": "))
+            {
+                Some((_, message)) => message.to_string(),
+                None => error.to_string(),
+            }
+        })
+        .unwrap_or_else(|| format!("yt-dlp exited with {}", output.status))
+}
+
+// Either an existing cookies.txt file or BROWSER[+KEYRING][:PROFILE][::CONTAINER]
+pub(crate) fn is_valid_cookies(cookies: &str) -> bool {
+    if Path::new(cookies).is_file() {
+        return true;
+    }
+    let browser = cookies.split(['+', ':']).next().unwrap_or_default();
+    COOKIE_BROWSERS.contains(&browser.to_ascii_lowercase().as_str())
+}
+
+pub(crate) fn max_permits(max_processes: usize) -> usize {
+    if max_processes == 0 {
+        Semaphore::MAX_PERMITS
+    } else {
+        max_processes
+    }
+}
+
+fn read_pending(path: &Path) -> Vec<PendingDownload> {
+    fs::read_to_string(path)
+        .ok()
+        .and_then(|contents| serde_json::from_str(&contents).ok())
+        .unwrap_or_default()
+}
+
+fn write_pending(path: &Path, pending: &[PendingDownload]) {
+    if pending.is_empty() {
+        let _ = fs::remove_file(path);
+    } else if let Ok(contents) = serde_json::to_string(pending) {
+        let _ = fs::write(path, contents);
+    }
+}
+
+/// Files in the songs directory left behind by interrupted downloads or loudness normalization
+pub fn partial_files(songs_dir: &Path) -> Vec<PathBuf> {
+    let Ok(entries) = fs::read_dir(songs_dir) else {
+        return Vec::new();
+    };
+
+    entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.file_name()
+                .map(|name| name.to_string_lossy())
+                .is_some_and(|name| {
+                    name.contains(".part-Frag")
+                        || PARTIAL_SUFFIXES.iter().any(|suffix| name.ends_with(suffix))
+                })
+        })
+        .collect()
+}
+
+fn write_pids(running: &HashSet<u32>) {
+    let path = get_quefi_dir().join(PID_FILE);
+    if running.is_empty() {
+        let _ = fs::remove_file(path);
+    } else {
+        let pids: Vec<String> = running.iter().map(u32::to_string).collect();
+        let _ = fs::write(path, pids.join("\n"));
+    }
+}
+
+/// Kills the yt-dlp processes left behind by a previous run that didn't exit cleanly.
+/// Returns how many were killed.
+pub fn kill_leftover_processes(dlp_path: &str) -> usize {
+    let path = get_quefi_dir().join(PID_FILE);
+    let Ok(contents) = fs::read_to_string(&path) else {
+        return 0;
+    };
+    let _ = fs::remove_file(path);
+
+    let name = Path::new(dlp_path)
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| DLP_EXECUTABLE_NAME.to_string());
+
+    contents
+        .lines()
+        .filter_map(|line| line.parse::<u32>().ok())
+        .filter(|&pid| is_process_named(pid, &name) && kill_process(pid))
+        .count()
+}
+
+// The pid could've been reused by a different program since then
+#[cfg(not(target_os = "windows"))]
+pub(crate) fn is_process_named(pid: u32, name: &str) -> bool {
+    std::process::Command::new("ps")
+        .args(["-p", &pid.to_string(), "-o", "command="])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(name))
+}
+
+#[cfg(target_os = "windows")]
+pub(crate) fn is_process_named(pid: u32, name: &str) -> bool {
+    std::process::Command::new("tasklist")
+        .creation_flags(0x08000000) // Create no window
+        .args(["/FI", &format!("PID eq {pid}"), "/FO", "CSV", "/NH"])
+        .output()
+        .is_ok_and(|output| String::from_utf8_lossy(&output.stdout).contains(name))
+}
+
+#[cfg(not(target_os = "windows"))]
+fn kill_process(pid: u32) -> bool {
+    // Kill the ffmpeg processes it started first
+    let _ = std::process::Command::new("pkill")
+        .args(["-P", &pid.to_string()])
+        .status();
+
+    std::process::Command::new("kill")
+        .arg(pid.to_string())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+
+#[cfg(target_os = "windows")]
+fn kill_process(pid: u32) -> bool {
+    std::process::Command::new("taskkill")
+        .creation_flags(0x08000000) // Create no window
+        .args(["/PID", &pid.to_string(), "/T", "/F"])
+        .status()
+        .is_ok_and(|status| status.success())
 }
 
 #[derive(Deserialize)]
-struct Release {
+pub(crate) struct Release {
     assets: Vec<Asset>,
+    #[serde(default)]
+    pub(crate) tag_name: String,
+    // Release notes
+    #[serde(default)]
+    pub(crate) body: String,
 }
 
 #[derive(Deserialize)]
@@ -54,79 +558,654 @@ struct Body<'a> {
     context: Value,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Serialize)]
+struct BrowseBody<'a> {
+    #[serde(rename = "browseId")]
+    browse_id: &'a str,
+    context: Value,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SongMetadata {
+    title: String,
+    track: Option<String>,
+    artist: Option<String>,
+    uploader: Option<String>,
+    album: Option<String>,
+}
+
+impl SongMetadata {
+    pub fn song_name(&self) -> String {
+        let title = self.track.as_ref().unwrap_or(&self.title);
+        let name = match self.artist.as_ref().or(self.uploader.as_ref()) {
+            Some(artist) => format!("{artist} - {title}"),
+            None => title.clone(),
+        };
+
+        match &self.album {
+            Some(album) if album != title => format!("{name} ({album})"),
+            _ => name,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct YoutubePlaylist {
+    pub title: String,
+    #[serde(default)]
+    pub entries: Vec<YoutubePlaylistEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct YoutubePlaylistEntry {
+    pub id: String,
+    pub title: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SearchResult {
     pub video_id: String,
     pub duration_ms: u32,
+    pub title: String,
+    pub artist: String,
+}
+
+// What kind of YT Music results a search looks through
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SearchFilter {
+    Songs,
+    // Podcast episodes aren't among the songs
+    Episodes,
+}
+
+impl SearchFilter {
+    // Ignoring spelling mistakes in both
+    fn params(self) -> &'static str {
+        match self {
+            SearchFilter::Songs => "EgWKAQIIAUICCAFqDBAOEAoQAxAEEAkQBQ%3D%3D",
+            SearchFilter::Episodes => "EgWKAQJIAUICCAFqDBAOEAoQAxAEEAkQBQ%3D%3D",
+        }
+    }
+}
+
+// Results shown when picking one for a single song
+pub(crate) const SEARCH_CHOICES: usize = 5;
+
+pub(crate) async fn fetch_latest_release(
+    client: &Client,
+    repo: &str,
+) -> Result<Release, reqwest::Error> {
+    client
+        .get(format!(
+            "https://api.github.com/repos/{repo}/releases/latest"
+        ))
+        .header("User-Agent", "nieboczek/quefi")
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await
+}
+
+// Where quefi was started from in portable mode, as it always was, the data directory otherwise
+pub fn downloaded_dlp_path() -> PathBuf {
+    if is_portable() {
+        PathBuf::from(DLP_EXECUTABLE_NAME)
+    } else {
+        get_quefi_dir().join(DLP_EXECUTABLE_NAME)
+    }
 }
 
 pub async fn download_dlp(client: &Client) -> TaskResult {
-    let response = client
-        .get("https://api.github.com/repos/yt-dlp/yt-dlp/releases/latest")
+    let release = fetch_latest_release(client, "yt-dlp/yt-dlp").await?;
+    let asset_url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+    };
+
+    let Some(url) = asset_url(DLP_EXECUTABLE_NAME) else {
+        return Err(Error::DlpDownload(String::from(
+            "the latest release has no build for this system",
+        )));
+    };
+    let Some(checksums_url) = asset_url(DLP_CHECKSUMS) else {
+        return Err(Error::DlpDownload(String::from(
+            "the latest release has no checksums",
+        )));
+    };
+
+    let checksums = client
+        .get(checksums_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let binary = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
+
+    // Checked before anything is written, so a bad download keeps the old yt-dlp
+    if !checksum_matches(&checksums, DLP_EXECUTABLE_NAME, &binary) {
+        return Err(Error::DlpDownload(String::from(
+            "the download doesn't match its SHA-256 checksum",
+        )));
+    }
+
+    let mut file = create_file().await?;
+    copy(&mut binary.as_ref(), &mut file).await?;
+    Ok(TaskReturn::DlpDownloaded)
+}
+
+// yt-dlp/FFmpeg-Builds only has builds for these, as archives with ffmpeg in bin/
+fn ffmpeg_build() -> Option<&'static str> {
+    match (std::env::consts::OS, std::env::consts::ARCH) {
+        ("windows", "x86_64") => Some("ffmpeg-master-latest-win64-gpl.zip"),
+        ("windows", "aarch64") => Some("ffmpeg-master-latest-winarm64-gpl.zip"),
+        ("linux", "x86_64") => Some("ffmpeg-master-latest-linux64-gpl.tar.xz"),
+        ("linux", "aarch64") => Some("ffmpeg-master-latest-linuxarm64-gpl.tar.xz"),
+        _ => None,
+    }
+}
+
+pub async fn download_ffmpeg(client: &Client) -> TaskResult {
+    let Some(archive_name) = ffmpeg_build() else {
+        return Err(Error::FfmpegDownload(String::from(
+            "there's no build for this system, install it with your package manager",
+        )));
+    };
+    let release: Release = client
+        .get("https://api.github.com/repos/yt-dlp/FFmpeg-Builds/releases/tags/latest")
         .header("User-Agent", "nieboczek/quefi")
         .send()
+        .await?
+        .error_for_status()?
+        .json()
         .await?;
+    let asset_url = |name: &str| {
+        release
+            .assets
+            .iter()
+            .find(|asset| asset.name == name)
+            .map(|asset| asset.browser_download_url.clone())
+    };
 
-    let release: Release = response.json().await?;
+    let (Some(url), Some(checksums_url)) = (asset_url(archive_name), asset_url(FFMPEG_CHECKSUMS))
+    else {
+        return Err(Error::FfmpegDownload(String::from(
+            "the latest release is missing files",
+        )));
+    };
 
-    let url = release
-        .assets
-        .into_iter()
-        .find(|asset| asset.name == DLP_EXECUTABLE_NAME)
-        .map(|asset| asset.browser_download_url)
-        .expect("Didn't find the correct dlp in releases");
+    let checksums = client
+        .get(checksums_url)
+        .send()
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let archive = client
+        .get(url)
+        .send()
+        .await?
+        .error_for_status()?
+        .bytes()
+        .await?;
 
-    let response = client.get(url).send().await?.error_for_status()?;
-    let mut file = create_file().await?;
+    if !checksum_matches(&checksums, archive_name, &archive) {
+        return Err(Error::FfmpegDownload(String::from(
+            "the download doesn't match its SHA-256 checksum",
+        )));
+    }
 
-    copy(&mut response.bytes().await?.as_ref(), &mut file).await?;
-    Ok(TaskReturn::DlpDownloaded)
+    let dir = get_quefi_dir().join("ffmpeg");
+    tokio::fs::create_dir_all(&dir).await?;
+    let archive_path = dir.join(archive_name);
+    tokio::fs::write(&archive_path, &archive).await?;
+
+    // tar comes with Windows 10 and later too, and it reads zips there
+    let status = Command::new("tar")
+        .arg("-xf")
+        .arg(&archive_path)
+        .arg("-C")
+        .arg(&dir)
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .await;
+    let _ = tokio::fs::remove_file(&archive_path).await;
+    if !status.is_ok_and(|status| status.success()) {
+        return Err(Error::FfmpegDownload(String::from(
+            "couldn't extract the archive with tar",
+        )));
+    }
+
+    let build = archive_name
+        .trim_end_matches(".zip")
+        .trim_end_matches(".tar.xz");
+    let path = dir.join(build).join("bin").join(FFMPEG_EXECUTABLE_NAME);
+    if !path.is_file() {
+        return Err(Error::FfmpegDownload(String::from(
+            "the archive doesn't have ffmpeg in it",
+        )));
+    }
+    Ok(TaskReturn::FfmpegDownloaded(path))
+}
+
+// Checksums are lines of "<SHA-256 in hex>  <file name>"
+pub(crate) fn checksum_matches(checksums: &str, name: &str, data: &[u8]) -> bool {
+    let hash: String = digest(&SHA256, data)
+        .as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    checksums.lines().any(|line| {
+        let mut parts = line.split_whitespace();
+        match (parts.next(), parts.next()) {
+            (Some(sum), Some(file)) => {
+                file.trim_start_matches('*') == name && sum.eq_ignore_ascii_case(&hash)
+            }
+            _ => false,
+        }
+    })
+}
+
+pub async fn fetch_dlp_version(dlp: &Dlp) -> TaskResult {
+    let (child, _guard) = dlp.spawn(&["--version"], None).await?;
+    let output = child.wait_with_output().await?;
+    let version = String::from_utf8_lossy(&output.stdout).trim().to_string();
+    Ok(TaskReturn::DlpVersion(version))
 }
 
 pub async fn download_song(
-    id: u8,
-    dlp_path: &str,
-    yt_link: &str,
-    filename: &str,
+    id: DownloadId,
+    dlp: &Dlp,
+    download: PendingDownload,
     search_for: SearchFor,
+    artist: Option<&str>,
 ) -> TaskResult {
-    let song_dir = get_quefi_dir().join("songs");
+    let format = download.format;
+    let output = format!("{}.{}", download.filename, format.extension());
+    // yt-dlp continues from the .part file if there's one
+    let mut args = vec![
+        "-q",
+        "-x",
+        "--audio-format",
+        format.extension(),
+        "--embed-metadata",
+        &download.link,
+        "-o",
+        &output,
+    ];
+    if let Some(quality) = format.quality() {
+        args.extend(["--audio-quality", quality]);
+    }
 
-    #[cfg(not(target_os = "windows"))]
-    let mut child = Command::new(dlp_path)
-        .current_dir(song_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .args([
-            "-q",
-            "-x",
-            "--audio-format",
-            "mp3",
-            yt_link,
-            "-o",
-            &format!("{}.mp3", filename),
-        ])
-        .spawn()?;
+    // Spotify knows the real artist/title, YouTube's are usually the uploader and video title
+    let artist_field = artist.map(|artist| metadata_field(artist, "artist"));
+    let title_field = metadata_field(search_for.song_name(), "title");
+    if let Some(artist_field) = &artist_field {
+        args.extend(["--parse-metadata", artist_field]);
+        args.extend(["--parse-metadata", &title_field]);
+    }
 
-    #[cfg(target_os = "windows")]
-    let mut child = Command::new(dlp_path)
-        .creation_flags(0x08000000) // Create no window
-        .current_dir(song_dir)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .args([
-            "-q",
-            "-x",
-            "--audio-format",
-            "mp3",
-            yt_link,
-            "-o",
-            &format!("{}.mp3", filename),
-        ])
-        .spawn()?;
-
-    child.wait().await?;
-    Ok(TaskReturn::SongDownloaded(id, search_for))
+    let (finished, guard) = dlp.run_pending(&args, &download).await?;
+    if !finished.status.success() {
+        dlp.report_failure(&finished);
+        return Err(Error::SongFailed(id, search_for, failure_reason(&finished)));
+    }
+    drop(guard);
+
+    // Normalizing re-encodes to a default quality mp3, which would undo a chosen format
+    if dlp.loudnorm && format == AudioFormat::Mp3 {
+        normalize_loudness(dlp, &dlp.songs_dir.join(&output)).await;
+    }
+    Ok(TaskReturn::SongDownloaded(id, search_for, download.link))
+}
+
+pub async fn fetch_metadata(id: DownloadId, dlp: &Dlp, link: &str) -> TaskResult {
+    let args = ["-q", "-j", "--no-playlist", link];
+
+    let (child, _guard) = dlp.spawn(&args, None).await?;
+
+    let output = child.wait_with_output().await?;
+    match serde_json::from_slice(&output.stdout) {
+        Ok(metadata) => Ok(TaskReturn::LinkMetadata(id, link.to_string(), metadata)),
+        Err(_) => {
+            dlp.report_failure(&output);
+            Err(Error::Metadata(id, link.to_string()))
+        }
+    }
+}
+
+// Only lists the videos, they're downloaded one by one afterwards
+pub async fn fetch_playlist_entries(id: DownloadId, dlp: &Dlp, link: &str) -> TaskResult {
+    let args = ["-q", "-J", "--flat-playlist", link];
+
+    let (child, _guard) = dlp.spawn(&args, None).await?;
+
+    let output = child.wait_with_output().await?;
+    match serde_json::from_slice(&output.stdout) {
+        Ok(playlist) => Ok(TaskReturn::YoutubePlaylist(id, playlist)),
+        Err(_) => Err(Error::YoutubePlaylist(id, link.to_string())),
+    }
+}
+
+// YT Music album/release links aren't playlists to yt-dlp, the track list comes from the browse endpoint
+pub async fn fetch_album(id: DownloadId, client: &Client, link: &str) -> TaskResult {
+    let Some(browse_id) = util::YOUTUBE_ALBUM_LINK
+        .captures(link)
+        .and_then(|captures| captures.get(2))
+    else {
+        return Err(Error::YoutubePlaylist(id, link.to_string()));
+    };
+    let body = BrowseBody {
+        browse_id: browse_id.as_str(),
+        context: client_context(),
+    };
+
+    // Any failure has to name the download, so it's taken out of the download manager
+    let json = send_request(client, "browse", &body)
+        .await
+        .map_err(|_| Error::YoutubePlaylist(id, link.to_string()))?;
+    match parse_album(&json) {
+        Some(album) => Ok(TaskReturn::YoutubePlaylist(id, album)),
+        None => Err(Error::YoutubePlaylist(id, link.to_string())),
+    }
+}
+
+// Songs are named "Artist - Title" like other links, track titles alone are often just "Intro"
+pub(crate) fn parse_album(json: &Value) -> Option<YoutubePlaylist> {
+    let text = |value: &Value| value["runs"][0]["text"].as_str().map(String::from);
+
+    // Newer responses have the header next to the track list, older ones on top
+    let columns = &json["contents"]["twoColumnBrowseResultsRenderer"];
+    let (title, artist, tracks) = if columns.is_object() {
+        let header = &columns["tabs"][0]["tabRenderer"]["content"]["sectionListRenderer"]
+            ["contents"][0]["musicResponsiveHeaderRenderer"];
+        (
+            text(&header["title"])?,
+            text(&header["straplineTextOne"]),
+            &columns["secondaryContents"]["sectionListRenderer"]["contents"][0]
+                ["musicShelfRenderer"]["contents"],
+        )
+    } else {
+        let header = &json["header"]["musicDetailHeaderRenderer"];
+        (
+            text(&header["title"])?,
+            header["subtitle"]["runs"][2]["text"]
+                .as_str()
+                .map(String::from),
+            &json["contents"]["singleColumnBrowseResultsRenderer"]["tabs"][0]["tabRenderer"]
+                ["content"]["sectionListRenderer"]["contents"][0]["musicShelfRenderer"]["contents"],
+        )
+    };
+
+    // Tracks that aren't available have no video id
+    let entries = tracks
+        .as_array()?
+        .iter()
+        .map(|track| &track["musicResponsiveListItemRenderer"])
+        .filter_map(|track| {
+            let id = track["playlistItemData"]["videoId"].as_str()?;
+            let name = text(
+                &track["flexColumns"][0]["musicResponsiveListItemFlexColumnRenderer"]["text"],
+            )?;
+            let title = match &artist {
+                Some(artist) => format!("{artist} - {name}"),
+                None => name,
+            };
+            Some(YoutubePlaylistEntry {
+                id: id.to_string(),
+                title,
+            })
+        })
+        .collect();
+
+    Some(YoutubePlaylist { title, entries })
+}
+
+// Sets a metadata field to a fixed value, the value is an output template so % needs escaping.
+// The @ stops yt-dlp from reading a single word value as a field name
+fn metadata_field(value: &str, field: &str) -> String {
+    format!("@{}:@(?P<meta_{}>.+)", value.replace('%', "%%"), field)
+}
+
+// Same as download_song, but also writes the site's artist/title/album into the file's tags
+pub async fn download_tagged_song(
+    id: DownloadId,
+    dlp: &Dlp,
+    download: PendingDownload,
+    search_for: SearchFor,
+) -> TaskResult {
+    let format = download.format;
+    let output = format!("{}.{}", download.filename, format.extension());
+    let mut args = vec![
+        "-q",
+        "-x",
+        "--audio-format",
+        format.extension(),
+        "--embed-metadata",
+        "--no-playlist",
+        &download.link,
+        "-o",
+        &output,
+    ];
+    if let Some(quality) = format.quality() {
+        args.extend(["--audio-quality", quality]);
+    }
+
+    let (finished, guard) = dlp.run_pending(&args, &download).await?;
+    if !finished.status.success() {
+        dlp.report_failure(&finished);
+        return Err(Error::SongFailed(id, search_for, failure_reason(&finished)));
+    }
+    drop(guard);
+
+    if dlp.loudnorm && format == AudioFormat::Mp3 {
+        normalize_loudness(dlp, &dlp.songs_dir.join(&output)).await;
+    }
+    Ok(TaskReturn::SongDownloaded(id, search_for, download.link))
+}
+
+const LOUDNORM_TARGET: &str = "I=-16:TP=-1.5:LRA=11";
+
+#[derive(Deserialize)]
+struct LoudnormMeasurement {
+    input_i: String,
+    input_tp: String,
+    input_lra: String,
+    input_thresh: String,
+    target_offset: String,
+}
+
+// Two-pass loudnorm: measure the song first, then adjust it linearly using the measurement.
+// If anything fails, the song is kept as downloaded.
+async fn normalize_loudness(dlp: &Dlp, path: &Path) {
+    let Some(input) = path.to_str() else {
+        return;
+    };
+
+    let measure_filter = format!("loudnorm={LOUDNORM_TARGET}:print_format=json");
+    let args = [
+        "-hide_banner",
+        "-nostats",
+        "-i",
+        input,
+        "-af",
+        &measure_filter,
+        "-f",
+        "null",
+        "-",
+    ];
+    let Ok((child, guard)) = dlp.spawn_program(dlp.ffmpeg(), &args, None).await else {
+        return;
+    };
+    let Ok(output) = child.wait_with_output().await else {
+        return;
+    };
+    drop(guard);
+
+    // The measurement is the last thing ffmpeg prints
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let Some(measurement) = stderr
+        .rfind('{')
+        .and_then(|start| serde_json::from_str::<LoudnormMeasurement>(&stderr[start..]).ok())
+    else {
+        return;
+    };
+
+    let normalized = path.with_extension("loudnorm.mp3");
+    let Some(normalized_str) = normalized.to_str() else {
+        return;
+    };
+    let filter = format!(
+        "loudnorm={LOUDNORM_TARGET}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+        measurement.input_i,
+        measurement.input_tp,
+        measurement.input_lra,
+        measurement.input_thresh,
+        measurement.target_offset,
+    );
+    let args = [
+        "-hide_banner",
+        "-nostats",
+        "-y",
+        "-i",
+        input,
+        "-map_metadata",
+        "0",
+        "-af",
+        &filter,
+        "-ar",
+        "44100",
+        "-q:a",
+        "2",
+        normalized_str,
+    ];
+    let Ok((mut child, _guard)) = dlp.spawn_program(dlp.ffmpeg(), &args, None).await else {
+        return;
+    };
+
+    if child.wait().await.is_ok_and(|status| status.success()) {
+        let _ = fs::rename(&normalized, path);
+    } else {
+        let _ = fs::remove_file(&normalized);
+    }
+}
+
+/// Writes Spotify's metadata into already downloaded songs, returns the paths it went through
+pub async fn write_tags(
+    id: DownloadId,
+    dlp: &Dlp,
+    client: &Client,
+    songs: Vec<(String, TrackInfo)>,
+) -> TaskResult {
+    let mut paths = Vec::with_capacity(songs.len());
+    for (path, track) in songs {
+        write_song_tags(dlp, client, Path::new(&path), &track).await;
+        paths.push(path);
+    }
+    Ok(TaskReturn::TagsWritten(id, paths))
+}
+
+async fn write_song_tags(dlp: &Dlp, client: &Client, path: &Path, track: &TrackInfo) {
+    let (Some(input), Some(extension)) = (path.to_str(), path.extension()) else {
+        return;
+    };
+    if !path.exists() {
+        return;
+    }
+    let extension = extension.to_string_lossy().to_string();
+
+    // Ogg can't hold a cover picture the way ffmpeg attaches it
+    let cover = match &track.cover_url {
+        Some(url) if extension == "mp3" || extension == "flac" => {
+            fetch_cover(client, url, &path.with_extension("cover.jpg")).await
+        }
+        _ => None,
+    };
+    let tagged = path.with_extension(format!("tagged.{extension}"));
+    let Some(tagged_str) = tagged.to_str() else {
+        return;
+    };
+
+    let title = format!("title={}", track.name);
+    let artist = format!("artist={}", track.artist);
+    let album = track.album.as_ref().map(|album| format!("album={album}"));
+    let track_number = format!("track={}", track.track_number);
+
+    let mut args = vec!["-hide_banner", "-nostats", "-y", "-i", input];
+    if let Some(cover) = cover.as_ref().and_then(|cover| cover.to_str()) {
+        args.extend(["-i", cover, "-map", "0:a", "-map", "1:v"]);
+        args.extend(["-disposition:v", "attached_pic"]);
+        args.extend(["-metadata:s:v", "title=Album cover"]);
+    } else {
+        args.extend(["-map", "0:a"]);
+    }
+    args.extend(["-c", "copy", "-map_metadata", "0"]);
+    args.extend(["-metadata", &title, "-metadata", &artist]);
+    if let Some(album) = &album {
+        args.extend(["-metadata", album]);
+    }
+    if track.track_number > 0 {
+        args.extend(["-metadata", &track_number]);
+    }
+    if extension == "mp3" {
+        args.extend(["-id3v2_version", "3"]);
+    }
+    args.push(tagged_str);
+
+    if let Ok((mut child, _guard)) = dlp.spawn_program(dlp.ffmpeg(), &args, None).await {
+        // Renaming fails on Windows while the song is playing, it just stays untagged then
+        let written = child.wait().await.is_ok_and(|status| status.success());
+        if !written || fs::rename(&tagged, path).is_err() {
+            let _ = fs::remove_file(&tagged);
+        }
+    }
+    if let Some(cover) = cover {
+        let _ = fs::remove_file(cover);
+    }
+}
+
+async fn fetch_cover(client: &Client, url: &str, path: &Path) -> Option<PathBuf> {
+    let response = client.get(url).send().await.ok()?.error_for_status().ok()?;
+    let bytes = response.bytes().await.ok()?;
+    fs::write(path, bytes).ok()?;
+    Some(path.to_path_buf())
+}
+
+pub async fn stream_song(id: DownloadId, dlp: &Dlp, yt_link: &str) -> TaskResult {
+    // Prefer m4a, since it can be decoded without converting it first
+    let args = [
+        "-q",
+        "-f",
+        "bestaudio[ext=m4a]/bestaudio",
+        "-o",
+        "-",
+        yt_link,
+    ];
+
+    let (child, _guard) = dlp.spawn(&args, None).await?;
+
+    let output = child.wait_with_output().await?;
+    if !output.status.success() || output.stdout.is_empty() {
+        dlp.report_failure(&output);
+        return Err(Error::Stream(id, yt_link.to_string()));
+    }
+    Ok(TaskReturn::SongStreamed(
+        id,
+        yt_link.to_string(),
+        output.stdout,
+    ))
 }
 
 fn get_timestamp() -> String {
@@ -167,7 +1246,7 @@ fn year_length(year: u64) -> u64 {
 }
 
 fn is_leap_year(year: u64) -> bool {
-    (year % 4 == 0 && year % 100 != 0) || year % 400 == 0
+    (year.is_multiple_of(4) && !year.is_multiple_of(100)) || year.is_multiple_of(400)
 }
 
 fn month_length(year: u64, month: u64) -> u64 {
@@ -180,10 +1259,27 @@ fn month_length(year: u64, month: u64) -> u64 {
     }
 }
 
-async fn send_request<'a>(client: &Client, body: Body<'a>) -> Result<Value, Error> {
+fn client_context() -> Value {
+    json!({
+        "client": {
+            "clientName": "WEB_REMIX",
+            "clientVersion": format!("1.{}.01.00", get_timestamp()),
+        },
+        "user": {},
+    })
+}
+
+// Endpoint is search or browse
+async fn send_request(
+    client: &Client,
+    endpoint: &str,
+    body: &impl Serialize,
+) -> Result<Value, Error> {
     let response = client
-        .post("https://music.youtube.com/youtubei/v1/search?alt=json")
-        .json(&body)
+        .post(format!(
+            "https://music.youtube.com/youtubei/v1/{endpoint}?alt=json"
+        ))
+        .json(body)
         .header(
             "User-Agent",
             "Mozilla/5.0 (Windows NT 10.0; Win64; x64; rv:88.0) Gecko/20100101 Firefox/88.0",
@@ -198,11 +1294,12 @@ async fn send_request<'a>(client: &Client, body: Body<'a>) -> Result<Value, Erro
     response.error_for_status_ref()?;
 
     let text = response.text().await?;
-    Ok(serde_json::from_str(&text).unwrap())
+    serde_json::from_str(&text).map_err(|_| Error::YtMusic)
 }
 
-fn parse_search_result(value: &Value) -> SearchResult {
-    let mut result = if let Some(video_id) = value
+// None if YT Music sent something that isn't a song
+fn parse_search_result(value: &Value) -> Option<SearchResult> {
+    let video_id = value
         .get("overlay")
         .and_then(|v| v.get("musicItemThumbnailOverlayRenderer"))
         .and_then(|v| v.get("content"))
@@ -210,34 +1307,36 @@ fn parse_search_result(value: &Value) -> SearchResult {
         .and_then(|v| v.get("playNavigationEndpoint"))
         .and_then(|v| v.get("watchEndpoint"))
         .and_then(|v| v.get("videoId"))
-    {
-        SearchResult {
-            video_id: video_id.as_str().unwrap().to_string(),
-            duration_ms: 0,
-        }
-    } else {
-        SearchResult {
-            video_id: String::new(),
-            duration_ms: 0,
-        }
+        .and_then(Value::as_str)
+        .unwrap_or_default();
+    let mut result = SearchResult {
+        video_id: video_id.to_string(),
+        duration_ms: 0,
+        title: String::new(),
+        artist: String::new(),
     };
+    result.title = value["flexColumns"][0]["musicResponsiveListItemFlexColumnRenderer"]["text"]
+        ["runs"][0]["text"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
 
     let runs =
         &value["flexColumns"][1]["musicResponsiveListItemFlexColumnRenderer"]["text"]["runs"];
 
-    let text = runs[0]["text"].as_str().unwrap().to_lowercase();
+    let text = runs[0]["text"].as_str()?.to_lowercase();
     let that_thing = [
         "album", "artist", "playlist", "song", "video", "station", "profile", "podcast", "episode",
     ]
     .contains(&text.as_str());
 
-    let runs_offset = if runs[0].as_object().unwrap().len() == 1 && that_thing {
+    let runs_offset = if runs[0].as_object()?.len() == 1 && that_thing {
         2
     } else {
         0
     };
 
-    let (_, runs) = runs.as_array().unwrap().split_at(runs_offset);
+    let runs = runs.as_array()?.get(runs_offset..)?;
     let mut i: u16 = 0;
     for run in runs {
         if i % 2 == 1 {
@@ -245,15 +1344,16 @@ fn parse_search_result(value: &Value) -> SearchResult {
             continue;
         }
 
-        let text = run["text"].as_str().unwrap();
-        if run.get("navigationEndpoint").is_none()
-            && Regex::new(r"^(\d+:)*\d+:\d+$").unwrap().is_match(text)
-        {
+        let text = run["text"].as_str().unwrap_or_default();
+        if run.get("navigationEndpoint").is_none() && util::DURATION.is_match(text) {
             result.duration_ms = parse_duration(text);
+        } else if result.artist.is_empty() {
+            // The artist comes first, then the album
+            result.artist = text.to_string();
         }
         i += 1;
     }
-    result
+    Some(result)
 }
 
 fn parse_duration(duration: &str) -> u32 {
@@ -278,26 +1378,160 @@ fn parse_duration(duration: &str) -> u32 {
     milliseconds
 }
 
+#[derive(Serialize, Deserialize)]
+struct CachedSearch {
+    searched: u64,
+    result: SearchResult,
+}
+
+// Duration is in milliseconds, 0 if unknown. Without a cache, YT Music is always searched
 pub async fn search_ytmusic(
-    id: u8,
+    id: DownloadId,
+    client: &Client,
+    cache: Option<&Cache>,
+    query: &str,
+    duration_ms: u32,
+    filter: SearchFilter,
+    search_for: SearchFor,
+) -> TaskResult {
+    if let Some(best) = cache.and_then(|cache| cached_search(cache, query, duration_ms, filter)) {
+        return Ok(TaskReturn::SearchResult(id, best, search_for));
+    }
+
+    let title = search_for.song_name();
+    let results = match ranked_results(client, query, duration_ms, filter, title).await {
+        Ok(results) => results,
+        Err(err) => return Err(Error::SongFailed(id, search_for, err.to_string())),
+    };
+    match results.into_iter().next() {
+        Some(best) => {
+            if let Some(cache) = cache {
+                cache_search(cache, query, duration_ms, filter, &best);
+            }
+            Ok(TaskReturn::SearchResult(id, best, search_for))
+        }
+        None => Err(Error::SongFailed(
+            id,
+            search_for,
+            String::from("Not found on YT Music"),
+        )),
+    }
+}
+
+// The length and filter change which result is the best one
+fn search_cache_key(query: &str, duration_ms: u32, filter: SearchFilter) -> String {
+    format!("{filter:?} {duration_ms} {query}")
+}
+
+fn cached_search(
+    cache: &Cache,
+    query: &str,
+    duration_ms: u32,
+    filter: SearchFilter,
+) -> Option<SearchResult> {
+    let data = cache.get(SEARCH_CACHE, &search_cache_key(query, duration_ms, filter))?;
+    let cached: CachedSearch = serde_json::from_slice(&data).ok()?;
+    (unix_timestamp().saturating_sub(cached.searched) < SEARCH_CACHE_TTL).then_some(cached.result)
+}
+
+pub(crate) fn cache_search(
+    cache: &Cache,
+    query: &str,
+    duration_ms: u32,
+    filter: SearchFilter,
+    result: &SearchResult,
+) {
+    let key = search_cache_key(query, duration_ms, filter);
+    let cached = CachedSearch {
+        searched: unix_timestamp(),
+        result: result.clone(),
+    };
+    let data = serde_json::to_vec(&cached).unwrap();
+    if let Err(err) = cache.put(SEARCH_CACHE, &key, &data) {
+        tracing::warn!(error = ?err, "Couldn't cache the search for {key}");
+    }
+}
+
+// Same as search_ytmusic, but gives back the top results to pick from
+pub async fn search_ytmusic_choices(
+    id: DownloadId,
     client: &Client,
     query: &str,
+    duration_ms: u32,
+    filter: SearchFilter,
     search_for: SearchFor,
 ) -> TaskResult {
+    let title = search_for.song_name();
+    match ranked_results(client, query, duration_ms, filter, title).await {
+        Ok(results) if results.is_empty() => Err(Error::SongFailed(
+            id,
+            search_for,
+            String::from("Not found on YT Music"),
+        )),
+        Ok(results) => Ok(TaskReturn::SearchResults(id, results, search_for)),
+        Err(err) => Err(Error::SongFailed(id, search_for, err.to_string())),
+    }
+}
+
+// The top results, best match first
+async fn ranked_results(
+    client: &Client,
+    query: &str,
+    duration_ms: u32,
+    filter: SearchFilter,
+    title: &str,
+) -> Result<Vec<SearchResult>, Error> {
+    let mut results: Vec<(f64, SearchResult)> = search_songs(client, query, filter)
+        .await?
+        .iter()
+        .filter_map(|song| parse_search_result(&song["musicResponsiveListItemRenderer"]))
+        .filter(|result| !result.video_id.is_empty())
+        .take(SEARCH_CHOICES)
+        .map(|result| (match_score(&result, title, duration_ms), result))
+        .collect();
+
+    // Stable, so YT Music's order decides between equally good matches
+    results.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+    Ok(results.into_iter().map(|(_, result)| result).collect())
+}
+
+// From 0 to 2, covers and live versions usually have extra words in the title or a different length
+pub(crate) fn match_score(result: &SearchResult, title: &str, duration_ms: u32) -> f64 {
+    let words = |text: &str| -> HashSet<String> {
+        text.split(|char: char| !char.is_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .map(str::to_lowercase)
+            .collect()
+    };
+    let wanted = words(title);
+    let found = words(&result.title);
+    let title_score = match wanted.union(&found).count() {
+        0 => 0.0,
+        all => wanted.intersection(&found).count() as f64 / all as f64,
+    };
+
+    let duration_score = if duration_ms == 0 || result.duration_ms == 0 {
+        0.5
+    } else {
+        // A few seconds off is the same recording, half a minute off is most likely another one
+        let off = duration_ms.abs_diff(result.duration_ms) as f64 / 1000.0;
+        (1.0 - off / 30.0).max(0.0)
+    };
+    title_score + duration_score
+}
+
+async fn search_songs(
+    client: &Client,
+    query: &str,
+    filter: SearchFilter,
+) -> Result<Vec<Value>, Error> {
     let body = Body {
         query,
-        // Filter only for songs, ignore spelling mistakes
-        params: "EgWKAQIIAUICCAFqDBAOEAoQAxAEEAkQBQ%3D%3D",
-        context: json!({
-            "client": {
-                "clientName": "WEB_REMIX",
-                "clientVersion": format!("1.{}.01.00", get_timestamp()),
-            },
-            "user": {},
-        }),
+        params: filter.params(),
+        context: client_context(),
     };
 
-    let json = send_request(client, body).await?;
+    let json = send_request(client, "search", &body).await?;
 
     if let Some(contents) = json.get("contents") {
         let results = if let Some(renderer) = contents.get("tabbedSearchResultsRenderer") {
@@ -309,26 +1543,147 @@ pub async fn search_ytmusic(
         let section_list = &results["sectionListRenderer"]["contents"];
         let has_renderer = section_list.get("itemSectionRenderer").is_some();
 
-        if section_list.as_array().unwrap().len() == 1 && has_renderer {
+        let sections = section_list.as_array().ok_or(Error::YtMusic)?;
+        if sections.len() == 1 && has_renderer {
             return Err(Error::YtMusic);
         }
 
         let mut shelf_contents: &Vec<Value> = &Vec::new();
-        for res in section_list.as_array().unwrap() {
+        for res in sections {
             if let Some(renderer) = res.get("musicCardShelfRenderer") {
-                if let Some(contents) = renderer.get("contents") {
-                    shelf_contents = contents.as_array().unwrap();
+                if let Some(contents) = renderer.get("contents").and_then(Value::as_array) {
+                    shelf_contents = contents;
                 }
             } else if let Some(renderer) = res.get("musicShelfRenderer") {
-                shelf_contents = renderer["contents"].as_array().unwrap();
+                shelf_contents = renderer["contents"].as_array().ok_or(Error::YtMusic)?;
             }
         }
 
-        return Ok(TaskReturn::SearchResult(
-            id,
-            parse_search_result(&shelf_contents[0]["musicResponsiveListItemRenderer"]),
+        return Ok(shelf_contents.clone());
+    }
+    Err(Error::YtMusic)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        cache_search, find_in_paths, match_score, parse_album, search_ytmusic, SearchFilter,
+        SearchResult, DLP_EXECUTABLE_NAME,
+    };
+    use crate::{cache::Cache, SearchFor, TaskReturn};
+    use std::{env, fs, path::Path, process, time::Duration};
+
+    #[tokio::test]
+    async fn reuses_cached_searches() {
+        let dir = env::temp_dir().join(format!("quefi-test-{}-searches", process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let cache = Cache::new(dir.clone(), 1);
+        let result = SearchResult {
+            video_id: String::from("dQw4w9WgXcQ"),
+            duration_ms: 213_000,
+            title: String::from("Never Gonna Give You Up"),
+            artist: String::from("Rick Astley"),
+        };
+        cache_search(&cache, "rick astley", 213_000, SearchFilter::Songs, &result);
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(1))
+            .build()
+            .unwrap();
+        let search_for = SearchFor::GlobalSong(String::from("Never Gonna Give You Up"));
+        // Found without a request to YT Music
+        let found = search_ytmusic(
+            0,
+            &client,
+            Some(&cache),
+            "rick astley",
+            213_000,
+            SearchFilter::Songs,
             search_for,
+        )
+        .await;
+        assert!(matches!(
+            found,
+            Ok(TaskReturn::SearchResult(0, ref best, _)) if best.video_id == "dQw4w9WgXcQ"
         ));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn scores_search_results_by_title_and_duration() {
+        let result = |title: &str, duration_ms: u32| SearchResult {
+            video_id: String::from("aaaaaaaaaaa"),
+            duration_ms,
+            title: String::from(title),
+            artist: String::from("Artist"),
+        };
+        let live = result("Song (Live at Wembley)", 291_000);
+        let studio = result("Song", 212_000);
+        let cover = result("Song", 250_000);
+
+        let score = |result| match_score(result, "Song", 213_000);
+        assert!(score(&studio) > score(&live));
+        assert!(score(&studio) > score(&cover));
+        assert!(score(&cover) > score(&live));
+
+        // Without a known length only the title counts
+        assert_eq!(
+            match_score(&studio, "Song", 0),
+            match_score(&cover, "Song", 0)
+        );
+    }
+
+    #[test]
+    fn reads_youtube_album_track_list() {
+        let track = |id: Option<&str>, name: &str| {
+            serde_json::json!({
+                "musicResponsiveListItemRenderer": {
+                    "playlistItemData": id.map(|id| serde_json::json!({ "videoId": id })),
+                    "flexColumns": [{
+                        "musicResponsiveListItemFlexColumnRenderer": {
+                            "text": { "runs": [{ "text": name }] }
+                        }
+                    }],
+                }
+            })
+        };
+        let json = serde_json::json!({
+            "contents": {
+                "twoColumnBrowseResultsRenderer": {
+                    "tabs": [{ "tabRenderer": { "content": { "sectionListRenderer": { "contents": [{
+                        "musicResponsiveHeaderRenderer": {
+                            "title": { "runs": [{ "text": "Album" }] },
+                            "straplineTextOne": { "runs": [{ "text": "Artist" }] },
+                        }
+                    }] } } } }],
+                    "secondaryContents": { "sectionListRenderer": { "contents": [{
+                        "musicShelfRenderer": {
+                            "contents": [track(Some("aaaaaaaaaaa"), "Intro"), track(None, "Gone")]
+                        }
+                    }] } },
+                }
+            }
+        });
+
+        let album = parse_album(&json).unwrap();
+        assert_eq!(album.title, "Album");
+        assert_eq!(album.entries.len(), 1);
+        assert_eq!(album.entries[0].id, "aaaaaaaaaaa");
+        assert_eq!(album.entries[0].title, "Artist - Intro");
+    }
+
+    #[test]
+    fn finds_yt_dlp_on_path() {
+        let dir = env::temp_dir().join(format!("quefi-test-{}-path", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let paths = env::join_paths([Path::new("/nonexistent"), &dir]).unwrap();
+        assert_eq!(find_in_paths(&paths, DLP_EXECUTABLE_NAME), None);
+
+        let dlp = dir.join(DLP_EXECUTABLE_NAME);
+        fs::write(&dlp, b"").unwrap();
+        let found = find_in_paths(&paths, DLP_EXECUTABLE_NAME);
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(found, Some(dlp));
     }
-    Err(Error::YtMusic)
 }